@@ -0,0 +1,94 @@
+use std::future::Future;
+use tokio::task::JoinSet;
+use tracing::{error, info};
+
+use crate::api::PerfectGymClient;
+use crate::config::Config;
+use crate::error::{GymSniperError, Result};
+use crate::snipe;
+
+/// Name used for the `[credentials]` account in per-account outcomes
+pub const MAIN_ACCOUNT: &str = "main";
+
+/// How booking a class went for one account
+#[derive(Debug)]
+pub struct AccountOutcome {
+    pub account: String,
+    pub result: Result<()>,
+}
+
+/// The main account followed by each named `[[accounts]]` entry, with a config that logs in as it
+pub fn resolve(config: &Config, names: &[String]) -> Result<Vec<(String, Config)>> {
+    let mut accounts = vec![(MAIN_ACCOUNT.to_string(), config.clone())];
+    for name in names {
+        accounts.push((name.clone(), config.for_account(name)?));
+    }
+    Ok(accounts)
+}
+
+/// Book a class for the main account and every named account at once. Each account
+/// logs in with its own client, so a daily limit on one doesn't stop the others.
+pub async fn book_for_accounts(config: &Config, class_id: u64, names: &[String]) -> Result<Vec<AccountOutcome>> {
+    fan_out(config, names, move |_, client| async move {
+        client.book_class(class_id).await.map(|_| ())
+    })
+    .await
+}
+
+/// Snipe a class for the main account and every named account at once, each waiting for
+/// the window and retrying on its own
+pub async fn snipe_for_accounts(
+    config: &Config,
+    class_id: u64,
+    spot_id: Option<u64>,
+    names: &[String],
+) -> Result<Vec<AccountOutcome>> {
+    fan_out(config, names, move |config, client| async move {
+        snipe::snipe_class(&config, &client, class_id, spot_id).await
+    })
+    .await
+}
+
+/// Log one line per account. Returns the first failure, if any.
+pub fn report(class_label: &str, outcomes: Vec<AccountOutcome>) -> Result<()> {
+    let mut first_error = None;
+    for outcome in outcomes {
+        match outcome.result {
+            Ok(()) => info!("{}: booked {}", outcome.account, class_label),
+            Err(e) => {
+                error!("{}: could not book {}: {}", outcome.account, class_label, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Run `action` for each account concurrently with a freshly logged-in client, returning
+/// outcomes in account order
+async fn fan_out<F, Fut>(config: &Config, names: &[String], action: F) -> Result<Vec<AccountOutcome>>
+where
+    F: Fn(Config, PerfectGymClient) -> Fut,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let accounts = resolve(config, names)?;
+    let mut tasks = JoinSet::new();
+    for (index, (account, config)) in accounts.into_iter().enumerate() {
+        let client = PerfectGymClient::new(&config)?;
+        let run = action(config, client.clone());
+        tasks.spawn(async move {
+            let result = match client.login().await {
+                Ok(()) => run.await,
+                Err(e) => Err(e),
+            };
+            (index, AccountOutcome { account, result })
+        });
+    }
+
+    let mut outcomes = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        outcomes.push(joined.map_err(|e| GymSniperError::Api(format!("Booking task failed: {}", e)))?);
+    }
+    outcomes.sort_by_key(|(index, _)| *index);
+    Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+}
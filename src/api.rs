@@ -1,18 +1,242 @@
-use chrono::{DateTime, Local, NaiveDateTime};
+use chrono::{DateTime, Local, LocalResult, NaiveDateTime, TimeZone, Utc};
 use reqwest::{header, Client};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
-use tracing::debug;
+use tracing::{debug, info, warn};
+
+use crate::clock::{Clock, SharedClock};
+use crate::config::{AmbiguousTime, Config, GymConfig};
+use crate::defend;
+use crate::error::{BookingErrorKind, GymSniperError, Result};
+use crate::rate_limit::RateLimiter;
+use crate::redact;
+use crate::token_cache::{self, CachedToken};
+use crate::util::{check_cancel_notice, window_opens};
+
+/// Times to wait out a 429 before handing the response back to the caller
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// Longest `Retry-After` we'll honour; longer waits are capped to this
+const MAX_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(30);
+/// Wait used when a 429 has no usable `Retry-After` header
+const DEFAULT_RETRY_AFTER: std::time::Duration = std::time::Duration::from_secs(1);
+/// Refresh the token this long before it expires, so no request is sent with a stale one
+const TOKEN_EXPIRY_MARGIN: chrono::Duration = chrono::Duration::seconds(60);
+
+/// How many days ahead to look for bookings unless told otherwise
+pub const DEFAULT_BOOKINGS_DAYS: u32 = 14;
+
+/// A request as it would be sent, for inspection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestPreview {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// A response as received, kept so failures can be compared with a later retry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RawResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
 
-use crate::config::Config;
-use crate::error::{GymSniperError, Result};
+impl RawResponse {
+    async fn read(response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let headers = header_pairs(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        Self { status, headers, body }
+    }
+}
 
 #[derive(Clone)]
 pub struct PerfectGymClient {
     client: Client,
     config: Config,
     token: Arc<RwLock<Option<String>>>,
+    /// When the current token was issued, by `clock`
+    logged_in_at: Arc<RwLock<Option<DateTime<Local>>>>,
+    /// When the current token expires by our clock, from its `exp` claim; `None` if unknown
+    token_expires_at: Arc<RwLock<Option<DateTime<Local>>>>,
+    /// How far the gym server's clock is ahead of ours, from the login response's `Date` header
+    server_offset: Arc<RwLock<chrono::Duration>>,
+    /// Response to the last booking or waitlist request, if it was rejected
+    last_booking_failure: Arc<RwLock<Option<RawResponse>>>,
+    /// Headers the HTTP client sends with every request
+    headers: header::HeaderMap,
+    clock: SharedClock,
+    /// `gym.base_url` and its mirrors, from `GymConfig::portal_urls`
+    portal_urls: Arc<Vec<String>>,
+    /// Index into `portal_urls` of the portal requests currently go to
+    active_portal: Arc<AtomicUsize>,
+    /// `gym.club_id`, or the member's home club from login when that is left out (0)
+    club_id: Arc<AtomicU32>,
+    /// Caps requests at `gym.max_requests_per_minute`, shared by every client for the portal
+    rate_limiter: RateLimiter,
+    /// Where `login --save-token` keeps the token; `None` if saved tokens are not used
+    token_file: Option<Arc<PathBuf>>,
+    /// Whether to write the token to `token_file` after each login
+    save_token: Arc<AtomicBool>,
+    /// Set while the token loaded from `token_file` hasn't been used in place of a login yet
+    reuse_saved_token: Arc<AtomicBool>,
+    /// Set once the portal answers 404 for the member's bookings list
+    no_bookings_endpoint: Arc<AtomicBool>,
+}
+
+/// Builds a `PerfectGymClient` with non-default HTTP settings, e.g. for tests or gyms
+/// behind a proxy. `PerfectGymClient::new` uses the defaults.
+pub struct PerfectGymClientBuilder {
+    config: Config,
+    headers: header::HeaderMap,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxy: Option<reqwest::Proxy>,
+    http_client: Option<Client>,
+    clock: SharedClock,
+    token_file: Option<PathBuf>,
+}
+
+impl PerfectGymClientBuilder {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            headers: default_headers(),
+            timeout: None,
+            connect_timeout: None,
+            proxy: None,
+            http_client: None,
+            clock: SharedClock::default(),
+            token_file: Some(token_cache::token_path(config.profile.as_deref())),
+        }
+    }
+
+    /// Use a different portal URL than `gym.base_url`. Mirrors in `gym.base_urls` are kept.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.config.gym.base_url = base_url.into();
+        self
+    }
+
+    /// Send an extra header with every request, replacing any default of the same name
+    pub fn header(mut self, name: header::HeaderName, value: header::HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Total time limit for each request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Time limit for establishing a connection
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Route requests through a proxy
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Use a ready-made HTTP client as-is. Headers, timeouts and proxy set on the
+    /// builder are then not applied to it.
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Clock used for rate-limit waits and retry backoff
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Where to look for a token saved by `login --save-token`, instead of `token.json`
+    /// (`token.<profile>.json` for a profile). `None` never reuses or saves a token.
+    pub fn token_file(mut self, path: Option<PathBuf>) -> Self {
+        self.token_file = path;
+        self
+    }
+
+    pub fn build(self) -> Result<PerfectGymClient> {
+        let client = match self.http_client {
+            Some(client) => client,
+            None => {
+                let mut builder = Client::builder()
+                    .cookie_store(true)
+                    .default_headers(self.headers.clone());
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(proxy) = self.proxy {
+                    builder = builder.proxy(proxy);
+                }
+                builder.build()?
+            }
+        };
+
+        let portal_urls = self.config.gym.portal_urls();
+        if portal_urls.is_empty() {
+            return Err(GymSniperError::Config("gym: set base_url or base_urls".to_string()));
+        }
+
+        let rate_limiter = RateLimiter::shared(&portal_urls[0], self.config.gym.max_requests_per_minute);
+
+        // A saved token for this login is kept up to date, and reused while still valid. It is
+        // keyed on the first portal, as `base_url` may be left empty when `base_urls` is set.
+        let saved = self
+            .token_file
+            .as_deref()
+            .and_then(token_cache::load)
+            .filter(|saved| saved.email == self.config.credentials.email && saved.base_url == portal_urls[0]);
+        let save_token = saved.is_some();
+        let saved = saved.filter(|saved| {
+            saved.usable_for(&self.config.credentials.email, &portal_urls[0], self.clock.now(), TOKEN_EXPIRY_MARGIN)
+        });
+        if let Some(saved) = &saved {
+            debug!("Found a saved token valid until {}", saved.expires_at.format("%a %d %b %H:%M:%S"));
+        }
+        let club_id = match &saved {
+            Some(saved) if self.config.gym.club_id == 0 => saved.club_id,
+            _ => self.config.gym.club_id,
+        };
+
+        Ok(PerfectGymClient {
+            client,
+            reuse_saved_token: Arc::new(AtomicBool::new(saved.is_some())),
+            token: Arc::new(RwLock::new(saved.as_ref().map(|saved| saved.token.clone()))),
+            logged_in_at: Arc::new(RwLock::new(saved.as_ref().map(|saved| saved.logged_in_at))),
+            token_expires_at: Arc::new(RwLock::new(saved.as_ref().map(|saved| saved.expires_at))),
+            server_offset: Arc::new(RwLock::new(
+                saved
+                    .as_ref()
+                    .map(|saved| chrono::Duration::milliseconds(saved.server_offset_ms))
+                    .unwrap_or_default(),
+            )),
+            last_booking_failure: Arc::new(RwLock::new(None)),
+            headers: self.headers,
+            clock: self.clock,
+            portal_urls: Arc::new(portal_urls),
+            active_portal: Arc::new(AtomicUsize::new(0)),
+            club_id: Arc::new(AtomicU32::new(club_id)),
+            rate_limiter,
+            token_file: self.token_file.map(Arc::new),
+            save_token: Arc::new(AtomicBool::new(save_token)),
+            no_bookings_endpoint: Arc::new(AtomicBool::new(false)),
+            config: self.config,
+        })
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -43,6 +267,23 @@ struct MemberInfo {
     id: u64,
     #[serde(rename = "FirstName")]
     first_name: String,
+    #[serde(rename = "HomeClubId", alias = "DefaultClubId", default)]
+    home_club_id: Option<u32>,
+    #[serde(rename = "HomeClub", default)]
+    home_club: Option<ClubRef>,
+}
+
+impl MemberInfo {
+    /// The member's home club, whichever way the portal gives it
+    fn home_club(&self) -> Option<u32> {
+        self.home_club_id.or(self.home_club.as_ref().map(|c| c.id))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ClubRef {
+    #[serde(rename = "Id")]
+    id: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -89,6 +330,12 @@ pub(crate) struct ClassItem {
     pub(crate) status: String,
     #[serde(rename = "Trainer")]
     pub(crate) trainer: Option<String>,
+    #[serde(rename = "BookableFrom", alias = "BookingOpensAt", default)]
+    pub(crate) bookable_from: Option<String>,
+    #[serde(rename = "MaxParticipants", default)]
+    pub(crate) max_participants: Option<u32>,
+    #[serde(rename = "BookedCount", default)]
+    pub(crate) booked_count: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
@@ -97,20 +344,80 @@ struct BookClassRequest {
     class_id: u64,
     #[serde(rename = "clubId")]
     club_id: String,
+    #[serde(rename = "spotId", skip_serializing_if = "Option::is_none")]
+    spot_id: Option<u64>,
 }
 
+/// Body of requests that only name a class, e.g. cancelling a booking
 #[derive(Debug, Serialize)]
-struct CancelBookingRequest {
+struct ClassRequest {
     #[serde(rename = "classId")]
     class_id: u64,
 }
 
 #[derive(Debug, Deserialize)]
 struct BookClassResponse {
-    #[serde(rename = "Tickets")]
+    #[serde(rename = "Tickets", default)]
     tickets: Vec<BookingTicket>,
-    #[serde(rename = "ClassId")]
-    class_id: u64,
+    #[serde(rename = "PaymentRequired", default)]
+    payment_required: bool,
+    /// Queue position, when the request joined the standby queue
+    #[serde(rename = "StandByQueueNumber", default)]
+    standby_queue_number: Option<u32>,
+}
+
+/// The error a portal gives for a rejected booking: a machine-readable code and, when
+/// sent, a human message (which may be localized, so it is never matched on)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookClassError {
+    pub error_code: String,
+    pub message: String,
+}
+
+/// The JSON shapes portals use for booking errors: `{"ErrorCode": .., "Message": ..}`,
+/// `{"Errors": ["TooSoonToBook"]}` or `{"Errors": [{"Code": .., "Message": ..}]}`
+#[derive(Debug, Deserialize)]
+struct BookClassErrorBody {
+    #[serde(rename = "ErrorCode", alias = "errorCode", alias = "Code", alias = "code", default)]
+    error_code: Option<String>,
+    #[serde(rename = "Message", alias = "message", default)]
+    message: Option<String>,
+    #[serde(rename = "Errors", alias = "errors", default)]
+    errors: Vec<BookClassErrorEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BookClassErrorEntry {
+    Code(String),
+    Detail {
+        #[serde(rename = "ErrorCode", alias = "errorCode", alias = "Code", alias = "code", default)]
+        error_code: Option<String>,
+        #[serde(rename = "Message", alias = "message", default)]
+        message: Option<String>,
+    },
+}
+
+impl BookClassError {
+    /// Read the code from an error body: a JSON object, a JSON string, or a bare code
+    /// such as `ClassIsFull`. `None` when the body carries no code.
+    pub fn parse(body: &str) -> Option<Self> {
+        let body = body.trim();
+        if let Ok(parsed) = serde_json::from_str::<BookClassErrorBody>(body) {
+            let (entry_code, entry_message) = match parsed.errors.into_iter().next() {
+                Some(BookClassErrorEntry::Code(code)) => (Some(code), None),
+                Some(BookClassErrorEntry::Detail { error_code, message }) => (error_code, message),
+                None => (None, None),
+            };
+            return Some(Self {
+                error_code: parsed.error_code.or(entry_code)?,
+                message: parsed.message.or(entry_message).unwrap_or_default(),
+            });
+        }
+        let code = serde_json::from_str::<String>(body).unwrap_or_else(|_| body.to_string());
+        let is_code = !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        is_code.then(|| Self { error_code: code, message: String::new() })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -123,13 +430,46 @@ struct BookingTicket {
     trainer: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ClassInfo {
     pub id: u64,
     pub name: String,
     pub start_time: DateTime<Local>,
     pub status: String,
     pub trainer: Option<String>,
+    pub zone: String,
+    /// When booking opens, if the gym says so
+    pub bookable_from: Option<DateTime<Local>>,
+    /// Length of the class; 0 when the gym doesn't say
+    pub duration_minutes: u32,
+    /// Total places, when the calendar gives it
+    pub capacity: Option<u32>,
+    /// Places taken, when the calendar gives it
+    pub booked_count: Option<u32>,
+}
+
+impl ClassInfo {
+    /// Places still free, when both counts are known
+    pub fn spots_remaining(&self) -> Option<u32> {
+        spots_remaining(self.booked_count, self.capacity)
+    }
+
+    /// When booking opens: the gym's own time if given, otherwise computed from the class time
+    pub fn window_opens(&self, gym: &GymConfig) -> DateTime<Local> {
+        window_opens(self.start_time, self.bookable_from, gym)
+    }
+
+    /// When the class finishes, using `default_minutes` if it has no duration.
+    /// Late classes may end on the next day.
+    pub fn end_time(&self, default_minutes: u32) -> DateTime<Local> {
+        let minutes = if self.duration_minutes == 0 { default_minutes } else { self.duration_minutes };
+        self.start_time + chrono::Duration::minutes(minutes as i64)
+    }
+
+    /// True once the class has finished, not merely started
+    pub fn has_ended(&self, default_minutes: u32, now: DateTime<Local>) -> bool {
+        self.end_time(default_minutes) <= now
+    }
 }
 
 #[derive(Debug)]
@@ -137,6 +477,33 @@ pub struct BookingResult {
     pub name: String,
     pub start_time: DateTime<Local>,
     pub trainer: Option<String>,
+    /// Position in the standby queue, when the gym reports one for a waitlist join
+    pub waitlist_position: Option<u32>,
+}
+
+/// One reservation from the member's future bookings list
+#[derive(Debug, Deserialize)]
+struct FutureBookingItem {
+    #[serde(rename = "ClassId", alias = "Id")]
+    class_id: u64,
+    #[serde(rename = "Name", alias = "ClassName")]
+    name: String,
+    #[serde(rename = "StartTime")]
+    start_time: String,
+    #[serde(rename = "Duration", default)]
+    duration: String,
+    #[serde(rename = "Status", default)]
+    status: String,
+    #[serde(rename = "StandByQueueNumber", default)]
+    standby_queue_number: Option<u32>,
+    #[serde(rename = "Trainer", default)]
+    trainer: Option<String>,
+    #[serde(rename = "Zone", default)]
+    zone: Option<String>,
+    #[serde(rename = "MaxParticipants", default)]
+    max_participants: Option<u32>,
+    #[serde(rename = "BookedCount", default)]
+    booked_count: Option<u32>,
 }
 
 // Class details response structures
@@ -152,8 +519,93 @@ struct ClassDetailsResponse {
     start_time: String,
     #[serde(rename = "TrainerDetails")]
     trainer_details: Option<TrainerDetails>,
-    #[serde(rename = "Users")]
-    users: Vec<ClassUser>,
+    #[serde(rename = "Description", default)]
+    description: Option<String>,
+    /// `None` when the gym leaves the attendee list out (or sends null)
+    #[serde(rename = "Users", default)]
+    users: Option<Vec<ClassUser>>,
+    #[serde(rename = "MaxParticipants", default)]
+    max_participants: Option<u32>,
+    /// Places taken; counted from `Users` when the gym leaves it out, unknown without either
+    #[serde(rename = "BookedCount", default)]
+    booked_count: Option<u32>,
+    #[serde(rename = "BookableFrom", alias = "BookingOpensAt", default)]
+    bookable_from: Option<String>,
+    /// Selectable places (e.g. numbered bikes), for classes that offer them
+    #[serde(rename = "Spots", alias = "Resources", default, deserialize_with = "null_as_default")]
+    spots: Vec<SpotItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpotItem {
+    #[serde(rename = "Id")]
+    id: u64,
+    #[serde(rename = "Name", default)]
+    name: Option<String>,
+    #[serde(rename = "IsAvailable", default = "default_true")]
+    is_available: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A selectable place in a class, such as a numbered bike
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spot {
+    pub id: u64,
+    pub name: String,
+    pub available: bool,
+}
+
+/// One entry of the category list, with its subcategories nested inside
+#[derive(Debug, Deserialize)]
+struct CategoryItem {
+    #[serde(rename = "Id")]
+    id: u32,
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Children", alias = "SubCategories", default, deserialize_with = "null_as_default")]
+    children: Vec<CategoryItem>,
+}
+
+/// A class category (activity type) and its subcategories. The ID is what
+/// `get_weekly_classes` and `gym.default_category` take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub id: u32,
+    pub name: String,
+    pub children: Vec<Category>,
+}
+
+impl From<CategoryItem> for Category {
+    fn from(item: CategoryItem) -> Self {
+        Self {
+            id: item.id,
+            name: item.name,
+            children: item.children.into_iter().map(Category::from).collect(),
+        }
+    }
+}
+
+impl Category {
+    /// This category and all below it, depth first, each with its depth (0 for this one)
+    pub fn walk(&self) -> Vec<(usize, &Category)> {
+        let mut out = vec![(0, self)];
+        for child in &self.children {
+            out.extend(child.walk().into_iter().map(|(depth, c)| (depth + 1, c)));
+        }
+        out
+    }
+}
+
+/// Treat an explicit `null` the same as a missing field
+fn null_as_default<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 #[derive(Debug, Deserialize)]
@@ -166,7 +618,7 @@ struct TrainerDetails {
 struct ClassUser {
     #[serde(rename = "Status")]
     status: String,
-    #[serde(rename = "StandByQueueNumber")]
+    #[serde(rename = "StandByQueueNumber", default)]
     standby_queue_number: Option<u32>,
     #[serde(rename = "User")]
     user: ClassUserInfo,
@@ -178,7 +630,7 @@ struct ClassUserInfo {
     is_current_user: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct MyBooking {
     pub id: u64,
     pub name: String,
@@ -186,58 +638,133 @@ pub struct MyBooking {
     pub status: String,
     pub waitlist_position: Option<u32>,
     pub trainer: Option<String>,
+    /// Number of members holding a confirmed place
+    pub booked_count: Option<u32>,
+    /// Total places in the class, when the gym reports it
+    pub capacity: Option<u32>,
+    /// When booking opens, if the gym says so
+    pub bookable_from: Option<DateTime<Local>>,
+    /// Free-text description, often including equipment or prerequisite notes
+    pub description: Option<String>,
+    /// Zone (room) from the calendar; the details endpoint doesn't give one
+    pub zone: Option<String>,
+    /// Length of the class from the calendar; 0 when unknown
+    pub duration_minutes: u32,
+}
+
+impl MyBooking {
+    /// True when on the waitlist rather than holding a confirmed place
+    pub fn is_waitlisted(&self) -> bool {
+        self.status == "Awaiting"
+    }
+
+    /// Status for display: "Booked", or "Waitlist #N" with the queue position when known
+    pub fn status_label(&self) -> String {
+        if !self.is_waitlisted() {
+            return self.status.clone();
+        }
+        match self.waitlist_position {
+            Some(pos) => format!("Waitlist #{}", pos),
+            None => "Waitlist".to_string(),
+        }
+    }
+
+    /// When booking opens: the gym's own time if given, otherwise computed from the class time
+    pub fn window_opens(&self, gym: &GymConfig) -> DateTime<Local> {
+        window_opens(self.start_time, self.bookable_from, gym)
+    }
+
+    /// Places still free, when both counts are known
+    pub fn spots_remaining(&self) -> Option<u32> {
+        spots_remaining(self.booked_count, self.capacity)
+    }
+
+    /// Share of places taken (0.0-1.0), when both counts are known
+    pub fn fill_ratio(&self) -> Option<f64> {
+        match (self.booked_count, self.capacity) {
+            (Some(booked), Some(capacity)) if capacity > 0 => Some(booked as f64 / capacity as f64),
+            _ => None,
+        }
+    }
+}
+
+fn spots_remaining(booked_count: Option<u32>, capacity: Option<u32>) -> Option<u32> {
+    Some(capacity?.saturating_sub(booked_count?))
 }
 
 // Browser-like headers to appear more natural
 const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:146.0) Gecko/20100101 Firefox/146.0";
 
-impl PerfectGymClient {
-    pub fn new(config: &Config) -> Self {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(header::USER_AGENT, USER_AGENT.parse().unwrap());
-        headers.insert(header::ACCEPT_LANGUAGE, "en-GB,en;q=0.5".parse().unwrap());
+/// Headers sent with every request
+fn default_headers() -> header::HeaderMap {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(header::USER_AGENT, USER_AGENT.parse().unwrap());
+    headers.insert(header::ACCEPT_LANGUAGE, "en-GB,en;q=0.5".parse().unwrap());
+    headers
+}
 
-        let client = Client::builder()
-            .cookie_store(true)
-            .default_headers(headers)
-            .build()
-            .expect("Failed to create HTTP client");
+/// `Origin` header for a portal URL: the site it is served from
+fn portal_origin(base_url: &str) -> String {
+    base_url.replace("/clientportal2", "")
+}
 
-        Self {
-            client,
-            config: config.clone(),
-            token: Arc::new(RwLock::new(None)),
-        }
+impl PerfectGymClient {
+    /// A client with default HTTP settings. Fails if the HTTP client can't be set up or the
+    /// config names no portal URL.
+    pub fn new(config: &Config) -> Result<Self> {
+        Self::builder(config).build()
     }
 
+    pub fn builder(config: &Config) -> PerfectGymClientBuilder {
+        PerfectGymClientBuilder::new(config)
+    }
+
+    /// Log in, unless this is the first login and a token saved by `login --save-token` is
+    /// still valid, in which case that token is used once the server has been checked to
+    /// still accept it, as `ensure_session` does
     pub async fn login(&self) -> Result<()> {
-        let url = format!("{}/Auth/Login", self.config.gym.base_url);
+        if self.reuse_saved_token.swap(false, Ordering::Relaxed) && self.get_token().await.is_ok() {
+            info!("Using the saved login token");
+            // It may have been revoked since, e.g. by a password change or a login elsewhere
+            self.check_session().await?;
+            return Ok(());
+        }
+        self.login_fresh().await
+    }
 
+    /// Log in even if a saved token could be used instead
+    pub async fn login_fresh(&self) -> Result<()> {
+        self.reuse_saved_token.store(false, Ordering::Relaxed);
         let request = LoginRequest {
             remember_me: false,
             login: self.config.credentials.email.clone(),
-            password: self.config.credentials.password.clone(),
+            password: self.config.credentials.resolve_password()?,
         };
 
-        debug!("Logging in to {}", url);
-
-        let origin = &self.config.gym.base_url.replace("/clientportal2", "");
-        let referer = format!("{}/", self.config.gym.base_url);
+        debug!("Logging in to {}", self.base_url());
 
         let response = self
-            .client
-            .post(&url)
-            .header(header::CONTENT_TYPE, "application/json;charset=utf-8")
-            .header(header::ACCEPT, "application/json, text/plain, */*")
-            .header(header::ORIGIN, origin)
-            .header(header::REFERER, &referer)
-            .header("X-Requested-With", "XMLHttpRequest")
-            .header("CP-LANG", "en")
-            .header("CP-MODE", "desktop")
-            .json(&request)
-            .send()
+            .send_throttled(|| {
+                let base_url = self.base_url();
+                self.client
+                    .post(format!("{}/Auth/Login", base_url))
+                    .header(header::CONTENT_TYPE, "application/json;charset=utf-8")
+                    .header(header::ACCEPT, "application/json, text/plain, */*")
+                    .header(header::ORIGIN, portal_origin(base_url))
+                    .header(header::REFERER, format!("{}/", base_url))
+                    .header("X-Requested-With", "XMLHttpRequest")
+                    .header("CP-LANG", "en")
+                    .header("CP-MODE", "desktop")
+                    .json(&request)
+            })
             .await?;
 
+        if response.status() == reqwest::StatusCode::FORBIDDEN {
+            return Err(GymSniperError::Blocked(
+                "Login refused (403) - your IP may be blocked".to_string(),
+            ));
+        }
+
         if !response.status().is_success() {
             return Err(GymSniperError::Auth(format!(
                 "Login failed with status: {}",
@@ -245,6 +772,11 @@ impl PerfectGymClient {
             )));
         }
 
+        let offset = server_clock_offset(response.headers(), self.clock.now());
+        if let Some(offset) = offset {
+            *self.server_offset.write().await = offset;
+        }
+
         // Extract JWT token from response header
         let token = response
             .headers()
@@ -259,35 +791,264 @@ impl PerfectGymClient {
         }
 
         let login_response: LoginResponse = response.json().await?;
+        let member = login_response.user.and_then(|u| u.member);
 
-        if let Some(user) = login_response.user {
-            if let Some(member) = user.member {
-                debug!("Logged in as {} (ID: {})", member.first_name, member.id);
-            }
+        if let Some(member) = &member {
+            debug!("Logged in as {} (ID: {})", member.first_name, member.id);
+        }
+
+        // Without a configured club, book at the member's home club
+        if self.club_id() == 0 {
+            let Some(club_id) = member.as_ref().and_then(MemberInfo::home_club) else {
+                return Err(GymSniperError::Config(
+                    "gym: club_id is not set and the login response names no home club".to_string(),
+                ));
+            };
+            info!("Using your home club {} (club_id isn't set under [gym])", club_id);
+            self.club_id.store(club_id, Ordering::Relaxed);
+        }
+
+        // `exp` is by the server's clock
+        let expires_at = token.as_deref().and_then(jwt_expiry).map(|exp| exp - offset.unwrap_or_default());
+        match expires_at {
+            Some(expires_at) => debug!("Token expires at {}", expires_at.format("%a %d %b %H:%M:%S")),
+            None => debug!("Token has no readable expiry, relying on token_refresh_minutes"),
+        }
+
+        if self.save_token.load(Ordering::Relaxed) {
+            self.write_token_file(token.as_deref().unwrap_or_default(), expires_at, offset.unwrap_or_default());
         }
 
         *self.token.write().await = token;
+        *self.logged_in_at.write().await = Some(self.clock.now());
+        *self.token_expires_at.write().await = expires_at;
 
         Ok(())
     }
 
-    /// Build an authenticated request with standard headers
-    fn build_request(&self, method: reqwest::Method, url: &str, token: &str) -> reqwest::RequestBuilder {
-        let origin = self.config.gym.base_url.replace("/clientportal2", "");
-        let referer = format!("{}/", self.config.gym.base_url);
+    /// Save the token from every login from now on, for later runs to reuse
+    pub fn set_save_token(&self, save: bool) {
+        self.save_token.store(save, Ordering::Relaxed);
+    }
+
+    /// Write a freshly issued token to `token_file`. Failures are only logged: the login
+    /// itself worked.
+    fn write_token_file(&self, token: &str, expires_at: Option<DateTime<Local>>, offset: chrono::Duration) {
+        let Some(path) = &self.token_file else {
+            return;
+        };
+        let Some(expires_at) = expires_at else {
+            warn!("Not saving the login token: its expiry can't be read");
+            return;
+        };
+        let saved = CachedToken {
+            email: self.config.credentials.email.clone(),
+            base_url: self.portal_urls[0].clone(),
+            token: token.to_string(),
+            expires_at,
+            logged_in_at: self.clock.now(),
+            club_id: self.club_id(),
+            server_offset_ms: offset.num_milliseconds(),
+        };
+        match token_cache::save(path, &saved) {
+            Ok(()) => debug!("Saved login token to {}", path.display()),
+            Err(e) => warn!("Could not save login token to {}: {}", path.display(), e),
+        }
+    }
+
+    /// When the current token expires, from its `exp` claim. `None` if not logged in or the
+    /// token doesn't say, in which case it is treated as never expiring.
+    pub async fn token_expires_at(&self) -> Option<DateTime<Local>> {
+        *self.token_expires_at.read().await
+    }
+
+    /// How far the gym server's clock is ahead of ours (negative if behind), as measured at
+    /// the last login. Zero until then, or if the skew is within the `Date` header's precision.
+    pub async fn server_offset(&self) -> chrono::Duration {
+        *self.server_offset.read().await
+    }
+
+    /// When the current token should be replaced: a minute before it expires, or after
+    /// `gym.token_refresh_minutes` if that comes first. `None` if neither is known.
+    pub async fn token_refresh_due_at(&self) -> Option<DateTime<Local>> {
+        let logged_in_at = (*self.logged_in_at.read().await)?;
+        let by_interval = self
+            .config
+            .gym
+            .token_refresh_minutes
+            .map(|minutes| logged_in_at + chrono::Duration::minutes(minutes as i64));
+        let by_expiry = self.token_expires_at().await.map(|exp| exp - TOKEN_EXPIRY_MARGIN);
+        by_interval.into_iter().chain(by_expiry).min()
+    }
+
+    /// Log in again if the token is due for a refresh. Returns whether it was refreshed.
+    pub async fn refresh_token_if_due(&self) -> Result<bool> {
+        match self.token_refresh_due_at().await {
+            Some(due) if self.clock.now() >= due => {
+                debug!("Token refresh due since {}, logging in again", due.format("%H:%M:%S"));
+                self.login_fresh().await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Make sure the client holds a token the server accepts before doing real work. Logs in
+    /// if there is no token. Otherwise, unless `gym.check_session_on_startup` is off, checks
+    /// the token with a one-day calendar request and logs in again if that gets a 401.
+    /// Returns whether a login was needed.
+    pub async fn ensure_session(&self) -> Result<bool> {
+        if self.get_token().await.is_err() {
+            self.login().await?;
+            return Ok(true);
+        }
+        self.check_session().await
+    }
+
+    /// Check the token held with a one-day calendar request, unless
+    /// `gym.check_session_on_startup` is off, and log in again if that gets a 401.
+    /// Returns whether a login was needed.
+    async fn check_session(&self) -> Result<bool> {
+        let Ok(token) = self.get_token().await else {
+            return Ok(false);
+        };
+        if !self.config.gym.check_session_on_startup {
+            return Ok(false);
+        }
+
+        let request = WeeklyClassesRequest {
+            club_id: self.club_id(),
+            category_id: None,
+            days_in_week: 1,
+        };
+        let response = self
+            .send_throttled(|| {
+                self.build_request(reqwest::Method::POST, "/Classes/ClassCalendar/WeeklyClasses", &token)
+                    .json(&request)
+            })
+            .await;
+
+        match response {
+            Ok(response) if response.status() == reqwest::StatusCode::UNAUTHORIZED => {
+                info!("Saved session was rejected by the server, logging in again");
+                self.clear_session().await;
+                self.login_fresh().await?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            // Anything else will show up again on the first real request
+            Err(e) => {
+                debug!("Session check failed: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    /// Forget the current token, as if never logged in
+    async fn clear_session(&self) {
+        *self.token.write().await = None;
+        *self.logged_in_at.write().await = None;
+        *self.token_expires_at.write().await = None;
+    }
+
+    /// The club requests are for: `gym.club_id`, or the member's home club once logged in if
+    /// that isn't set. 0 until then.
+    pub fn club_id(&self) -> u32 {
+        self.club_id.load(Ordering::Relaxed)
+    }
+
+    /// The portal requests currently go to: `gym.base_url`, or a mirror after a failover
+    pub fn base_url(&self) -> &str {
+        &self.portal_urls[self.active_portal.load(Ordering::Relaxed)]
+    }
+
+    /// Move on to the next mirror after `error` connecting to the current one
+    fn fail_over(&self, error: &reqwest::Error) {
+        let failed = self.active_portal.load(Ordering::Relaxed);
+        let next = (failed + 1) % self.portal_urls.len();
+        // Another request may already have moved on; only the first one switches
+        if self
+            .active_portal
+            .compare_exchange(failed, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            warn!(
+                "Could not connect to {} ({}), switching to {}",
+                self.portal_urls[failed], error, self.portal_urls[next]
+            );
+        }
+    }
+
+    /// Build an authenticated request with standard headers to `path` on the active portal,
+    /// e.g. `/Classes/ClassCalendar/WeeklyClasses`
+    fn build_request(&self, method: reqwest::Method, path: &str, token: &str) -> reqwest::RequestBuilder {
+        let base_url = self.base_url();
 
         self.client
-            .request(method, url)
+            .request(method, format!("{}{}", base_url, path))
             .header(header::AUTHORIZATION, format!("Bearer {}", token))
             .header(header::CONTENT_TYPE, "application/json;charset=utf-8")
             .header(header::ACCEPT, "application/json, text/plain, */*")
-            .header(header::ORIGIN, origin)
-            .header(header::REFERER, referer)
+            .header(header::ORIGIN, portal_origin(base_url))
+            .header(header::REFERER, format!("{}/", base_url))
             .header("X-Requested-With", "XMLHttpRequest")
             .header("CP-LANG", "en")
             .header("CP-MODE", "desktop")
     }
 
+    /// The BookClass request for a class, ready to send
+    fn book_request(&self, class_id: u64, spot_id: Option<u64>, token: &str) -> reqwest::RequestBuilder {
+        self.booking_request("BookClass", class_id, spot_id, token)
+    }
+
+    /// A booking-shaped request to one of the class calendar endpoints
+    fn booking_request(&self, endpoint: &str, class_id: u64, spot_id: Option<u64>, token: &str) -> reqwest::RequestBuilder {
+        let path = format!("/Classes/ClassCalendar/{}", endpoint);
+
+        let request = BookClassRequest {
+            class_id,
+            club_id: self.club_id().to_string(),
+            spot_id,
+        };
+
+        self.build_request(reqwest::Method::POST, &path, token)
+            .json(&request)
+    }
+
+    /// Build the exact BookClass request `book_class` would send, without sending it.
+    /// Uses the current token if logged in, otherwise a `<token>` placeholder.
+    pub async fn preview_book_request(&self, class_id: u64) -> Result<RequestPreview> {
+        self.preview_book_request_at(class_id, None).await
+    }
+
+    /// Preview the BookClass request for a specific spot
+    pub async fn preview_book_request_at(&self, class_id: u64, spot_id: Option<u64>) -> Result<RequestPreview> {
+        let token = self.get_token().await.unwrap_or_else(|_| "<token>".to_string());
+        let request = self.book_request(class_id, spot_id, &token).build()?;
+
+        let mut headers: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .chain(request.headers().iter())
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
+            .collect();
+        headers.sort();
+        headers.dedup_by(|a, b| a.0 == b.0);
+
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+            .unwrap_or_default();
+
+        Ok(RequestPreview {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers,
+            body,
+        })
+    }
+
     /// Read the token from the RwLock, returning an error if not logged in
     async fn get_token(&self) -> Result<String> {
         self.token
@@ -297,24 +1058,135 @@ impl PerfectGymClient {
             .ok_or_else(|| GymSniperError::Auth("Not logged in".to_string()))
     }
 
-    pub async fn get_weekly_classes(&self, days: u32) -> Result<Vec<ClassInfo>> {
-        let url = format!(
-            "{}/Classes/ClassCalendar/WeeklyClasses",
-            self.config.gym.base_url
-        );
+    /// Send a request, retrying on network errors (connection failures, timeouts) and 5xx
+    /// responses, up to `gym.retry_attempts` tries. 4xx responses are returned as-is since
+    /// retrying won't change them. Bookings don't come through here: one that timed out
+    /// may still have gone through, and sending it again could book twice.
+    async fn send_with_retry<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        self.send_with_retry_counted(build).await.map(|(response, _)| response)
+    }
+
+    /// `send_with_retry`, also returning how many attempts it took
+    async fn send_with_retry_counted<F>(&self, build: F) -> Result<(reqwest::Response, u32)>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let max_attempts = self.config.gym.retry_attempts.max(1);
+        let mut attempt = 1;
+
+        loop {
+            match self.send_throttled(&build).await {
+                Ok(response) if response.status().is_server_error() && attempt < max_attempts => {
+                    debug!("Request attempt {} got {}, retrying...", attempt, response.status());
+                }
+                Ok(response) => return Ok((response, attempt)),
+                Err(e) if attempt < max_attempts => {
+                    debug!("Request attempt {} failed: {}, retrying...", attempt, e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            self.clock.sleep(Duration::from_millis(250 * attempt as u64)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send a request, waiting out 429 responses as directed by `Retry-After` (capped).
+    /// Gives up after a few throttled responses and returns the last one. Every attempt
+    /// first waits for the rate limiter.
+    async fn send_throttled<F>(&self, build: F) -> reqwest::Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let mut throttled = 0;
+        let mut failovers = 0;
+
+        loop {
+            // Only connection failures move to a mirror: after a timeout the request may
+            // have reached the gym, and sending it again elsewhere could book twice
+            let request = build().build()?;
+            self.rate_limiter.acquire(&self.clock).await;
+            self.log_request(&request);
+            let response = match self.client.execute(request).await {
+                Ok(response) => {
+                    self.log_response(&response);
+                    response
+                }
+                Err(e) if e.is_connect() && failovers + 1 < self.portal_urls.len() => {
+                    failovers += 1;
+                    self.fail_over(&e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS || throttled >= MAX_RATE_LIMIT_RETRIES {
+                return Ok(response);
+            }
+
+            throttled += 1;
+            let wait = response
+                .headers()
+                .get(header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| parse_retry_after(v, self.clock.now().with_timezone(&Utc)))
+                .unwrap_or(DEFAULT_RETRY_AFTER)
+                .min(MAX_RETRY_AFTER);
+            warn!("Rate limited (429), retrying in {}ms", wait.as_millis());
+            self.clock.sleep(wait).await;
+        }
+    }
+
+    /// Log a request about to be sent, at debug level, with secrets masked unless
+    /// `gym.redact_logs` is off
+    fn log_request(&self, request: &reqwest::Request) {
+        if !tracing::enabled!(tracing::Level::DEBUG) {
+            return;
+        }
+        let headers = header_pairs(request.headers());
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+            .unwrap_or_default();
+        let (headers, body) = if self.config.gym.redact_logs {
+            (redact::headers(&headers), redact::body(&body))
+        } else {
+            (headers, body)
+        };
+        debug!("Request: {} {} headers={:?} body={}", request.method(), request.url(), headers, body);
+    }
+
+    /// Log a response's status and headers (which carry the login token), like `log_request`
+    fn log_response(&self, response: &reqwest::Response) {
+        if !tracing::enabled!(tracing::Level::DEBUG) {
+            return;
+        }
+        let headers = header_pairs(response.headers());
+        let headers = if self.config.gym.redact_logs { redact::headers(&headers) } else { headers };
+        debug!("Response: {} from {} headers={:?}", response.status(), response.url(), headers);
+    }
+
+    /// Classes from today up to `days` ahead, only those of one category (activity type)
+    /// if `category_id` is given
+    pub async fn get_weekly_classes(&self, days: u32, category_id: Option<u32>) -> Result<Vec<ClassInfo>> {
+        let path = "/Classes/ClassCalendar/WeeklyClasses";
 
         let request = WeeklyClassesRequest {
-            club_id: self.config.gym.club_id,
-            category_id: None,
+            club_id: self.club_id(),
+            category_id,
             days_in_week: days,
         };
 
         let token = self.get_token().await?;
 
         let response = self
-            .build_request(reqwest::Method::POST, &url, &token)
-            .json(&request)
-            .send()
+            .send_with_retry(|| {
+                self.build_request(reqwest::Method::POST, path, &token)
+                    .json(&request)
+            })
             .await?;
 
         if !response.status().is_success() {
@@ -331,7 +1203,7 @@ impl PerfectGymClient {
             for hour in zone.classes_per_hour {
                 for day_classes in hour.classes_per_day {
                     for class in day_classes {
-                        if let Ok(class_info) = parse_class_item(class) {
+                        if let Ok(class_info) = parse_class_item(class, &zone.zone_name, self.config.gym.ambiguous_time) {
                             classes.push(class_info);
                         }
                     }
@@ -339,88 +1211,152 @@ impl PerfectGymClient {
             }
         }
 
-        // Sort by start time
-        classes.sort_by_key(|c| c.start_time);
+        // By start time, then ID so classes at the same time keep their order between fetches
+        classes.sort_by_key(|c| (c.start_time, c.id));
 
         Ok(classes)
     }
 
     pub async fn book_class(&self, class_id: u64) -> Result<BookingResult> {
-        let url = format!(
-            "{}/Classes/ClassCalendar/BookClass",
-            self.config.gym.base_url
-        );
+        self.book_class_at(class_id, None).await
+    }
 
-        let request = BookClassRequest {
-            class_id,
-            club_id: self.config.gym.club_id.to_string(),
-        };
+    /// Book a class, choosing a specific spot when `spot_id` is given
+    pub async fn book_class_at(&self, class_id: u64, spot_id: Option<u64>) -> Result<BookingResult> {
+        self.post_booking("BookClass", class_id, spot_id).await
+    }
+
+    /// Join the standby queue of a full class. The position is in `waitlist_position`
+    /// if the gym reports it.
+    pub async fn join_waitlist(&self, class_id: u64) -> Result<BookingResult> {
+        self.post_booking("BookToStandbyQueue", class_id, None).await
+    }
+
+    /// Post a booking, accepting the class's waiver and trying once more if it needs one
+    /// and `gym.auto_accept_waivers` is set
+    async fn post_booking(&self, endpoint: &str, class_id: u64, spot_id: Option<u64>) -> Result<BookingResult> {
+        match self.send_booking(endpoint, class_id, spot_id).await {
+            Err(GymSniperError::Booking(BookingErrorKind::WaiverRequired)) if self.config.gym.auto_accept_waivers => {
+                info!("Class {} needs its waiver accepted - accepting it (auto_accept_waivers)", class_id);
+                self.accept_waiver(class_id).await?;
+                self.send_booking(endpoint, class_id, spot_id).await
+            }
+            result => result,
+        }
+    }
 
+    /// Acknowledge the waiver or terms of a class, as the portal does before booking it
+    pub async fn accept_waiver(&self, class_id: u64) -> Result<()> {
+        let path = "/Classes/ClassCalendar/AcceptWaiver";
+        let request = ClassRequest { class_id };
         let token = self.get_token().await?;
 
         let response = self
-            .build_request(reqwest::Method::POST, &url, &token)
-            .json(&request)
-            .send()
+            .send_with_retry(|| {
+                self.build_request(reqwest::Method::POST, path, &token)
+                    .json(&request)
+            })
             .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
             return Err(GymSniperError::Api(format!(
-                "Booking failed ({}): {}",
+                "Accepting waiver failed ({}): {}",
                 status, body
             )));
         }
 
+        Ok(())
+    }
+
+    async fn send_booking(&self, endpoint: &str, class_id: u64, spot_id: Option<u64>) -> Result<BookingResult> {
+        let token = self.get_token().await?;
+        *self.last_booking_failure.write().await = None;
+
+        let response = self
+            .send_throttled(|| self.booking_request(endpoint, class_id, spot_id, &token))
+            .await?;
+
+        if !response.status().is_success() {
+            let raw = RawResponse::read(response).await;
+            *self.last_booking_failure.write().await = Some(raw.clone());
+
+            let status = reqwest::StatusCode::from_u16(raw.status).unwrap_or_default();
+            if status == reqwest::StatusCode::FORBIDDEN {
+                return Err(GymSniperError::Blocked(
+                    "Booking refused (403) - your IP may be blocked".to_string(),
+                ));
+            }
+            if status == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(GymSniperError::Auth("Session rejected by the server (401)".to_string()));
+            }
+            let body = raw.body;
+            let kind = match BookClassError::parse(&body).and_then(|e| BookingErrorKind::from_code(&e.error_code)) {
+                Some(kind) => kind,
+                None if status == reqwest::StatusCode::PAYMENT_REQUIRED => BookingErrorKind::PaymentRequired,
+                None => BookingErrorKind::Unknown(format!("({}) {}", status, body)),
+            };
+            return Err(GymSniperError::Booking(kind));
+        }
+
         let book_response: BookClassResponse = response.json().await?;
 
+        // Paid classes can be accepted but left pending until the payment is confirmed
+        if book_response.payment_required {
+            return Err(GymSniperError::Booking(BookingErrorKind::PaymentRequired));
+        }
+
         let ticket = book_response
             .tickets
             .into_iter()
             .next()
             .ok_or_else(|| GymSniperError::Api("No ticket in booking response".to_string()))?;
 
-        let start_time = parse_local_datetime(&ticket.start_time)?;
+        let start_time = parse_local_datetime(&ticket.start_time, self.config.gym.ambiguous_time)?;
 
         Ok(BookingResult {
             name: ticket.name,
             start_time,
             trainer: ticket.trainer,
+            waitlist_position: book_response.standby_queue_number,
         })
     }
 
-    pub async fn get_class_details(&self, class_id: u64) -> Result<MyBooking> {
-        let url = format!(
-            "{}/Classes/ClassCalendar/Details?classId={}",
-            self.config.gym.base_url, class_id
-        );
+    /// The rejected response to the last `book_class` call, if it was rejected by the server
+    pub async fn last_booking_failure(&self) -> Option<RawResponse> {
+        self.last_booking_failure.read().await.clone()
+    }
 
+    /// Send the BookClass request and return the response as-is, whatever its status
+    pub async fn send_book_request(&self, class_id: u64, spot_id: Option<u64>) -> Result<RawResponse> {
         let token = self.get_token().await?;
-
         let response = self
-            .build_request(reqwest::Method::GET, &url, &token)
-            .send()
+            .send_throttled(|| self.book_request(class_id, spot_id, &token))
             .await?;
+        Ok(RawResponse::read(response).await)
+    }
 
-        if !response.status().is_success() {
-            return Err(GymSniperError::Api(format!(
-                "Failed to get class details: {}",
-                response.status()
-            )));
-        }
-
-        let details: ClassDetailsResponse = response.json().await?;
+    pub async fn get_class_details(&self, class_id: u64) -> Result<MyBooking> {
+        let details = self.fetch_class_details(class_id).await?;
 
-        let start_time = parse_local_datetime(&details.start_time)?;
+        let ambiguous = self.config.gym.ambiguous_time;
+        let start_time = parse_local_datetime(&details.start_time, ambiguous)?;
 
         // Find current user's waitlist position
-        let waitlist_position = details
-            .users
+        let users = details.users.as_deref().unwrap_or_default();
+        let waitlist_position = users
             .iter()
             .find(|u| u.user.is_current_user)
             .and_then(|u| u.standby_queue_number);
 
+        let booked_count = details.booked_count.or_else(|| {
+            details
+                .users
+                .as_ref()
+                .map(|users| users.iter().filter(|u| u.status == "Booked").count() as u32)
+        });
+
         Ok(MyBooking {
             id: details.id,
             name: details.name,
@@ -428,14 +1364,150 @@ impl PerfectGymClient {
             status: details.status,
             waitlist_position,
             trainer: details.trainer_details.map(|t| t.title),
+            booked_count,
+            capacity: details.max_participants,
+            bookable_from: details.bookable_from.as_deref().and_then(|t| parse_local_datetime(t, ambiguous).ok()),
+            description: details.description.filter(|d| !d.trim().is_empty()),
+            zone: None,
+            duration_minutes: 0,
         })
     }
 
-    pub async fn get_my_bookings(&self) -> Result<Vec<MyBooking>> {
-        let classes = self.get_weekly_classes(14).await?;
+    /// Selectable spots for a class (e.g. numbered bikes); empty if the class has none
+    pub async fn get_class_spots(&self, class_id: u64) -> Result<Vec<Spot>> {
+        let details = self.fetch_class_details(class_id).await?;
+        Ok(details
+            .spots
+            .into_iter()
+            .map(|s| Spot {
+                name: s.name.unwrap_or_else(|| format!("Spot {}", s.id)),
+                id: s.id,
+                available: s.is_available,
+            })
+            .collect())
+    }
+
+    /// The club's class categories as a tree, for picking a `category_id`
+    pub async fn get_categories(&self) -> Result<Vec<Category>> {
+        let path = format!("/Classes/ClassCalendar/Categories?clubId={}", self.club_id());
+
+        let token = self.get_token().await?;
+
+        let response = self
+            .send_with_retry(|| self.build_request(reqwest::Method::GET, &path, &token))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(GymSniperError::Api(format!(
+                "Failed to get categories: {}",
+                response.status()
+            )));
+        }
+
+        let items: Vec<CategoryItem> = response.json().await?;
+        Ok(items.into_iter().map(Category::from).collect())
+    }
+
+    async fn fetch_class_details(&self, class_id: u64) -> Result<ClassDetailsResponse> {
+        let path = format!("/Classes/ClassCalendar/Details?classId={}", class_id);
+
+        let token = self.get_token().await?;
+
+        let response = self
+            .send_with_retry(|| self.build_request(reqwest::Method::GET, &path, &token))
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(GymSniperError::NotFound(format!("class {}", class_id)));
+        }
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(GymSniperError::Auth("Session rejected by the server (401)".to_string()));
+        }
+
+        if !response.status().is_success() {
+            return Err(GymSniperError::Api(format!(
+                "Failed to get class details: {}",
+                response.status()
+            )));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Booked and waitlisted classes that haven't finished yet. Uses the member's bookings
+    /// list, which has every future booking; only if the portal has no such endpoint is the
+    /// calendar scanned instead, up to `days` ahead, and the endpoint isn't asked for again
+    /// by this client.
+    pub async fn get_my_bookings(&self, days: u32) -> Result<Vec<MyBooking>> {
+        if !self.no_bookings_endpoint.load(Ordering::Relaxed) {
+            match self.get_future_bookings().await {
+                Err(GymSniperError::NotFound(_)) => {
+                    debug!("No bookings endpoint on this portal, scanning the calendar instead");
+                    self.no_bookings_endpoint.store(true, Ordering::Relaxed);
+                }
+                result => return result,
+            }
+        }
+        self.scan_my_bookings(days).await
+    }
+
+    /// Bookings from `/Members/Bookings/Future`, in one request
+    async fn get_future_bookings(&self) -> Result<Vec<MyBooking>> {
+        let path = "/Members/Bookings/Future";
+
+        let token = self.get_token().await?;
+
+        let response = self
+            .send_with_retry(|| self.build_request(reqwest::Method::GET, path, &token))
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(GymSniperError::NotFound("bookings endpoint".to_string()));
+        }
+
+        if !response.status().is_success() {
+            return Err(GymSniperError::Api(format!(
+                "Failed to get bookings: {}",
+                response.status()
+            )));
+        }
+
+        let items: Vec<FutureBookingItem> = response.json().await?;
+        let ambiguous = self.config.gym.ambiguous_time;
+        let default_minutes = self.config.gym.default_duration_minutes;
+        let now = self.clock.now();
+        let mut bookings = Vec::new();
+        for item in items {
+            let (class_id, name) = (item.class_id, item.name.clone());
+            let booking = match parse_future_booking(item, ambiguous) {
+                Ok(booking) => booking,
+                Err(e) => {
+                    warn!("Skipping unreadable booking {} (class ID {}): {}", name, class_id, e);
+                    continue;
+                }
+            };
+            let minutes = if booking.duration_minutes == 0 { default_minutes } else { booking.duration_minutes };
+            if booking.start_time + chrono::Duration::minutes(minutes as i64) > now {
+                bookings.push(booking);
+            }
+        }
+        bookings.sort_by_key(|b| (b.start_time, b.id));
+        Ok(bookings)
+    }
+
+    /// Booked and waitlisted classes from today up to `days` ahead, found by looking up
+    /// every booked class in the calendar
+    async fn scan_my_bookings(&self, days: u32) -> Result<Vec<MyBooking>> {
+        let classes = self.get_weekly_classes(days, None).await?;
         let mut bookings = Vec::new();
+        let now = self.clock.now();
 
         for class in classes {
+            // Classes earlier today that have finished aren't current bookings
+            if class.has_ended(self.config.gym.default_duration_minutes, now) {
+                continue;
+            }
             if class.status == "Booked" || class.status == "Awaiting" {
                 match self.get_class_details(class.id).await {
                     Ok(mut booking) => {
@@ -443,9 +1515,11 @@ impl PerfectGymClient {
                         if booking.trainer.is_none() {
                             booking.trainer = class.trainer.clone();
                         }
-                        // Set status based on waitlist position
-                        booking.status = if booking.waitlist_position.is_some() {
-                            "Waitlist".to_string()
+                        booking.zone = Some(class.zone.clone());
+                        booking.duration_minutes = class.duration_minutes;
+                        // Normalise to the calendar's Booked/Awaiting status
+                        booking.status = if class.status == "Awaiting" || booking.waitlist_position.is_some() {
+                            "Awaiting".to_string()
                         } else {
                             "Booked".to_string()
                         };
@@ -459,25 +1533,71 @@ impl PerfectGymClient {
         Ok(bookings)
     }
 
+    /// Waitlisted classes (not confirmed bookings) from today up to `days` ahead
+    pub async fn get_my_waitlists(&self, days: u32) -> Result<Vec<MyBooking>> {
+        let mut bookings = self.get_my_bookings(days).await?;
+        bookings.retain(|b| b.is_waitlisted());
+        Ok(bookings)
+    }
+
+    /// Leave the waitlist for a class. Refuses to touch a confirmed booking.
+    pub async fn leave_waitlist(&self, class_id: u64) -> Result<()> {
+        let details = self.get_class_details(class_id).await?;
+        if details.waitlist_position.is_none() && !details.is_waitlisted() {
+            return Err(GymSniperError::NotFound(format!(
+                "not on the waitlist for {} (class ID {})",
+                details.name, class_id
+            )));
+        }
+        self.cancel_booking(class_id).await
+    }
+
+    /// Cancel a booking, refusing if the class starts within `min_cancel_notice_hours`
+    /// unless `force` is set. With `defend_bookings` on, the cancellation is recorded so
+    /// the daemon doesn't book the class again.
+    pub async fn cancel_booking_with_notice(
+        &self,
+        class_id: u64,
+        class_name: &str,
+        start_time: DateTime<Local>,
+        force: bool,
+    ) -> Result<()> {
+        if !force {
+            check_cancel_notice(class_name, start_time, self.clock.now(), &self.config.gym)?;
+        }
+        self.cancel_booking(class_id).await?;
+        if self.config.snipe.defend_bookings {
+            let path = defend::cancelled_path(self.config.profile.as_deref());
+            if let Err(e) = defend::record_cancelled(&path, class_id, start_time, self.clock.now()) {
+                warn!("Could not record the cancellation of {}, the daemon may book it again: {}", class_name, e);
+            }
+        }
+        Ok(())
+    }
+
     pub async fn cancel_booking(&self, class_id: u64) -> Result<()> {
-        let url = format!(
-            "{}/Classes/ClassCalendar/CancelBooking",
-            self.config.gym.base_url
-        );
+        let path = "/Classes/ClassCalendar/CancelBooking";
 
-        let request = CancelBookingRequest { class_id };
+        let request = ClassRequest { class_id };
 
         let token = self.get_token().await?;
 
-        let response = self
-            .build_request(reqwest::Method::POST, &url, &token)
-            .json(&request)
-            .send()
+        let (response, attempts) = self
+            .send_with_retry_counted(|| {
+                self.build_request(reqwest::Method::POST, path, &token)
+                    .json(&request)
+            })
             .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
+            // An attempt that failed on our side may still have cancelled the booking, in
+            // which case the retry is refused as there is nothing left to cancel
+            if attempts > 1 && status.is_client_error() && !self.still_booked(class_id).await {
+                info!("Cancel of class {} went through on an earlier attempt", class_id);
+                return Ok(());
+            }
             return Err(GymSniperError::Api(format!(
                 "Cancel failed ({}): {}",
                 status, body
@@ -486,31 +1606,297 @@ impl PerfectGymClient {
 
         Ok(())
     }
+
+    /// Whether you are booked or waitlisted for the class. Assumed so if it can't be checked.
+    async fn still_booked(&self, class_id: u64) -> bool {
+        match self.get_class_details(class_id).await {
+            Ok(details) => details.status == "Booked" || details.is_waitlisted(),
+            Err(e) => {
+                warn!("Could not check whether class {} is still booked: {}", class_id, e);
+                true
+            }
+        }
+    }
+}
+
+fn parse_local_datetime(s: &str, ambiguous: AmbiguousTime) -> Result<DateTime<Local>> {
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .map_err(|e| GymSniperError::Api(format!("Failed to parse datetime: {}", e)))?;
+    resolve_local_time(&Local, naive, ambiguous)
+        .ok_or_else(|| GymSniperError::Api(format!("No local time matches {}", s)))
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtClaims {
+    exp: Option<i64>,
+}
+
+/// Expiry of a JWT from its `exp` claim. The signature isn't checked; anything that
+/// can't be read gives `None`.
+pub(crate) fn jwt_expiry(token: &str) -> Option<DateTime<Local>> {
+    use base64::Engine;
+
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: JwtClaims = serde_json::from_slice(&bytes).ok()?;
+    Local.timestamp_opt(claims.exp?, 0).single()
 }
 
-fn parse_local_datetime(s: &str) -> Result<DateTime<Local>> {
-    NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
-        .map_err(|e| GymSniperError::Api(format!("Failed to parse datetime: {}", e)))?
-        .and_local_timezone(Local)
-        .single()
-        .ok_or_else(|| GymSniperError::Api("Invalid timezone".to_string()))
+/// Turn a wall-clock time into an instant without dropping DST edge cases. A time that
+/// occurs twice (clocks going back) uses `ambiguous`; a time skipped when the clocks go
+/// forward is moved on by the hour that was skipped.
+pub(crate) fn resolve_local_time<Tz: TimeZone>(
+    tz: &Tz,
+    naive: NaiveDateTime,
+    ambiguous: AmbiguousTime,
+) -> Option<DateTime<Tz>> {
+    match pick_instant(tz.from_local_datetime(&naive), ambiguous) {
+        Some(instant) => Some(instant),
+        None => {
+            let later = naive + chrono::Duration::hours(1);
+            debug!("{} does not exist locally, using {}", naive, later);
+            pick_instant(tz.from_local_datetime(&later), ambiguous)
+        }
+    }
 }
 
-pub(crate) fn parse_class_item(item: ClassItem) -> Result<ClassInfo> {
-    let start_time = parse_local_datetime(&item.start_time)?;
+fn pick_instant<T>(result: LocalResult<T>, ambiguous: AmbiguousTime) -> Option<T> {
+    match result {
+        LocalResult::Single(instant) => Some(instant),
+        LocalResult::Ambiguous(earliest, latest) => Some(match ambiguous {
+            AmbiguousTime::Earliest => earliest,
+            AmbiguousTime::Latest => latest,
+        }),
+        LocalResult::None => None,
+    }
+}
+
+/// Skew smaller than this is treated as none: the `Date` header only has whole seconds
+const MIN_CLOCK_SKEW: chrono::Duration = chrono::Duration::seconds(2);
+
+/// Work out how far the server's clock is ahead of `now` from a response's `Date` header.
+/// The header is truncated to the second, so the server is assumed to be half a second
+/// past it. Returns `None` without a usable header.
+pub(crate) fn server_clock_offset(headers: &header::HeaderMap, now: DateTime<Local>) -> Option<chrono::Duration> {
+    let value = headers.get(header::DATE)?.to_str().ok()?;
+    let server_time = DateTime::parse_from_rfc2822(value).ok()? + chrono::Duration::milliseconds(500);
+    let offset = server_time.signed_duration_since(now);
+
+    if offset.abs() < MIN_CLOCK_SKEW {
+        debug!("Clock is within {}ms of the gym server", offset.num_milliseconds().abs());
+        return Some(chrono::Duration::zero());
+    }
+    warn!(
+        "Local clock is {:.1}s {} the gym server - adjusting booking window timing",
+        offset.num_milliseconds().abs() as f64 / 1000.0,
+        if offset > chrono::Duration::zero() { "behind" } else { "ahead of" }
+    );
+    Some(offset)
+}
+
+/// Headers as name/value strings, for logging or keeping
+fn header_pairs(headers: &header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
+        .collect()
+}
+
+/// Parse a `Retry-After` header value, either delay-seconds or an HTTP date
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+    let when = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(when.with_timezone(&Utc).signed_duration_since(now).to_std().unwrap_or_default())
+}
+
+pub(crate) fn parse_class_item(item: ClassItem, zone: &str, ambiguous: AmbiguousTime) -> Result<ClassInfo> {
+    let start_time = parse_local_datetime(&item.start_time, ambiguous)?;
 
     Ok(ClassInfo {
         id: item.id,
-        name: item.name,
         start_time,
         status: item.status,
         trainer: item.trainer,
+        zone: zone.to_string(),
+        bookable_from: item.bookable_from.as_deref().and_then(|t| parse_local_datetime(t, ambiguous).ok()),
+        duration_minutes: parse_duration_minutes(&item.duration, &item.name),
+        capacity: item.max_participants,
+        booked_count: item.booked_count,
+        name: item.name,
+    })
+}
+
+/// A future bookings entry as a `MyBooking`, with the status normalised to the
+/// calendar's Booked/Awaiting
+fn parse_future_booking(item: FutureBookingItem, ambiguous: AmbiguousTime) -> Result<MyBooking> {
+    let waitlisted = item.standby_queue_number.is_some()
+        || matches!(item.status.as_str(), "Awaiting" | "StandBy" | "Standby" | "Waitlisted");
+    Ok(MyBooking {
+        id: item.class_id,
+        start_time: parse_local_datetime(&item.start_time, ambiguous)?,
+        status: if waitlisted { "Awaiting" } else { "Booked" }.to_string(),
+        waitlist_position: item.standby_queue_number,
+        trainer: item.trainer,
+        booked_count: item.booked_count,
+        capacity: item.max_participants,
+        bookable_from: None,
+        description: None,
+        zone: item.zone,
+        duration_minutes: parse_duration_minutes(&item.duration, &item.name),
+        name: item.name,
     })
 }
 
+/// Class length from the `Duration` field, e.g. "45", "45 min", "1h30" or "01:30:00". 0 if
+/// there is none or it can't be read, so an odd value never costs us the whole class.
+fn parse_duration_minutes(raw: &str, class_name: &str) -> u32 {
+    let raw = raw.trim();
+    match duration_minutes(&raw.to_ascii_lowercase()) {
+        Some(minutes) => minutes,
+        None => {
+            if !raw.is_empty() {
+                debug!("Could not parse duration '{}' of {}, treating it as unknown", raw, class_name);
+            }
+            0
+        }
+    }
+}
+
+fn duration_minutes(raw: &str) -> Option<u32> {
+    let is_unit = |c: char| c.is_ascii_alphabetic() || c == '.';
+    // "01:30" or "01:30:00"
+    if let Some((hours, rest)) = raw.split_once(':') {
+        let minutes = rest.split(':').next().unwrap_or_default();
+        return Some(hours.trim().parse::<u32>().ok()? * 60 + minutes.trim().parse::<u32>().ok()?);
+    }
+    // "1h", "1h30", "1 hr 30 min"; minute units never contain an 'h'
+    let (hours, rest) = match raw.split_once('h') {
+        Some((hours, rest)) => (Some(hours.trim().parse::<u32>().ok()?), rest.trim_start_matches(is_unit)),
+        None => (None, raw),
+    };
+    let number = rest.trim().trim_end_matches(is_unit).trim_end();
+    let minutes = match (hours, number) {
+        (Some(_), "") => 0,
+        _ => number.parse().ok()?,
+    };
+    Some(hours.unwrap_or(0) * 60 + minutes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{FixedOffset, NaiveDate};
+
+    #[test]
+    fn booking_error_codes_are_read_from_each_body_shape() {
+        let parsed = BookClassError::parse(r#"{"ErrorCode":"AlreadyBooked","Message":"Vous êtes déjà inscrit"}"#).unwrap();
+        assert_eq!(parsed.error_code, "AlreadyBooked");
+        assert_eq!(parsed.message, "Vous êtes déjà inscrit");
+
+        let code = |body: &str| BookClassError::parse(body).map(|e| e.error_code);
+        assert_eq!(code(r#"{"Errors":["TooSoonToBook"]}"#).as_deref(), Some("TooSoonToBook"));
+        let detail = BookClassError::parse(r#"{"Errors":[{"Code":"WaiverRequired","Message":"Accept the terms"}]}"#).unwrap();
+        assert_eq!((detail.error_code.as_str(), detail.message.as_str()), ("WaiverRequired", "Accept the terms"));
+        assert_eq!(code(r#"{"code":"payment_required"}"#).as_deref(), Some("payment_required"));
+        assert_eq!(code(r#""DailyBookingLimitReached""#).as_deref(), Some("DailyBookingLimitReached"));
+        assert_eq!(code(" ClassIsFull\n").as_deref(), Some("ClassIsFull"));
+
+        // Prose and empty objects carry no code, so they can't be mistaken for one
+        assert_eq!(code("Class is full of energy"), None);
+        assert_eq!(code(r#"{"Message":"Something went wrong"}"#), None);
+        assert_eq!(code("{}"), None);
+        assert_eq!(code(""), None);
+    }
+
+    /// A zone on +01:00 in winter and +02:00 in summer. Clocks go forward at 02:00 on
+    /// 30 March 2025 (02:00-03:00 never happens) and back at 03:00 on 26 October 2025
+    /// (02:00-03:00 happens twice).
+    #[derive(Debug, Clone, Copy)]
+    struct TestDst;
+
+    impl TestDst {
+        fn winter() -> FixedOffset {
+            FixedOffset::east_opt(3600).unwrap()
+        }
+
+        fn summer() -> FixedOffset {
+            FixedOffset::east_opt(7200).unwrap()
+        }
+    }
+
+    impl TimeZone for TestDst {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            TestDst
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(12, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // An offset fits if the instant it gives is on that offset; summer first, as it is earlier
+            let fits: Vec<FixedOffset> = [Self::summer(), Self::winter()]
+                .into_iter()
+                .filter(|offset| self.offset_from_utc_datetime(&(*local - *offset)) == *offset)
+                .collect();
+            match fits[..] {
+                [] => LocalResult::None,
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest, ..] => LocalResult::Ambiguous(earliest, latest),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(12, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let at = |m, d| NaiveDate::from_ymd_opt(2025, m, d).unwrap().and_hms_opt(1, 0, 0).unwrap();
+            if *utc >= at(3, 30) && *utc < at(10, 26) {
+                Self::summer()
+            } else {
+                Self::winter()
+            }
+        }
+    }
+
+    fn local(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn ambiguous_class_time_is_kept_at_chosen_instant() {
+        let naive = local("2025-10-26T02:30:00");
+        assert!(matches!(TestDst.from_local_datetime(&naive), LocalResult::Ambiguous(..)));
+
+        let earliest = resolve_local_time(&TestDst, naive, AmbiguousTime::Earliest).unwrap();
+        let latest = resolve_local_time(&TestDst, naive, AmbiguousTime::Latest).unwrap();
+        assert_eq!(earliest.naive_utc(), local("2025-10-26T00:30:00"));
+        assert_eq!(latest.naive_utc(), local("2025-10-26T01:30:00"));
+        assert_eq!(earliest.naive_local(), naive);
+        assert_eq!(latest.naive_local(), naive);
+    }
+
+    #[test]
+    fn skipped_class_time_moves_past_the_gap() {
+        let naive = local("2025-03-30T02:30:00");
+        assert!(matches!(TestDst.from_local_datetime(&naive), LocalResult::None));
+
+        let resolved = resolve_local_time(&TestDst, naive, AmbiguousTime::Earliest).unwrap();
+        assert_eq!(resolved.naive_local(), local("2025-03-30T03:30:00"));
+        assert_eq!(resolved.naive_utc(), local("2025-03-30T01:30:00"));
+
+        // Ordinary times are untouched
+        let normal = local("2025-06-01T09:00:00");
+        assert_eq!(resolve_local_time(&TestDst, normal, AmbiguousTime::Latest).unwrap().naive_local(), normal);
+    }
 
     #[test]
     fn parse_class_item_valid() {
@@ -521,9 +1907,12 @@ mod tests {
             duration: "60".to_string(),
             status: "Bookable".to_string(),
             trainer: Some("Jane Doe".to_string()),
+            bookable_from: None,
+            max_participants: None,
+            booked_count: None,
         };
 
-        let result = parse_class_item(item).unwrap();
+        let result = parse_class_item(item, "Studio A", AmbiguousTime::Earliest).unwrap();
         assert_eq!(result.id, 12345);
         assert_eq!(result.name, "Yoga Flow");
         assert_eq!(result.status, "Bookable");
@@ -540,12 +1929,60 @@ mod tests {
             duration: "45".to_string(),
             status: "Full".to_string(),
             trainer: None,
+            bookable_from: None,
+            max_participants: None,
+            booked_count: None,
         };
 
-        let result = parse_class_item(item).unwrap();
+        let result = parse_class_item(item, "Studio A", AmbiguousTime::Earliest).unwrap();
         assert_eq!(result.trainer, None);
     }
 
+    #[test]
+    fn class_end_time_crosses_midnight() {
+        use chrono::TimeZone;
+        let item = ClassItem {
+            id: 7,
+            name: "Late Spin".to_string(),
+            start_time: "2025-03-01T23:30:00".to_string(),
+            duration: "60".to_string(),
+            status: "Bookable".to_string(),
+            trainer: None,
+            bookable_from: None,
+            max_participants: None,
+            booked_count: None,
+        };
+        let mut class = parse_class_item(item, "Studio A", AmbiguousTime::Earliest).unwrap();
+        assert_eq!(class.duration_minutes, 60);
+
+        let end = class.end_time(45);
+        assert_eq!(end.format("%Y-%m-%d %H:%M").to_string(), "2025-03-02 00:30");
+
+        // Started before midnight but still running just after it
+        let just_after_midnight = Local.with_ymd_and_hms(2025, 3, 2, 0, 10, 0).unwrap();
+        assert!(!class.has_ended(45, just_after_midnight));
+        assert!(class.has_ended(45, end));
+
+        // Without a duration the default applies
+        class.duration_minutes = 0;
+        assert_eq!(class.end_time(45).format("%Y-%m-%d %H:%M").to_string(), "2025-03-02 00:15");
+        assert!(class.has_ended(45, Local.with_ymd_and_hms(2025, 3, 2, 0, 20, 0).unwrap()));
+    }
+
+    #[test]
+    fn duration_tolerates_unit_suffix() {
+        assert_eq!(parse_duration_minutes("45", "Spin"), 45);
+        assert_eq!(parse_duration_minutes(" 60 min", "Spin"), 60);
+        assert_eq!(parse_duration_minutes("30mins", "Spin"), 30);
+        assert_eq!(parse_duration_minutes("1h", "Spin"), 60);
+        assert_eq!(parse_duration_minutes("1h30", "Spin"), 90);
+        assert_eq!(parse_duration_minutes("1 hr 15 min", "Spin"), 75);
+        assert_eq!(parse_duration_minutes("2 Hours", "Spin"), 120);
+        assert_eq!(parse_duration_minutes("01:30:00", "Spin"), 90);
+        assert_eq!(parse_duration_minutes("", "Spin"), 0);
+        assert_eq!(parse_duration_minutes("about an hour", "Spin"), 0);
+    }
+
     #[test]
     fn parse_class_item_invalid_datetime() {
         let item = ClassItem {
@@ -555,9 +1992,136 @@ mod tests {
             duration: "30".to_string(),
             status: "Bookable".to_string(),
             trainer: None,
+            bookable_from: None,
+            max_participants: None,
+            booked_count: None,
         };
 
-        let result = parse_class_item(item);
+        let result = parse_class_item(item, "Studio A", AmbiguousTime::Earliest);
         assert!(result.is_err());
     }
+
+    fn make_booking(status: &str, waitlist_position: Option<u32>) -> MyBooking {
+        MyBooking {
+            id: 1,
+            name: "Pilates".to_string(),
+            start_time: Local::now(),
+            status: status.to_string(),
+            waitlist_position,
+            trainer: None,
+            booked_count: None,
+            capacity: None,
+            bookable_from: None,
+            description: None,
+            zone: None,
+            duration_minutes: 0,
+        }
+    }
+
+    #[test]
+    fn status_label_awaiting_shows_position() {
+        let booking = make_booking("Awaiting", Some(8));
+        assert!(booking.is_waitlisted());
+        assert_eq!(booking.status_label(), "Waitlist #8");
+    }
+
+    #[test]
+    fn status_label_awaiting_without_position() {
+        assert_eq!(make_booking("Awaiting", None).status_label(), "Waitlist");
+    }
+
+    #[test]
+    fn status_label_booked() {
+        let booking = make_booking("Booked", None);
+        assert!(!booking.is_waitlisted());
+        assert_eq!(booking.status_label(), "Booked");
+    }
+
+    #[test]
+    fn spots_remaining_tolerates_missing_counts() {
+        let mut booking = make_booking("Bookable", None);
+        assert_eq!(booking.spots_remaining(), None);
+
+        booking.capacity = Some(20);
+        assert_eq!(booking.spots_remaining(), None);
+
+        booking.booked_count = Some(17);
+        assert_eq!(booking.spots_remaining(), Some(3));
+
+        // Overbooked classes don't underflow
+        booking.booked_count = Some(22);
+        assert_eq!(booking.spots_remaining(), Some(0));
+
+        let item: ClassItem = serde_json::from_value(serde_json::json!({
+            "Id": 1, "Name": "Spin", "StartTime": "2025-01-15T09:30:00", "Duration": "45",
+            "Status": "Bookable", "Trainer": null, "MaxParticipants": 20, "BookedCount": 17
+        }))
+        .unwrap();
+        let class = parse_class_item(item, "Studio A", AmbiguousTime::Earliest).unwrap();
+        assert_eq!(class.spots_remaining(), Some(3));
+    }
+
+    #[test]
+    fn fill_ratio_needs_booked_count_and_capacity() {
+        let mut booking = make_booking("Bookable", None);
+        assert_eq!(booking.fill_ratio(), None);
+
+        booking.booked_count = Some(18);
+        booking.capacity = Some(20);
+        assert_eq!(booking.fill_ratio(), Some(0.9));
+
+        booking.capacity = Some(0);
+        assert_eq!(booking.fill_ratio(), None);
+    }
+
+    #[test]
+    fn jwt_expiry_reads_exp_claim() {
+        use base64::Engine;
+        let encode = |json: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+        let token = |claims: &str| format!("{}.{}.signature", encode(r#"{"alg":"HS256"}"#), encode(claims));
+
+        let expiry = jwt_expiry(&token(r#"{"sub":"42","exp":1738396800}"#)).unwrap();
+        assert_eq!(expiry.timestamp(), 1_738_396_800);
+
+        // Unreadable tokens are treated as never expiring
+        assert_eq!(jwt_expiry(&token(r#"{"sub":"42"}"#)), None);
+        assert_eq!(jwt_expiry("test-jwt-token-123"), None);
+        assert_eq!(jwt_expiry("a.!!!.c"), None);
+    }
+
+    #[test]
+    fn server_clock_offset_ignores_skew_within_header_precision() {
+        let headers = |date: &str| {
+            let mut headers = header::HeaderMap::new();
+            headers.insert(header::DATE, date.parse().unwrap());
+            headers
+        };
+        let now = Utc.with_ymd_and_hms(2025, 2, 1, 8, 0, 0).unwrap().with_timezone(&Local);
+
+        let ahead = server_clock_offset(&headers("Sat, 01 Feb 2025 08:00:30 GMT"), now);
+        assert_eq!(ahead, Some(chrono::Duration::milliseconds(30_500)));
+        let behind = server_clock_offset(&headers("Sat, 01 Feb 2025 07:59:50 GMT"), now);
+        assert_eq!(behind, Some(chrono::Duration::milliseconds(-9_500)));
+        let synced = server_clock_offset(&headers("Sat, 01 Feb 2025 08:00:00 GMT"), now);
+        assert_eq!(synced, Some(chrono::Duration::zero()));
+        assert_eq!(server_clock_offset(&header::HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn parse_retry_after_seconds_and_http_date() {
+        use chrono::TimeZone;
+        let now = Utc.with_ymd_and_hms(2025, 2, 1, 8, 0, 0).unwrap();
+
+        assert_eq!(parse_retry_after("5", now), Some(std::time::Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after("Sat, 01 Feb 2025 08:00:12 GMT", now),
+            Some(std::time::Duration::from_secs(12))
+        );
+        // Dates in the past mean retry straight away
+        assert_eq!(
+            parse_retry_after("Sat, 01 Feb 2025 07:59:00 GMT", now),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
 }
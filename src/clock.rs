@@ -0,0 +1,219 @@
+use chrono::{DateTime, Local, Utc};
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::warn;
+
+use crate::api::server_clock_offset;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970)
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// How long to wait for an NTP reply or the gym's response
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Source of the current time and of waits, so the snipe daemon can run against a
+/// simulated clock in tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send;
+}
+
+impl<C: Clock> Clock for Arc<C> {
+    fn now(&self) -> DateTime<Local> {
+        (**self).now()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        (**self).sleep(duration)
+    }
+}
+
+/// The real wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// A clock that only moves when slept on. Sleeps return immediately after advancing
+/// the time, and every sleep is recorded so poll intervals can be asserted.
+#[derive(Debug)]
+pub struct SimulatedClock {
+    now: Mutex<DateTime<Local>>,
+    sleeps: Mutex<Vec<Duration>>,
+}
+
+impl SimulatedClock {
+    pub fn new(start: DateTime<Local>) -> Self {
+        Self {
+            now: Mutex::new(start),
+            sleeps: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Move the clock forward without recording a sleep
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX);
+    }
+
+    /// Every sleep taken so far, in order
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.lock().unwrap().clone()
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        self.advance(duration);
+        self.sleeps.lock().unwrap().push(duration);
+        // Still yield, so other tasks (e.g. a mock server) get to run
+        tokio::task::yield_now()
+    }
+}
+
+/// A clock that can be stored in long-lived values such as the API client
+#[derive(Debug, Clone, Default)]
+pub enum SharedClock {
+    #[default]
+    System,
+    Simulated(Arc<SimulatedClock>),
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> DateTime<Local> {
+        match self {
+            Self::System => SystemClock.now(),
+            Self::Simulated(clock) => clock.now(),
+        }
+    }
+
+    fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send {
+        let clock = self.clone();
+        async move {
+            match clock {
+                Self::System => SystemClock.sleep(duration).await,
+                Self::Simulated(clock) => clock.sleep(duration).await,
+            }
+        }
+    }
+}
+
+/// How far the gym server's clock is ahead of ours (negative if behind), from the `Date`
+/// header of a request to `base_url`, taken as of the middle of the round trip. Zero if the
+/// gym can't be reached or sends no usable header, or if the skew is within the header's
+/// one-second precision.
+pub async fn server_offset(base_url: &str) -> chrono::Duration {
+    let sent = Local::now();
+    let response = match reqwest::Client::new().head(base_url).timeout(SYNC_TIMEOUT).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("Could not reach {} to check the clock: {}", base_url, e.without_url());
+            return chrono::Duration::zero();
+        }
+    };
+    let midpoint = sent + (Local::now() - sent) / 2;
+    server_clock_offset(response.headers(), midpoint).unwrap_or_default()
+}
+
+/// How far true time is ahead of our clock (negative if behind), by one SNTP exchange
+/// with `server` (`host` or `host:port`, port 123 by default)
+pub async fn ntp_offset(server: &str) -> io::Result<chrono::Duration> {
+    let address = if server.contains(':') { server.to_string() } else { format!("{}:123", server) };
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(&address).await?;
+
+    // Leap indicator 0, version 4, mode 3 (client)
+    let mut packet = [0u8; 48];
+    packet[0] = 0x23;
+    let sent = Utc::now();
+    socket.send(&packet).await?;
+    let len = tokio::time::timeout(SYNC_TIMEOUT, socket.recv(&mut packet))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("no reply from {}", address)))??;
+    let received = Utc::now();
+
+    if len < 48 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("short reply from {}", address)));
+    }
+    let (Some(server_received), Some(server_sent)) = (ntp_timestamp(&packet[32..40]), ntp_timestamp(&packet[40..48]))
+    else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad reply from {}", address)));
+    };
+
+    // The standard SNTP estimate, which cancels out the network delay if it is symmetric
+    Ok(((server_received - sent) + (server_sent - received)) / 2)
+}
+
+/// An NTP timestamp: seconds since 1900 and a binary fraction. `None` for zero, which
+/// servers send when refusing to answer.
+fn ntp_timestamp(bytes: &[u8]) -> Option<DateTime<Utc>> {
+    let seconds = u32::from_be_bytes(bytes[..4].try_into().ok()?) as i64;
+    let fraction = u32::from_be_bytes(bytes[4..8].try_into().ok()?) as i64;
+    if seconds == 0 {
+        return None;
+    }
+    DateTime::from_timestamp(seconds - NTP_UNIX_OFFSET, ((fraction * 1_000_000_000) >> 32) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn simulated_clock_advances_on_sleep() {
+        let start = Local.with_ymd_and_hms(2025, 2, 1, 8, 0, 0).unwrap();
+        let clock = SimulatedClock::new(start);
+
+        clock.sleep(Duration::from_secs(90)).await;
+        clock.advance(Duration::from_millis(500));
+        clock.sleep(Duration::from_secs(30)).await;
+
+        assert_eq!(clock.now(), start + chrono::Duration::milliseconds(120_500));
+        assert_eq!(clock.sleeps(), vec![Duration::from_secs(90), Duration::from_secs(30)]);
+    }
+
+    /// Encode a time as an NTP timestamp
+    fn ntp_bytes(time: DateTime<Utc>) -> [u8; 8] {
+        let seconds = (time.timestamp() + NTP_UNIX_OFFSET) as u32;
+        let fraction = ((time.timestamp_subsec_nanos() as u64) << 32) / 1_000_000_000;
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+        bytes[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+        bytes
+    }
+
+    #[tokio::test]
+    async fn ntp_offset_measures_server_ahead() {
+        // A local NTP server whose clock is 2.5 seconds ahead
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut packet = [0u8; 48];
+            let (_, peer) = server.recv_from(&mut packet).await.unwrap();
+            let ahead = Utc::now() + chrono::Duration::milliseconds(2500);
+            packet[0] = 0x24; // version 4, mode 4 (server)
+            packet[32..40].copy_from_slice(&ntp_bytes(ahead));
+            packet[40..48].copy_from_slice(&ntp_bytes(ahead));
+            server.send_to(&packet, peer).await.unwrap();
+        });
+
+        let offset = ntp_offset(&address).await.unwrap();
+        assert!((offset - chrono::Duration::milliseconds(2500)).abs() < chrono::Duration::milliseconds(100), "{}", offset);
+    }
+}
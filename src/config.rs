@@ -1,55 +1,807 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::PathBuf;
 
 use crate::error::{GymSniperError, Result};
+use crate::secrets;
+use crate::util::profile_file;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub gym: GymConfig,
+    /// Login for the account; may be left out when `[[profiles]]` give their own
+    #[serde(default)]
     pub credentials: Credentials,
+    /// Other memberships at the same gym, e.g. family members', that classes can also be booked for
+    #[serde(default)]
+    pub accounts: Vec<Account>,
     #[serde(default)]
     pub targets: Vec<ClassTarget>,
     pub email: Option<EmailConfig>,
+    pub webhook: Option<WebhookConfig>,
+    #[serde(default)]
+    pub stealth: StealthConfig,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub snipe: SnipeConfig,
+    #[serde(default)]
+    pub matching: MatchingConfig,
+    /// Separate memberships sharing this file, each with its own login (see `for_profile`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<Profile>,
+    /// Profile selected on the command line; namespaces the snipe queue and other state files
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+/// How class names are normalized before matching targets and search filters
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MatchingConfig {
+    /// Drop "(Express)" / "[Virtual]" style suffixes
+    pub strip_parenthesized: bool,
+    /// Drop trailing numbers such as durations ("BODYPUMP 45"). Off by default, as some
+    /// gyms number classes that are otherwise alike ("Spin 1", "Spin 2").
+    pub strip_trailing_numbers: bool,
+    /// Leading words to ignore, e.g. "Les Mills"
+    pub strip_prefixes: Vec<String>,
+}
+
+impl Default for MatchingConfig {
+    fn default() -> Self {
+        Self {
+            strip_parenthesized: true,
+            strip_trailing_numbers: false,
+            strip_prefixes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SnipeConfig {
+    /// Skip a queued snipe if you already have a booking that day (daily limit enforced)
+    pub check_same_day_bookings: bool,
+    /// Let the snipe queue hold more than one pending class per day. Lower `priority` ones
+    /// are backups, only tried if a higher one hits the daily limit.
+    pub allow_multiple_per_day: bool,
+    /// Re-book confirmed bookings that silently disappear
+    pub defend_bookings: bool,
+    /// How often the daemon checks bookings when `defend_bookings` is on
+    pub defend_interval_minutes: u64,
+    /// Seconds before the window to send a cheap request that warms up the connection (0 = off)
+    pub prime_lead_secs: u64,
+    /// Append each snipe's timing to the timings file (see `estimate-window`)
+    pub record_timings: bool,
+    /// Append each queued snipe's outcome to the history file (see `stats`)
+    pub record_history: bool,
+    /// Save the last failed booking's request and response for `replay`
+    pub record_failures: bool,
+    /// Skip a snipe when the class is already at least this full (0.0-1.0) when the window opens
+    pub max_fill_ratio: Option<f64>,
+    /// Minutes after a successful snipe to check the booking is still there (0 = off)
+    pub confirm_after_minutes: u64,
+    /// What to do when the class is already full once the window opens
+    pub on_full: OnFull,
+    /// Booking attempts before giving up (or joining the waitlist if the class is full)
+    pub max_attempts: u32,
+    /// First backoff step added to the attempt delay, growing by `backoff_multiplier` after
+    /// each attempt (0 = no backoff)
+    pub initial_backoff_ms: u64,
+    /// Largest backoff step
+    pub max_backoff_ms: u64,
+    /// How much the backoff step grows after each attempt
+    pub backoff_multiplier: f64,
+    /// Where snipes get the clock offset that the booking window is shifted by
+    pub clock_sync: ClockSync,
+    /// NTP server for `clock_sync = "ntp"`, as `host` or `host:port`
+    pub ntp_server: String,
+    /// Minutes between cheap requests that keep the session alive during a long wait for
+    /// the window, for portals that drop idle sessions early (0 = off)
+    pub keep_alive_minutes: u64,
+    /// How long the daemon sleeps between queue checks while the next window is more than
+    /// `far_threshold_minutes` away
+    pub far_interval_secs: u64,
+    /// Sleep while the window is between `near_threshold_minutes` and `far_threshold_minutes` away
+    pub near_interval_secs: u64,
+    /// Sleep while the window is closer than `near_threshold_minutes`, until the daemon takes
+    /// over `takeover_minutes` before it
+    pub imminent_interval_secs: u64,
+    /// Minutes to the window beyond which the daemon polls at `far_interval_secs`
+    pub far_threshold_minutes: u64,
+    /// Minutes to the window within which the daemon polls at `imminent_interval_secs`
+    pub near_threshold_minutes: u64,
+    /// Minutes before its window that a snipe gets its own task and login. Snipes for
+    /// different days taken over at once run side by side.
+    pub takeover_minutes: u64,
+}
+
+impl SnipeConfig {
+    /// How long the daemon sleeps before checking the queue again, for a window this far off.
+    /// Never past the moment it should take over, however long the configured interval.
+    pub fn daemon_poll_interval(&self, until_window: chrono::Duration) -> std::time::Duration {
+        let minutes = until_window.num_minutes();
+        let secs = if minutes > self.far_threshold_minutes as i64 {
+            self.far_interval_secs
+        } else if minutes > self.near_threshold_minutes as i64 {
+            self.near_interval_secs
+        } else {
+            self.imminent_interval_secs
+        };
+        let until_takeover = (until_window - chrono::Duration::minutes(self.takeover_minutes as i64)).num_seconds();
+        std::time::Duration::from_secs(secs.min(until_takeover.max(1) as u64))
+    }
+}
+
+impl Default for SnipeConfig {
+    fn default() -> Self {
+        Self {
+            check_same_day_bookings: true,
+            allow_multiple_per_day: false,
+            defend_bookings: false,
+            defend_interval_minutes: 15,
+            prime_lead_secs: 5,
+            record_timings: true,
+            record_history: true,
+            record_failures: true,
+            max_fill_ratio: None,
+            confirm_after_minutes: 0,
+            on_full: OnFull::Retry,
+            max_attempts: 10,
+            initial_backoff_ms: 0,
+            max_backoff_ms: 5000,
+            backoff_multiplier: 2.0,
+            clock_sync: ClockSync::Gym,
+            ntp_server: "pool.ntp.org".to_string(),
+            keep_alive_minutes: 0,
+            far_interval_secs: 30 * 60,
+            near_interval_secs: 10 * 60,
+            imminent_interval_secs: 60,
+            far_threshold_minutes: 60,
+            near_threshold_minutes: 30,
+            takeover_minutes: 5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSync {
+    /// The gym server's `Date` header, as measured at login
+    #[default]
+    Gym,
+    /// An NTP server, queried when the snipe starts; falls back to the gym if it doesn't answer
+    Ntp,
+    /// Trust the local clock
+    Off,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnFull {
+    /// Keep retrying the booking (joining the waitlist once it is accepted)
+    #[default]
+    Retry,
+    /// Make a single waitlist join on the first Full response and report the position
+    WaitlistImmediately,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    /// Bookings allowed in flight at once; the rest wait for a free slot
+    pub max_concurrent_bookings: usize,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_bookings: 3,
+        }
+    }
+}
+
+/// Settings that make request timing look less robotic
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StealthConfig {
+    #[serde(default)]
+    pub attempt_delay: AttemptDelayConfig,
+}
+
+/// Delay between booking attempts. Defaults to a flat 200ms.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AttemptDelayConfig {
+    pub distribution: DelayDistribution,
+    /// Lower bound for every delay
+    pub min_ms: u64,
+    /// Upper bound for every delay
+    pub max_ms: u64,
+    /// Centre of the lognormal distribution
+    pub median_ms: u64,
+    /// Spread of the lognormal distribution (standard deviation of the underlying normal)
+    pub sigma: f64,
+    /// Cap on total delay above `min_ms` across one booking run; once spent, delays drop to `min_ms`
+    pub jitter_budget_ms: Option<u64>,
+    /// Fixed RNG seed for reproducible delay sequences
+    pub seed: Option<u64>,
+    /// With `burst`: attempts made `burst_delay_ms` apart before slowing down
+    pub burst_count: u32,
+    /// With `burst`: delay between the first `burst_count` attempts
+    pub burst_delay_ms: u64,
+    /// With `burst`: delay between attempts after the burst
+    pub steady_delay_ms: u64,
+}
+
+impl Default for AttemptDelayConfig {
+    fn default() -> Self {
+        Self {
+            distribution: DelayDistribution::Uniform,
+            min_ms: 200,
+            max_ms: 200,
+            median_ms: 300,
+            sigma: 0.25,
+            jitter_budget_ms: None,
+            seed: None,
+            burst_count: 5,
+            burst_delay_ms: 50,
+            steady_delay_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DelayDistribution {
+    #[default]
+    Uniform,
+    Lognormal,
+    /// A quick burst of attempts as the window opens, then a steadier pace
+    Burst,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EmailConfig {
     pub smtp_server: String,
     pub smtp_port: u16,
     pub username: String,
     pub password: String,
     pub from: String,
-    pub to: String,
+    pub to: Recipients,
+    /// Addresses copied on every notification
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<String>,
+    /// Identical notifications within this many seconds are suppressed and counted (0 = off)
+    #[serde(default = "default_dedupe_window_secs")]
+    pub dedupe_window_secs: u64,
+    /// Set to false to stop sending email without losing the SMTP settings
+    #[serde(default = "default_email_enabled")]
+    pub enabled: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+fn default_dedupe_window_secs() -> u64 {
+    600
+}
+
+fn default_email_enabled() -> bool {
+    true
+}
+
+/// `to` as one address or a list, so single-address configs keep working
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Recipients {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Recipients {
+    pub fn addresses(&self) -> &[String] {
+        match self {
+            Recipients::One(address) => std::slice::from_ref(address),
+            Recipients::Many(addresses) => addresses,
+        }
+    }
+}
+
+/// Booking outcomes POSTed to a URL, alongside or instead of email
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Shape of the JSON body
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Identical notifications within this many seconds are suppressed and counted (0 = off)
+    #[serde(default = "default_dedupe_window_secs")]
+    pub dedupe_window_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// `{class, time, trainer, outcome, reason}`
+    #[default]
+    Json,
+    /// A Discord message (`{content}`)
+    Discord,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GymConfig {
+    /// Portal URL, ending in `/clientportal2`. May be left out if `base_urls` is set.
+    #[serde(default)]
     pub base_url: String,
+    /// Mirror portal URLs, tried in order after `base_url` when a connection fails
+    #[serde(default)]
+    pub base_urls: Vec<String>,
+    /// Club to book at. If left out (or 0), the member's home club from the login response.
+    #[serde(default)]
     pub club_id: u32,
+    /// Attempts for read and cancel requests that hit network errors or 5xx responses
+    #[serde(default = "default_retry_attempts")]
+    pub retry_attempts: u32,
+    /// Most requests to send to the gym in any minute, across all calls of a client.
+    /// High by default; lower it if the gym locks accounts for suspicious activity.
+    #[serde(default = "default_max_requests_per_minute")]
+    pub max_requests_per_minute: u32,
+    /// Seconds to shift the expected booking window by (see `estimate-window`)
+    #[serde(default)]
+    pub window_offset_secs: i64,
+    /// Start snipe attempts this many milliseconds before the window opens
+    #[serde(default)]
+    pub window_slack_ms: u64,
+    /// Class length to assume when the gym gives none, for working out when a class ends
+    #[serde(default = "default_duration_minutes")]
+    pub default_duration_minutes: u32,
+    /// Which instant to use for a class time that occurs twice when the clocks go back
+    #[serde(default)]
+    pub ambiguous_time: AmbiguousTime,
+    /// Log in again after this many minutes during long waits, for tokens that expire
+    #[serde(default)]
+    pub token_refresh_minutes: Option<u32>,
+    /// Days before a class that booking opens, plus `booking_window_hours`
+    #[serde(default = "default_booking_window_days")]
+    pub booking_window_days: u32,
+    /// Hours added to `booking_window_days`
+    #[serde(default = "default_booking_window_hours")]
+    pub booking_window_hours: u32,
+    /// Refuse to cancel a class starting within this many hours (late-cancel penalties)
+    #[serde(default)]
+    pub min_cancel_notice_hours: Option<u32>,
+    /// Category (activity type) ID that `list` and `trainer` show unless `--category` is given
+    #[serde(default)]
+    pub default_category: Option<u32>,
+    /// Accept a class's waiver and retry when a booking is refused for want of it
+    #[serde(default)]
+    pub auto_accept_waivers: bool,
+    /// On startup, check a token the client already holds with a cheap request and log in
+    /// again if the server rejects it, rather than finding out on the first real request
+    #[serde(default = "default_check_session_on_startup")]
+    pub check_session_on_startup: bool,
+    /// Mask tokens and passwords in logged requests and responses and in `replay` output
+    #[serde(default = "default_redact_logs")]
+    pub redact_logs: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbiguousTime {
+    /// The first occurrence (still on summer time)
+    #[default]
+    Earliest,
+    /// The second occurrence (after the clocks went back)
+    Latest,
+}
+
+impl GymConfig {
+    /// `base_url` followed by any `base_urls` mirrors, without duplicates
+    pub fn portal_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for url in std::iter::once(&self.base_url).chain(&self.base_urls) {
+            let url = url.trim_end_matches('/');
+            if !url.is_empty() && !urls.iter().any(|u| u == url) {
+                urls.push(url.to_string());
+            }
+        }
+        urls
+    }
+}
+
+fn default_booking_window_days() -> u32 {
+    7
+}
+
+fn default_booking_window_hours() -> u32 {
+    2
+}
+
+fn default_duration_minutes() -> u32 {
+    60
+}
+
+fn default_check_session_on_startup() -> bool {
+    true
+}
+
+fn default_redact_logs() -> bool {
+    true
+}
+
+fn default_retry_attempts() -> u32 {
+    3
+}
+
+fn default_max_requests_per_minute() -> u32 {
+    600
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Credentials {
     pub email: String,
+    /// Plaintext password; leave out to read it from the keyring instead
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub password: String,
+    /// Keyring service holding the password under `email`, used when `password` is unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyring_service: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl Credentials {
+    /// The login password: the plaintext `password` if set, otherwise the one stored in
+    /// the keyring under `keyring_service`
+    pub fn resolve_password(&self) -> Result<String> {
+        match &self.keyring_service {
+            Some(service) if self.password.is_empty() => secrets::stored_password(service, &self.email),
+            _ => Ok(self.password.clone()),
+        }
+    }
+}
+
+/// A named extra login, referred to by name from `targets[].accounts` and `snipe --accounts`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Account {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+/// A membership with its own login in `[[profiles]]`, chosen with `--profile <name>`.
+/// Everything it doesn't set comes from the rest of the file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub credentials: Credentials,
+    /// Club to book at instead of `gym.club_id`
+    pub club_id: Option<u32>,
+    /// Classes to auto-book instead of the top-level `[[targets]]`
+    #[serde(default)]
+    pub targets: Vec<ClassTarget>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ClassTarget {
-    pub class_name: String,
+    /// Class name to match (set this or `class_id`)
+    pub class_name: Option<String>,
+    /// Exact class ID to book, for gyms whose recurring classes keep their ID (set this or `class_name`)
+    pub class_id: Option<u64>,
     pub days: Option<Vec<String>>,
     pub time: Option<String>,
+    /// How to pick one class when several match at the same time
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    /// Zone to prefer when `tie_break = "preferred_zone"`
+    pub preferred_zone: Option<String>,
+    /// Try every match on the day, earliest first, until one books
+    #[serde(default)]
+    pub flexible: bool,
+    /// Skip booking when the class is already at least this full (0.0-1.0) at window time
+    pub max_fill_ratio: Option<f64>,
+    /// Stop booking this target once this many of its classes are booked in an ISO week,
+    /// which runs Monday to Sunday
+    pub max_per_week: Option<u32>,
+    /// Names from `[[accounts]]` to book the same class for, alongside the main account
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    /// Only book while the class's trainer contains this (case-insensitive), checked again
+    /// just before booking so a substitute isn't booked
+    pub trainer: Option<String>,
+}
+
+impl ClassTarget {
+    /// Name for log messages: the class name, or the ID for ID targets
+    pub fn label(&self) -> String {
+        match (&self.class_name, self.class_id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("class {}", id),
+            (None, None) => "unnamed target".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// Lowest class ID wins
+    #[default]
+    FirstById,
+    /// Class in `preferred_zone` wins, falling back to lowest ID
+    PreferredZone,
+    /// Class with the fewest confirmed bookings wins
+    FewestBooked,
+}
+
+/// Where the effective configuration came from, for diagnostics
+#[derive(Debug)]
+pub struct ConfigSources {
+    /// The config file that was read
+    pub path: PathBuf,
+    /// Keys not present in the file, filled from built-in defaults
+    pub defaults: Vec<String>,
+    /// Keys replaced by environment variables, as "key (VAR)"
+    pub env_overrides: Vec<String>,
 }
 
+const REDACTED: &str = "********";
+
 impl Config {
     pub fn load(path: &str) -> Result<Self> {
+        Ok(Self::load_with_sources(path)?.0)
+    }
+
+    /// Load the config, applying environment overrides, and report where each setting came from
+    pub fn load_with_sources(path: &str) -> Result<(Self, ConfigSources)> {
         let content = fs::read_to_string(path).map_err(|e| {
             GymSniperError::Config(format!("Failed to read config file '{}': {}", path, e))
         })?;
 
-        let config: Config = toml::from_str(&content)?;
+        let mut config: Config = toml::from_str(&content)?;
+        let env_overrides = config.apply_env_overrides(|name| std::env::var(name).ok())?;
+        config.validate()?;
+
+        let raw: toml::Value = toml::from_str(&content)?;
+        let mut defaults = Vec::new();
+        collect_defaults(&config.to_toml_value()?, Some(&raw), "", &mut defaults);
+
+        let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        Ok((config, ConfigSources { path, defaults, env_overrides }))
+    }
+
+    /// Load the config for a `--profile`: `config.<profile>.toml` next to `path` if there is
+    /// one, otherwise the `[[profiles]]` entry of that name in `path`. With no profile
+    /// given, the first `[[profiles]]` entry is used if the file has any.
+    pub fn load_for_profile(path: &str, profile: Option<&str>) -> Result<(Self, ConfigSources)> {
+        let own_file = profile_file(path, profile);
+        let Some(name) = profile.filter(|_| !own_file.exists()) else {
+            let (config, sources) = Self::load_with_sources(&own_file.to_string_lossy())?;
+            let mut config = config.for_profile(None)?;
+            if config.profile.is_none() {
+                config.profile = profile.map(str::to_string);
+            }
+            return Ok((config, sources));
+        };
+
+        let (config, sources) = Self::load_with_sources(path)?;
+        if config.profiles.is_empty() {
+            return Err(GymSniperError::Config(format!(
+                "No {} for profile '{}', and {} has no [[profiles]]",
+                own_file.display(),
+                name,
+                path
+            )));
+        }
+        Ok((config.for_profile(Some(name))?, sources))
+    }
+
+    /// Check settings that parse fine but contradict each other
+    pub fn validate(&self) -> Result<()> {
+        if self.gym.portal_urls().is_empty() {
+            return Err(GymSniperError::Config("gym: set base_url or base_urls".to_string()));
+        }
+        if self.profiles.is_empty() && self.credentials.email.is_empty() {
+            return Err(GymSniperError::Config(
+                "credentials: set email and password, or add [[profiles]]".to_string(),
+            ));
+        }
+        self.validate_targets(&self.targets, "targets")?;
+        for (i, profile) in self.profiles.iter().enumerate() {
+            if self.profiles[..i].iter().any(|p| p.name == profile.name) {
+                return Err(GymSniperError::Config(format!("Duplicate profile name '{}'", profile.name)));
+            }
+            self.validate_targets(&profile.targets, &format!("profiles[{}].targets", i))?;
+        }
+        for (i, account) in self.accounts.iter().enumerate() {
+            if self.accounts[..i].iter().any(|a| a.name == account.name) {
+                return Err(GymSniperError::Config(format!("Duplicate account name '{}'", account.name)));
+            }
+        }
+        if self.gym.max_requests_per_minute == 0 {
+            return Err(GymSniperError::Config("gym.max_requests_per_minute must be greater than 0".to_string()));
+        }
+        let intervals = [
+            ("far_interval_secs", self.snipe.far_interval_secs),
+            ("near_interval_secs", self.snipe.near_interval_secs),
+            ("imminent_interval_secs", self.snipe.imminent_interval_secs),
+        ];
+        if let Some((key, _)) = intervals.iter().find(|(_, secs)| *secs == 0) {
+            return Err(GymSniperError::Config(format!("snipe.{} must be greater than 0", key)));
+        }
+        if !(self.snipe.backoff_multiplier.is_finite() && self.snipe.backoff_multiplier >= 1.0) {
+            return Err(GymSniperError::Config("snipe.backoff_multiplier must be a number of at least 1.0".to_string()));
+        }
+        if self.snipe.near_threshold_minutes >= self.snipe.far_threshold_minutes {
+            return Err(GymSniperError::Config(
+                "snipe.near_threshold_minutes must be less than snipe.far_threshold_minutes".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check one list of targets; `label` names it in errors, e.g. "targets"
+    fn validate_targets(&self, targets: &[ClassTarget], label: &str) -> Result<()> {
+        for (i, target) in targets.iter().enumerate() {
+            match (&target.class_name, target.class_id) {
+                (Some(_), None) | (None, Some(_)) => {}
+                _ => {
+                    return Err(GymSniperError::Config(format!(
+                        "{}[{}]: set exactly one of class_name or class_id",
+                        label, i
+                    )));
+                }
+            }
+            if !target.accounts.is_empty() && target.flexible {
+                return Err(GymSniperError::Config(format!(
+                    "{}[{}]: accounts can't be combined with flexible",
+                    label, i
+                )));
+            }
+            if let Some(name) = target.accounts.iter().find(|name| self.account(name).is_err()) {
+                return Err(GymSniperError::Config(format!(
+                    "{}[{}]: unknown account '{}' (not in [[accounts]])",
+                    label, i, name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The config for one of `[[profiles]]`: the named one, or the first when `name` is
+    /// `None`. Its login, club and targets replace the top-level ones, and its name
+    /// namespaces the state files. A config without profiles is returned as it is.
+    pub fn for_profile(&self, name: Option<&str>) -> Result<Self> {
+        let profile = match name {
+            Some(name) => self.profiles.iter().find(|p| p.name == name).ok_or_else(|| {
+                GymSniperError::Config(format!("Unknown profile '{}' (not in [[profiles]])", name))
+            })?,
+            None => match self.profiles.first() {
+                Some(profile) => profile,
+                None => return Ok(self.clone()),
+            },
+        };
+        let mut config = self.clone();
+        config.credentials = profile.credentials.clone();
+        if let Some(club_id) = profile.club_id {
+            config.gym.club_id = club_id;
+        }
+        if !profile.targets.is_empty() {
+            config.targets = profile.targets.clone();
+        }
+        config.profile = Some(profile.name.clone());
+        Ok(config)
+    }
+
+    /// The email settings, unless email notifications are missing or turned off
+    pub fn email_notifications(&self) -> Option<&EmailConfig> {
+        self.email.as_ref().filter(|email| email.enabled)
+    }
+
+    /// Look up an account from `[[accounts]]` by name
+    pub fn account(&self, name: &str) -> Result<&Account> {
+        self.accounts
+            .iter()
+            .find(|a| a.name == name)
+            .ok_or_else(|| GymSniperError::Config(format!("Unknown account '{}' (not in [[accounts]])", name)))
+    }
+
+    /// A copy of this config that logs in as the named account
+    pub fn for_account(&self, name: &str) -> Result<Self> {
+        let account = self.account(name)?;
+        let mut config = self.clone();
+        config.credentials = Credentials {
+            email: account.email.clone(),
+            password: account.password.clone(),
+            keyring_service: None,
+        };
         Ok(config)
     }
+
+    /// Override settings from environment variables (looked up via `var`).
+    /// Returns the overridden keys.
+    pub fn apply_env_overrides<F>(&mut self, var: F) -> Result<Vec<String>>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut applied = Vec::new();
+
+        if let Some(v) = var("GYM_SNIPER_BASE_URL") {
+            self.gym.base_url = v;
+            applied.push("gym.base_url (GYM_SNIPER_BASE_URL)".to_string());
+        }
+        if let Some(v) = var("GYM_SNIPER_CLUB_ID") {
+            self.gym.club_id = v.parse().map_err(|_| {
+                GymSniperError::Config(format!("GYM_SNIPER_CLUB_ID is not a number: '{}'", v))
+            })?;
+            applied.push("gym.club_id (GYM_SNIPER_CLUB_ID)".to_string());
+        }
+        if let Some(v) = var("GYM_SNIPER_EMAIL") {
+            self.credentials.email = v;
+            applied.push("credentials.email (GYM_SNIPER_EMAIL)".to_string());
+        }
+        if let Some(v) = var("GYM_SNIPER_PASSWORD") {
+            self.credentials.password = v;
+            applied.push("credentials.password (GYM_SNIPER_PASSWORD)".to_string());
+        }
+        if let (Some(v), Some(email)) = (var("GYM_SNIPER_SMTP_PASSWORD"), self.email.as_mut()) {
+            email.password = v;
+            applied.push("email.password (GYM_SNIPER_SMTP_PASSWORD)".to_string());
+        }
+
+        Ok(applied)
+    }
+
+    /// A copy with all secrets masked, safe to print or share
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        // A password left to the keyring stays unset, so it's clear where it comes from
+        if !config.credentials.password.is_empty() {
+            config.credentials.password = REDACTED.to_string();
+        }
+        for account in &mut config.accounts {
+            account.password = REDACTED.to_string();
+        }
+        for profile in config.profiles.iter_mut().filter(|p| !p.credentials.password.is_empty()) {
+            profile.credentials.password = REDACTED.to_string();
+        }
+        if let Some(email) = config.email.as_mut() {
+            email.password = REDACTED.to_string();
+        }
+        // Webhook URLs carry their own secret (e.g. Discord's token)
+        if let Some(webhook) = config.webhook.as_mut() {
+            webhook.url = REDACTED.to_string();
+        }
+        config
+    }
+
+    fn to_toml_value(&self) -> Result<toml::Value> {
+        toml::Value::try_from(self)
+            .map_err(|e| GymSniperError::Config(format!("Failed to serialize config: {}", e)))
+    }
+}
+
+/// Record every key in `resolved` that is missing from `raw` (the file as written)
+fn collect_defaults(resolved: &toml::Value, raw: Option<&toml::Value>, prefix: &str, out: &mut Vec<String>) {
+    match resolved {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                let raw_value = raw.and_then(|r| r.get(key));
+                if value.is_table() || (value.is_array() && raw_value.is_some()) {
+                    collect_defaults(value, raw_value, &path, out);
+                } else if raw_value.is_none() {
+                    out.push(path);
+                }
+            }
+        }
+        toml::Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if item.is_table() {
+                    let raw_item = raw.and_then(|r| r.get(i));
+                    collect_defaults(item, raw_item, &format!("{}[{}]", prefix, i), out);
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
 #[cfg(test)]
@@ -72,6 +824,8 @@ password = "secret"
         assert_eq!(config.credentials.email, "user@example.com");
         assert!(config.targets.is_empty());
         assert!(config.email.is_none());
+        assert_eq!(config.gym.retry_attempts, 3);
+        assert_eq!(config.scheduler.max_concurrent_bookings, 3);
     }
 
     #[test]
@@ -103,20 +857,411 @@ to = "c@d.com"
 "#;
         let config: Config = toml::from_str(toml_str).unwrap();
         assert_eq!(config.targets.len(), 2);
-        assert_eq!(config.targets[0].class_name, "Yoga");
+        assert_eq!(config.targets[0].class_name.as_deref(), Some("Yoga"));
         assert_eq!(config.targets[0].days.as_ref().unwrap().len(), 2);
         assert_eq!(config.targets[1].time, None);
+        assert_eq!(config.targets[1].tie_break, TieBreak::FirstById);
         assert!(config.email.is_some());
         assert_eq!(config.email.unwrap().smtp_port, 587);
     }
 
+    #[test]
+    fn email_to_accepts_a_list_and_cc() {
+        let toml_str = r#"
+[gym]
+base_url = "https://example.com"
+
+[credentials]
+email = "user@example.com"
+password = "secret"
+
+[email]
+smtp_server = "smtp.example.com"
+smtp_port = 587
+username = "user"
+password = "pass"
+from = "a@b.com"
+to = ["me@example.com", "partner@example.com"]
+cc = ["coach@example.com"]
+"#;
+        let email = toml::from_str::<Config>(toml_str).unwrap().email.unwrap();
+        assert_eq!(email.to.addresses(), ["me@example.com", "partner@example.com"]);
+        assert_eq!(email.cc, ["coach@example.com"]);
+    }
+
     #[test]
     fn parse_missing_required_fields() {
         let toml_str = r#"
 [gym]
 base_url = "https://example.com"
 "#;
-        let result: std::result::Result<Config, _> = toml::from_str(toml_str);
+        // Credentials may come from [[profiles]] instead, so their absence is caught by validation
+        let result = toml::from_str::<Config>(toml_str).map_err(GymSniperError::from).and_then(|c| c.validate());
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("credentials"), "{}", err);
+    }
+
+    #[test]
+    fn daemon_poll_intervals_default_to_tiers_and_must_be_positive() {
+        let toml_str = r#"
+[gym]
+base_url = "https://example.com"
+
+[credentials]
+email = "me@example.com"
+password = "secret"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let snipe = &config.snipe;
+        let mins = chrono::Duration::minutes;
+        assert_eq!(snipe.daemon_poll_interval(mins(90)).as_secs(), 1800);
+        assert_eq!(snipe.daemon_poll_interval(mins(45)).as_secs(), 600);
+        assert_eq!(snipe.daemon_poll_interval(mins(10)).as_secs(), 60);
+
+        let tuned: Config = toml::from_str(&format!(
+            "{}\n[snipe]\nimminent_interval_secs = 10\nnear_threshold_minutes = 15\n",
+            toml_str
+        ))
+        .unwrap();
+        tuned.validate().unwrap();
+        assert_eq!(tuned.snipe.daemon_poll_interval(mins(20)).as_secs(), 600);
+        assert_eq!(tuned.snipe.daemon_poll_interval(mins(10)).as_secs(), 10);
+
+        // A long interval is cut short to wake in time to take over (5 minutes before)
+        let slow: Config = toml::from_str(&format!("{}\n[snipe]\nimminent_interval_secs = 600\n", toml_str)).unwrap();
+        assert_eq!(slow.snipe.daemon_poll_interval(mins(8)).as_secs(), 180);
+        assert_eq!(slow.snipe.daemon_poll_interval(mins(5)).as_secs(), 1);
+
+        let zero: Config = toml::from_str(&format!("{}\n[snipe]\nnear_interval_secs = 0\n", toml_str)).unwrap();
+        let err = zero.validate().unwrap_err();
+        assert!(matches!(err, GymSniperError::Config(_)));
+        assert!(err.to_string().contains("snipe.near_interval_secs"), "{}", err);
+    }
+
+    #[test]
+    fn backoff_multiplier_must_be_finite_and_at_least_one() {
+        let base = "[gym]\nbase_url = \"https://example.com\"\n\n[credentials]\nemail = \"me@example.com\"\npassword = \"secret\"\n";
+        for multiplier in ["0.5", "inf", "nan"] {
+            let config: Config =
+                toml::from_str(&format!("{}\n[snipe]\nbackoff_multiplier = {}\n", base, multiplier)).unwrap();
+            let err = config.validate().unwrap_err();
+            assert!(err.to_string().contains("snipe.backoff_multiplier"), "{}", err);
+        }
+        let config: Config = toml::from_str(&format!("{}\n[snipe]\nbackoff_multiplier = 1.0\n", base)).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn profiles_replace_login_club_and_targets() {
+        let toml_str = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+club_id = 42
+
+[[targets]]
+class_name = "Yoga"
+
+[[profiles]]
+name = "me"
+credentials = { email = "me@example.com", password = "my-secret" }
+
+[[profiles]]
+name = "partner"
+credentials = { email = "partner@example.com", password = "their-secret" }
+club_id = 7
+
+[[profiles.targets]]
+class_name = "Swim"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+
+        // The first profile is the default, and keeps the shared club and targets
+        let me = config.for_profile(None).unwrap();
+        assert_eq!(me.profile.as_deref(), Some("me"));
+        assert_eq!(me.credentials.email, "me@example.com");
+        assert_eq!(me.gym.club_id, 42);
+        assert_eq!(me.targets[0].class_name.as_deref(), Some("Yoga"));
+
+        let partner = config.for_profile(Some("partner")).unwrap();
+        assert_eq!(partner.credentials.password, "their-secret");
+        assert_eq!(partner.gym.club_id, 7);
+        assert_eq!(partner.targets.len(), 1);
+        assert_eq!(partner.targets[0].class_name.as_deref(), Some("Swim"));
+
+        let err = config.for_profile(Some("nobody")).unwrap_err().to_string();
+        assert!(err.contains("nobody"), "{}", err);
+        let redacted = toml::to_string(&config.redacted()).unwrap();
+        assert!(!redacted.contains("my-secret") && !redacted.contains("their-secret"));
+
+        // A config without profiles is used as it is
+        let single: Config = toml::from_str(MINIMAL).unwrap();
+        let selected = single.for_profile(None).unwrap();
+        assert_eq!(selected.credentials.email, "user@example.com");
+        assert_eq!(selected.profile, None);
+    }
+
+    #[test]
+    fn target_by_class_id_needs_exactly_one_selector() {
+        let base = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+club_id = 42
+
+[credentials]
+email = "user@example.com"
+password = "secret"
+"#;
+        let by_id: Config = toml::from_str(&format!("{}\n[[targets]]\nclass_id = 76014\n", base)).unwrap();
+        assert_eq!(by_id.targets[0].class_id, Some(76014));
+        assert_eq!(by_id.targets[0].class_name, None);
+        assert!(by_id.validate().is_ok());
+
+        let both: Config =
+            toml::from_str(&format!("{}\n[[targets]]\nclass_id = 76014\nclass_name = \"Yoga\"\n", base)).unwrap();
+        let err = both.validate().unwrap_err().to_string();
+        assert!(err.contains("targets[0]"), "{}", err);
+
+        let neither: Config = toml::from_str(&format!("{}\n[[targets]]\ntime = \"09:00\"\n", base)).unwrap();
+        assert!(neither.validate().is_err());
+    }
+
+    #[test]
+    fn target_accounts_must_be_defined() {
+        let base = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+club_id = 42
+
+[credentials]
+email = "user@example.com"
+password = "secret"
+
+[[accounts]]
+name = "alice"
+email = "alice@example.com"
+password = "alice-secret"
+"#;
+        let known: Config =
+            toml::from_str(&format!("{}\n[[targets]]\nclass_name = \"Swim\"\naccounts = [\"alice\"]\n", base)).unwrap();
+        assert!(known.validate().is_ok());
+        let alice = known.for_account("alice").unwrap();
+        assert_eq!(alice.credentials.email, "alice@example.com");
+        assert_eq!(alice.credentials.password, "alice-secret");
+
+        let unknown: Config =
+            toml::from_str(&format!("{}\n[[targets]]\nclass_name = \"Swim\"\naccounts = [\"bob\"]\n", base)).unwrap();
+        let err = unknown.validate().unwrap_err().to_string();
+        assert!(err.contains("targets[0]") && err.contains("bob"), "{}", err);
+        assert!(unknown.for_account("bob").is_err());
+    }
+
+    #[test]
+    fn mirrors_follow_base_url() {
+        let config: Config = toml::from_str(
+            r#"
+[gym]
+base_url = "https://a.example.com/clientportal2"
+base_urls = ["https://b.example.com/clientportal2/", "https://a.example.com/clientportal2"]
+club_id = 1
+
+[credentials]
+email = "me@example.com"
+password = "pw"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.gym.portal_urls(),
+            ["https://a.example.com/clientportal2", "https://b.example.com/clientportal2"]
+        );
+
+        let mut mirrors_only = config.clone();
+        mirrors_only.gym.base_url.clear();
+        assert!(mirrors_only.validate().is_ok());
+        assert_eq!(mirrors_only.gym.portal_urls()[0], "https://b.example.com/clientportal2");
+
+        let mut neither = mirrors_only;
+        neither.gym.base_urls.clear();
+        assert!(neither.validate().is_err());
+    }
+
+    #[test]
+    fn parse_target_tie_break() {
+        let toml_str = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+club_id = 42
+
+[credentials]
+email = "user@example.com"
+password = "secret"
+
+[[targets]]
+class_name = "Spin"
+tie_break = "preferred_zone"
+preferred_zone = "Cycle Studio"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.targets[0].tie_break, TieBreak::PreferredZone);
+        assert_eq!(config.targets[0].preferred_zone.as_deref(), Some("Cycle Studio"));
+    }
+
+    #[test]
+    fn parse_stealth_attempt_delay() {
+        let toml_str = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+club_id = 42
+
+[credentials]
+email = "user@example.com"
+password = "secret"
+
+[stealth.attempt_delay]
+distribution = "lognormal"
+min_ms = 150
+max_ms = 900
+median_ms = 300
+seed = 7
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let delay = &config.stealth.attempt_delay;
+        assert_eq!(delay.distribution, DelayDistribution::Lognormal);
+        assert_eq!(delay.min_ms, 150);
+        assert_eq!(delay.max_ms, 900);
+        assert_eq!(delay.sigma, 0.25);
+        assert_eq!(delay.seed, Some(7));
+    }
+
+    const MINIMAL: &str = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+club_id = 42
+
+[credentials]
+email = "user@example.com"
+password = "secret"
+
+[email]
+smtp_server = "smtp.example.com"
+smtp_port = 587
+username = "user"
+password = "smtp-secret"
+from = "a@b.com"
+to = "c@d.com"
+"#;
+
+    #[test]
+    fn redacted_hides_passwords() {
+        let config: Config = toml::from_str(MINIMAL).unwrap();
+        let shown = toml::to_string(&config.redacted()).unwrap();
+        assert!(!shown.contains("secret"));
+        assert!(shown.contains(REDACTED));
+        assert!(shown.contains("user@example.com"));
+        // The original is untouched
+        assert_eq!(config.credentials.password, "secret");
+    }
+
+    #[test]
+    fn keyring_credentials_need_no_plaintext_password() {
+        let text = r#"
+[gym]
+base_url = "https://example.com/clientportal2"
+
+[credentials]
+email = "user@example.com"
+keyring_service = "gym_sniper"
+"#;
+        let config: Config = toml::from_str(text).unwrap();
+        assert!(config.credentials.password.is_empty());
+        assert_eq!(config.credentials.keyring_service.as_deref(), Some("gym_sniper"));
+        let shown = toml::to_string(&config.redacted()).unwrap();
+        assert!(!shown.contains(REDACTED));
+        assert!(shown.contains("keyring_service = \"gym_sniper\""));
+
+        // A plaintext password still wins, without touching the keyring
+        let mut config = config;
+        config.credentials.password = "secret".to_string();
+        assert_eq!(config.credentials.resolve_password().unwrap(), "secret");
+    }
+
+    #[test]
+    fn webhook_defaults_to_json_and_is_redacted() {
+        let text = format!("{}\n[webhook]\nurl = \"https://discord.com/api/webhooks/1/s3cr3t\"\n", MINIMAL);
+        let config: Config = toml::from_str(&text).unwrap();
+        assert_eq!(config.webhook.as_ref().unwrap().format, WebhookFormat::Json);
+        let shown = toml::to_string(&config.redacted()).unwrap();
+        assert!(!shown.contains("s3cr3t"));
+    }
+
+    #[test]
+    fn env_overrides_are_applied_and_reported() {
+        let mut config: Config = toml::from_str(MINIMAL).unwrap();
+        let applied = config
+            .apply_env_overrides(|name| match name {
+                "GYM_SNIPER_EMAIL" => Some("other@example.com".to_string()),
+                "GYM_SNIPER_CLUB_ID" => Some("7".to_string()),
+                _ => None,
+            })
+            .unwrap();
+
+        assert_eq!(config.credentials.email, "other@example.com");
+        assert_eq!(config.gym.club_id, 7);
+        assert_eq!(applied.len(), 2);
+        assert!(applied[0].contains("GYM_SNIPER_CLUB_ID"));
+    }
+
+    #[test]
+    fn env_override_invalid_club_id_errors() {
+        let mut config: Config = toml::from_str(MINIMAL).unwrap();
+        let result = config.apply_env_overrides(|name| {
+            (name == "GYM_SNIPER_CLUB_ID").then(|| "abc".to_string())
+        });
         assert!(result.is_err());
     }
+
+    #[test]
+    fn load_with_sources_reports_defaults() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, MINIMAL).unwrap();
+
+        let (_, sources) = Config::load_with_sources(path.to_str().unwrap()).unwrap();
+        assert!(sources.defaults.contains(&"gym.retry_attempts".to_string()));
+        assert!(sources.defaults.contains(&"scheduler.max_concurrent_bookings".to_string()));
+        assert!(!sources.defaults.contains(&"gym.club_id".to_string()));
+        assert!(sources.path.ends_with("config.toml"));
+    }
+
+    #[test]
+    fn profile_selects_own_file_or_profiles_entry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let shared = "[gym]\nbase_url = \"https://example.com/clientportal2\"\nclub_id = 42\n";
+        fs::write(
+            &path,
+            format!(
+                "{}\n[[profiles]]\nname = \"me\"\ncredentials = {{ email = \"me@example.com\", password = \"a\" }}\n\n\
+                 [[profiles]]\nname = \"partner\"\ncredentials = {{ email = \"partner@example.com\", password = \"b\" }}\n",
+                shared
+            ),
+        )
+        .unwrap();
+        fs::write(dir.path().join("config.gym2.toml"), MINIMAL).unwrap();
+        let path = path.to_str().unwrap();
+
+        let (config, _) = Config::load_for_profile(path, None).unwrap();
+        assert_eq!((config.profile.as_deref(), config.credentials.email.as_str()), (Some("me"), "me@example.com"));
+
+        let (config, _) = Config::load_for_profile(path, Some("partner")).unwrap();
+        assert_eq!(config.credentials.email, "partner@example.com");
+
+        // A profile with its own file keeps working as before
+        let (config, sources) = Config::load_for_profile(path, Some("gym2")).unwrap();
+        assert_eq!((config.profile.as_deref(), config.credentials.email.as_str()), (Some("gym2"), "user@example.com"));
+        assert!(sources.path.ends_with("config.gym2.toml"));
+
+        assert!(Config::load_for_profile(path, Some("nobody")).is_err());
+    }
 }
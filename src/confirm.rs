@@ -0,0 +1,105 @@
+use chrono::{DateTime, Duration, Local};
+use tracing::{info, warn};
+
+use crate::api::{PerfectGymClient, DEFAULT_BOOKINGS_DAYS};
+use crate::config::Config;
+use crate::email::FailureExplanation;
+use crate::error::Result;
+use crate::notify::{Notifier, Notifiers};
+
+/// A successful snipe waiting to be re-checked
+#[derive(Debug, Clone)]
+struct PendingConfirmation {
+    class_id: u64,
+    name: String,
+    class_time: DateTime<Local>,
+    trainer: Option<String>,
+    due: DateTime<Local>,
+}
+
+/// Re-checks successful snipes some time later, alerting if the booking was rolled back
+#[derive(Debug, Default)]
+pub struct BookingConfirmations {
+    pending: Vec<PendingConfirmation>,
+}
+
+impl BookingConfirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedule a re-check of a booking `delay` after `now`
+    pub fn schedule(
+        &mut self,
+        class_id: u64,
+        name: &str,
+        class_time: DateTime<Local>,
+        trainer: Option<&str>,
+        now: DateTime<Local>,
+        delay: Duration,
+    ) {
+        let due = (now + delay).min(class_time);
+        info!("Will confirm the booking for {} is still there at {}", name, due.format("%a %d %b %H:%M"));
+        self.pending.push(PendingConfirmation {
+            class_id,
+            name: name.to_string(),
+            class_time,
+            trainer: trainer.map(str::to_string),
+            due,
+        });
+    }
+
+    /// True if any re-check is due at `now`
+    pub fn has_due(&self, now: DateTime<Local>) -> bool {
+        self.pending.iter().any(|p| p.due <= now)
+    }
+
+    /// Re-check every due booking, notifying about any that vanished.
+    /// Returns the class IDs of vanished bookings. On error, due checks stay pending.
+    pub async fn check_due(
+        &mut self,
+        config: &Config,
+        client: &PerfectGymClient,
+        now: DateTime<Local>,
+    ) -> Result<Vec<u64>> {
+        let (due, later): (Vec<_>, Vec<_>) = self.pending.drain(..).partition(|p| p.due <= now);
+        self.pending = later;
+        if due.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let bookings = match client.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await {
+            Ok(bookings) => bookings,
+            Err(e) => {
+                self.pending.extend(due);
+                return Err(e);
+            }
+        };
+
+        let mut vanished = Vec::new();
+        for confirmation in due {
+            if bookings.iter().any(|b| b.id == confirmation.class_id) {
+                info!("Confirmed booking for {} is still there", confirmation.name);
+                continue;
+            }
+
+            warn!(
+                "Booking for {} (class ID {}) has vanished since it was sniped",
+                confirmation.name, confirmation.class_id
+            );
+            let time_str = confirmation.class_time.format("%a %d %b %H:%M").to_string();
+            Notifiers::new(config).notify_failure(
+                &confirmation.name,
+                &time_str,
+                confirmation.trainer.as_deref(),
+                &FailureExplanation::other(
+                    "The booking was confirmed at snipe time but is no longer in your bookings - it may have been rolled back",
+                ),
+            )
+            .await;
+            vanished.push(confirmation.class_id);
+        }
+
+        Ok(vanished)
+    }
+}
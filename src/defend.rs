@@ -0,0 +1,192 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::api::{PerfectGymClient, DEFAULT_BOOKINGS_DAYS};
+use crate::config::Config;
+use crate::email::FailureExplanation;
+use crate::error::{BookingErrorKind, GymSniperError, Result};
+use crate::hold;
+use crate::notify::{Notifier, Notifiers};
+use crate::util::{profile_file, window_opens};
+
+pub const CANCELLED_FILE: &str = "cancelled.json";
+
+/// File of bookings cancelled on purpose for a profile (`cancelled.<profile>.json`)
+pub fn cancelled_path(profile: Option<&str>) -> PathBuf {
+    profile_file(CANCELLED_FILE, profile)
+}
+
+/// A booking the user cancelled themselves, so the defender leaves it alone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cancelled {
+    pub class_id: u64,
+    pub class_time: DateTime<Local>,
+}
+
+/// Note that a booking was cancelled on purpose. Entries for classes that have started
+/// are dropped as new ones are added.
+pub fn record_cancelled(path: &Path, class_id: u64, class_time: DateTime<Local>, now: DateTime<Local>) -> Result<()> {
+    let mut cancelled = load_cancelled(path);
+    cancelled.retain(|c| c.class_time > now && c.class_id != class_id);
+    cancelled.push(Cancelled { class_id, class_time });
+    let json = serde_json::to_string_pretty(&cancelled)
+        .map_err(|e| GymSniperError::Config(format!("Failed to serialize cancelled bookings: {}", e)))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Drop a recorded cancellation, e.g. when the class is booked again
+pub fn forget_cancelled(path: &Path, class_id: u64) -> Result<()> {
+    let mut cancelled = load_cancelled(path);
+    if !cancelled.iter().any(|c| c.class_id == class_id) {
+        return Ok(());
+    }
+    cancelled.retain(|c| c.class_id != class_id);
+    let json = serde_json::to_string_pretty(&cancelled)
+        .map_err(|e| GymSniperError::Config(format!("Failed to serialize cancelled bookings: {}", e)))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Bookings cancelled on purpose, or none if the file is missing or can't be read
+pub fn load_cancelled(path: &Path) -> Vec<Cancelled> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// A confirmed booking we're keeping an eye on
+#[derive(Debug, Clone)]
+struct HeldBooking {
+    id: u64,
+    name: String,
+    start_time: DateTime<Local>,
+    trainer: Option<String>,
+}
+
+/// What happened when a vanished booking was defended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Defense {
+    /// Re-booked successfully
+    Rebooked { class_id: u64, name: String },
+    /// Re-booking was attempted but failed
+    Failed { class_id: u64, name: String, reason: String },
+}
+
+/// Notices confirmed bookings that silently disappear and re-books them. Bookings cancelled
+/// on purpose (recorded by `cancel_booking_with_notice`) and classes a running `hold` is
+/// swapping are let go.
+#[derive(Debug, Default)]
+pub struct BookingDefender {
+    held: Option<Vec<HeldBooking>>,
+    /// Where cancellations are recorded, instead of the config profile's `cancelled.json`
+    cancelled_file: Option<PathBuf>,
+    /// Where running holds are recorded, instead of the config profile's `holds.json`
+    holds_file: Option<PathBuf>,
+}
+
+impl BookingDefender {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read cancellations from `path` instead of `cancelled.json`
+    pub fn with_cancelled_file(mut self, path: PathBuf) -> Self {
+        self.cancelled_file = Some(path);
+        self
+    }
+
+    /// Read running holds from `path` instead of `holds.json`
+    pub fn with_holds_file(mut self, path: PathBuf) -> Self {
+        self.holds_file = Some(path);
+        self
+    }
+
+    /// Compare current bookings against the last check and re-book any that vanished.
+    /// The first check only records the bookings to defend.
+    pub async fn check(&mut self, config: &Config, client: &PerfectGymClient) -> Result<Vec<Defense>> {
+        let now = Local::now();
+        let current: Vec<HeldBooking> = client
+            .get_my_bookings(DEFAULT_BOOKINGS_DAYS)
+            .await?
+            .into_iter()
+            .filter(|b| b.status == "Booked")
+            .map(|b| HeldBooking { id: b.id, name: b.name, start_time: b.start_time, trainer: b.trainer })
+            .collect();
+
+        let previous = self.held.replace(current.clone()).unwrap_or_else(|| current.clone());
+        let cancelled_file = self.cancelled_file.clone().unwrap_or_else(|| cancelled_path(config.profile.as_deref()));
+        let cancelled = load_cancelled(&cancelled_file);
+        let holds_file = self.holds_file.clone().unwrap_or_else(|| hold::holds_path(config.profile.as_deref()));
+        let holds = hold::load_active_holds(&holds_file, now);
+        let mut defenses = Vec::new();
+
+        for booking in previous {
+            if current.iter().any(|c| c.id == booking.id) || booking.start_time <= now {
+                continue;
+            }
+            if cancelled.iter().any(|c| c.class_id == booking.id) {
+                info!("Booking for {} (class ID {}) was cancelled on purpose - not re-booking", booking.name, booking.id);
+                continue;
+            }
+            if holds.iter().any(|h| h.involves(booking.id)) {
+                info!("Booking for {} (class ID {}) is part of a running hold - leaving it to the hold", booking.name, booking.id);
+                continue;
+            }
+            if window_opens(booking.start_time, None, &config.gym) > now {
+                warn!("Booking for {} (class ID {}) vanished but its window isn't open", booking.name, booking.id);
+                continue;
+            }
+
+            warn!("Booking for {} (class ID {}) vanished - re-booking...", booking.name, booking.id);
+            let time_str = booking.start_time.format("%a %d %b %H:%M").to_string();
+
+            match client.book_class(booking.id).await {
+                Ok(_) | Err(GymSniperError::Booking(BookingErrorKind::AlreadyBooked)) => {
+                    info!("Defended booking for {} at {}", booking.name, time_str);
+                    Notifiers::new(config).notify_defended(&booking.name, &time_str, booking.trainer.as_deref()).await;
+                    if let Some(held) = self.held.as_mut() {
+                        held.push(booking.clone());
+                    }
+                    defenses.push(Defense::Rebooked { class_id: booking.id, name: booking.name });
+                }
+                Err(e) => {
+                    warn!("Could not re-book {}: {}", booking.name, e);
+                    let failure = FailureExplanation::from_error(&e)
+                        .with_context("Your booking disappeared and re-booking it failed");
+                    Notifiers::new(config).notify_failure(&booking.name, &time_str, booking.trainer.as_deref(), &failure).await;
+                    defenses.push(Defense::Failed { class_id: booking.id, name: booking.name, reason: e.to_string() });
+                }
+            }
+        }
+
+        Ok(defenses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    #[test]
+    fn record_cancelled_drops_classes_that_have_started() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CANCELLED_FILE);
+        let now = Local.with_ymd_and_hms(2026, 3, 10, 8, 0, 0).unwrap();
+
+        record_cancelled(&path, 1, now + chrono::Duration::hours(1), now).unwrap();
+        record_cancelled(&path, 2, now + chrono::Duration::days(1), now).unwrap();
+        // By the time the third is cancelled, the first class has started
+        let later = now + chrono::Duration::hours(2);
+        record_cancelled(&path, 3, now + chrono::Duration::days(2), later).unwrap();
+
+        let ids: Vec<u64> = load_cancelled(&path).iter().map(|c| c.class_id).collect();
+        assert_eq!(ids, [2, 3]);
+    }
+}
@@ -1,64 +1,391 @@
+use lettre::message::Mailbox;
 use lettre::message::header::ContentType;
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 use tracing::{error, info};
 
 use crate::config::EmailConfig;
+use crate::error::{BookingErrorKind, GymSniperError};
 
+/// Whose side a booking failure is on, so the reader knows whether there's anything to fix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureSource {
+    /// Something about the account: limits, payments, waivers, login
+    Account,
+    /// The class itself: full, not open yet, gone
+    Class,
+    /// The gym's server or the connection to it
+    Server,
+}
+
+impl FailureSource {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Account => "Something on your account needs sorting out",
+            Self::Class => "Nothing is wrong on your side - the class itself couldn't be booked",
+            Self::Server => "A problem with the gym's system or the connection to it, not your account",
+        }
+    }
+}
+
+/// A booking failure in plain words: what happened, whose side it's on and what to do next
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailureExplanation {
+    pub summary: String,
+    /// `None` when the failure is a mix of things, e.g. a hold that went wrong
+    pub source: Option<FailureSource>,
+    pub action: String,
+    /// The technical reason, for anyone who wants it
+    pub detail: Option<String>,
+}
+
+impl FailureExplanation {
+    fn new(summary: impl Into<String>, source: FailureSource, action: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            source: Some(source),
+            action: action.into(),
+            detail: None,
+        }
+    }
+
+    /// Explain a booking the gym refused
+    pub fn from_kind(kind: &BookingErrorKind) -> Self {
+        use FailureSource::*;
+        match kind {
+            BookingErrorKind::TooSoon => Self::new(
+                "The booking window hadn't opened yet and we ran out of time.",
+                Class,
+                "Book it yourself if there's still space. If this keeps happening, run `estimate-window` to tune window_offset_secs.",
+            ),
+            BookingErrorKind::Full => Self::new(
+                "The class was already full.",
+                Class,
+                "Join the waitlist in the gym portal, or pick another class.",
+            ),
+            BookingErrorKind::AlreadyBooked => Self::new(
+                "You were already booked or on the waitlist for this class.",
+                Account,
+                "Nothing to do - check your bookings to be sure.",
+            ),
+            BookingErrorKind::DailyLimit => Self::new(
+                "Your account already has a class that day.",
+                Account,
+                "Cancel the other class first if you'd rather go to this one.",
+            ),
+            BookingErrorKind::PaymentRequired => Self::new(
+                "This is a paid class and the payment wasn't confirmed.",
+                Account,
+                "Confirm the payment in the gym portal, then book again.",
+            ),
+            BookingErrorKind::InsufficientFunds => Self::new(
+                "Your account balance is too low for this paid class.",
+                Account,
+                "Top up your account, then book again.",
+            ),
+            BookingErrorKind::WaiverRequired => Self::new(
+                "The class needs its waiver or terms accepted before it can be booked.",
+                Account,
+                "Accept them in the gym portal (or set auto_accept_waivers = true), then book again.",
+            ),
+            BookingErrorKind::TooFull(ratio) => Self::new(
+                format!("The class was already {:.0}% full, over your max_fill_ratio, so it wasn't booked.", ratio * 100.0),
+                Class,
+                "Raise max_fill_ratio if you want busier classes booked anyway.",
+            ),
+            BookingErrorKind::TrainerChanged { wanted, trainer } => Self::new(
+                match trainer {
+                    Some(trainer) => format!("The class is now taken by {}, not {} as you asked, so it wasn't booked.", trainer, wanted),
+                    None => format!("The class no longer lists {} as its trainer, so it wasn't booked.", wanted),
+                },
+                Class,
+                "Book it yourself if you don't mind the substitute, or pick another class.",
+            ),
+            BookingErrorKind::Unknown(body) => Self {
+                detail: Some(body.clone()),
+                ..Self::new(
+                    "The gym refused the booking without a reason we recognise.",
+                    Server,
+                    "Try booking it yourself in the gym portal.",
+                )
+            },
+        }
+    }
+
+    /// Explain any error that stopped a booking
+    pub fn from_error(error: &GymSniperError) -> Self {
+        use FailureSource::*;
+        let explanation = match error {
+            GymSniperError::Booking(kind) => return Self::from_kind(kind),
+            GymSniperError::Auth(_) => Self::new(
+                "The gym didn't accept your login.",
+                Account,
+                "Check the email and password in your config.",
+            ),
+            GymSniperError::Blocked(_) => Self::new(
+                "The gym's server refused to talk to us - your IP address may be blocked for a while.",
+                Server,
+                "Wait an hour or so before trying again, and book this class yourself.",
+            ),
+            GymSniperError::Request(_) => Self::new(
+                "The gym's server couldn't be reached.",
+                Server,
+                "Check the internet connection of the machine running gym_sniper, and book this class yourself.",
+            ),
+            GymSniperError::NotFound(_) => Self::new(
+                "The class couldn't be found - it may have been cancelled or moved.",
+                Class,
+                "Check the timetable for a replacement.",
+            ),
+            _ => Self::new(
+                "Something went wrong talking to the gym.",
+                Server,
+                "Try booking it yourself in the gym portal.",
+            ),
+        };
+        Self { detail: Some(error.to_string()), ..explanation }
+    }
+
+    /// A failure described in our own words, with no single cause
+    pub fn other(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            source: None,
+            action: "You may want to try booking manually or check the waitlist.".to_string(),
+            detail: None,
+        }
+    }
+
+    /// Say what was being attempted before the explanation itself
+    pub fn with_context(mut self, context: &str) -> Self {
+        self.summary = format!("{}. {}", context, self.summary);
+        self
+    }
+
+    /// The part of the email below the class details
+    pub(crate) fn body(&self) -> String {
+        let mut body = format!("What happened: {}\n", self.summary);
+        if let Some(source) = self.source {
+            body.push_str(&format!("Whose side: {}\n", source.describe()));
+        }
+        body.push_str(&format!("What to do: {}", self.action));
+        if let Some(detail) = &self.detail {
+            body.push_str(&format!("\n\nDetails: {}", detail));
+        }
+        body
+    }
+}
+
+/// Subject and body of a booking confirmation
+fn success_message(class_name: &str, time: &str, trainer: Option<&str>) -> (String, String) {
+    let trainer_str = trainer.unwrap_or("Not assigned");
+    let subject = format!("Gym Booking Confirmed: {}", class_name);
+    let body = format!(
+        "Your gym class has been successfully booked!\n\n\
+         Class: {}\n\
+         Time: {}\n\
+         Trainer: {}\n\n\
+         See you there!",
+        class_name, time, trainer_str
+    );
+    (subject, body)
+}
+
+/// Subject and body of a booking failure
+fn failure_message(class_name: &str, time: &str, trainer: Option<&str>, failure: &FailureExplanation) -> (String, String) {
+    let trainer_str = trainer.unwrap_or("Not assigned");
+    let subject = format!("Gym Booking Failed: {}", class_name);
+    let body = format!(
+        "Failed to book your gym class.\n\n\
+         Class: {}\n\
+         Time: {}\n\
+         Trainer: {}\n\n\
+         {}",
+        class_name, time, trainer_str, failure.body()
+    );
+    (subject, body)
+}
+
+/// Email a booking confirmation standing for `repeats` identical ones. Returns whether it
+/// was sent.
 pub async fn send_booking_success(
     config: &EmailConfig,
     class_name: &str,
     time: &str,
     trainer: Option<&str>,
-) {
+    repeats: u32,
+) -> bool {
+    let (subject, body) = success_message(class_name, time, trainer);
+    match send_email(config, &subject, &body, repeats).await {
+        Ok(()) => {
+            info!("Booking confirmation email sent");
+            true
+        }
+        Err(e) => {
+            error!("Failed to send success email: {}", e);
+            false
+        }
+    }
+}
+
+/// Email a booking failure standing for `repeats` identical ones. Returns whether it was sent.
+pub async fn send_booking_failure(
+    config: &EmailConfig,
+    class_name: &str,
+    time: &str,
+    trainer: Option<&str>,
+    failure: &FailureExplanation,
+    repeats: u32,
+) -> bool {
+    let (subject, body) = failure_message(class_name, time, trainer, failure);
+    match send_email(config, &subject, &body, repeats).await {
+        Ok(()) => {
+            info!("Booking failure email sent");
+            true
+        }
+        Err(e) => {
+            error!("Failed to send failure email: {}", e);
+            false
+        }
+    }
+}
+
+pub async fn send_availability_alert(
+    config: &EmailConfig,
+    class_name: &str,
+    time: &str,
+    trainer: Option<&str>,
+    repeats: u32,
+) -> bool {
     let trainer_str = trainer.unwrap_or("Not assigned");
-    let subject = format!("Gym Booking Confirmed: {}", class_name);
+    let subject = format!("Gym Class Available: {}", class_name);
     let body = format!(
-        "Your gym class has been successfully booked!\n\n\
+        "A spot has opened up in a class you're watching.\n\n\
          Class: {}\n\
          Time: {}\n\
          Trainer: {}\n\n\
-         See you there!",
+         It has not been booked for you - book it now if you still want it.",
         class_name, time, trainer_str
     );
 
-    if let Err(e) = send_email(config, &subject, &body).await {
-        error!("Failed to send success email: {}", e);
-    } else {
-        info!("Booking confirmation email sent");
+    match send_email(config, &subject, &body, repeats).await {
+        Ok(()) => {
+            info!("Availability alert email sent");
+            true
+        }
+        Err(e) => {
+            error!("Failed to send availability alert email: {}", e);
+            false
+        }
     }
 }
 
-pub async fn send_booking_failure(
+/// Email that a vanished booking was re-booked, standing for `repeats` identical ones.
+/// Returns whether it was sent.
+pub async fn send_booking_defended(
     config: &EmailConfig,
     class_name: &str,
     time: &str,
     trainer: Option<&str>,
-    reason: &str,
-) {
+    repeats: u32,
+) -> bool {
     let trainer_str = trainer.unwrap_or("Not assigned");
-    let subject = format!("Gym Booking Failed: {}", class_name);
+    let subject = format!("Gym Booking Restored: {}", class_name);
     let body = format!(
-        "Failed to book your gym class.\n\n\
+        "One of your bookings disappeared (cancelled by the gym?) and has been re-booked.\n\n\
          Class: {}\n\
          Time: {}\n\
          Trainer: {}\n\n\
-         Reason: {}\n\n\
-         You may want to try booking manually or check the waitlist.",
-        class_name, time, trainer_str, reason
+         You may want to check why it was cancelled.",
+        class_name, time, trainer_str
     );
 
-    if let Err(e) = send_email(config, &subject, &body).await {
-        error!("Failed to send failure email: {}", e);
-    } else {
-        info!("Booking failure email sent");
+    match send_email(config, &subject, &body, repeats).await {
+        Ok(()) => {
+            info!("Defended-booking email sent");
+            true
+        }
+        Err(e) => {
+            error!("Failed to send defended-booking email: {}", e);
+            false
+        }
+    }
+}
+
+/// Send a sample booking confirmation and failure, marked as tests, and return the first
+/// error. These don't go through the dedupe window, so are never suppressed as repeats.
+pub async fn send_test_notifications(
+    config: &EmailConfig,
+    class_name: &str,
+    time: &str,
+    trainer: Option<&str>,
+    failure: &FailureExplanation,
+) -> Result<(), String> {
+    for (subject, body) in [
+        success_message(class_name, time, trainer),
+        failure_message(class_name, time, trainer, failure),
+    ] {
+        let subject = format!("[Test] {}", subject);
+        let body = format!("This is a test notification from gym_sniper - nothing was booked.\n\n{}", body);
+        send_email(config, &subject, &body, 1).await?;
+    }
+    Ok(())
+}
+
+/// Every address a notification goes to, parsed up front
+struct Addresses {
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    cc: Vec<Mailbox>,
+}
+
+/// Parse the sender and all recipients, collecting every bad address rather than
+/// stopping at the first
+fn parse_addresses(config: &EmailConfig) -> Result<Addresses, Vec<String>> {
+    let mut problems = Vec::new();
+    let mut parse = |key: &str, address: &str| match address.parse::<Mailbox>() {
+        Ok(mailbox) => Some(mailbox),
+        Err(e) => {
+            problems.push(format!("email.{}: '{}' is not a valid address ({})", key, address, e));
+            None
+        }
+    };
+
+    let from = parse("from", &config.from);
+    let to: Vec<_> = config.to.addresses().iter().filter_map(|a| parse("to", a)).collect();
+    let cc: Vec<_> = config.cc.iter().filter_map(|a| parse("cc", a)).collect();
+    if config.to.addresses().is_empty() {
+        problems.push("email.to lists no addresses".to_string());
+    }
+
+    match from {
+        Some(from) if problems.is_empty() => Ok(Addresses { from, to, cc }),
+        _ => Err(problems),
     }
 }
 
-async fn send_email(config: &EmailConfig, subject: &str, body: &str) -> Result<(), String> {
-    let email = Message::builder()
-        .from(config.from.parse().map_err(|e| format!("Invalid from address: {}", e))?)
-        .to(config.to.parse().map_err(|e| format!("Invalid to address: {}", e))?)
+/// Each bad or missing address in the email config, as a line for the user
+pub fn address_problems(config: &EmailConfig) -> Vec<String> {
+    parse_addresses(config).err().unwrap_or_default()
+}
+
+/// Send an email standing for `repeats` identical notifications, counting them in the
+/// subject (e.g. "Gym Booking Failed: Spin (x5)") when there was more than one
+async fn send_email(config: &EmailConfig, subject: &str, body: &str, repeats: u32) -> Result<(), String> {
+    let subject = match repeats {
+        0 | 1 => subject.to_string(),
+        n => format!("{} (x{})", subject, n),
+    };
+
+    let addresses = parse_addresses(config).map_err(|problems| problems.join("; "))?;
+    let mut builder = Message::builder().from(addresses.from);
+    for mailbox in addresses.to {
+        builder = builder.to(mailbox);
+    }
+    for mailbox in addresses.cc {
+        builder = builder.cc(mailbox);
+    }
+    let email = builder
         .subject(subject)
         .header(ContentType::TEXT_PLAIN)
         .body(body.to_string())
@@ -80,3 +407,80 @@ async fn send_email(config: &EmailConfig, subject: &str, body: &str) -> Result<(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn booking_error_kinds_get_plain_explanations() {
+        let cases = [
+            (BookingErrorKind::TooSoon, "The booking window hadn't opened yet and we ran out of time.", FailureSource::Class),
+            (BookingErrorKind::Full, "The class was already full.", FailureSource::Class),
+            (BookingErrorKind::AlreadyBooked, "You were already booked or on the waitlist for this class.", FailureSource::Account),
+            (BookingErrorKind::DailyLimit, "Your account already has a class that day.", FailureSource::Account),
+            (BookingErrorKind::PaymentRequired, "This is a paid class and the payment wasn't confirmed.", FailureSource::Account),
+            (BookingErrorKind::InsufficientFunds, "Your account balance is too low for this paid class.", FailureSource::Account),
+            (
+                BookingErrorKind::WaiverRequired,
+                "The class needs its waiver or terms accepted before it can be booked.",
+                FailureSource::Account,
+            ),
+            (
+                BookingErrorKind::TooFull(0.95),
+                "The class was already 95% full, over your max_fill_ratio, so it wasn't booked.",
+                FailureSource::Class,
+            ),
+            (
+                BookingErrorKind::Unknown("{\"Errors\":[\"Odd\"]}".to_string()),
+                "The gym refused the booking without a reason we recognise.",
+                FailureSource::Server,
+            ),
+        ];
+        for (kind, summary, source) in cases {
+            let explanation = FailureExplanation::from_kind(&kind);
+            assert_eq!(explanation.summary, summary, "{:?}", kind);
+            assert_eq!(explanation.source, Some(source), "{:?}", kind);
+            assert!(!explanation.action.is_empty());
+        }
+
+        // The gym's raw response is kept when we can't say what it means
+        let unknown = FailureExplanation::from_kind(&BookingErrorKind::Unknown("Odd".to_string()));
+        assert_eq!(unknown.detail.as_deref(), Some("Odd"));
+        assert!(FailureExplanation::from_kind(&BookingErrorKind::Full).detail.is_none());
+    }
+
+    #[test]
+    fn other_errors_are_split_into_account_and_server_problems() {
+        let booking = FailureExplanation::from_error(&GymSniperError::Booking(BookingErrorKind::DailyLimit));
+        assert_eq!(booking, FailureExplanation::from_kind(&BookingErrorKind::DailyLimit));
+
+        let auth = FailureExplanation::from_error(&GymSniperError::Auth("bad password".into()));
+        assert_eq!(auth.source, Some(FailureSource::Account));
+        assert_eq!(auth.detail.as_deref(), Some("Authentication failed: bad password"));
+
+        let blocked = FailureExplanation::from_error(&GymSniperError::Blocked("403".into()));
+        assert_eq!(blocked.source, Some(FailureSource::Server));
+        let api = FailureExplanation::from_error(&GymSniperError::Api("500".into()));
+        assert_eq!(api.source, Some(FailureSource::Server));
+        let gone = FailureExplanation::from_error(&GymSniperError::NotFound("class 7".into()));
+        assert_eq!(gone.source, Some(FailureSource::Class));
+    }
+
+    #[test]
+    fn failure_body_reads_as_plain_sections() {
+        let body = FailureExplanation::from_kind(&BookingErrorKind::DailyLimit)
+            .with_context("Your booking disappeared and re-booking it failed")
+            .body();
+        assert_eq!(
+            body,
+            "What happened: Your booking disappeared and re-booking it failed. Your account already has a class that day.\n\
+             Whose side: Something on your account needs sorting out\n\
+             What to do: Cancel the other class first if you'd rather go to this one."
+        );
+
+        let body = FailureExplanation::other("Lost both classes").body();
+        assert!(!body.contains("Whose side"));
+        assert!(body.starts_with("What happened: Lost both classes\n"));
+    }
+}
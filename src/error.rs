@@ -1,3 +1,4 @@
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,6 +12,18 @@ pub enum GymSniperError {
     #[error("Authentication failed: {0}")]
     Auth(String),
 
+    #[error("Access blocked: {0}")]
+    Blocked(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Booking failed: {0}")]
+    Booking(BookingErrorKind),
+
+    #[error("Cancellation refused: {0}")]
+    CancelRefused(String),
+
     #[error("API error: {0}")]
     Api(String),
 
@@ -21,4 +34,139 @@ pub enum GymSniperError {
     Toml(#[from] toml::de::Error),
 }
 
+/// Why the gym rejected a booking
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookingErrorKind {
+    /// The booking window hasn't opened yet
+    TooSoon,
+    /// No spots left
+    Full,
+    /// Already booked or on the waitlist for this class
+    AlreadyBooked,
+    /// Already at the daily booking limit
+    DailyLimit,
+    /// Paid class that needs a payment confirmed in the portal
+    PaymentRequired,
+    /// Paid class and the account balance is too low
+    InsufficientFunds,
+    /// The class has a waiver or terms that have to be accepted first
+    WaiverRequired,
+    /// Not attempted: the class was already fuller than `max_fill_ratio`
+    TooFull(f64),
+    /// Not attempted: the class's trainer (if any) no longer contains the `wanted` one
+    TrainerChanged { wanted: String, trainer: Option<String> },
+    /// Anything else, with the raw response preserved
+    Unknown(String),
+}
+
+impl BookingErrorKind {
+    /// The kind for a portal error code, e.g. `TooSoonToBook` or `payment_required`.
+    /// Case, `_` and `-` are ignored. `None` for codes this doesn't know.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_lowercase();
+        let kind = match code.as_str() {
+            "toosoontobook" | "toosoon" | "bookingnotopenyet" => Self::TooSoon,
+            "classisfull" | "classfull" | "full" | "awaitable" => Self::Full,
+            "alreadybooked" | "classalreadybooked" | "alreadyonstandbyqueue" | "alreadyonwaitlist" => Self::AlreadyBooked,
+            "dailybookinglimitreached" | "dailylimitreached" => Self::DailyLimit,
+            "paymentrequired" => Self::PaymentRequired,
+            "insufficientfunds" | "insufficientbalance" => Self::InsufficientFunds,
+            "waiverrequired" | "termsnotaccepted" => Self::WaiverRequired,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+impl fmt::Display for BookingErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooSoon => write!(f, "booking window not open yet"),
+            Self::Full => write!(f, "class is full"),
+            Self::AlreadyBooked => write!(f, "already booked or on the waitlist"),
+            Self::DailyLimit => write!(f, "daily booking limit reached"),
+            Self::PaymentRequired => write!(f, "payment required - confirm the payment in the gym portal"),
+            Self::InsufficientFunds => write!(f, "insufficient account balance - top up your account and book again"),
+            Self::WaiverRequired => write!(
+                f,
+                "class requires accepting its waiver - accept it in the gym portal or set auto_accept_waivers = true"
+            ),
+            Self::TooFull(ratio) => write!(f, "class already {:.0}% full, over max_fill_ratio", ratio * 100.0),
+            Self::TrainerChanged { wanted, trainer } => write!(
+                f,
+                "trainer is now {}, not '{}'",
+                trainer.as_deref().unwrap_or("unlisted"),
+                wanted
+            ),
+            Self::Unknown(body) => write!(f, "{}", body),
+        }
+    }
+}
+
+/// Process exit codes, so wrapper scripts can react to the kind of failure
+pub mod exit_code {
+    pub const GENERAL: u8 = 1;
+    // 2 is used by clap for invalid command-line usage
+    pub const CONFIG: u8 = 3;
+    pub const AUTH: u8 = 4;
+    pub const BLOCKED: u8 = 5;
+    pub const NETWORK: u8 = 6;
+    pub const BOOKING_FAILED: u8 = 7;
+    pub const DAILY_LIMIT: u8 = 8;
+    pub const NOT_FOUND: u8 = 9;
+    pub const CANCEL_REFUSED: u8 = 10;
+}
+
+impl GymSniperError {
+    /// Exit code for this error when it ends the CLI
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Config(_) | Self::Toml(_) => exit_code::CONFIG,
+            Self::Auth(_) => exit_code::AUTH,
+            Self::Blocked(_) => exit_code::BLOCKED,
+            Self::Request(_) => exit_code::NETWORK,
+            Self::Booking(BookingErrorKind::DailyLimit) => exit_code::DAILY_LIMIT,
+            Self::Booking(_) => exit_code::BOOKING_FAILED,
+            Self::NotFound(_) => exit_code::NOT_FOUND,
+            Self::CancelRefused(_) => exit_code::CANCEL_REFUSED,
+            Self::Api(_) | Self::Io(_) => exit_code::GENERAL,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, GymSniperError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_map_to_exit_codes() {
+        assert_eq!(GymSniperError::Config("bad".into()).exit_code(), 3);
+        assert_eq!(GymSniperError::Auth("bad password".into()).exit_code(), 4);
+        assert_eq!(GymSniperError::Blocked("403".into()).exit_code(), 5);
+        assert_eq!(GymSniperError::Booking(BookingErrorKind::Full).exit_code(), 7);
+        assert_eq!(GymSniperError::Booking(BookingErrorKind::DailyLimit).exit_code(), 8);
+        assert_eq!(GymSniperError::NotFound("class 1".into()).exit_code(), 9);
+        assert_eq!(GymSniperError::CancelRefused("too late".into()).exit_code(), 10);
+        assert_eq!(GymSniperError::Api("odd".into()).exit_code(), 1);
+
+        let toml_err = toml::from_str::<toml::Value>("= nope").unwrap_err();
+        assert_eq!(GymSniperError::Toml(toml_err).exit_code(), exit_code::CONFIG);
+    }
+
+    #[test]
+    fn booking_error_codes_map_to_kinds() {
+        assert_eq!(BookingErrorKind::from_code("TooSoonToBook"), Some(BookingErrorKind::TooSoon));
+        assert_eq!(BookingErrorKind::from_code("DailyBookingLimitReached"), Some(BookingErrorKind::DailyLimit));
+        assert_eq!(BookingErrorKind::from_code("ClassIsFull"), Some(BookingErrorKind::Full));
+        assert_eq!(BookingErrorKind::from_code("AlreadyBooked"), Some(BookingErrorKind::AlreadyBooked));
+        assert_eq!(BookingErrorKind::from_code("payment_required"), Some(BookingErrorKind::PaymentRequired));
+        assert_eq!(BookingErrorKind::from_code("InsufficientFunds"), Some(BookingErrorKind::InsufficientFunds));
+        assert_eq!(BookingErrorKind::from_code("WaiverRequired"), Some(BookingErrorKind::WaiverRequired));
+        assert_eq!(BookingErrorKind::from_code("terms-not-accepted"), Some(BookingErrorKind::WaiverRequired));
+        // Whole codes only: a code merely containing "full" or "already" is not recognised
+        assert_eq!(BookingErrorKind::from_code("FullyBookedUntilFurtherNotice"), None);
+        assert_eq!(BookingErrorKind::from_code("AlreadyCancelled"), None);
+    }
+}
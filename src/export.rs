@@ -0,0 +1,279 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::api::{ClassInfo, MyBooking};
+use crate::util::{format_class_length, status_with_places};
+
+/// File formats for `export`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// A self-contained web page, one table per day
+    Html,
+    /// An iCalendar (.ics) file of your confirmed bookings, for phone calendars
+    Ical,
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+h2{margin-top:1.5em;border-bottom:1px solid #ccc}\
+table{border-collapse:collapse;width:100%}\
+th,td{text-align:left;padding:4px 8px;border-bottom:1px solid #eee}\
+.bookable{background:#e3f6e3}.full{background:#fbe3e3}\
+.booked{background:#e3ecfb}.other{background:#f3f3f3}";
+
+/// The weekly calendar as a standalone HTML page: a table per day with time, class,
+/// trainer, length and status, rows shaded by status. No scripts or external assets,
+/// so the file can be mailed or opened anywhere.
+pub fn classes_to_html(classes: &[ClassInfo]) -> String {
+    let mut sorted: Vec<&ClassInfo> = classes.iter().collect();
+    sorted.sort_by_key(|c| c.start_time);
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Class timetable</title>\n");
+    html.push_str(&format!("<style>{}</style>\n", STYLE));
+    html.push_str("</head>\n<body>\n<h1>Class timetable</h1>\n");
+
+    if sorted.is_empty() {
+        html.push_str("<p>No classes.</p>\n");
+    }
+
+    let mut day: Option<NaiveDate> = None;
+    for class in sorted {
+        let date = class.start_time.date_naive();
+        if day != Some(date) {
+            if day.is_some() {
+                html.push_str("</table>\n");
+            }
+            day = Some(date);
+            html.push_str(&format!("<h2>{}</h2>\n<table>\n", class.start_time.format("%A %d %B")));
+            html.push_str("<tr><th>Time</th><th>Class</th><th>Trainer</th><th>Length</th><th>Status</th></tr>\n");
+        }
+        html.push_str(&format!(
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            status_class(&class.status),
+            class.start_time.format("%H:%M"),
+            escape(&class.name),
+            escape(class.trainer.as_deref().unwrap_or("-")),
+            format_class_length(class.duration_minutes),
+            escape(&status_with_places(&class.status, class.booked_count, class.capacity)),
+        ));
+    }
+    if day.is_some() {
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Length given to events whose class length the gym doesn't report
+const ICAL_FALLBACK_MINUTES: u32 = 60;
+
+/// Confirmed bookings as an iCalendar file, one VEVENT per class with the class name as
+/// summary and the trainer in the description. Waitlist places are left out, since they
+/// aren't a place in the class. Times are written in UTC so no time zone table is needed;
+/// `stamp` is the DTSTAMP every event carries.
+pub fn bookings_to_ical(bookings: &[MyBooking], stamp: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//gym_sniper//Bookings//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for booking in bookings.iter().filter(|b| !b.is_waitlisted()) {
+        let minutes = match booking.duration_minutes {
+            0 => ICAL_FALLBACK_MINUTES,
+            m => m,
+        };
+        let start = booking.start_time.with_timezone(&Utc);
+        let end = start + Duration::minutes(minutes as i64);
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}-{}@gym_sniper", booking.id, start.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("DTSTART:{}", start.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("DTEND:{}", end.format("%Y%m%dT%H%M%SZ")));
+        lines.push(format!("SUMMARY:{}", ical_text(&booking.name)));
+        if let Some(trainer) = &booking.trainer {
+            lines.push(format!("DESCRIPTION:{}", ical_text(&format!("Trainer: {}", trainer))));
+        }
+        if let Some(zone) = &booking.zone {
+            lines.push(format!("LOCATION:{}", ical_text(zone)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines {
+        ics.push_str(&fold(&line));
+    }
+    ics
+}
+
+/// Text with the characters iCalendar reserves escaped
+fn ical_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A content line with its CRLF ending, folded so no line is over 75 octets. Continuation
+/// lines start with a space, and multi-byte characters are never split.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// CSS class for a row, grouping the gym's statuses the way the GUI colours them
+fn status_class(status: &str) -> &'static str {
+    match status {
+        "Bookable" => "bookable",
+        "Full" | "Awaitable" => "full",
+        "Booked" | "Awaiting" => "booked",
+        _ => "other",
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    fn class(id: u64, name: &str, day: u32, hour: u32, status: &str, trainer: Option<&str>) -> ClassInfo {
+        ClassInfo {
+            id,
+            name: name.to_string(),
+            start_time: Local.with_ymd_and_hms(2025, 3, day, hour, 0, 0).unwrap(),
+            status: status.to_string(),
+            trainer: trainer.map(str::to_string),
+            zone: "Studio".to_string(),
+            bookable_from: None,
+            duration_minutes: 45,
+            capacity: Some(20),
+            booked_count: Some(12),
+        }
+    }
+
+    fn booking(id: u64, name: &str, day: u32, status: &str, duration_minutes: u32) -> MyBooking {
+        MyBooking {
+            id,
+            name: name.to_string(),
+            start_time: Local.with_ymd_and_hms(2025, 3, day, 18, 30, 0).unwrap(),
+            status: status.to_string(),
+            waitlist_position: None,
+            trainer: Some("Anna".to_string()),
+            booked_count: None,
+            capacity: None,
+            bookable_from: None,
+            description: None,
+            zone: Some("Studio 1".to_string()),
+            duration_minutes,
+        }
+    }
+
+    #[test]
+    fn ical_has_an_event_per_confirmed_booking() {
+        let bookings = vec![
+            booking(70, "Spin, Rhythm & Ride", 3, "Booked", 45),
+            booking(71, "Yoga", 4, "Awaiting", 60),
+            booking(72, "Pump", 5, "Booked", 0),
+        ];
+        let stamp = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+
+        let ics = bookings_to_ical(&bookings, stamp);
+
+        assert!(ics.split("\r\n").all(|line| line.len() <= 75));
+        // Unfold continuation lines before reading properties back
+        let unfolded = ics.replace("\r\n ", "");
+        let lines: Vec<&str> = unfolded.split("\r\n").filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&"END:VCALENDAR"));
+        assert_eq!(lines.iter().filter(|l| **l == "BEGIN:VEVENT").count(), 2);
+        assert_eq!(lines.iter().filter(|l| **l == "END:VEVENT").count(), 2);
+
+        let value = |key: &str| -> Vec<&str> { lines.iter().filter_map(|l| l.strip_prefix(key)).collect() };
+        let starts = value("DTSTART:");
+        assert_eq!(starts.len(), 2);
+        for (start, booking) in starts.iter().zip([&bookings[0], &bookings[2]]) {
+            let parsed = chrono::NaiveDateTime::parse_from_str(start, "%Y%m%dT%H%M%SZ").unwrap().and_utc();
+            assert_eq!(parsed, booking.start_time.with_timezone(&Utc));
+        }
+        let ends = value("DTEND:");
+        let end = |i: usize| chrono::NaiveDateTime::parse_from_str(ends[i], "%Y%m%dT%H%M%SZ").unwrap().and_utc();
+        assert_eq!(end(0) - bookings[0].start_time.with_timezone(&Utc), Duration::minutes(45));
+        assert_eq!(end(1) - bookings[2].start_time.with_timezone(&Utc), Duration::minutes(60));
+
+        assert_eq!(value("SUMMARY:"), ["Spin\\, Rhythm & Ride", "Pump"]);
+        assert_eq!(value("DESCRIPTION:"), ["Trainer: Anna", "Trainer: Anna"]);
+        assert_eq!(value("DTSTAMP:"), ["20250301T120000Z", "20250301T120000Z"]);
+    }
+
+    #[test]
+    fn ical_folds_long_lines_without_splitting_characters() {
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let folded = fold(&line);
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn html_groups_classes_by_day() {
+        let classes = vec![
+            class(3, "Spin", 4, 18, "Full", None),
+            class(1, "Yoga & Stretch", 3, 9, "Bookable", Some("Anna")),
+            class(2, "Pilates", 3, 12, "Booked", Some("Ben")),
+        ];
+
+        let html = classes_to_html(&classes);
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("<script") && !html.contains("<link"));
+        let monday = html.find("<h2>Monday 03 March</h2>").expect("Monday header");
+        let tuesday = html.find("<h2>Tuesday 04 March</h2>").expect("Tuesday header");
+        assert!(monday < tuesday);
+
+        let yoga = html.find("<td>Yoga &amp; Stretch</td>").expect("escaped class name");
+        let pilates = html.find("<td>Pilates</td>").unwrap();
+        let spin = html.find("<td>Spin</td>").unwrap();
+        assert!(monday < yoga && yoga < pilates && pilates < tuesday && tuesday < spin);
+
+        assert!(html.contains(
+            "<tr class=\"bookable\"><td>09:00</td><td>Yoga &amp; Stretch</td><td>Anna</td><td>45 min</td><td>Bookable (12/20)</td></tr>"
+        ));
+        assert!(html.contains("<tr class=\"full\"><td>18:00</td><td>Spin</td><td>-</td>"));
+        assert_eq!(html.matches("<table>").count(), 2);
+    }
+}
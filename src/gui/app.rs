@@ -1,14 +1,18 @@
+use std::path::PathBuf;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::Instant;
 
 use eframe::egui;
 
 use crate::api::{ClassInfo, MyBooking};
-use crate::config::Config;
+use crate::config::{Config, GymConfig};
 use crate::gui::async_bridge::{run_async_bridge, Command, Response};
 use crate::gui::views::bookings::BookingsView;
+use crate::gui::views::details::DetailsPopup;
 use crate::gui::views::search::{SearchState, SearchView};
+use crate::gui::views::settings::{SettingsForm, SettingsView};
 use crate::gui::views::snipe_queue::SnipeQueueView;
+use crate::init::{self, Settings};
 use crate::snipe_queue::SnipeEntry;
 
 pub struct GymSniperApp {
@@ -17,21 +21,42 @@ pub struct GymSniperApp {
 
     bookings: Vec<MyBooking>,
     snipe_queue: Vec<SnipeEntry>,
+    /// Snipe whose attempt log is shown under the queue table
+    expanded_snipe: Option<u64>,
     search_results: Vec<ClassInfo>,
     search_state: SearchState,
+    /// Class details window, open after a class name is clicked
+    details_popup: Option<DetailsPopup>,
 
     loading: bool,
+    /// What the running command is doing, while `loading`
+    progress: Option<String>,
+    /// Whether the running command offers a Cancel button
+    cancellable: bool,
+    /// Session state reported by the async bridge; `None` until the first login finishes
+    connected: Option<bool>,
     status_message: Option<(String, bool)>, // (message, is_error)
     message_timer: f32,
     last_snipe_refresh: Instant,
+    /// Gym settings the views need, such as the booking window
+    gym: GymConfig,
+    settings_form: SettingsForm,
+    /// The config file the settings panel saves to
+    config_path: PathBuf,
+    /// Profile the settings panel edits the login of
+    profile: Option<String>,
 }
 
 impl GymSniperApp {
-    pub fn new(cc: &eframe::CreationContext<'_>, config: Config) -> Self {
+    /// `config_path` is the file `config` was loaded from, which the settings panel saves to
+    pub fn new(cc: &eframe::CreationContext<'_>, config: Config, config_path: PathBuf) -> Self {
         let (cmd_tx, cmd_rx) = channel();
         let (resp_tx, resp_rx) = channel();
 
         // Start the async bridge
+        let gym = config.gym.clone();
+        let settings_form = SettingsForm::new(&Settings::from_config(&config));
+        let profile = config.profile.clone();
         run_async_bridge(config, cmd_rx, resp_tx, cc.egui_ctx.clone());
 
         // Trigger initial data refresh
@@ -43,15 +68,53 @@ impl GymSniperApp {
             resp_rx,
             bookings: Vec::new(),
             snipe_queue: Vec::new(),
+            expanded_snipe: None,
             search_results: Vec::new(),
             search_state: SearchState {
                 days_offset: 7,
                 ..Default::default()
             },
+            details_popup: None,
             loading: false,
+            progress: None,
+            cancellable: false,
+            connected: None,
             status_message: None,
             message_timer: 0.0,
             last_snipe_refresh: Instant::now(),
+            gym,
+            settings_form,
+            config_path,
+            profile,
+        }
+    }
+
+    /// Check the settings form and write it to the config file, then have the bridge log
+    /// in with the new config. Problems are shown in the status bar.
+    fn save_settings(&mut self) {
+        let saved = match self.settings_form.to_settings() {
+            Ok(settings) => init::save_settings(&self.config_path, self.profile.as_deref(), &settings)
+                .map_err(|e| e.to_string()),
+            Err(problems) => Err(problems.join("; ")),
+        };
+        match saved {
+            Ok(config) => {
+                self.gym = config.gym.clone();
+                self.settings_form = SettingsForm::new(&Settings::from_config(&config));
+                let _ = self.cmd_tx.send(Command::Reconfigure(Box::new(config)));
+            }
+            Err(e) => {
+                self.status_message = Some((format!("Settings not saved: {}", e), true));
+                self.message_timer = 8.0;
+            }
+        }
+    }
+
+    /// Open the details popup for a clicked row and start loading its details
+    fn open_details(&mut self, popup: Option<DetailsPopup>) {
+        if let Some(popup) = popup {
+            let _ = self.cmd_tx.send(Command::FetchDetails(popup.class_id));
+            self.details_popup = Some(popup);
         }
     }
 
@@ -67,6 +130,13 @@ impl GymSniperApp {
                 Response::SearchResults(results) => {
                     self.search_results = results;
                 }
+                Response::DetailsLoaded(details) => {
+                    if let Some(popup) = self.details_popup.as_mut()
+                        && popup.class_id == details.id
+                    {
+                        popup.details = Some(details);
+                    }
+                }
                 Response::OperationSuccess(msg) => {
                     self.status_message = Some((msg, false));
                     self.message_timer = 5.0;
@@ -77,6 +147,16 @@ impl GymSniperApp {
                 }
                 Response::Loading(loading) => {
                     self.loading = loading;
+                    if !loading {
+                        self.progress = None;
+                    }
+                }
+                Response::Progress { message, cancellable } => {
+                    self.progress = Some(message);
+                    self.cancellable = cancellable;
+                }
+                Response::Connection(connected) => {
+                    self.connected = Some(connected);
                 }
             }
         }
@@ -105,6 +185,39 @@ impl eframe::App for GymSniperApp {
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            // Connection indicator and reconnect control
+            ui.horizontal(|ui| {
+                let (label, color) = match self.connected {
+                    Some(true) => ("Connected", egui::Color32::from_rgb(50, 180, 50)),
+                    Some(false) => ("Disconnected", egui::Color32::from_rgb(220, 50, 50)),
+                    None => ("Connecting...", egui::Color32::GRAY),
+                };
+                ui.colored_label(color, format!("● {}", label));
+
+                if ui
+                    .add_enabled(!self.loading, egui::Button::new("Reconnect"))
+                    .on_hover_text("Log in again with a fresh session")
+                    .clicked()
+                {
+                    let _ = self.cmd_tx.send(Command::Relogin);
+                }
+
+                // What the bridge is busy with, and a way to stop it
+                if self.loading {
+                    ui.separator();
+                    ui.spinner();
+                    if let Some(progress) = &self.progress {
+                        ui.label(progress);
+                    }
+                    if self.cancellable
+                        && ui.button("Cancel").on_hover_text("Stop the running request").clicked()
+                    {
+                        let _ = self.cmd_tx.send(Command::Cancel);
+                    }
+                }
+            });
+            ui.add_space(4.0);
+
             // Status bar at the top
             if let Some((ref msg, is_error)) = self.status_message {
                 let color = if is_error {
@@ -124,30 +237,47 @@ impl eframe::App for GymSniperApp {
 
             egui::ScrollArea::vertical().show(ui, |ui| {
                 // Confirmed Bookings section
-                ui.group(|ui| {
-                    BookingsView::show(ui, &self.bookings, self.loading, &self.cmd_tx);
-                });
+                let clicked = ui
+                    .group(|ui| BookingsView::show(ui, &self.bookings, self.loading, &self.cmd_tx))
+                    .inner;
+                self.open_details(clicked);
 
                 ui.add_space(16.0);
 
                 // Snipe Queue section
-                ui.group(|ui| {
-                    SnipeQueueView::show(ui, &self.snipe_queue, self.loading, &self.cmd_tx);
-                });
+                let clicked = ui
+                    .group(|ui| SnipeQueueView::show(ui, &self.snipe_queue, &mut self.expanded_snipe, self.loading, &self.cmd_tx))
+                    .inner;
+                self.open_details(clicked);
 
                 ui.add_space(16.0);
 
                 // Search section
-                ui.group(|ui| {
-                    SearchView::show(
-                        ui,
-                        &mut self.search_state,
-                        &self.search_results,
-                        self.loading,
-                        &self.cmd_tx,
-                    );
-                });
+                let clicked = ui
+                    .group(|ui| {
+                        SearchView::show(
+                            ui,
+                            &mut self.search_state,
+                            &self.search_results,
+                            self.loading,
+                            &self.cmd_tx,
+                        )
+                    })
+                    .inner;
+                self.open_details(clicked);
+
+                ui.add_space(16.0);
+
+                // Settings section
+                let save = ui
+                    .group(|ui| SettingsView::show(ui, &mut self.settings_form, self.loading))
+                    .inner;
+                if save {
+                    self.save_settings();
+                }
             });
         });
+
+        DetailsPopup::show(ctx, &mut self.details_popup, self.loading, &self.gym);
     }
 }
@@ -1,13 +1,16 @@
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 
 use eframe::egui;
 use tokio::runtime::Runtime;
+use tokio::sync::Notify;
 
-use crate::api::{ClassInfo, MyBooking, PerfectGymClient};
+use crate::api::{ClassInfo, MyBooking, PerfectGymClient, DEFAULT_BOOKINGS_DAYS};
 use crate::config::Config;
 use crate::snipe_queue::{SnipeEntry, SnipeQueue, SnipeStatus};
-use crate::util::booking_window;
+use crate::util::class_name_matches;
 
 /// Commands sent from GUI to async thread
 #[derive(Debug)]
@@ -22,7 +25,51 @@ pub enum Command {
     },
     AddToSnipeQueue(ClassInfo),
     RemoveFromSnipeQueue(u64),
-    CancelBooking(u64),
+    CancelBooking {
+        class_id: u64,
+        name: String,
+        start_time: chrono::DateTime<chrono::Local>,
+    },
+    /// Load full details of a class for the details popup
+    FetchDetails(u64),
+    /// Drop the current session and log in again
+    Relogin,
+    /// Log in again with settings saved from the settings panel
+    Reconfigure(Box<Config>),
+    /// Stop the command currently running, if it only reads. Takes effect straight away,
+    /// not after the commands queued before it.
+    Cancel,
+}
+
+impl Command {
+    /// Status line shown while the command runs
+    fn progress(&self) -> String {
+        match self {
+            Self::RefreshBookings => "Loading bookings...".to_string(),
+            Self::RefreshSnipeQueue => "Loading snipe queue...".to_string(),
+            Self::SearchClasses { days_offset, .. } => format!("Fetching classes for {} days...", days_offset + 7),
+            Self::AddToSnipeQueue(class) => format!("Adding {} to the snipe queue...", class.name),
+            Self::RemoveFromSnipeQueue(class_id) => format!("Removing class {} from the snipe queue...", class_id),
+            Self::CancelBooking { name, .. } => format!("Cancelling {}...", name),
+            Self::FetchDetails(_) => "Loading class details...".to_string(),
+            Self::Relogin => "Logging in...".to_string(),
+            Self::Reconfigure(_) => "Logging in with the new settings...".to_string(),
+            Self::Cancel => "Cancelling...".to_string(),
+        }
+    }
+
+    /// Whether `Cancel` may stop the command partway. Commands that book, cancel or change
+    /// the snipe queue always run to the end, so a cancel never leaves it unclear whether
+    /// they went through.
+    fn cancellable(&self) -> bool {
+        matches!(
+            self,
+            Self::RefreshBookings
+                | Self::RefreshSnipeQueue
+                | Self::SearchClasses { .. }
+                | Self::FetchDetails(_)
+        )
+    }
 }
 
 /// Responses sent from async thread to GUI
@@ -31,9 +78,15 @@ pub enum Response {
     BookingsLoaded(Vec<MyBooking>),
     SnipeQueueLoaded(Vec<SnipeEntry>),
     SearchResults(Vec<ClassInfo>),
+    DetailsLoaded(MyBooking),
     OperationSuccess(String),
     OperationError(String),
     Loading(bool),
+    /// What the running command is doing, and whether it can be cancelled; sent after
+    /// `Loading(true)`
+    Progress { message: String, cancellable: bool },
+    /// Whether we currently hold a logged-in session
+    Connection(bool),
 }
 
 /// Manages API client with automatic re-authentication on token expiration
@@ -50,8 +103,13 @@ impl ClientManager {
         }
     }
 
-    /// Get a valid client, logging in if necessary
+    /// Get a valid client, logging in if necessary or if the token is about to expire
     async fn get_client(&mut self) -> Result<&PerfectGymClient, String> {
+        if let Some(client) = &self.client
+            && client.refresh_token_if_due().await.is_err()
+        {
+            self.invalidate();
+        }
         if self.client.is_none() {
             self.login().await?;
         }
@@ -60,8 +118,8 @@ impl ClientManager {
 
     /// Force a fresh login
     async fn login(&mut self) -> Result<(), String> {
-        let client = PerfectGymClient::new(&self.config);
-        client.login()
+        let client = PerfectGymClient::new(&self.config).map_err(|e| format!("Login failed: {}", e))?;
+        client.ensure_session()
             .await
             .map_err(|e| format!("Login failed: {}", e))?;
         self.client = Some(client);
@@ -73,6 +131,11 @@ impl ClientManager {
         self.client = None;
     }
 
+    /// Whether a logged-in client is available
+    fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
     /// Execute an API call with automatic auth-retry on failure.
     /// Clones the client so the async block can own it without lifetime issues.
     async fn with_retry<T, F, Fut>(&mut self, f: F) -> Result<T, String>
@@ -93,6 +156,51 @@ impl ClientManager {
     }
 }
 
+/// Lets `Command::Cancel` stop the command in flight. Awaits inside a command are where
+/// it can stop; anything after that point is skipped.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Clear a cancel meant for an earlier command
+    fn reset(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    async fn cancelled(&self) {
+        loop {
+            // Registered before the check, so a cancel in between isn't missed
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Run `operation` to completion, or drop it at its next await once cancelled.
+    /// Returns `None` if it was cancelled.
+    pub async fn run<T>(&self, operation: impl Future<Output = T>) -> Option<T> {
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => None,
+            result = operation => Some(result),
+        }
+    }
+}
+
 /// Check if an error is an authentication error
 fn is_auth_error(error: &str) -> bool {
     error.contains("401")
@@ -101,6 +209,18 @@ fn is_auth_error(error: &str) -> bool {
         || error.contains("token")
 }
 
+/// The snipes the queue view lists, by class time. Failed snipes stay listed so their
+/// attempt logs can be read.
+fn listed_snipes(queue: SnipeQueue) -> Vec<SnipeEntry> {
+    let mut snipes: Vec<_> = queue
+        .snipes
+        .into_iter()
+        .filter(|s| s.status != SnipeStatus::Completed)
+        .collect();
+    snipes.sort_by_key(|s| (s.class_time, s.class_id));
+    snipes
+}
+
 /// Runs the async bridge in a background thread
 pub fn run_async_bridge(
     config: Config,
@@ -112,221 +232,332 @@ pub fn run_async_bridge(
         let rt = Runtime::new().expect("Failed to create tokio runtime");
 
         rt.block_on(async {
+            let matching = config.matching.clone();
+            let gym = config.gym.clone();
+            let profile = config.profile.clone();
+            let allow_multiple_per_day = config.snipe.allow_multiple_per_day;
             let mut manager = ClientManager::new(config);
 
+            // Commands are forwarded by their own thread so a Cancel reaches the token
+            // while another command is still running. Exits when the GUI drops its sender.
+            let cancel = CancelToken::default();
+            let (queue_tx, mut queue_rx) = tokio::sync::mpsc::unbounded_channel();
+            let forward_cancel = cancel.clone();
+            std::thread::spawn(move || {
+                while let Ok(cmd) = cmd_rx.recv() {
+                    match cmd {
+                        Command::Cancel => forward_cancel.cancel(),
+                        cmd => {
+                            if queue_tx.send(cmd).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+
             // Initial login
             if let Err(e) = manager.login().await {
                 let _ = resp_tx.send(Response::OperationError(e));
-                ctx.request_repaint();
             }
+            let _ = resp_tx.send(Response::Connection(manager.is_connected()));
+            ctx.request_repaint();
 
-            loop {
-                match cmd_rx.recv() {
-                    Ok(cmd) => {
-                        let _ = resp_tx.send(Response::Loading(true));
-                        ctx.request_repaint();
-
-                        match cmd {
-                            Command::RefreshBookings => {
-                                match manager.with_retry(|c| async move {
-                                    c.get_my_bookings().await.map_err(|e| e.to_string())
-                                }).await {
-                                    Ok(bookings) => {
-                                        let _ = resp_tx.send(Response::BookingsLoaded(bookings));
-                                    }
-                                    Err(e) => {
-                                        let _ = resp_tx.send(Response::OperationError(format!(
-                                            "Failed to load bookings: {}", e
-                                        )));
-                                    }
+            while let Some(cmd) = queue_rx.recv().await {
+                cancel.reset();
+                let progress = cmd.progress();
+                let cancellable = cmd.cancellable();
+                let _ = resp_tx.send(Response::Loading(true));
+                let _ = resp_tx.send(Response::Progress { message: progress.clone(), cancellable });
+                ctx.request_repaint();
+
+                let operation = async {
+                    match cmd {
+                        Command::RefreshBookings => {
+                            match manager.with_retry(|c| async move {
+                                c.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await.map_err(|e| e.to_string())
+                            }).await {
+                                Ok(bookings) => {
+                                    let _ = resp_tx.send(Response::BookingsLoaded(bookings));
                                 }
-                            }
-                            Command::RefreshSnipeQueue => {
-                                match SnipeQueue::load() {
-                                    Ok(queue) => {
-                                        let mut pending: Vec<_> = queue
-                                            .snipes
-                                            .into_iter()
-                                            .filter(|s| s.status == SnipeStatus::Pending)
-                                            .collect();
-                                        pending.sort_by_key(|s| s.class_time);
-                                        let _ = resp_tx.send(Response::SnipeQueueLoaded(pending));
-                                    }
-                                    Err(e) => {
-                                        let _ = resp_tx.send(Response::OperationError(format!(
-                                            "Failed to load snipe queue: {}",
-                                            e
-                                        )));
-                                    }
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Failed to load bookings: {}", e
+                                    )));
                                 }
                             }
-                            Command::SearchClasses {
-                                days_offset,
-                                time_filter,
-                                class_filter,
-                                trainer_filter,
-                            } => {
-                                let fetch_days = days_offset + 7;
-
-                                let classes = manager.with_retry(|c| async move {
-                                    c.get_weekly_classes(fetch_days).await.map_err(|e| e.to_string())
-                                }).await;
-
-                                if let Ok(classes) = classes {
-                                    let now = chrono::Local::now();
-                                    let target_date =
-                                        (now + chrono::Duration::days(days_offset as i64))
-                                            .date_naive();
-
-                                    let filtered: Vec<_> = classes
-                                        .into_iter()
-                                        .filter(|c| {
-                                            if c.start_time.date_naive() != target_date {
-                                                return false;
-                                            }
-                                            if let Some(ref time) = time_filter {
-                                                if !time.is_empty() {
-                                                    let class_time =
-                                                        c.start_time.format("%H:%M").to_string();
-                                                    if !class_time.starts_with(time) {
-                                                        return false;
-                                                    }
-                                                }
-                                            }
-                                            if let Some(ref class_name) = class_filter {
-                                                if !class_name.is_empty()
-                                                    && !c.name.to_lowercase().contains(&class_name.to_lowercase())
-                                                {
-                                                    return false;
-                                                }
-                                            }
-                                            if let Some(ref trainer) = trainer_filter {
-                                                if !trainer.is_empty() {
-                                                    if let Some(ref t) = c.trainer {
-                                                        if !t.to_lowercase().contains(&trainer.to_lowercase()) {
-                                                            return false;
-                                                        }
-                                                    } else {
-                                                        return false;
-                                                    }
-                                                }
-                                            }
-                                            true
-                                        })
-                                        .collect();
-
-                                    let _ = resp_tx.send(Response::SearchResults(filtered));
-                                } else if let Err(e) = classes {
+                        }
+                        Command::RefreshSnipeQueue => {
+                            match SnipeQueue::load_profile(profile.as_deref()) {
+                                Ok(queue) => {
+                                    let _ = resp_tx.send(Response::SnipeQueueLoaded(listed_snipes(queue)));
+                                }
+                                Err(e) => {
                                     let _ = resp_tx.send(Response::OperationError(format!(
-                                        "Search failed: {}", e
+                                        "Failed to load snipe queue: {}",
+                                        e
                                     )));
                                 }
                             }
-                            Command::AddToSnipeQueue(class_info) => {
-                                let bw = class_info.start_time - booking_window();
-
-                                let entry = SnipeEntry {
-                                    class_id: class_info.id,
-                                    class_name: class_info.name.clone(),
-                                    class_time: class_info.start_time,
-                                    booking_window: bw,
-                                    trainer: class_info.trainer.clone(),
-                                    added_at: chrono::Local::now(),
-                                    status: SnipeStatus::Pending,
-                                    error_message: None,
-                                };
-
-                                match SnipeQueue::load() {
-                                    Ok(mut queue) => match queue.add(entry) {
-                                        Ok(()) => {
-                                            let _ = resp_tx.send(Response::OperationSuccess(
-                                                format!("Added {} to snipe queue", class_info.name),
-                                            ));
-                                            let mut pending: Vec<_> = queue
-                                                .snipes
-                                                .into_iter()
-                                                .filter(|s| s.status == SnipeStatus::Pending)
-                                                .collect();
-                                            pending.sort_by_key(|s| s.class_time);
-                                            let _ = resp_tx.send(Response::SnipeQueueLoaded(pending));
-                                        }
-                                        Err(e) => {
-                                            let _ = resp_tx.send(Response::OperationError(
-                                                format!("Failed to add to queue: {}", e),
-                                            ));
+                        }
+                        Command::SearchClasses {
+                            days_offset,
+                            time_filter,
+                            class_filter,
+                            trainer_filter,
+                        } => {
+                            let fetch_days = days_offset + 7;
+
+                            let classes = manager.with_retry(|c| async move {
+                                c.get_weekly_classes(fetch_days, None).await.map_err(|e| e.to_string())
+                            }).await;
+
+                            if let Ok(classes) = classes {
+                                let now = chrono::Local::now();
+                                let target_date =
+                                    (now + chrono::Duration::days(days_offset as i64))
+                                        .date_naive();
+
+                                let filtered: Vec<_> = classes
+                                    .into_iter()
+                                    .filter(|c| {
+                                        if c.start_time.date_naive() != target_date {
+                                            return false;
                                         }
-                                    },
-                                    Err(e) => {
-                                        let _ = resp_tx.send(Response::OperationError(format!(
-                                            "Failed to load queue: {}",
-                                            e
-                                        )));
-                                    }
-                                }
-                            }
-                            Command::RemoveFromSnipeQueue(class_id) => {
-                                match SnipeQueue::load() {
-                                    Ok(mut queue) => match queue.remove(class_id) {
-                                        Ok(true) => {
-                                            let _ = resp_tx.send(Response::OperationSuccess(
-                                                format!("Removed class {} from queue", class_id),
-                                            ));
-                                            let mut pending: Vec<_> = queue
-                                                .snipes
-                                                .into_iter()
-                                                .filter(|s| s.status == SnipeStatus::Pending)
-                                                .collect();
-                                            pending.sort_by_key(|s| s.class_time);
-                                            let _ = resp_tx.send(Response::SnipeQueueLoaded(pending));
+                                        if let Some(ref time) = time_filter
+                                            && !time.is_empty()
+                                            && !c.start_time.format("%H:%M").to_string().starts_with(time)
+                                        {
+                                            return false;
                                         }
-                                        Ok(false) => {
-                                            let _ = resp_tx.send(Response::OperationError(
-                                                format!("Class {} not found in queue", class_id),
-                                            ));
+                                        if let Some(ref class_name) = class_filter
+                                            && !class_name.is_empty()
+                                            && !class_name_matches(&c.name, class_name, &matching)
+                                        {
+                                            return false;
                                         }
-                                        Err(e) => {
-                                            let _ = resp_tx.send(Response::OperationError(
-                                                format!("Failed to remove: {}", e),
-                                            ));
+                                        if let Some(ref trainer) = trainer_filter
+                                            && !trainer.is_empty()
+                                        {
+                                            match c.trainer {
+                                                Some(ref t) if t.to_lowercase().contains(&trainer.to_lowercase()) => {}
+                                                _ => return false,
+                                            }
                                         }
-                                    },
+                                        true
+                                    })
+                                    .collect();
+
+                                let _ = resp_tx.send(Response::SearchResults(filtered));
+                            } else if let Err(e) = classes {
+                                let _ = resp_tx.send(Response::OperationError(format!(
+                                    "Search failed: {}", e
+                                )));
+                            }
+                        }
+                        Command::AddToSnipeQueue(class_info) => {
+                            let entry = SnipeEntry::new(
+                                class_info.id,
+                                class_info.name.clone(),
+                                class_info.start_time,
+                                class_info.bookable_from,
+                                class_info.trainer.clone(),
+                                &gym,
+                            );
+
+                            match SnipeQueue::load_profile(profile.as_deref()) {
+                                Ok(mut queue) => match queue.add_with(entry, allow_multiple_per_day) {
+                                    Ok(()) => {
+                                        let _ = resp_tx.send(Response::OperationSuccess(
+                                            format!("Added {} to snipe queue", class_info.name),
+                                        ));
+                                        let _ = resp_tx.send(Response::SnipeQueueLoaded(listed_snipes(queue)));
+                                    }
                                     Err(e) => {
-                                        let _ = resp_tx.send(Response::OperationError(format!(
-                                            "Failed to load queue: {}",
-                                            e
-                                        )));
+                                        let _ = resp_tx.send(Response::OperationError(
+                                            format!("Failed to add to queue: {}", e),
+                                        ));
                                     }
+                                },
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Failed to load queue: {}",
+                                        e
+                                    )));
                                 }
                             }
-                            Command::CancelBooking(class_id) => {
-                                match manager.with_retry(|c| async move {
-                                    c.cancel_booking(class_id).await.map_err(|e| e.to_string())?;
-                                    c.get_my_bookings().await.map_err(|e| e.to_string())
-                                }).await {
-                                    Ok(bookings) => {
+                        }
+                        Command::RemoveFromSnipeQueue(class_id) => {
+                            match SnipeQueue::load_profile(profile.as_deref()) {
+                                Ok(mut queue) => match queue.remove(class_id) {
+                                    Ok(true) => {
                                         let _ = resp_tx.send(Response::OperationSuccess(
-                                            format!("Cancelled booking for class {}", class_id),
+                                            format!("Removed class {} from queue", class_id),
+                                        ));
+                                        let _ = resp_tx.send(Response::SnipeQueueLoaded(listed_snipes(queue)));
+                                    }
+                                    Ok(false) => {
+                                        let _ = resp_tx.send(Response::OperationError(
+                                            format!("Class {} not found in queue", class_id),
                                         ));
-                                        let _ = resp_tx.send(Response::BookingsLoaded(bookings));
                                     }
                                     Err(e) => {
-                                        let _ = resp_tx.send(Response::OperationError(format!(
-                                            "Failed to cancel booking: {}", e
-                                        )));
+                                        let _ = resp_tx.send(Response::OperationError(
+                                            format!("Failed to remove: {}", e),
+                                        ));
                                     }
+                                },
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Failed to load queue: {}",
+                                        e
+                                    )));
                                 }
                             }
                         }
-
-                        let _ = resp_tx.send(Response::Loading(false));
-                        ctx.request_repaint();
-                    }
-                    Err(_) => {
-                        // Channel closed, exit
-                        break;
+                        Command::CancelBooking { class_id, name, start_time } => {
+                            match manager.with_retry(|c| {
+                                let name = name.clone();
+                                async move {
+                                    c.cancel_booking_with_notice(class_id, &name, start_time, false)
+                                        .await
+                                        .map_err(|e| e.to_string())?;
+                                    c.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await.map_err(|e| e.to_string())
+                                }
+                            }).await {
+                                Ok(bookings) => {
+                                    let _ = resp_tx.send(Response::OperationSuccess(
+                                        format!("Cancelled booking for class {}", class_id),
+                                    ));
+                                    let _ = resp_tx.send(Response::BookingsLoaded(bookings));
+                                }
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Failed to cancel booking: {}", e
+                                    )));
+                                }
+                            }
+                        }
+                        Command::FetchDetails(class_id) => {
+                            match manager.with_retry(|c| async move {
+                                c.get_class_details(class_id).await.map_err(|e| e.to_string())
+                            }).await {
+                                Ok(details) => {
+                                    let _ = resp_tx.send(Response::DetailsLoaded(details));
+                                }
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Failed to load class details: {}", e
+                                    )));
+                                }
+                            }
+                        }
+                        Command::Relogin => {
+                            manager.invalidate();
+                            match manager.login().await {
+                                Ok(()) => {
+                                    let _ = resp_tx.send(Response::OperationSuccess(
+                                        "Reconnected".to_string(),
+                                    ));
+                                }
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Reconnect failed: {}", e
+                                    )));
+                                }
+                            }
+                        }
+                        Command::Reconfigure(config) => {
+                            manager = ClientManager::new(*config);
+                            match manager.login().await {
+                                Ok(()) => {
+                                    let _ = resp_tx.send(Response::OperationSuccess(
+                                        "Settings saved and logged in".to_string(),
+                                    ));
+                                }
+                                Err(e) => {
+                                    let _ = resp_tx.send(Response::OperationError(format!(
+                                        "Settings saved. {}", e
+                                    )));
+                                }
+                            }
+                        }
+                        // Handled by the forwarding thread
+                        Command::Cancel => {}
                     }
+                };
+                let cancelled = if cancellable {
+                    cancel.run(operation).await.is_none()
+                } else {
+                    operation.await;
+                    false
+                };
+
+                if cancelled {
+                    let _ = resp_tx.send(Response::OperationError(format!(
+                        "Cancelled: {}", progress.trim_end_matches("...")
+                    )));
                 }
+
+                let _ = resp_tx.send(Response::Connection(manager.is_connected()));
+                let _ = resp_tx.send(Response::Loading(false));
+                ctx.request_repaint();
             }
         });
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn cancel_stops_remaining_steps() {
+        let cancel = CancelToken::default();
+        let steps = AtomicU32::new(0);
+
+        let result = cancel
+            .run(async {
+                steps.fetch_add(1, Ordering::SeqCst);
+                cancel.cancel();
+                tokio::task::yield_now().await;
+                steps.fetch_add(1, Ordering::SeqCst);
+            })
+            .await;
+
+        assert!(result.is_none());
+        assert_eq!(steps.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_from_another_thread_interrupts_a_slow_step() {
+        let cancel = CancelToken::default();
+        let remote = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            remote.cancel();
+        });
+
+        let started = std::time::Instant::now();
+        let result = cancel.run(tokio::time::sleep(Duration::from_secs(30))).await;
+        assert!(result.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The next command starts with a clear token
+        cancel.reset();
+        assert_eq!(cancel.run(async { 7 }).await, Some(7));
+    }
+
+    #[test]
+    fn only_reads_can_be_cancelled() {
+        assert!(Command::RefreshBookings.cancellable());
+        assert!(Command::FetchDetails(1).cancellable());
+        let cancel_booking =
+            Command::CancelBooking { class_id: 1, name: "Spin".to_string(), start_time: chrono::Local::now() };
+        assert!(!cancel_booking.cancellable());
+        assert!(!Command::RemoveFromSnipeQueue(1).cancellable());
+        assert!(!Command::Relogin.cancellable());
+    }
+}
@@ -3,17 +3,19 @@ use egui_extras::{Column, TableBuilder};
 
 use crate::api::MyBooking;
 use crate::gui::async_bridge::Command;
-use crate::util::truncate;
+use crate::gui::views::details::DetailsPopup;
+use crate::util::{format_class_length, status_with_places, truncate};
 
 pub struct BookingsView;
 
 impl BookingsView {
+    /// Returns the popup to open when a class name was clicked
     pub fn show(
         ui: &mut Ui,
         bookings: &[MyBooking],
         loading: bool,
         cmd_tx: &std::sync::mpsc::Sender<Command>,
-    ) {
+    ) -> Option<DetailsPopup> {
         ui.horizontal(|ui| {
             ui.heading("CONFIRMED BOOKINGS");
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -33,7 +35,7 @@ impl BookingsView {
 
         if bookings.is_empty() {
             ui.label("No confirmed bookings found.");
-            return;
+            return None;
         }
 
         const MAX_ROWS: usize = 5;
@@ -41,6 +43,7 @@ impl BookingsView {
         const ROW_HEIGHT: f32 = 25.0;
 
         let needs_scroll = bookings.len() > MAX_ROWS;
+        let mut clicked = None;
 
         // Use unique ID to prevent scroll conflicts with other tables
         ui.push_id("bookings_table", |ui| {
@@ -52,6 +55,7 @@ impl BookingsView {
                 .column(Column::remainder().at_least(70.0)) // Class
                 .column(Column::auto().at_least(96.0)) // Trainer
                 .column(Column::auto().at_least(144.0)) // Class Time
+                .column(Column::auto().at_least(64.0)) // Duration
                 .column(Column::auto().at_least(80.0)) // Status
                 .column(Column::auto().at_least(60.0)); // Actions
 
@@ -74,6 +78,9 @@ impl BookingsView {
                 header.col(|ui| {
                     ui.strong("Class Time");
                 });
+                header.col(|ui| {
+                    ui.strong("Duration");
+                });
                 header.col(|ui| {
                     ui.strong("Status");
                 });
@@ -88,7 +95,17 @@ impl BookingsView {
                             ui.label(booking.id.to_string());
                         });
                         row.col(|ui| {
-                            ui.label(truncate(&booking.name, 25));
+                            // Keyed by class ID so widget state stays with its class across refreshes
+                            ui.push_id(booking.id, |ui| {
+                                let label = ui.add(egui::Label::new(truncate(&booking.name, 25)).sense(egui::Sense::click()));
+                                let label = match &booking.description {
+                                    Some(description) => label.on_hover_text(format!("{}\n\n{}", booking.name, description)),
+                                    None => label.on_hover_text(&booking.name),
+                                };
+                                if label.clicked() {
+                                    clicked = Some(DetailsPopup::for_booking(booking));
+                                }
+                            });
                         });
                         row.col(|ui| {
                             ui.label(
@@ -103,30 +120,36 @@ impl BookingsView {
                             ui.label(booking.start_time.format("%a %d %b %H:%M").to_string());
                         });
                         row.col(|ui| {
-                            let (status_text, color): (String, Color32) = match booking.status.as_str() {
-                                "Booked" => ("Booked".to_string(), Color32::GREEN),
-                                "Waitlist" => {
-                                    let pos = booking
-                                        .waitlist_position
-                                        .map(|p| format!("Waitlist #{}", p))
-                                        .unwrap_or_else(|| "Waitlist".to_string());
-                                    (pos, Color32::YELLOW)
-                                }
-                                _ => (booking.status.clone(), Color32::GRAY),
+                            ui.label(format_class_length(booking.duration_minutes));
+                        });
+                        row.col(|ui| {
+                            let color = match booking.status.as_str() {
+                                "Booked" => Color32::GREEN,
+                                "Awaiting" => Color32::YELLOW,
+                                _ => Color32::GRAY,
                             };
-                            ui.label(RichText::new(status_text).color(color));
+                            let status = status_with_places(&booking.status_label(), booking.booked_count, booking.capacity);
+                            ui.label(RichText::new(status).color(color));
                         });
                         row.col(|ui| {
-                            if ui
-                                .add_enabled(!loading, egui::Button::new("Cancel"))
-                                .clicked()
-                            {
-                                let _ = cmd_tx.send(Command::CancelBooking(booking.id));
-                            }
+                            ui.push_id(booking.id, |ui| {
+                                if ui
+                                    .add_enabled(!loading, egui::Button::new("Cancel"))
+                                    .clicked()
+                                {
+                                    let _ = cmd_tx.send(Command::CancelBooking {
+                                        class_id: booking.id,
+                                        name: booking.name.clone(),
+                                        start_time: booking.start_time,
+                                    });
+                                }
+                            });
                         });
                     });
                 }
             });
         });
+
+        clicked
     }
 }
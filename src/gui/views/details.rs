@@ -0,0 +1,125 @@
+use eframe::egui::{self, Color32, RichText};
+
+use crate::api::{ClassInfo, MyBooking};
+use crate::config::GymConfig;
+
+/// Full class info for a clicked row, shown in its own window
+pub struct DetailsPopup {
+    pub class_id: u64,
+    /// Zone and duration from the clicked row, which the details endpoint doesn't return
+    zone: Option<String>,
+    duration_minutes: u32,
+    /// Filled in once `Response::DetailsLoaded` arrives
+    pub details: Option<MyBooking>,
+}
+
+impl DetailsPopup {
+    pub fn for_class(class: &ClassInfo) -> Self {
+        Self {
+            class_id: class.id,
+            zone: Some(class.zone.clone()),
+            duration_minutes: class.duration_minutes,
+            details: None,
+        }
+    }
+
+    pub fn for_booking(booking: &MyBooking) -> Self {
+        Self {
+            class_id: booking.id,
+            zone: booking.zone.clone(),
+            duration_minutes: booking.duration_minutes,
+            details: None,
+        }
+    }
+
+    pub fn for_class_id(class_id: u64) -> Self {
+        Self { class_id, zone: None, duration_minutes: 0, details: None }
+    }
+
+    /// Show the popup. Clears `popup` when the user closes it.
+    pub fn show(ctx: &egui::Context, popup: &mut Option<Self>, loading: bool, gym: &GymConfig) {
+        let Some(state) = popup.as_ref() else {
+            return;
+        };
+
+        let mut open = true;
+        egui::Window::new(format!("Class {}", state.class_id))
+            .id(egui::Id::new("class_details_popup"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(360.0)
+            .show(ctx, |ui| match &state.details {
+                Some(details) => state.show_details(ui, details, gym),
+                None if loading => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Loading class details...");
+                    });
+                }
+                None => {
+                    ui.label("Class details could not be loaded.");
+                }
+            });
+
+        if !open {
+            *popup = None;
+        }
+    }
+
+    fn show_details(&self, ui: &mut egui::Ui, details: &MyBooking, gym: &GymConfig) {
+        ui.heading(&details.name);
+        ui.add_space(8.0);
+
+        egui::Grid::new("class_details_grid")
+            .num_columns(2)
+            .spacing([16.0, 4.0])
+            .show(ui, |ui| {
+                ui.strong("Trainer");
+                ui.label(details.trainer.as_deref().unwrap_or("-"));
+                ui.end_row();
+
+                ui.strong("Time");
+                ui.label(details.start_time.format("%a %d %b %Y %H:%M").to_string());
+                ui.end_row();
+
+                let duration = details.duration_minutes.max(self.duration_minutes);
+                ui.strong("Duration");
+                ui.label(if duration > 0 { format!("{} min", duration) } else { "-".to_string() });
+                ui.end_row();
+
+                ui.strong("Zone");
+                ui.label(details.zone.as_deref().or(self.zone.as_deref()).unwrap_or("-"));
+                ui.end_row();
+
+                ui.strong("Capacity");
+                ui.label(match (details.booked_count, details.capacity) {
+                    (Some(booked), Some(capacity)) => format!("{}/{} booked", booked, capacity),
+                    (Some(booked), None) => format!("{} booked", booked),
+                    _ => "-".to_string(),
+                });
+                ui.end_row();
+
+                ui.strong("Status");
+                let color = match details.status.as_str() {
+                    "Bookable" => Color32::GREEN,
+                    "Booked" => Color32::LIGHT_BLUE,
+                    "Awaiting" => Color32::YELLOW,
+                    "Full" => Color32::RED,
+                    _ => Color32::GRAY,
+                };
+                ui.label(RichText::new(details.status_label()).color(color));
+                ui.end_row();
+
+                ui.strong("Window opens");
+                ui.label(details.window_opens(gym).format("%a %d %b %H:%M").to_string());
+                ui.end_row();
+            });
+
+        if let Some(description) = &details.description {
+            ui.add_space(8.0);
+            ui.separator();
+            ui.label(description);
+        }
+    }
+}
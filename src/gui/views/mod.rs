@@ -1,3 +1,5 @@
 pub mod bookings;
+pub mod details;
 pub mod search;
+pub mod settings;
 pub mod snipe_queue;
@@ -3,7 +3,8 @@ use egui_extras::{Column, TableBuilder};
 
 use crate::api::ClassInfo;
 use crate::gui::async_bridge::Command;
-use crate::util::truncate;
+use crate::gui::views::details::DetailsPopup;
+use crate::util::{format_class_length, status_with_places, truncate};
 
 pub struct SearchView;
 
@@ -16,13 +17,14 @@ pub struct SearchState {
 }
 
 impl SearchView {
+    /// Returns the popup to open when a class name was clicked
     pub fn show(
         ui: &mut Ui,
         state: &mut SearchState,
         results: &[ClassInfo],
         loading: bool,
         cmd_tx: &std::sync::mpsc::Sender<Command>,
-    ) {
+    ) -> Option<DetailsPopup> {
         ui.heading("ADD FUTURE CLASS");
         ui.add_space(8.0);
 
@@ -107,7 +109,7 @@ impl SearchView {
 
         if results.is_empty() {
             ui.label("No results. Use the search form above to find classes.");
-            return;
+            return None;
         }
 
         const MAX_ROWS: usize = 10;
@@ -115,6 +117,7 @@ impl SearchView {
         const ROW_HEIGHT: f32 = 25.0;
 
         let needs_scroll = results.len() > MAX_ROWS;
+        let mut clicked = None;
 
         // Use unique ID to prevent scroll conflicts with other tables
         ui.push_id("search_results_table", |ui| {
@@ -125,7 +128,9 @@ impl SearchView {
                 .column(Column::auto().at_least(60.0)) // ID
                 .column(Column::remainder().at_least(70.0)) // Class
                 .column(Column::auto().at_least(96.0)) // Trainer
+                .column(Column::auto().at_least(80.0)) // Zone
                 .column(Column::auto().at_least(144.0)) // Class Time
+                .column(Column::auto().at_least(64.0)) // Duration
                 .column(Column::auto().at_least(80.0)) // Status
                 .column(Column::auto().at_least(60.0)); // Actions
 
@@ -145,9 +150,15 @@ impl SearchView {
                 header.col(|ui| {
                     ui.strong("Trainer");
                 });
+                header.col(|ui| {
+                    ui.strong("Zone");
+                });
                 header.col(|ui| {
                     ui.strong("Class Time");
                 });
+                header.col(|ui| {
+                    ui.strong("Duration");
+                });
                 header.col(|ui| {
                     ui.strong("Status");
                 });
@@ -162,7 +173,14 @@ impl SearchView {
                             ui.label(class.id.to_string());
                         });
                         row.col(|ui| {
-                            ui.label(truncate(&class.name, 25));
+                            ui.push_id(class.id, |ui| {
+                                let label = ui
+                                    .add(egui::Label::new(truncate(&class.name, 25)).sense(egui::Sense::click()))
+                                    .on_hover_text(format!("{}\n\nClick for details", class.name));
+                                if label.clicked() {
+                                    clicked = Some(DetailsPopup::for_class(class));
+                                }
+                            });
                         });
                         row.col(|ui| {
                             ui.label(
@@ -173,9 +191,15 @@ impl SearchView {
                                     .unwrap_or_else(|| "-".to_string()),
                             );
                         });
+                        row.col(|ui| {
+                            ui.label(truncate(&class.zone, 14)).on_hover_text(&class.zone);
+                        });
                         row.col(|ui| {
                             ui.label(class.start_time.format("%a %d %b %H:%M").to_string());
                         });
+                        row.col(|ui| {
+                            ui.label(format_class_length(class.duration_minutes));
+                        });
                         row.col(|ui| {
                             let color = match class.status.as_str() {
                                 "Bookable" => Color32::GREEN,
@@ -183,19 +207,24 @@ impl SearchView {
                                 "Booked" => Color32::LIGHT_BLUE,
                                 _ => Color32::GRAY,
                             };
-                            ui.label(RichText::new(&class.status).color(color));
+                            let status = status_with_places(&class.status, class.booked_count, class.capacity);
+                            ui.label(RichText::new(status).color(color));
                         });
                         row.col(|ui| {
-                            if ui
-                                .add_enabled(!loading, egui::Button::new("Add"))
-                                .clicked()
-                            {
-                                let _ = cmd_tx.send(Command::AddToSnipeQueue(class.clone()));
-                            }
+                            ui.push_id(class.id, |ui| {
+                                if ui
+                                    .add_enabled(!loading, egui::Button::new("Add"))
+                                    .clicked()
+                                {
+                                    let _ = cmd_tx.send(Command::AddToSnipeQueue(class.clone()));
+                                }
+                            });
                         });
                     });
                 }
             });
         });
+
+        clicked
     }
 }
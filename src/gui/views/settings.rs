@@ -0,0 +1,150 @@
+use eframe::egui::{self, Ui};
+
+use crate::init::{Settings, SmtpSettings};
+
+pub struct SettingsView;
+
+/// The settings panel's text fields. Password fields start empty and are only saved
+/// when something is typed into them.
+#[derive(Default)]
+pub struct SettingsForm {
+    pub base_url: String,
+    pub club_id: String,
+    pub email: String,
+    pub password: String,
+    pub smtp_enabled: bool,
+    pub smtp_server: String,
+    pub smtp_port: String,
+    pub smtp_username: String,
+    pub smtp_password: String,
+    pub smtp_from: String,
+    pub smtp_to: String,
+}
+
+impl SettingsForm {
+    pub fn new(settings: &Settings) -> Self {
+        let smtp = settings.smtp.clone().unwrap_or(SmtpSettings { port: 587, ..Default::default() });
+        Self {
+            base_url: settings.base_url.clone(),
+            club_id: if settings.club_id == 0 { String::new() } else { settings.club_id.to_string() },
+            email: settings.email.clone(),
+            password: String::new(),
+            smtp_enabled: settings.email_notifications,
+            smtp_server: smtp.server,
+            smtp_port: smtp.port.to_string(),
+            smtp_username: smtp.username,
+            smtp_password: String::new(),
+            smtp_from: smtp.from,
+            smtp_to: smtp.to,
+        }
+    }
+
+    /// The settings entered, or every required field that is missing or malformed
+    pub fn to_settings(&self) -> Result<Settings, Vec<String>> {
+        let mut problems = Vec::new();
+        let mut required = |value: &str, label: &str| {
+            if value.trim().is_empty() {
+                problems.push(format!("{} is required", label));
+            }
+            value.trim().to_string()
+        };
+
+        let base_url = required(&self.base_url, "Portal URL");
+        let email = required(&self.email, "Login email");
+        let smtp_fields = self.smtp_enabled.then(|| {
+            (
+                required(&self.smtp_server, "SMTP server"),
+                required(&self.smtp_username, "SMTP username"),
+                required(&self.smtp_from, "From address"),
+                required(&self.smtp_to, "To addresses"),
+            )
+        });
+
+        let club_id = match self.club_id.trim() {
+            "" => 0,
+            id => id.parse().unwrap_or_else(|_| {
+                problems.push("Club ID must be a number".to_string());
+                0
+            }),
+        };
+        let smtp = smtp_fields.map(|(server, username, from, to)| SmtpSettings {
+            server,
+            port: self.smtp_port.trim().parse().unwrap_or_else(|_| {
+                problems.push("SMTP port must be a number up to 65535".to_string());
+                0
+            }),
+            username,
+            password: non_empty(&self.smtp_password),
+            from,
+            to,
+        });
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        Ok(Settings {
+            base_url,
+            club_id,
+            email,
+            password: non_empty(&self.password),
+            email_notifications: self.smtp_enabled,
+            smtp,
+        })
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+impl SettingsView {
+    /// Returns true when Save was clicked
+    pub fn show(ui: &mut Ui, form: &mut SettingsForm, loading: bool) -> bool {
+        let mut save = false;
+
+        egui::CollapsingHeader::new(egui::RichText::new("SETTINGS").heading())
+            .id_salt("settings")
+            .show(ui, |ui| {
+                egui::Grid::new("settings_grid").num_columns(2).spacing([12.0, 6.0]).show(ui, |ui| {
+                    text_row(ui, "Portal URL:", &mut form.base_url, "https://.../clientportal2", false);
+                    text_row(ui, "Club ID:", &mut form.club_id, "Blank = your home club", false);
+                    text_row(ui, "Login email:", &mut form.email, "", false);
+                    text_row(ui, "Login password:", &mut form.password, "Leave blank to keep", true);
+
+                    ui.label("Email notifications:");
+                    ui.checkbox(&mut form.smtp_enabled, "");
+                    ui.end_row();
+
+                    if form.smtp_enabled {
+                        text_row(ui, "SMTP server:", &mut form.smtp_server, "", false);
+                        text_row(ui, "SMTP port:", &mut form.smtp_port, "587", false);
+                        text_row(ui, "SMTP username:", &mut form.smtp_username, "", false);
+                        text_row(ui, "SMTP password:", &mut form.smtp_password, "Leave blank to keep", true);
+                        text_row(ui, "From address:", &mut form.smtp_from, "", false);
+                        text_row(ui, "To addresses:", &mut form.smtp_to, "Comma-separated", false);
+                    }
+                });
+
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    save = ui
+                        .add_enabled(!loading, egui::Button::new("Save"))
+                        .on_hover_text("Write these settings to the config file and log in again.")
+                        .clicked();
+                });
+            });
+
+        save
+    }
+}
+
+fn text_row(ui: &mut Ui, label: &str, value: &mut String, hint: &str, password: bool) {
+    ui.label(label);
+    ui.add(
+        egui::TextEdit::singleline(value)
+            .hint_text(hint)
+            .password(password)
+            .desired_width(320.0),
+    );
+    ui.end_row();
+}
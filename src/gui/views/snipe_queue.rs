@@ -2,18 +2,22 @@ use eframe::egui::{self, Ui};
 use egui_extras::{Column, TableBuilder};
 
 use crate::gui::async_bridge::Command;
-use crate::snipe_queue::SnipeEntry;
-use crate::util::truncate;
+use crate::gui::views::details::DetailsPopup;
+use crate::snipe_queue::{SnipeEntry, SnipeStatus};
+use crate::util::{format_duration, truncate};
 
 pub struct SnipeQueueView;
 
 impl SnipeQueueView {
+    /// Returns the popup to open when a class name was clicked. Clicking a snipe's
+    /// attempt count shows or hides its attempt log under the table.
     pub fn show(
         ui: &mut Ui,
         snipes: &[SnipeEntry],
+        expanded: &mut Option<u64>,
         loading: bool,
         cmd_tx: &std::sync::mpsc::Sender<Command>,
-    ) {
+    ) -> Option<DetailsPopup> {
         ui.horizontal(|ui| {
             ui.heading("FUTURE BOOKINGS (Snipe Queue)");
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
@@ -33,7 +37,7 @@ impl SnipeQueueView {
 
         if snipes.is_empty() {
             ui.label("No classes in snipe queue.");
-            return;
+            return None;
         }
 
         const MAX_ROWS: usize = 5;
@@ -41,6 +45,13 @@ impl SnipeQueueView {
         const ROW_HEIGHT: f32 = 25.0;
 
         let needs_scroll = snipes.len() > MAX_ROWS;
+        let mut clicked = None;
+
+        // Keep the countdowns ticking while there is something to count down to
+        let now = chrono::Local::now();
+        if snipes.iter().any(|s| s.status == SnipeStatus::Pending) {
+            ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+        }
 
         // Use unique ID to prevent scroll conflicts with other tables
         ui.push_id("snipe_queue_table", |ui| {
@@ -53,6 +64,8 @@ impl SnipeQueueView {
                 .column(Column::auto().at_least(96.0)) // Trainer
                 .column(Column::auto().at_least(144.0)) // Class Time
                 .column(Column::auto().at_least(120.0)) // Window Opens
+                .column(Column::auto().at_least(100.0)) // Opens In
+                .column(Column::auto().at_least(70.0)) // Attempts
                 .column(Column::auto().at_least(60.0)); // Actions
 
             if needs_scroll {
@@ -77,6 +90,12 @@ impl SnipeQueueView {
                 header.col(|ui| {
                     ui.strong("Window Opens");
                 });
+                header.col(|ui| {
+                    ui.strong("Opens In");
+                });
+                header.col(|ui| {
+                    ui.strong("Attempts");
+                });
                 header.col(|ui| {
                     ui.strong("Actions");
                 });
@@ -88,7 +107,14 @@ impl SnipeQueueView {
                             ui.label(snipe.class_id.to_string());
                         });
                         row.col(|ui| {
-                            ui.label(truncate(&snipe.class_name, 25));
+                            ui.push_id(snipe.class_id, |ui| {
+                                let label = ui
+                                    .add(egui::Label::new(truncate(&snipe.class_name, 25)).sense(egui::Sense::click()))
+                                    .on_hover_text(format!("{}\n\nClick for details", snipe.class_name));
+                                if label.clicked() {
+                                    clicked = Some(DetailsPopup::for_class_id(snipe.class_id));
+                                }
+                            });
                         });
                         row.col(|ui| {
                             ui.label(
@@ -103,19 +129,69 @@ impl SnipeQueueView {
                             ui.label(snipe.class_time.format("%a %d %b %H:%M").to_string());
                         });
                         row.col(|ui| {
-                            ui.label(snipe.booking_window.format("%a %d %b %H:%M").to_string());
+                            if snipe.status == SnipeStatus::Failed {
+                                ui.colored_label(egui::Color32::RED, "Failed")
+                                    .on_hover_text(snipe.error_message.as_deref().unwrap_or("Failed"));
+                            } else if snipe.status == SnipeStatus::Waitlisted {
+                                let label = match snipe.waitlist_position {
+                                    Some(pos) => format!("Waitlist #{}", pos),
+                                    None => "Waitlist".to_string(),
+                                };
+                                ui.colored_label(egui::Color32::YELLOW, label)
+                                    .on_hover_text("On the waitlist; the daemon books a place if one opens");
+                            } else {
+                                ui.label(snipe.booking_window.format("%a %d %b %H:%M").to_string());
+                            }
+                        });
+                        row.col(|ui| {
+                            if snipe.status != SnipeStatus::Pending {
+                                ui.label("-");
+                            } else if snipe.booking_window > now {
+                                ui.label(format_duration(snipe.booking_window - now));
+                            } else {
+                                ui.colored_label(egui::Color32::RED, format!("{} ago", format_duration(now - snipe.booking_window)))
+                                    .on_hover_text("The window has opened but the snipe hasn't run - is the snipe daemon running?");
+                            }
                         });
                         row.col(|ui| {
-                            if ui
-                                .add_enabled(!loading, egui::Button::new("Remove"))
-                                .clicked()
-                            {
-                                let _ = cmd_tx.send(Command::RemoveFromSnipeQueue(snipe.class_id));
+                            if snipe.attempt_log.is_empty() {
+                                ui.label("-");
+                                return;
                             }
+                            let open = *expanded == Some(snipe.class_id);
+                            let text = format!("{} {}", snipe.attempts, if open { "▾" } else { "▸" });
+                            ui.push_id(("attempts", snipe.class_id), |ui| {
+                                let label = ui
+                                    .add(egui::Label::new(text).sense(egui::Sense::click()))
+                                    .on_hover_text("Click to show the attempt log");
+                                if label.clicked() {
+                                    *expanded = if open { None } else { Some(snipe.class_id) };
+                                }
+                            });
+                        });
+                        row.col(|ui| {
+                            ui.push_id(snipe.class_id, |ui| {
+                                if ui
+                                    .add_enabled(!loading, egui::Button::new("Remove"))
+                                    .clicked()
+                                {
+                                    let _ = cmd_tx.send(Command::RemoveFromSnipeQueue(snipe.class_id));
+                                }
+                            });
                         });
                     });
                 }
             });
         });
+
+        if let Some(snipe) = expanded.and_then(|id| snipes.iter().find(|s| s.class_id == id)) {
+            ui.add_space(8.0);
+            ui.strong(format!("Attempts for {}", snipe.class_name));
+            for (at, outcome) in &snipe.attempt_log {
+                ui.label(format!("{}  {}", at.format("%H:%M:%S%.3f"), outcome));
+            }
+        }
+
+        clicked
     }
 }
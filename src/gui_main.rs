@@ -38,8 +38,8 @@ fn load_icon() -> IconData {
                 }
 
                 // Crosshair lines
-                let on_vertical = dx.abs() < 1.2 && (dist < 24.0) && !(dist >= 6.0 && dist <= 10.0);
-                let on_horizontal = dy.abs() < 1.2 && (dist < 24.0) && !(dist >= 6.0 && dist <= 10.0);
+                let on_vertical = dx.abs() < 1.2 && (dist < 24.0) && !(6.0..=10.0).contains(&dist);
+                let on_horizontal = dy.abs() < 1.2 && (dist < 24.0) && !(6.0..=10.0).contains(&dist);
                 if on_vertical || on_horizontal {
                     rgba[idx] = 231;
                     rgba[idx + 1] = 76;
@@ -65,8 +65,10 @@ fn main() -> eframe::Result<()> {
         )
         .init();
 
-    // Load config
-    let config = Config::load("config.toml").expect("Failed to load config.toml");
+    // Load config, optionally for a profile given as `--profile <name>`
+    let profile = std::env::args().skip_while(|a| a != "--profile").nth(1);
+    let (config, sources) = Config::load_for_profile("config.toml", profile.as_deref())
+        .unwrap_or_else(|e| panic!("Failed to load config.toml: {}", e));
 
     // Run the GUI
     let options = eframe::NativeOptions {
@@ -82,6 +84,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "The Laboratory - Classes",
         options,
-        Box::new(|cc| Ok(Box::new(GymSniperApp::new(cc, config)))),
+        Box::new(|cc| Ok(Box::new(GymSniperApp::new(cc, config, sources.path)))),
     )
 }
@@ -0,0 +1,196 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::error::{GymSniperError, Result};
+use crate::snipe_queue::SnipeEntry;
+use crate::util::profile_file;
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// Most outcomes kept; older ones are dropped as new ones are appended
+pub const MAX_ENTRIES: usize = 5000;
+
+/// History file for a profile (`history.<profile>.jsonl`)
+pub fn history_path(profile: Option<&str>) -> PathBuf {
+    profile_file(HISTORY_FILE, profile)
+}
+
+/// How one queued snipe finally turned out. Unlike the queue entry, this is kept for good.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnipeOutcome {
+    pub class_id: u64,
+    pub class_name: String,
+    pub class_time: DateTime<Local>,
+    pub trainer: Option<String>,
+    pub booked: bool,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub recorded_at: DateTime<Local>,
+}
+
+impl SnipeOutcome {
+    /// Outcome of `entry`: booked unless there is an `error`
+    pub fn new(entry: &SnipeEntry, attempts: u32, error: Option<&str>, recorded_at: DateTime<Local>) -> Self {
+        Self {
+            class_id: entry.class_id,
+            class_name: entry.class_name.clone(),
+            class_time: entry.class_time,
+            trainer: entry.trainer.clone(),
+            booked: error.is_none(),
+            attempts,
+            error: error.map(str::to_string),
+            recorded_at,
+        }
+    }
+}
+
+/// Append an outcome to the history file, dropping the oldest once it holds more
+/// than `max_entries`
+pub fn record(path: &Path, outcome: &SnipeOutcome, max_entries: usize) -> Result<()> {
+    let line = serde_json::to_string(outcome).map_err(|e| {
+        GymSniperError::Config(format!("Failed to serialize snipe outcome: {}", e))
+    })?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)?;
+    drop(file);
+
+    let content = fs::read_to_string(path)?;
+    let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+    if lines.len() > max_entries {
+        let kept = &lines[lines.len() - max_entries..];
+        fs::write(path, format!("{}\n", kept.join("\n")))?;
+    }
+    Ok(())
+}
+
+/// Record the outcome of a queued snipe in the config's history file, if enabled. A failure
+/// to write is logged rather than returned, so it never holds up the snipe itself.
+pub fn record_outcome(config: &Config, entry: &SnipeEntry, attempts: u32, error: Option<&str>, at: DateTime<Local>) {
+    if !config.snipe.record_history {
+        return;
+    }
+    let outcome = SnipeOutcome::new(entry, attempts, error, at);
+    if let Err(e) = record(&history_path(config.profile.as_deref()), &outcome, MAX_ENTRIES) {
+        warn!("Could not record snipe outcome: {}", e);
+    }
+}
+
+/// Load all outcomes, skipping lines that fail to parse
+pub fn load(path: &Path) -> Result<Vec<SnipeOutcome>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Totals over a run of snipe outcomes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnipeStats {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Most often booked class and how many times, first seen winning ties
+    pub most_booked: Option<(String, usize)>,
+    /// Mean attempts among snipes that made any (those skipped beforehand are left out)
+    pub average_attempts: Option<f64>,
+}
+
+impl SnipeStats {
+    /// Share of snipes that booked, as a percentage
+    pub fn success_rate(&self) -> Option<f64> {
+        let total = self.succeeded + self.failed;
+        (total > 0).then(|| self.succeeded as f64 * 100.0 / total as f64)
+    }
+}
+
+pub fn stats(outcomes: &[SnipeOutcome]) -> SnipeStats {
+    let succeeded = outcomes.iter().filter(|o| o.booked).count();
+
+    let mut booked: Vec<(String, usize)> = Vec::new();
+    for outcome in outcomes.iter().filter(|o| o.booked) {
+        match booked.iter_mut().find(|(name, _)| *name == outcome.class_name) {
+            Some((_, count)) => *count += 1,
+            None => booked.push((outcome.class_name.clone(), 1)),
+        }
+    }
+    let most_booked = booked.into_iter().fold(None, |best: Option<(String, usize)>, (name, count)| match best {
+        Some((_, best_count)) if best_count >= count => best,
+        _ => Some((name, count)),
+    });
+
+    let attempted: Vec<u32> = outcomes.iter().map(|o| o.attempts).filter(|a| *a > 0).collect();
+    let average_attempts =
+        (!attempted.is_empty()).then(|| attempted.iter().sum::<u32>() as f64 / attempted.len() as f64);
+
+    SnipeStats {
+        succeeded,
+        failed: outcomes.len() - succeeded,
+        most_booked,
+        average_attempts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn outcome(name: &str, attempts: u32, error: Option<&str>) -> SnipeOutcome {
+        let class_time = Local.with_ymd_and_hms(2026, 3, 10, 7, 0, 0).unwrap();
+        SnipeOutcome {
+            class_id: 1,
+            class_name: name.to_string(),
+            class_time,
+            trainer: None,
+            booked: error.is_none(),
+            attempts,
+            error: error.map(str::to_string),
+            recorded_at: class_time,
+        }
+    }
+
+    #[test]
+    fn stats_total_outcomes() {
+        let outcomes = vec![
+            outcome("Yoga", 3, None),
+            outcome("Spin", 1, None),
+            outcome("Yoga", 5, None),
+            outcome("Spin", 0, Some("Already booked Yoga at 07:00 that day")),
+            outcome("Spin", 4, Some("Booking failed: class is full")),
+        ];
+        let totals = stats(&outcomes);
+        assert_eq!(totals.succeeded, 3);
+        assert_eq!(totals.failed, 2);
+        assert_eq!(totals.success_rate(), Some(60.0));
+        assert_eq!(totals.most_booked, Some(("Yoga".to_string(), 2)));
+        // The skipped snipe made no attempts, so it doesn't pull the average down
+        assert_eq!(totals.average_attempts, Some(13.0 / 4.0));
+
+        let empty = stats(&[]);
+        assert_eq!(empty.success_rate(), None);
+        assert_eq!(empty.most_booked, None);
+        assert_eq!(empty.average_attempts, None);
+    }
+
+    #[test]
+    fn record_keeps_only_the_newest_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(HISTORY_FILE);
+        for attempts in 1..=5 {
+            record(&path, &outcome("Yoga", attempts, None), 3).unwrap();
+        }
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.iter().map(|o| o.attempts).collect::<Vec<_>>(), [3, 4, 5]);
+    }
+}
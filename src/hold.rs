@@ -0,0 +1,268 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::api::{MyBooking, PerfectGymClient};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::defend;
+use crate::email::FailureExplanation;
+use crate::error::{BookingErrorKind, GymSniperError, Result};
+use crate::notify::{Notifier, Notifiers};
+use crate::util::{format_duration, profile_file};
+use crate::watch::poll_interval;
+
+pub const HOLDS_FILE: &str = "holds.json";
+
+/// File of the holds running for a profile (`holds.<profile>.json`)
+pub fn holds_path(profile: Option<&str>) -> PathBuf {
+    profile_file(HOLDS_FILE, profile)
+}
+
+/// A running `hold`, recorded so the defender doesn't re-book the classes it swaps and the
+/// daemon doesn't snipe the class it's watching
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActiveHold {
+    pub hold_id: u64,
+    pub prefer_id: u64,
+    /// When the hold gives up watching: whichever class starts first
+    pub until: DateTime<Local>,
+}
+
+impl ActiveHold {
+    /// Whether `class_id` is either side of this hold
+    pub fn involves(&self, class_id: u64) -> bool {
+        self.hold_id == class_id || self.prefer_id == class_id
+    }
+}
+
+/// Holds still running at `now`, or none if the file is missing or can't be read. One
+/// left behind by a hold that was killed is ignored once its classes start.
+pub fn load_active_holds(path: &Path, now: DateTime<Local>) -> Vec<ActiveHold> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Vec<ActiveHold>>(&content).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|h| h.until > now)
+        .collect()
+}
+
+/// Add `hold` to the file, replacing any earlier record of the same hold
+fn record_hold(path: &Path, hold: ActiveHold, now: DateTime<Local>) -> Result<()> {
+    let mut holds = load_active_holds(path, now);
+    holds.retain(|h| h.hold_id != hold.hold_id);
+    holds.push(hold);
+    save_holds(path, &holds)
+}
+
+/// Drop the record of the hold on `hold_id` once it has finished
+fn release_hold(path: &Path, hold_id: u64, now: DateTime<Local>) -> Result<()> {
+    let mut holds = load_active_holds(path, now);
+    holds.retain(|h| h.hold_id != hold_id);
+    save_holds(path, &holds)
+}
+
+fn save_holds(path: &Path, holds: &[ActiveHold]) -> Result<()> {
+    let json = serde_json::to_string_pretty(holds)
+        .map_err(|e| GymSniperError::Config(format!("Failed to serialize holds: {}", e)))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// How a soft hold ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum HoldOutcome {
+    /// The preferred class was booked and the hold released
+    Switched,
+    /// The preferred class never opened up before it started, so the hold was kept
+    KeptHold,
+    /// Booking the preferred class failed after releasing the hold, and the hold was re-booked
+    RolledBack { reason: String },
+    /// Booking the preferred class failed and the hold couldn't be re-booked either
+    Lost { reason: String },
+}
+
+/// Book `hold_id` as a backup, then watch `prefer_id` and switch to it if it opens up
+/// before either class starts. The hold is recorded in `holds_file` while it runs.
+pub async fn hold_and_prefer(
+    config: &Config,
+    client: &PerfectGymClient,
+    hold_id: u64,
+    prefer_id: u64,
+    holds_file: &Path,
+    clock: &impl Clock,
+) -> Result<HoldOutcome> {
+    let hold = client.get_class_details(hold_id).await?;
+    let preferred = client.get_class_details(prefer_id).await?;
+
+    match client.book_class(hold_id).await {
+        Ok(_) => info!("Holding {} at {}", hold.name, hold.start_time.format("%a %d %b %H:%M")),
+        Err(GymSniperError::Booking(BookingErrorKind::AlreadyBooked)) => {
+            info!("Already holding {}", hold.name)
+        }
+        Err(e) => return Err(e),
+    }
+
+    let deadline = hold.start_time.min(preferred.start_time);
+    let record = ActiveHold { hold_id, prefer_id, until: deadline };
+    if let Err(e) = record_hold(holds_file, record, clock.now()) {
+        warn!("Could not record the hold in {}: {}", holds_file.display(), e);
+    }
+    let outcome = watch_preferred(config, client, &hold, &preferred, deadline, clock).await;
+    if let Err(e) = release_hold(holds_file, hold_id, clock.now()) {
+        warn!("Could not clear the hold from {}: {}", holds_file.display(), e);
+    }
+    outcome
+}
+
+/// Poll the preferred class until it can be switched to or `deadline` passes
+async fn watch_preferred(
+    config: &Config,
+    client: &PerfectGymClient,
+    hold: &MyBooking,
+    preferred: &MyBooking,
+    deadline: DateTime<Local>,
+    clock: &impl Clock,
+) -> Result<HoldOutcome> {
+    let mut status = preferred.status.clone();
+    info!(
+        "Watching {} at {} (current status: {})",
+        preferred.name,
+        preferred.start_time.format("%a %d %b %H:%M"),
+        status
+    );
+
+    loop {
+        if status == "Bookable"
+            && let Some(outcome) = switch_to_preferred(config, client, hold, preferred).await?
+        {
+            return Ok(outcome);
+        }
+
+        let time_left = deadline.signed_duration_since(clock.now());
+        if time_left <= chrono::Duration::zero() {
+            info!("{} didn't open up in time - keeping {}", preferred.name, hold.name);
+            return Ok(HoldOutcome::KeptHold);
+        }
+
+        let interval = poll_interval(time_left).min(time_left);
+        info!("Checking {} again in {}...", preferred.name, format_duration(interval));
+        clock.sleep(interval.to_std().unwrap_or_default()).await;
+        client.refresh_token_if_due().await?;
+
+        let current = match client.get_class_details(preferred.id).await {
+            Ok(current) => current,
+            Err(e) => {
+                // Session may have expired during a long watch
+                info!("Status check failed ({}), logging in again...", e);
+                client.login().await?;
+                client.get_class_details(preferred.id).await?
+            }
+        };
+        if current.status != status {
+            info!("{} status changed: {} -> {}", preferred.name, status, current.status);
+        }
+        status = current.status;
+    }
+}
+
+/// Swap the hold for the preferred class. The preferred class is booked first; if that
+/// hits the daily limit, the hold is released to make room and re-booked should the
+/// preferred booking still fail. Returns `None` if the spot was taken before we got it.
+pub async fn switch_to_preferred(
+    config: &Config,
+    client: &PerfectGymClient,
+    hold: &MyBooking,
+    preferred: &MyBooking,
+) -> Result<Option<HoldOutcome>> {
+    match client.book_class(preferred.id).await {
+        Ok(_) => {
+            info!("Booked {} - releasing {}", preferred.name, hold.name);
+            if let Err(e) = client.cancel_booking_with_notice(hold.id, &hold.name, hold.start_time, false).await {
+                warn!("Booked {} but could not release {}: {}", preferred.name, hold.name, e);
+                let reason = format!("{} was booked but this hold could not be released: {}", preferred.name, e);
+                notify_failure(config, hold, &reason).await;
+            }
+            notify_success(config, preferred).await;
+            Ok(Some(HoldOutcome::Switched))
+        }
+        Err(GymSniperError::Booking(BookingErrorKind::Full)) => {
+            info!("{} filled up again before we could book it", preferred.name);
+            Ok(None)
+        }
+        Err(GymSniperError::Booking(BookingErrorKind::DailyLimit)) => {
+            info!("Daily limit reached - releasing {} to make room for {}", hold.name, preferred.name);
+            client.cancel_booking_with_notice(hold.id, &hold.name, hold.start_time, false).await?;
+
+            let reason = match client.book_class(preferred.id).await {
+                Ok(_) => {
+                    notify_success(config, preferred).await;
+                    return Ok(Some(HoldOutcome::Switched));
+                }
+                Err(e) => e.to_string(),
+            };
+
+            warn!("Booking {} failed ({}) - re-booking {}", preferred.name, reason, hold.name);
+            match client.book_class(hold.id).await {
+                Ok(_) => {
+                    // Releasing it noted a cancellation; the defender should guard it again
+                    if config.snipe.defend_bookings {
+                        let path = defend::cancelled_path(config.profile.as_deref());
+                        if let Err(e) = defend::forget_cancelled(&path, hold.id) {
+                            warn!("Could not update {}: {}", path.display(), e);
+                        }
+                    }
+                    Ok(Some(HoldOutcome::RolledBack { reason }))
+                }
+                Err(e) => {
+                    let reason = format!("{}; re-booking the hold failed: {}", reason, e);
+                    warn!("Lost both {} and {}: {}", preferred.name, hold.name, reason);
+                    let message = format!("The hold was released for {} but neither could be booked: {}", preferred.name, reason);
+                    notify_failure(config, hold, &message).await;
+                    Ok(Some(HoldOutcome::Lost { reason }))
+                }
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn notify_success(config: &Config, class: &MyBooking) {
+    let time_str = class.start_time.format("%a %d %b %H:%M").to_string();
+    Notifiers::new(config).notify_success(&class.name, &time_str, class.trainer.as_deref()).await;
+}
+
+async fn notify_failure(config: &Config, class: &MyBooking, reason: &str) {
+    let time_str = class.start_time.format("%a %d %b %H:%M").to_string();
+    let failure = FailureExplanation::other(reason);
+    Notifiers::new(config).notify_failure(&class.name, &time_str, class.trainer.as_deref(), &failure).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    #[test]
+    fn holds_are_recorded_until_released_or_over() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(HOLDS_FILE);
+        let now = Local.with_ymd_and_hms(2026, 3, 10, 8, 0, 0).unwrap();
+        let hold = |hold_id, hours| ActiveHold { hold_id, prefer_id: hold_id + 1, until: now + chrono::Duration::hours(hours) };
+
+        record_hold(&path, hold(1, 1), now).unwrap();
+        record_hold(&path, hold(3, 4), now).unwrap();
+        assert_eq!(load_active_holds(&path, now), [hold(1, 1), hold(3, 4)]);
+        assert!(load_active_holds(&path, now).iter().any(|h| h.involves(2)));
+
+        // One whose classes have started no longer counts, even if never released
+        assert_eq!(load_active_holds(&path, now + chrono::Duration::hours(2)), [hold(3, 4)]);
+
+        release_hold(&path, 3, now).unwrap();
+        assert_eq!(load_active_holds(&path, now), [hold(1, 1)]);
+    }
+}
@@ -0,0 +1,534 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+use crate::config::Config;
+use crate::error::{GymSniperError, Result};
+use crate::secrets;
+use crate::util;
+
+/// Ask for the settings a first config needs and return them as `config.toml` content.
+/// Answers are read line by line from `input`, so the wizard can be scripted; passwords
+/// come from `read_password`, given the prompt, so they needn't be echoed. The result is
+/// parsed back and validated before it is returned.
+pub fn prompt_config(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    mut read_password: impl FnMut(&str) -> io::Result<String>,
+) -> Result<String> {
+    writeln!(output, "Setting up gym_sniper. Press Enter to accept a [default].")?;
+
+    let base_url = ask(input, output, "Gym portal URL (ending in /clientportal2)", None)?;
+    let club_id = ask_number::<u32>(input, output, "Club ID", None)?;
+    let email = ask(input, output, "Login email", None)?;
+    let password = ask_password(output, "Login password", &mut read_password)?;
+
+    let mut doc = toml::Table::new();
+    doc.insert("gym".into(), table([("base_url", base_url.into()), ("club_id", i64::from(club_id).into())]));
+    doc.insert("credentials".into(), table([("email", email.into()), ("password", password.into())]));
+
+    if ask_yes_no(input, output, "Set up email notifications?")? {
+        let smtp_server = ask(input, output, "SMTP server", None)?;
+        let smtp_port = ask_number::<u16>(input, output, "SMTP port", Some("587"))?;
+        let username = ask(input, output, "SMTP username", None)?;
+        let smtp_password = ask_password(output, "SMTP password", &mut read_password)?;
+        let from = ask(input, output, "Send from address", Some(&username))?;
+        let to = ask(input, output, "Send to address", Some(&from))?;
+        doc.insert(
+            "email".into(),
+            table([
+                ("smtp_server", smtp_server.into()),
+                ("smtp_port", i64::from(smtp_port).into()),
+                ("username", username.into()),
+                ("password", smtp_password.into()),
+                ("from", from.into()),
+                ("to", to.into()),
+            ]),
+        );
+    }
+
+    let content = toml::to_string(&doc)
+        .map_err(|e| GymSniperError::Config(format!("Failed to serialize config: {}", e)))?;
+    let config: Config = toml::from_str(&content)?;
+    config.validate()?;
+    Ok(content)
+}
+
+/// Write generated config content to `path`, refusing to replace an existing file
+/// unless `force` is set. It holds passwords, so is readable by the current user only on Unix.
+pub fn write_config(path: &Path, content: &str, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(GymSniperError::Config(format!(
+            "{} already exists (use --force to overwrite)",
+            path.display()
+        )));
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // `mode` only applies to new files, so tighten one being overwritten too
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Write a detected `club_id` into the `[gym]` section of the config at `path`, keeping
+/// the rest of the file (comments included) as it is
+pub fn save_club_id(path: &Path, club_id: u32) -> Result<()> {
+    let content = fs::read_to_string(path)?;
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let gym = lines
+        .iter()
+        .position(|l| l.trim() == "[gym]")
+        .ok_or_else(|| GymSniperError::Config(format!("No [gym] section in {}", path.display())))?;
+    let section_end = lines[gym + 1..]
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .map_or(lines.len(), |i| gym + 1 + i);
+
+    let setting = format!("club_id = {}", club_id);
+    let existing = (gym + 1..section_end).find(|&i| {
+        lines[i].split('=').next().is_some_and(|key| key.trim() == "club_id")
+    });
+    match existing {
+        Some(i) => lines[i] = setting,
+        None => lines.insert(gym + 1, setting),
+    }
+
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    fs::write(path, updated)?;
+    Ok(())
+}
+
+/// Connection, login and notification settings, as edited in the GUI's settings panel
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    pub base_url: String,
+    /// 0 to use the member's home club
+    pub club_id: u32,
+    pub email: String,
+    /// New login password, or `None` to keep the current one (which may be in the keyring)
+    pub password: Option<String>,
+    /// Whether to send email notifications. Turning them off keeps the SMTP settings.
+    pub email_notifications: bool,
+    /// The SMTP settings; `None` when there are none, or when they're left as they are
+    /// because notifications are off
+    pub smtp: Option<SmtpSettings>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SmtpSettings {
+    pub server: String,
+    pub port: u16,
+    pub username: String,
+    /// New SMTP password, or `None` to keep the current one
+    pub password: Option<String>,
+    pub from: String,
+    /// Recipients, comma-separated
+    pub to: String,
+}
+
+impl Settings {
+    /// The settings in effect. Passwords are left out, so saving keeps them unless changed.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            base_url: config.gym.base_url.clone(),
+            club_id: config.gym.club_id,
+            email: config.credentials.email.clone(),
+            password: None,
+            email_notifications: config.email_notifications().is_some(),
+            smtp: config.email.as_ref().map(|email| SmtpSettings {
+                server: email.smtp_server.clone(),
+                port: email.smtp_port,
+                username: email.username.clone(),
+                password: None,
+                from: email.from.clone(),
+                to: email.to.addresses().join(", "),
+            }),
+        }
+    }
+}
+
+/// Write `settings` into the config at `path`; the login and club go to the `profile`
+/// entry when it is one of the file's `[[profiles]]`. The rest of the file, comments
+/// included, is kept as it is. A new login password goes to the keyring when the login
+/// has a `keyring_service`. The new file is checked before it replaces the old one, and
+/// the config as it now loads is returned.
+pub fn save_settings(path: &Path, profile: Option<&str>, settings: &Settings) -> Result<Config> {
+    let mut doc: DocumentMut = fs::read_to_string(path)?
+        .parse()
+        .map_err(|e| GymSniperError::Config(format!("Failed to parse {}: {}", path.display(), e)))?;
+
+    let gym = section(doc.as_table_mut(), "gym")?;
+    set(gym, "base_url", settings.base_url.as_str().into());
+
+    // The login and club live in the profile's entry when editing one of [[profiles]]
+    let in_profile = profile.is_some_and(|name| profile_entry(&mut doc, name).is_some());
+    let login: &mut dyn TableLike = match profile {
+        Some(name) if in_profile => profile_entry(&mut doc, name).expect("profile entry was found"),
+        _ => doc.as_table_mut(),
+    };
+    let club_owner: &mut dyn TableLike = if in_profile { &mut *login } else { section(login, "gym")? };
+    if settings.club_id == 0 {
+        club_owner.remove("club_id");
+    } else {
+        set(club_owner, "club_id", i64::from(settings.club_id).into());
+    }
+    let credentials = section(login, "credentials")?;
+    set(credentials, "email", settings.email.as_str().into());
+    let keyring_service = credentials
+        .get("keyring_service")
+        .and_then(Item::as_str)
+        .map(str::to_string);
+    if let Some(password) = &settings.password {
+        if keyring_service.is_some() {
+            // Stored once the new file checks out; a plaintext password would take precedence
+            credentials.remove("password");
+        } else {
+            set(credentials, "password", password.as_str().into());
+        }
+    }
+
+    if settings.email_notifications {
+        let smtp = settings
+            .smtp
+            .as_ref()
+            .ok_or_else(|| GymSniperError::Config("email: enter the SMTP settings".to_string()))?;
+        let email = section(doc.as_table_mut(), "email")?;
+        set(email, "smtp_server", smtp.server.as_str().into());
+        set(email, "smtp_port", i64::from(smtp.port).into());
+        set(email, "username", smtp.username.as_str().into());
+        if let Some(password) = &smtp.password {
+            set(email, "password", password.as_str().into());
+        } else if !email.contains_key("password") {
+            return Err(GymSniperError::Config("email: enter the SMTP password".to_string()));
+        }
+        set(email, "from", smtp.from.as_str().into());
+        let to: Vec<&str> = smtp.to.split(',').map(str::trim).filter(|a| !a.is_empty()).collect();
+        set(
+            email,
+            "to",
+            match to.as_slice() {
+                [one] => (*one).into(),
+                many => many.iter().copied().collect(),
+            },
+        );
+        email.remove("enabled");
+    } else if let Some(email) = doc.get_mut("email").and_then(Item::as_table_like_mut) {
+        // Keep the SMTP settings (password included) for turning notifications back on
+        set(email, "enabled", false.into());
+    }
+
+    let content = doc.to_string();
+    let config: Config = toml::from_str(&content)?;
+    config.validate()?;
+    if let (Some(service), Some(password)) = (&keyring_service, &settings.password) {
+        secrets::store_password(service, &settings.email, password)?;
+    }
+    util::replace_file(path, content.as_bytes(), true)?;
+
+    Ok(Config::load_for_profile(&path.to_string_lossy(), profile)?.0)
+}
+
+/// The `[[profiles]]` entry called `name`
+fn profile_entry<'a>(doc: &'a mut DocumentMut, name: &str) -> Option<&'a mut dyn TableLike> {
+    let profiles = doc.get_mut("profiles")?;
+    let entries: Vec<&mut dyn TableLike> = match profiles {
+        Item::ArrayOfTables(tables) => tables.iter_mut().map(|t| t as &mut dyn TableLike).collect(),
+        Item::Value(Value::Array(array)) => array
+            .iter_mut()
+            .filter_map(Value::as_inline_table_mut)
+            .map(|t| t as &mut dyn TableLike)
+            .collect(),
+        _ => return None,
+    };
+    entries
+        .into_iter()
+        .find(|p| p.get("name").and_then(Item::as_str) == Some(name))
+}
+
+/// The table `key` in `table`, created if missing
+fn section<'a>(table: &'a mut (dyn TableLike + '_), key: &str) -> Result<&'a mut dyn TableLike> {
+    table
+        .entry(key)
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| GymSniperError::Config(format!("'{}' in the config is not a section", key)))
+}
+
+/// Set `key` to `value`, keeping any comment beside the value it replaces
+fn set(table: &mut (dyn TableLike + '_), key: &str, mut value: Value) {
+    if let Some(old) = table.get(key).and_then(Item::as_value) {
+        *value.decor_mut() = old.decor().clone();
+    }
+    table.insert(key, Item::Value(value));
+}
+
+fn table<const N: usize>(entries: [(&str, toml::Value); N]) -> toml::Value {
+    toml::Value::Table(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+/// Prompt until a non-empty answer (or the default) is given
+fn ask(input: &mut impl BufRead, output: &mut impl Write, label: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(default) => write!(output, "{} [{}]: ", label, default)?,
+            None => write!(output, "{}: ", label)?,
+        }
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(GymSniperError::Config(format!("No answer given for {}", label)));
+        }
+        let answer = line.trim();
+        if !answer.is_empty() {
+            return Ok(answer.to_string());
+        }
+        if let Some(default) = default {
+            return Ok(default.to_string());
+        }
+        writeln!(output, "{} is required.", label)?;
+    }
+}
+
+/// Prompt for a password with `read_password` until a non-empty one is given
+fn ask_password(
+    output: &mut impl Write,
+    label: &str,
+    read_password: &mut impl FnMut(&str) -> io::Result<String>,
+) -> Result<String> {
+    loop {
+        let answer = read_password(&format!("{}: ", label))?;
+        let answer = answer.trim();
+        if !answer.is_empty() {
+            return Ok(answer.to_string());
+        }
+        writeln!(output, "{} is required.", label)?;
+    }
+}
+
+fn ask_number<T: std::str::FromStr>(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    label: &str,
+    default: Option<&str>,
+) -> Result<T> {
+    loop {
+        let answer = ask(input, output, label, default)?;
+        match answer.parse() {
+            Ok(n) => return Ok(n),
+            Err(_) => writeln!(output, "{} must be a number.", label)?,
+        }
+    }
+}
+
+/// Yes/no prompt, defaulting to no
+pub fn ask_yes_no(input: &mut impl BufRead, output: &mut impl Write, label: &str) -> Result<bool> {
+    let answer = ask(input, output, &format!("{} (y/N)", label), Some("n"))?;
+    Ok(matches!(answer.as_str(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn scripted_answers_write_a_loadable_config() {
+        let answers = [
+            "https://example.com/clientportal2",
+            "abc", // not a number, asked again
+            "42",
+            "",    // required, asked again
+            "me@example.com",
+            "y",
+            "smtp.example.com",
+            "", // default port
+            "alerts@example.com",
+            "", // from defaults to the username
+            "phone@example.com",
+        ]
+        .join("\n");
+        let mut passwords = vec!["hunter2", "", "app-password"].into_iter(); // "" is asked again
+        let mut prompted = Vec::new();
+        let read_password = |prompt: &str| {
+            prompted.push(prompt.to_string());
+            Ok(passwords.next().unwrap().to_string())
+        };
+        let mut output = Vec::new();
+        let content = prompt_config(&mut Cursor::new(answers), &mut output, read_password).unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        write_config(&path, &content, false).unwrap();
+        let loaded = Config::load(&path.to_string_lossy()).unwrap();
+
+        assert_eq!(loaded.gym.base_url, "https://example.com/clientportal2");
+        assert_eq!(loaded.gym.club_id, 42);
+        assert_eq!(loaded.credentials.email, "me@example.com");
+        assert_eq!(loaded.credentials.password, "hunter2");
+        let email = loaded.email.unwrap();
+        assert_eq!(email.smtp_port, 587);
+        assert_eq!(email.from, "alerts@example.com");
+        assert_eq!(email.to.addresses(), ["phone@example.com"]);
+        assert!(loaded.targets.is_empty());
+
+        let prompts = String::from_utf8(output).unwrap();
+        assert!(prompts.contains("Club ID must be a number."));
+        assert!(prompts.contains("Login email is required."));
+        assert!(prompts.contains("SMTP password is required."));
+        // Passwords are only ever read through `read_password`, never echoed
+        assert_eq!(prompted, ["Login password: ", "SMTP password: ", "SMTP password: "]);
+        assert!(!prompts.contains("hunter2") && !prompts.contains("app-password"));
+    }
+
+    #[test]
+    fn detected_club_id_is_saved_into_gym_section() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "# my gym\n[gym]\nbase_url = \"https://example.com/clientportal2\"\n\n[credentials]\nemail = \"me@example.com\"\npassword = \"pw\"\n";
+        fs::write(&path, original).unwrap();
+
+        save_club_id(&path, 42).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("# my gym\n[gym]\nclub_id = 42\n"));
+        assert_eq!(Config::load(&path.to_string_lossy()).unwrap().gym.club_id, 42);
+
+        // Saving again replaces the setting rather than adding another
+        save_club_id(&path, 7).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert_eq!(saved.matches("club_id").count(), 1);
+        assert_eq!(Config::load(&path.to_string_lossy()).unwrap().gym.club_id, 7);
+    }
+
+    #[test]
+    fn email_notifications_are_optional() {
+        let answers = "https://example.com/clientportal2\n7\nme@example.com\n\n";
+        let content = prompt_config(&mut Cursor::new(answers), &mut Vec::new(), |_| Ok("pw".to_string())).unwrap();
+        let config: Config = toml::from_str(&content).unwrap();
+        assert!(config.email.is_none());
+    }
+
+    #[test]
+    fn settings_are_saved_without_touching_the_rest_of_the_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "# my gym\n[gym]\nbase_url = \"https://old.example.com/clientportal2\" # the old site\nretry_attempts = 5\n\n[credentials]\nemail = \"old@example.com\"\npassword = \"pw\"\n\n[[targets]]\nclass_name = \"Yoga\" # Thursdays\n";
+        fs::write(&path, original).unwrap();
+
+        let config = Config::load(&path.to_string_lossy()).unwrap();
+        let mut settings = Settings::from_config(&config);
+        settings.base_url = "https://new.example.com/clientportal2".to_string();
+        settings.club_id = 42;
+        settings.email = "new@example.com".to_string();
+        settings.email_notifications = true;
+        settings.smtp = Some(SmtpSettings {
+            server: "smtp.example.com".to_string(),
+            port: 587,
+            username: "alerts@example.com".to_string(),
+            password: Some("app-password".to_string()),
+            from: "alerts@example.com".to_string(),
+            to: "me@example.com, partner@example.com".to_string(),
+        });
+
+        let saved = save_settings(&path, None, &settings).unwrap();
+        assert_eq!(saved.gym.base_url, "https://new.example.com/clientportal2");
+        assert_eq!(saved.gym.club_id, 42);
+        assert_eq!(saved.gym.retry_attempts, 5);
+        assert_eq!(saved.credentials.email, "new@example.com");
+        // No new password entered, so the old one is kept
+        assert_eq!(saved.credentials.password, "pw");
+        assert_eq!(saved.targets.len(), 1);
+        let email = saved.email.unwrap();
+        assert_eq!(email.password, "app-password");
+        assert_eq!(email.to.addresses(), ["me@example.com", "partner@example.com"]);
+
+        // Comments and the order of the file are kept
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# my gym\n[gym]\nbase_url = \"https://new.example.com/clientportal2\" # the old site\n"));
+        assert!(content.contains("class_name = \"Yoga\" # Thursdays"));
+        assert!(content.find("[[targets]]").unwrap() < content.find("[email]").unwrap());
+
+        // An invalid result leaves the file alone
+        settings.email.clear();
+        assert!(save_settings(&path, None, &settings).is_err());
+        assert_eq!(Config::load(&path.to_string_lossy()).unwrap().credentials.email, "new@example.com");
+    }
+
+    #[test]
+    fn turning_email_off_keeps_the_smtp_settings() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "[gym]\nbase_url = \"https://example.com/clientportal2\"\n\n[credentials]\nemail = \"me@example.com\"\npassword = \"pw\"\n\n[email]\nsmtp_server = \"smtp.example.com\"\nsmtp_port = 587\nusername = \"alerts@example.com\"\npassword = \"app-password\"\nfrom = \"alerts@example.com\"\nto = \"me@example.com\"\n";
+        fs::write(&path, original).unwrap();
+
+        let mut settings = Settings::from_config(&Config::load(&path.to_string_lossy()).unwrap());
+        assert!(settings.email_notifications);
+        settings.email_notifications = false;
+        settings.smtp = None;
+        let saved = save_settings(&path, None, &settings).unwrap();
+        assert!(saved.email_notifications().is_none());
+        assert_eq!(saved.email.as_ref().unwrap().password, "app-password");
+
+        // Turning them back on needs no password, as the old one is still there
+        let mut settings = Settings::from_config(&saved);
+        assert!(!settings.email_notifications);
+        assert_eq!(settings.smtp.as_ref().unwrap().server, "smtp.example.com");
+        settings.email_notifications = true;
+        let saved = save_settings(&path, None, &settings).unwrap();
+        assert_eq!(saved.email_notifications().unwrap().password, "app-password");
+        assert!(!fs::read_to_string(&path).unwrap().contains("enabled"));
+    }
+
+    #[test]
+    fn profile_settings_go_to_its_entry() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = "[gym]\nbase_url = \"https://example.com/clientportal2\"\nclub_id = 1\n\n[[profiles]]\nname = \"me\"\ncredentials = { email = \"me@example.com\", password = \"a\" }\n\n[[profiles]]\nname = \"partner\"\ncredentials = { email = \"partner@example.com\", password = \"b\" }\n";
+        fs::write(&path, original).unwrap();
+
+        let (config, _) = Config::load_for_profile(&path.to_string_lossy(), Some("partner")).unwrap();
+        let mut settings = Settings::from_config(&config);
+        settings.club_id = 7;
+        settings.password = Some("c".to_string());
+
+        let saved = save_settings(&path, Some("partner"), &settings).unwrap();
+        assert_eq!(saved.gym.club_id, 7);
+        assert_eq!(saved.credentials.password, "c");
+        let me = Config::load_for_profile(&path.to_string_lossy(), Some("me")).unwrap().0;
+        assert_eq!(me.gym.club_id, 1);
+        assert_eq!(me.credentials.password, "a");
+    }
+
+    #[test]
+    fn existing_config_is_not_overwritten_without_force() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "original").unwrap();
+
+        assert!(write_config(&path, "new", false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+
+        write_config(&path, "new", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
@@ -1,9 +1,29 @@
+pub mod accounts;
 pub mod api;
+pub mod clock;
 pub mod config;
+pub mod confirm;
+pub mod defend;
 pub mod email;
 pub mod error;
+pub mod export;
 pub mod gui;
+pub mod history;
+pub mod hold;
+pub mod init;
+pub mod logging;
+pub mod notify;
+pub mod rate_limit;
+pub mod redact;
+pub mod replay;
+pub mod report;
 pub mod scheduler;
+pub mod secrets;
+pub mod shutdown;
 pub mod snipe;
 pub mod snipe_queue;
+pub mod timing;
+pub mod token_cache;
 pub mod util;
+pub mod validate;
+pub mod watch;
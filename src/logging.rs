@@ -0,0 +1,76 @@
+use clap::ValueEnum;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::Subscriber;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// How log events are written to stderr
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per event, with the fields of enclosing spans, for log shippers
+    Json,
+}
+
+/// Install the global log subscriber. `RUST_LOG` is honoured, with `gym_sniper=info` added.
+pub fn init(format: LogFormat) {
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(env_filter()).with_writer(std::io::stderr).init(),
+        LogFormat::Json => tracing::subscriber::set_global_default(json_subscriber(std::io::stderr))
+            .expect("a log subscriber is already installed"),
+    }
+}
+
+/// JSON-lines subscriber writing to `make_writer`. Each line carries the event's fields,
+/// the current span and the full span list, so e.g. `class_id` is present on every
+/// event logged while booking a class.
+pub fn json_subscriber<W>(make_writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(env_filter())
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(make_writer)
+        .finish()
+}
+
+/// Log output collected in memory, for checking what was logged. Clones share the buffer,
+/// so one can be given to a subscriber as its writer and another read afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl LogBuffer {
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBuffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+fn env_filter() -> EnvFilter {
+    EnvFilter::from_default_env().add_directive("gym_sniper=info".parse().unwrap())
+}
@@ -1,13 +1,35 @@
-use clap::{Parser, Subcommand};
-use tracing::{error, info};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
+use tracing::{error, info, warn};
 
-use gym_sniper::api::PerfectGymClient;
-use gym_sniper::config::Config;
-use gym_sniper::error::Result;
+use gym_sniper::accounts;
+use gym_sniper::api::{MyBooking, PerfectGymClient, RawResponse, DEFAULT_BOOKINGS_DAYS};
+use gym_sniper::clock::SystemClock;
+use gym_sniper::config::{ClassTarget, Config, ConfigSources, OnFull};
+use gym_sniper::error::{GymSniperError, Result};
+use gym_sniper::export::{self, ExportFormat};
+use gym_sniper::history;
+use gym_sniper::hold::{self, HoldOutcome};
+use gym_sniper::init;
+use gym_sniper::logging::{self, LogFormat};
+use gym_sniper::notify::{Notifiers, TestSample};
+use gym_sniper::redact;
+use gym_sniper::replay;
+use gym_sniper::report::ReportDocument;
 use gym_sniper::scheduler;
+use gym_sniper::secrets;
+use gym_sniper::shutdown::Shutdown;
 use gym_sniper::snipe;
 use gym_sniper::snipe_queue::{SnipeEntry, SnipeQueue, SnipeStatus};
-use gym_sniper::util::{booking_window, truncate};
+use gym_sniper::timing;
+use gym_sniper::token_cache;
+use gym_sniper::util::{
+    booking_window, bookings_matching, format_class_length, format_duration, profile_file, single_match, status_with_places,
+    summarize_classes, trainer_matches, truncate,
+};
+use gym_sniper::validate;
+use gym_sniper::watch::{self, WaitlistCheck};
 
 #[derive(Parser)]
 #[command(name = "gym_sniper")]
@@ -17,17 +39,82 @@ struct Cli {
     #[arg(short, long, default_value = "config.toml")]
     config: String,
 
+    /// Profile name: loads config.<name>.toml, or the [[profiles]] entry of that name, and
+    /// keeps its own snipes.<name>.json
+    #[arg(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Log output format: human-readable text, or one JSON object per line
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+
+    /// Output format: table (default) or json for `list` and `bookings`, html (default) or
+    /// ical for `export`
+    #[arg(long, value_enum, global = true)]
+    format: Option<OutputFormat>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// How a command prints its results: `list` and `bookings` as a table or JSON, `export`
+/// as a web page or calendar file
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// Aligned columns for reading at a terminal
+    Table,
+    /// A pretty-printed JSON array, times as RFC 3339
+    Json,
+    /// A self-contained web page of the timetable, one table per day (`export`)
+    Html,
+    /// An iCalendar (.ics) file of your confirmed bookings, for phone calendars (`export`)
+    Ical,
+}
+
+impl OutputFormat {
+    /// Whether `list` or `bookings` should print JSON rather than a table
+    fn json(format: Option<Self>) -> Result<bool> {
+        match format {
+            None | Some(Self::Table) => Ok(false),
+            Some(Self::Json) => Ok(true),
+            Some(other) => Err(other.unsupported("export")),
+        }
+    }
+
+    /// The file format for `export`
+    fn export(format: Option<Self>) -> Result<ExportFormat> {
+        match format {
+            None | Some(Self::Html) => Ok(ExportFormat::Html),
+            Some(Self::Ical) => Ok(ExportFormat::Ical),
+            Some(other) => Err(other.unsupported("list and bookings")),
+        }
+    }
+
+    fn unsupported(self, commands: &str) -> GymSniperError {
+        let name = self.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+        GymSniperError::Config(format!("--format {} only applies to {}", name, commands))
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
+    /// Create a config file by answering a few questions, testing the login before saving
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
     /// List available classes
     List {
         /// Number of days to show (default: 7)
         #[arg(short, long, default_value = "7")]
         days: u32,
+        /// Only show classes in this category ID (default: gym.default_category)
+        #[arg(long)]
+        category: Option<u32>,
+        /// Only show classes in a studio/zone whose name contains this (case-insensitive)
+        #[arg(long)]
+        zone: Option<String>,
     },
     /// Search classes by trainer name
     Trainer {
@@ -36,6 +123,15 @@ enum Commands {
         /// Number of days to search (default: 28)
         #[arg(short, long, default_value = "28")]
         days: u32,
+        /// Only search classes in this category ID (default: gym.default_category)
+        #[arg(long)]
+        category: Option<u32>,
+    },
+    /// List the club's class categories, for picking a --category / default_category ID
+    Categories {
+        /// Show subcategories indented under their parents
+        #[arg(long)]
+        tree: bool,
     },
     /// List classes not yet bookable (booking window not open)
     Upcoming {
@@ -43,82 +139,723 @@ enum Commands {
         #[arg(short, long, default_value = "7")]
         days: u32,
     },
+    /// Export the class timetable as a web page, or your bookings as a calendar file
+    Export {
+        /// Number of days to include (default: 7)
+        #[arg(short, long, default_value = "7")]
+        days: u32,
+        /// Only include classes in this category ID (default: gym.default_category)
+        #[arg(long)]
+        category: Option<u32>,
+        /// File to write (default: standard output)
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Book a class by name, weekday and start time, for when you don't know its ID
+    BookMatch {
+        /// Class name (partial match, normalized like targets)
+        class_name: String,
+        /// Day of the week, e.g. mon or monday
+        day: String,
+        /// Start time, e.g. 18:30
+        time: String,
+    },
     /// Book a specific class by ID
     Book {
         /// Class ID to book
         class_id: u64,
+        /// Spot ID to book (e.g. a numbered bike), for classes with spot selection
+        #[arg(long)]
+        spot: Option<u64>,
+    },
+    /// Print the exact BookClass request for a class without sending it
+    PreviewBook {
+        /// Class ID to preview
+        class_id: u64,
+    },
+    /// Re-send the last failed booking request now and compare the responses (books the class if it succeeds)
+    Replay,
+    /// Show full details for a class, including its description
+    Details {
+        /// Class ID to show
+        class_id: u64,
     },
     /// Show your booked and waitlisted classes
-    Bookings,
+    Bookings {
+        /// Number of days ahead to scan for bookings when the portal has no bookings list (default: 14)
+        #[arg(short, long, default_value_t = DEFAULT_BOOKINGS_DAYS)]
+        days: u32,
+    },
+    /// Summarize your bookings, the snipe queue and the countdown to the next booking window
+    Status,
+    /// List the classes you're waitlisted for, with your position
+    Waitlists {
+        /// Number of days ahead to look
+        #[arg(short, long, default_value_t = DEFAULT_BOOKINGS_DAYS)]
+        days: u32,
+    },
+    /// Leave the waitlist for a class (refuses confirmed bookings)
+    LeaveWaitlist {
+        /// Class ID to leave the waitlist for
+        class_id: u64,
+    },
+    /// Cancel a booking by class ID, or all of your bookings with --all
+    Cancel {
+        /// Class ID to cancel
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        class_id: Option<u64>,
+        /// Cancel every booking and waitlist place you have
+        #[arg(long)]
+        all: bool,
+        /// Don't ask for confirmation (with --all)
+        #[arg(short, long)]
+        yes: bool,
+        /// Cancel even classes inside `min_cancel_notice_hours`
+        #[arg(long)]
+        force: bool,
+    },
+    /// Cancel all bookings whose class name matches a pattern
+    CancelMatching {
+        /// Class name to match (partial, case-insensitive)
+        name_pattern: String,
+        /// Only cancel classes within this many days (default: 7)
+        #[arg(short, long, default_value = "7")]
+        days: u32,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Cancel even classes inside `min_cancel_notice_hours`
+        #[arg(long)]
+        force: bool,
+    },
     /// Snipe a class - wait for booking window and book immediately (single class)
     Snipe {
         /// Class ID to snipe
         class_id: u64,
+        /// Spot ID to book (e.g. a numbered bike), for classes with spot selection
+        #[arg(long)]
+        spot: Option<u64>,
+        /// If the class is already full, join the waitlist once instead of retrying
+        #[arg(long)]
+        waitlist_immediately: bool,
+        /// Also book the class for these [[accounts]] (comma-separated names)
+        #[arg(long, value_delimiter = ',')]
+        accounts: Vec<String>,
     },
-    /// Add a class to the snipe queue
+    /// Watch a full class and notify when a spot opens (does not book)
+    Watch {
+        /// Class ID to watch
+        class_id: u64,
+    },
+    /// Watch a class you're waitlisted for, logging position changes and booking at once if a spot opens
+    WatchWaitlist {
+        /// Class ID you're waitlisted for
+        class_id: u64,
+    },
+    /// Book a backup class now and switch to a preferred (full) class if it opens up
+    Hold {
+        /// Class ID to book as the backup
+        class_id: u64,
+        /// Class ID to switch to if a spot opens
+        #[arg(long)]
+        prefer: u64,
+    },
+    /// Manage the snipe queue run by `snipe-daemon`
+    Queue {
+        #[command(subcommand)]
+        action: QueueCommand,
+    },
+    /// Same as `queue add`
+    #[command(hide = true)]
     SnipeAdd {
-        /// Class ID to add
         class_id: u64,
     },
-    /// Remove a class from the snipe queue
+    /// Same as `queue remove`
+    #[command(hide = true)]
     SnipeRemove {
-        /// Class ID to remove
         class_id: u64,
     },
-    /// List all queued snipes
+    /// Same as `queue list`
+    #[command(hide = true)]
     Snipes,
+    /// Same as `queue compact`
+    #[command(hide = true)]
+    QueueCompact,
     /// Run the snipe daemon to automatically snipe all queued classes
     SnipeDaemon,
+    /// Estimate when booking windows really open from recorded snipe timings
+    EstimateWindow,
+    /// Summarize past snipe outcomes: bookings made and missed, success rate and attempts
+    Stats,
     /// Run the scheduler to auto-book configured classes
-    Schedule,
+    Schedule {
+        /// Run a scheduler for every [[profiles]] entry at once
+        #[arg(long)]
+        all_profiles: bool,
+        /// Make a single pass, booking classes whose window is already open, then exit
+        /// (non-zero if any booking failed). For running from cron.
+        #[arg(long)]
+        once: bool,
+    },
     /// Test login credentials
-    Login,
+    Login {
+        /// Save the login token (to `token.json`) so later commands reuse it until it
+        /// expires instead of logging in every time
+        #[arg(long)]
+        save_token: bool,
+    },
+    /// Delete the login token saved by `login --save-token`
+    Logout,
+    /// Prompt for the login password and store it in the system keyring under
+    /// `credentials.keyring_service`
+    SetPassword,
+    /// Print the effective configuration (passwords redacted) and where it came from
+    ConfigShow {
+        /// Print as JSON instead of TOML
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a JSON report of config (redacted), bookings, snipe queue and snipe history
+    Report,
+    /// Check the config for mistakes that would only show up at runtime
+    Validate {
+        /// Skip checking that the gym's portal answers
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Send a sample booking success and failure through every configured notification
+    /// channel (email, webhook) to check they work
+    TestNotify,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("gym_sniper=info".parse().unwrap()),
-        )
-        .init();
+#[derive(Subcommand)]
+enum QueueCommand {
+    /// Add a class to the snipe queue
+    Add {
+        /// Class ID to add
+        class_id: u64,
+        /// With snipe.allow_multiple_per_day, higher priorities are tried first on the same day;
+        /// lower ones are backups, only tried if a higher one hits the daily limit
+        #[arg(long, default_value_t = 0)]
+        priority: u8,
+        /// Only book while the trainer's name contains this (case-insensitive), so a
+        /// substitute isn't booked
+        #[arg(long)]
+        trainer: Option<String>,
+    },
+    /// Remove a class from the snipe queue
+    Remove {
+        /// Class ID to remove
+        class_id: u64,
+    },
+    /// List all queued snipes
+    List,
+    /// Remove all completed/failed entries from the snipe queue file
+    Compact,
+}
 
+#[tokio::main]
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    logging::init(cli.log_format);
 
-    let config = Config::load(&cli.config)?;
-    let client = PerfectGymClient::new(&config);
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-    match cli.command {
-        Commands::Login => {
-            info!("Testing login...");
+fn print_response(label: &str, response: &RawResponse, redact_logs: bool) {
+    println!("\n--- {} ---", label);
+    println!("Status: {}", response.status);
+    for (name, value) in &response.headers {
+        let value = if redact_logs { redact::header_value(name, value) } else { value.clone() };
+        println!("{}: {}", name, value);
+    }
+    println!();
+    println!("{}", response.body);
+}
+
+/// List bookings, ask before cancelling them unless `yes`, then cancel each, reporting
+/// any that fail or fall inside the cancel notice. Returns the first such error, once
+/// the rest have been tried.
+async fn cancel_bookings(client: &PerfectGymClient, bookings: &[&MyBooking], yes: bool, force: bool) -> Result<()> {
+    println!("\nBookings to cancel:");
+    for booking in bookings {
+        println!(
+            "  {:<8} {:<25} {:<20} {}",
+            booking.id,
+            truncate(&booking.name, 23),
+            booking.start_time.format("%a %d %b %H:%M"),
+            booking.status_label()
+        );
+    }
+
+    if !yes {
+        print!("\nCancel {} booking(s)? [y/N] ", bookings.len());
+        std::io::stdout().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            println!("Nothing cancelled.");
+            return Ok(());
+        }
+    }
+
+    let mut cancelled = 0;
+    let mut first_error = None;
+    for booking in bookings {
+        match client.cancel_booking_with_notice(booking.id, &booking.name, booking.start_time, force).await {
+            Ok(()) => {
+                cancelled += 1;
+                info!("Cancelled {} at {}", booking.name, booking.start_time.format("%a %d %b %H:%M"));
+            }
+            Err(e) => {
+                match &e {
+                    GymSniperError::CancelRefused(_) => error!("{} - use --force to cancel anyway", e),
+                    _ => error!("Failed to cancel {} (class ID {}): {}", booking.name, booking.id, e),
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    println!("\nCancelled {} of {} booking(s).", cancelled, bookings.len());
+    first_error.map_or(Ok(()), Err)
+}
+
+async fn run_init(path: &std::path::Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(GymSniperError::Config(format!(
+            "{} already exists (use --force to overwrite)",
+            path.display()
+        )));
+    }
+
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout();
+    let content = init::prompt_config(&mut stdin, &mut stdout, |prompt| rpassword::prompt_password(prompt))?;
+
+    let config: Config = toml::from_str(&content)?;
+    println!("Testing login...");
+    let login = match PerfectGymClient::new(&config) {
+        Ok(client) => client.login_fresh().await,
+        Err(e) => Err(e),
+    };
+    match login {
+        Ok(()) => println!("Login successful!"),
+        Err(e) => {
+            println!("Login failed: {}", e);
+            if !init::ask_yes_no(&mut stdin, &mut stdout, "Save the config anyway?")? {
+                return Err(e);
+            }
+        }
+    }
+
+    init::write_config(path, &content, force)?;
+    println!("Wrote {}. Add [[targets]] to it to start auto-booking.", path.display());
+    Ok(())
+}
+
+/// Add, remove, list or compact entries in the persisted snipe queue
+async fn run_queue(config: &Config, client: &PerfectGymClient, action: QueueCommand) -> Result<()> {
+    match action {
+        QueueCommand::Add { class_id, priority, trainer } => {
+            info!("Adding class {} to snipe queue...", class_id);
             client.login().await?;
+
+            // Get class details
+            let details = client.get_class_details(class_id).await?;
+            let entry = SnipeEntry::new(
+                class_id,
+                details.name.clone(),
+                details.start_time,
+                details.bookable_from,
+                details.trainer.clone(),
+                &config.gym,
+            )
+            .with_priority(priority)
+            .with_required_trainer(trainer.clone());
+            let window_opens = entry.booking_window;
+
+            let mut queue = SnipeQueue::load_profile(config.profile.as_deref())?;
+            queue.add_with(entry, config.snipe.allow_multiple_per_day)?;
+
+            println!("\nQueued:       {} (class {})", details.name, class_id);
+            println!("Class time:   {}", details.start_time.format("%a %d %b %H:%M"));
+            println!("Trainer:      {}", details.trainer.as_deref().unwrap_or("-"));
+            if let Some(trainer) = &trainer {
+                println!("Only with:    trainers matching '{}'", trainer);
+                if !trainer_matches(details.trainer.as_deref(), trainer) {
+                    println!("Warning: the class is currently listed with a different trainer.");
+                }
+            }
+            println!("Window opens: {}", window_opens.format("%a %d %b %H:%M:%S"));
+            if window_opens <= chrono::Local::now() {
+                println!("The window is already open - `book {}` books it now.", class_id);
+            }
+        }
+        QueueCommand::Remove { class_id } => {
+            let mut queue = SnipeQueue::load_profile(config.profile.as_deref())?;
+            if queue.remove(class_id)? {
+                info!("Removed class {} from snipe queue", class_id);
+            } else {
+                error!("Class {} not found in snipe queue", class_id);
+            }
+        }
+        QueueCommand::List => {
+            let queue = SnipeQueue::load_profile(config.profile.as_deref())?;
+            let pending = queue.pending_snipes();
+
+            if pending.is_empty() {
+                println!("\nNo pending snipes in queue.");
+            } else {
+                println!(
+                    "\n{:<8} {:<25} {:<12} {:<18} {:<18} {:<8}",
+                    "ID", "Class", "Trainer", "Class Time", "Window Opens", "Priority"
+                );
+                println!("{}", "-".repeat(92));
+
+                for snipe in pending {
+                    let trainer = snipe.trainer.as_deref().unwrap_or("-");
+                    println!(
+                        "{:<8} {:<25} {:<12} {:<18} {:<18} {:<8}",
+                        snipe.class_id,
+                        truncate(&snipe.class_name, 23),
+                        truncate(trainer, 10),
+                        snipe.class_time.format("%a %d %b %H:%M"),
+                        snipe.booking_window.format("%a %d %b %H:%M"),
+                        snipe.priority
+                    );
+                }
+            }
+
+            // Also show recent completed/failed
+            let non_pending: Vec<_> = queue.snipes.iter()
+                .filter(|s| s.status != SnipeStatus::Pending)
+                .collect();
+
+            if !non_pending.is_empty() {
+                println!("\nWaitlisted and recent completed/failed:");
+                println!("{:<8} {:<25} {:<18} {:<10} Note", "ID", "Class", "Class Time", "Status");
+                println!("{}", "-".repeat(83));
+
+                for snipe in non_pending {
+                    let status = match snipe.status {
+                        SnipeStatus::Completed => "Completed",
+                        SnipeStatus::Failed => "Failed",
+                        SnipeStatus::Pending => "Pending",
+                        SnipeStatus::Waitlisted => "Waitlisted",
+                    };
+                    let mut note = snipe.error_message.clone().unwrap_or_default();
+                    if snipe.status == SnipeStatus::Waitlisted {
+                        note = match snipe.waitlist_position {
+                            Some(pos) => format!("#{} on the waitlist, watching for a place", pos),
+                            None => "On the waitlist, watching for a place".to_string(),
+                        };
+                    }
+                    if snipe.attempts > 0 {
+                        note.push_str(&format!(" (after {} attempts)", snipe.attempts));
+                    }
+                    println!(
+                        "{:<8} {:<25} {:<18} {:<10} {}",
+                        snipe.class_id,
+                        truncate(&snipe.class_name, 23),
+                        snipe.class_time.format("%a %d %b %H:%M"),
+                        status,
+                        note
+                    );
+                }
+            }
+        }
+        QueueCommand::Compact => {
+            let mut queue = SnipeQueue::load_profile(config.profile.as_deref())?;
+            let removed = queue.compact()?;
+            info!(
+                "Removed {} completed/failed entries, {} pending remain",
+                removed,
+                queue.snipes.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    if let Commands::Init { force } = cli.command {
+        return run_init(&profile_file(&cli.config, cli.profile.as_deref()), force).await;
+    }
+    if let Commands::Schedule { all_profiles: true, once } = cli.command {
+        info!("Starting scheduler for all profiles...");
+        return scheduler::run_profiles(&Config::load(&cli.config)?, once, &Shutdown::on_signal()).await;
+    }
+    let (config, sources) = Config::load_for_profile(&cli.config, cli.profile.as_deref())?;
+    let config_path = sources.path.clone();
+    let client = PerfectGymClient::new(&config)?;
+
+    // The client is cloned so the club it detects at login is still visible here. A club
+    // saved under [gym] would apply to every profile, so only single-login files are offered it.
+    let result = run_command(cli.command, cli.format, config.clone(), sources, client.clone()).await;
+    if config.gym.club_id == 0 && client.club_id() != 0 && config.profiles.is_empty() {
+        offer_to_save_club_id(&config_path, client.club_id());
+    }
+    result
+}
+
+/// A one-off target for `book-match`, checking the day is a weekday and the time is HH:MM
+fn match_target(class_name: String, day: &str, time: &str) -> Result<ClassTarget> {
+    if day.parse::<chrono::Weekday>().is_err() {
+        return Err(GymSniperError::Config(format!("Unknown day '{}' (use e.g. mon or monday)", day)));
+    }
+    let time = chrono::NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| GymSniperError::Config(format!("Invalid time '{}' (use HH:MM, e.g. 18:30)", time)))?;
+    Ok(ClassTarget {
+        class_name: Some(class_name),
+        days: Some(vec![day.to_string()]),
+        time: Some(time.format("%H:%M").to_string()),
+        ..Default::default()
+    })
+}
+
+/// Print a value as pretty JSON on stdout, for `--format json`
+fn print_json<T: serde::Serialize>(value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(std::io::Error::from)?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Offer to write a club ID detected at login into the config file, so it isn't looked
+/// up every time. Only asks at a terminal; otherwise just says how to set it.
+fn offer_to_save_club_id(path: &std::path::Path, club_id: u32) {
+    let label = format!("Save club_id = {} to {}?", club_id, path.display());
+    let save = std::io::stdin().is_terminal()
+        && init::ask_yes_no(&mut std::io::stdin().lock(), &mut std::io::stderr(), &label).unwrap_or(false);
+    if !save {
+        info!("Add club_id = {} under [gym] in {} to skip detecting it", club_id, path.display());
+        return;
+    }
+    match init::save_club_id(path, club_id) {
+        Ok(()) => info!("Saved club_id = {} to {}", club_id, path.display()),
+        Err(e) => warn!("Could not save club_id: {}", e),
+    }
+}
+
+async fn run_command(
+    command: Commands,
+    format: Option<OutputFormat>,
+    mut config: Config,
+    sources: ConfigSources,
+    client: PerfectGymClient,
+) -> Result<()> {
+    match command {
+        Commands::Validate { offline } => {
+            let mut problems = validate::config_problems(&config);
+            if !offline {
+                problems.extend(validate::unreachable_portals(&config).await);
+            }
+            if problems.is_empty() {
+                println!("{}: no problems found", sources.path.display());
+                return Ok(());
+            }
+            println!("{}:", sources.path.display());
+            for problem in &problems {
+                println!("  - {}", problem);
+            }
+            return Err(GymSniperError::Config(format!("{} problem(s) found", problems.len())));
+        }
+        Commands::TestNotify => {
+            let results = Notifiers::new(&config).send_test(&TestSample::default()).await;
+            if results.is_empty() {
+                println!("No notifications configured. Add [email] or [webhook] to {}.", sources.path.display());
+                return Ok(());
+            }
+            let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+            for (channel, result) in &results {
+                match result {
+                    Ok(()) => println!("  {:<8} sent", channel),
+                    Err(e) => println!("  {:<8} FAILED: {}", channel, e),
+                }
+            }
+            if failed > 0 {
+                return Err(GymSniperError::Config(format!("{} notification channel(s) failed", failed)));
+            }
+        }
+        Commands::ConfigShow { json } => {
+            let redacted = config.redacted();
+            if json {
+                let doc = serde_json::json!({
+                    "source": sources.path,
+                    "defaults": sources.defaults,
+                    "env_overrides": sources.env_overrides,
+                    "config": redacted,
+                });
+                println!("{}", serde_json::to_string_pretty(&doc).unwrap_or_default());
+            } else {
+                println!("# Loaded from: {}", sources.path.display());
+                if let Some(profile) = &config.profile {
+                    println!("# Profile: {}", profile);
+                }
+                if sources.env_overrides.is_empty() {
+                    println!("# Environment overrides: none");
+                } else {
+                    println!("# Environment overrides: {}", sources.env_overrides.join(", "));
+                }
+                if sources.defaults.is_empty() {
+                    println!("# Defaults applied: none");
+                } else {
+                    println!("# Defaults applied: {}", sources.defaults.join(", "));
+                }
+                println!();
+                print!("{}", toml::to_string(&redacted).unwrap_or_default());
+            }
+        }
+        Commands::Report => {
+            client.login().await?;
+            let queue = SnipeQueue::load_profile(config.profile.as_deref())?;
+            let report = ReportDocument::build(&config, &client, &queue).await?;
+            let json = serde_json::to_string_pretty(&report)
+                .map_err(|e| GymSniperError::Config(format!("Failed to serialize report: {}", e)))?;
+            println!("{}", json);
+        }
+        Commands::PreviewBook { class_id } => {
+            let preview = client.preview_book_request(class_id).await?;
+            println!("{} {}", preview.method, preview.url);
+            for (name, value) in &preview.headers {
+                println!("{}: {}", name, value);
+            }
+            println!();
+            let body = serde_json::from_str::<serde_json::Value>(&preview.body)
+                .and_then(|v| serde_json::to_string_pretty(&v))
+                .unwrap_or(preview.body);
+            println!("{}", body);
+        }
+        Commands::Replay => {
+            let path = replay::failure_path(config.profile.as_deref());
+            let Some(failure) = replay::load(&path)? else {
+                println!("No failed booking recorded in {}", path.display());
+                return Ok(());
+            };
+
+            println!(
+                "{} (class {}) at {}",
+                failure.class_name,
+                failure.class_id,
+                failure.class_time.format("%a %d %b %H:%M")
+            );
+            if let Some(opens) = failure.window_opens {
+                println!("Window opened:  {}", opens.format("%a %d %b %H:%M:%S%.3f"));
+            }
+            println!("Failed at:      {}", failure.recorded_at.format("%a %d %b %H:%M:%S%.3f"));
+            println!("Error:          {}", failure.error);
+            println!("{} {}", failure.request.method, failure.request.url);
+            println!("{}", failure.request.body);
+
+            client.login().await?;
+            let fresh = replay::replay(&client, &failure).await?;
+
+            match &failure.response {
+                Some(original) => print_response("Original response", original, config.gym.redact_logs),
+                None => println!("\n--- Original response ---\n(none - the request failed before a response arrived)"),
+            }
+            print_response("Response now", &fresh, config.gym.redact_logs);
+
+            match &failure.response {
+                Some(original) if original.status == fresh.status && original.body == fresh.body => {
+                    println!("\nSame response as before - the failure looks structural, not transient.")
+                }
+                _ => println!("\nThe response has changed - the original failure may have been transient."),
+            }
+        }
+        Commands::Init { .. } => unreachable!("init runs before a config is loaded"),
+        Commands::Login { save_token } => {
+            info!("Testing login...");
+            client.set_save_token(save_token);
+            client.login_fresh().await?;
             info!("Login successful!");
+            if save_token {
+                info!(
+                    "Saved the login token to {}; later commands reuse it until it expires",
+                    token_cache::token_path(config.profile.as_deref()).display()
+                );
+            }
+        }
+        Commands::Logout => {
+            let path = token_cache::token_path(config.profile.as_deref());
+            if token_cache::remove(&path)? {
+                info!("Deleted the saved login token {}", path.display());
+            } else {
+                info!("No saved login token to delete");
+            }
         }
-        Commands::List { days } => {
+        Commands::SetPassword => {
+            let credentials = &config.credentials;
+            let Some(service) = credentials.keyring_service.as_deref() else {
+                return Err(GymSniperError::Config(
+                    "Set keyring_service in the credentials (e.g. keyring_service = \"gym_sniper\") to keep the password in the keyring".to_string(),
+                ));
+            };
+            let password = rpassword::prompt_password(format!("Password for {}: ", credentials.email))?;
+            if password.is_empty() {
+                return Err(GymSniperError::Config("No password entered".to_string()));
+            }
+            secrets::store_password(service, &credentials.email, &password)?;
+            println!("Stored the password for {} in keyring service '{}'.", credentials.email, service);
+            if !credentials.password.is_empty() {
+                println!("The config still has a plaintext password, which takes precedence - remove it to use the keyring.");
+            }
+        }
+        Commands::List { days, category, zone } => {
+            let json = OutputFormat::json(format)?;
             info!("Fetching classes for next {} days...", days);
             client.login().await?;
-            let classes = client.get_weekly_classes(days).await?;
+            let mut classes = client.get_weekly_classes(days, category.or(config.gym.default_category)).await?;
+            if let Some(zone) = zone {
+                let zone = zone.to_lowercase();
+                classes.retain(|c| c.zone.to_lowercase().contains(&zone));
+            }
+            if json {
+                return print_json(&classes);
+            }
 
-            println!("\n{:<8} {:<25} {:<15} {:<20} {:<12}", "ID", "Class", "Trainer", "Class Time", "Status");
-            println!("{}", "-".repeat(87));
+            println!(
+                "\n{:<8} {:<25} {:<15} {:<20} {:<10} {:<12}",
+                "ID", "Class", "Trainer", "Class Time", "Duration", "Status"
+            );
+            println!("{}", "-".repeat(98));
 
-            for class in classes {
+            for class in &classes {
                 let trainer = class.trainer.as_deref().unwrap_or("-");
                 println!(
-                    "{:<8} {:<25} {:<15} {:<20} {:<12}",
+                    "{:<8} {:<25} {:<15} {:<20} {:<10} {:<12}",
                     class.id,
                     truncate(&class.name, 23),
                     truncate(trainer, 13),
                     class.start_time.format("%a %d %b %H:%M"),
+                    format_class_length(class.duration_minutes),
                     class.status
                 );
             }
+
+            let summary = summarize_classes(&classes);
+            println!("{}", "-".repeat(98));
+            println!(
+                "{} classes across {} zones, {} bookable, {} full",
+                summary.total,
+                summary.zones.len(),
+                summary.bookable,
+                summary.full
+            );
+            for (zone, count) in &summary.zones {
+                println!("  {}: {}", zone, count);
+            }
         }
-        Commands::Trainer { name, days } => {
+        Commands::Trainer { name, days, category } => {
             info!("Searching for trainer '{}' in next {} days...", name, days);
             client.login().await?;
-            let classes = client.get_weekly_classes(days).await?;
+            let classes = client.get_weekly_classes(days, category.or(config.gym.default_category)).await?;
 
             let search = name.to_lowercase();
             let filtered: Vec<_> = classes
@@ -150,14 +887,39 @@ async fn main() -> Result<()> {
                 }
             }
         }
+        Commands::Categories { tree } => {
+            client.login().await?;
+            let categories = client.get_categories().await?;
+
+            if categories.is_empty() {
+                println!("\nNo categories found.");
+                return Ok(());
+            }
+
+            println!("\n{:<8} Category", "ID");
+            println!("{}", "-".repeat(50));
+            for root in &categories {
+                let mut path: Vec<&str> = Vec::new();
+                for (depth, category) in root.walk() {
+                    path.truncate(depth);
+                    path.push(&category.name);
+                    if tree {
+                        println!("{:<8} {}{}", category.id, "  ".repeat(depth), category.name);
+                    } else {
+                        println!("{:<8} {}", category.id, path.join(" > "));
+                    }
+                }
+            }
+        }
         Commands::Upcoming { days } => {
             let days = days.min(21); // Cap at 21 days
             info!("Fetching upcoming classes (not yet bookable) for next {} days...", days);
             client.login().await?;
 
-            // Need to fetch 7 days ahead of requested range since booking window is 7d+2h before class
-            let fetch_days = days + 8;
-            let classes = client.get_weekly_classes(fetch_days).await?;
+            // Fetch a booking window beyond the requested range, since the classes there are
+            // the ones whose windows open within it
+            let fetch_days = days + booking_window(&config.gym).num_days() as u32 + 1;
+            let classes = client.get_weekly_classes(fetch_days, None).await?;
 
             let now = chrono::Local::now();
 
@@ -165,8 +927,7 @@ async fn main() -> Result<()> {
             let filtered: Vec<_> = classes
                 .into_iter()
                 .filter(|c| {
-                    let window_opens = c.start_time - booking_window();
-                    window_opens > now
+                    c.window_opens(&config.gym) > now
                 })
                 .collect();
 
@@ -178,7 +939,7 @@ async fn main() -> Result<()> {
 
                 for class in filtered {
                     let trainer = class.trainer.as_deref().unwrap_or("-");
-                    let window_opens = class.start_time - booking_window();
+                    let window_opens = class.window_opens(&config.gym);
                     println!(
                         "{:<8} {:<25} {:<15} {:<20} {:<20}",
                         class.id,
@@ -190,139 +951,352 @@ async fn main() -> Result<()> {
                 }
             }
         }
-        Commands::Book { class_id } => {
+        Commands::Export { days, category, out } => {
+            let format = OutputFormat::export(format)?;
+            client.login().await?;
+            let (document, exported) = match format {
+                ExportFormat::Html => {
+                    info!("Fetching classes for next {} days...", days);
+                    let classes = client.get_weekly_classes(days, category.or(config.gym.default_category)).await?;
+                    (export::classes_to_html(&classes), format!("{} classes", classes.len()))
+                }
+                ExportFormat::Ical => {
+                    info!("Fetching your bookings...");
+                    let bookings = client.get_my_bookings(days).await?;
+                    let booked = bookings.iter().filter(|b| !b.is_waitlisted()).count();
+                    (export::bookings_to_ical(&bookings, chrono::Utc::now()), format!("{} bookings", booked))
+                }
+            };
+            match out {
+                Some(path) => {
+                    std::fs::write(&path, document)?;
+                    println!("Exported {} to {}", exported, path.display());
+                }
+                None => print!("{}", document),
+            }
+        }
+        Commands::Book { class_id, spot } => {
             info!("Booking class {}...", class_id);
             client.login().await?;
-            let result = client.book_class(class_id).await?;
+            let result = client.book_class_at(class_id, spot).await?;
+            info!("Booked: {} at {}", result.name, result.start_time);
+        }
+        Commands::BookMatch { class_name, day, time } => {
+            let target = match_target(class_name, &day, &time)?;
+            info!("Looking for {} {} at {}...", target.label(), day, target.time.as_deref().unwrap_or_default());
+            client.login().await?;
+            let classes = client.get_weekly_classes(7, None).await?;
+            let class = single_match(&classes, &target, &config.matching)?;
+            info!(
+                "Booking {} at {} (class ID {})...",
+                class.name,
+                class.start_time.format("%a %d %b %H:%M"),
+                class.id
+            );
+            let result = client.book_class(class.id).await?;
             info!("Booked: {} at {}", result.name, result.start_time);
         }
-        Commands::Bookings => {
+        Commands::Details { class_id } => {
+            client.login().await?;
+            let details = client.get_class_details(class_id).await?;
+
+            println!("\nClass:        {}", details.name);
+            println!("ID:           {}", details.id);
+            println!("Time:         {}", details.start_time.format("%a %d %b %H:%M"));
+            println!("Trainer:      {}", details.trainer.as_deref().unwrap_or("-"));
+            println!("Status:       {}", details.status);
+            println!(
+                "Window opens: {}",
+                details.window_opens(&config.gym).format("%a %d %b %H:%M")
+            );
+            let spots = client.get_class_spots(class_id).await?;
+            if !spots.is_empty() {
+                println!("\nSpots (book one with --spot <ID>):");
+                for spot in spots {
+                    let state = if spot.available { "free" } else { "taken" };
+                    println!("  {:<8} {:<20} {}", spot.id, truncate(&spot.name, 18), state);
+                }
+            }
+            if let Some(description) = &details.description {
+                println!("\n{}", description);
+            }
+        }
+        Commands::Bookings { days } => {
+            let json = OutputFormat::json(format)?;
             info!("Fetching your bookings...");
             client.login().await?;
-            let bookings = client.get_my_bookings().await?;
+            let bookings = client.get_my_bookings(days).await?;
 
+            if json {
+                return print_json(&bookings);
+            }
             if bookings.is_empty() {
                 println!("\nNo current bookings found.");
             } else {
-                println!("\n{:<8} {:<25} {:<15} {:<20} {:<12} {:<10}", "ID", "Class", "Trainer", "Class Time", "Status", "Waitlist");
-                println!("{}", "-".repeat(97));
+                println!("\n{:<8} {:<25} {:<15} {:<20} {:<20}", "ID", "Class", "Trainer", "Class Time", "Status");
+                println!("{}", "-".repeat(91));
 
                 for booking in bookings {
-                    let waitlist = match booking.waitlist_position {
-                        Some(pos) => format!("#{}", pos),
-                        None => "-".to_string(),
-                    };
                     let trainer = booking.trainer.as_deref().unwrap_or("-");
                     println!(
-                        "{:<8} {:<25} {:<15} {:<20} {:<12} {:<10}",
+                        "{:<8} {:<25} {:<15} {:<20} {:<20}",
                         booking.id,
                         truncate(&booking.name, 23),
                         truncate(trainer, 13),
                         booking.start_time.format("%a %d %b %H:%M"),
-                        booking.status,
-                        waitlist
+                        status_with_places(&booking.status_label(), booking.booked_count, booking.capacity)
                     );
                 }
             }
         }
-        Commands::Snipe { class_id } => {
-            info!("Sniping class {}...", class_id);
-            client.login().await?;
-            snipe::snipe_class(&config, &client, class_id).await?;
-        }
-        Commands::SnipeAdd { class_id } => {
-            info!("Adding class {} to snipe queue...", class_id);
+        Commands::Status => {
+            let queue = SnipeQueue::load_profile(config.profile.as_deref())?;
             client.login().await?;
+            let bookings = client.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await?;
+            let now = chrono::Local::now();
 
-            // Get class details
-            let details = client.get_class_details(class_id).await?;
-            let bw = details.start_time - booking_window();
-
-            let entry = SnipeEntry {
-                class_id,
-                class_name: details.name.clone(),
-                class_time: details.start_time,
-                booking_window: bw,
-                trainer: details.trainer.clone(),
-                added_at: chrono::Local::now(),
-                status: SnipeStatus::Pending,
-                error_message: None,
-            };
+            let confirmed: Vec<_> = bookings.iter().filter(|b| !b.is_waitlisted()).collect();
+            println!("\nBookings ({}):", confirmed.len());
+            if confirmed.is_empty() {
+                println!("  none in the next {} days", DEFAULT_BOOKINGS_DAYS);
+            }
+            for booking in &confirmed {
+                println!(
+                    "  {:<20} {:<25} {}",
+                    booking.start_time.format("%a %d %b %H:%M"),
+                    truncate(&booking.name, 23),
+                    status_with_places(&booking.status, booking.booked_count, booking.capacity)
+                );
+            }
 
-            let mut queue = SnipeQueue::load()?;
-            queue.add(entry)?;
+            let pending = queue.pending_snipes();
+            let missed = queue.missed_snipes(now);
+            println!("\nQueued snipes ({}):", pending.len());
+            if pending.is_empty() {
+                println!("  none");
+            }
+            for snipe in &pending {
+                let flag = if snipe.booking_window < now { "  MISSED" } else { "" };
+                println!(
+                    "  window {:<18} {:<25} class {}{}",
+                    snipe.booking_window.format("%a %d %b %H:%M"),
+                    truncate(&snipe.class_name, 23),
+                    snipe.class_time.format("%a %d %b %H:%M"),
+                    flag
+                );
+            }
+            for snipe in queue.waitlisted_snipes() {
+                let position = snipe.waitlist_position.map(|p| format!(" #{}", p)).unwrap_or_default();
+                println!(
+                    "  waitlist{:<17} {:<25} class {}",
+                    position,
+                    truncate(&snipe.class_name, 23),
+                    snipe.class_time.format("%a %d %b %H:%M")
+                );
+            }
 
-            info!(
-                "Added to snipe queue: {} at {} (window opens {})",
-                details.name,
-                details.start_time.format("%a %d %b %H:%M"),
-                bw.format("%a %d %b %H:%M")
-            );
-        }
-        Commands::SnipeRemove { class_id } => {
-            let mut queue = SnipeQueue::load()?;
-            if queue.remove(class_id)? {
-                info!("Removed class {} from snipe queue", class_id);
-            } else {
-                error!("Class {} not found in snipe queue", class_id);
+            if let Some(next) = pending.iter().find(|s| s.booking_window >= now) {
+                println!(
+                    "\nNext booking window: {} opens in {} ({})",
+                    next.class_name,
+                    format_duration(next.booking_window - now),
+                    next.booking_window.format("%a %d %b %H:%M")
+                );
+            }
+            if !missed.is_empty() {
+                println!(
+                    "\nWarning: {} queued snipe(s) passed their window without running. Is `snipe-daemon` running? \
+                     Remove them with `queue remove <ID>` or book them now if there's space.",
+                    missed.len()
+                );
             }
         }
-        Commands::Snipes => {
-            let queue = SnipeQueue::load()?;
-            let pending = queue.pending_snipes();
+        Commands::Waitlists { days } => {
+            info!("Fetching your waitlists for the next {} days...", days);
+            client.login().await?;
+            let waitlists = client.get_my_waitlists(days).await?;
 
-            if pending.is_empty() {
-                println!("\nNo pending snipes in queue.");
+            if waitlists.is_empty() {
+                println!("\nNot on any waitlists.");
             } else {
-                println!("\n{:<8} {:<25} {:<12} {:<18} {:<18}", "ID", "Class", "Trainer", "Class Time", "Window Opens");
-                println!("{}", "-".repeat(83));
+                println!("\n{:<8} {:<25} {:<20} {:<10}", "ID", "Class", "Class Time", "Position");
+                println!("{}", "-".repeat(65));
 
-                for snipe in pending {
-                    let trainer = snipe.trainer.as_deref().unwrap_or("-");
+                for entry in waitlists {
+                    let position = entry.waitlist_position.map(|p| format!("#{}", p)).unwrap_or_else(|| "-".to_string());
                     println!(
-                        "{:<8} {:<25} {:<12} {:<18} {:<18}",
-                        snipe.class_id,
-                        truncate(&snipe.class_name, 23),
-                        truncate(trainer, 10),
-                        snipe.class_time.format("%a %d %b %H:%M"),
-                        snipe.booking_window.format("%a %d %b %H:%M")
+                        "{:<8} {:<25} {:<20} {:<10}",
+                        entry.id,
+                        truncate(&entry.name, 23),
+                        entry.start_time.format("%a %d %b %H:%M"),
+                        position
                     );
                 }
             }
+        }
+        Commands::LeaveWaitlist { class_id } => {
+            client.login().await?;
+            client.leave_waitlist(class_id).await?;
+            println!("Left the waitlist for class {}", class_id);
+        }
+        Commands::CancelMatching { name_pattern, days, yes, force } => {
+            client.login().await?;
+            // One extra day so the whole of the last day is covered
+            let bookings = client.get_my_bookings(days + 1).await?;
+            let matching = bookings_matching(&bookings, &name_pattern, days, chrono::Local::now(), &config.matching);
 
-            // Also show recent completed/failed
-            let non_pending: Vec<_> = queue.snipes.iter()
-                .filter(|s| s.status != SnipeStatus::Pending)
-                .collect();
+            if matching.is_empty() {
+                println!("\nNo bookings matching '{}' in the next {} days.", name_pattern, days);
+                return Ok(());
+            }
+            cancel_bookings(&client, &matching, yes, force).await?;
+        }
+        Commands::Cancel { class_id: Some(class_id), force, .. } => {
+            client.login().await?;
+            let class = client.get_class_details(class_id).await?;
+            match client.cancel_booking_with_notice(class_id, &class.name, class.start_time, force).await {
+                Ok(()) => println!("Cancelled {} at {}", class.name, class.start_time.format("%a %d %b %H:%M")),
+                Err(GymSniperError::CancelRefused(reason)) => {
+                    return Err(GymSniperError::CancelRefused(format!("{} - use --force to cancel anyway", reason)));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Commands::Cancel { class_id: None, yes, force, .. } => {
+            client.login().await?;
+            let bookings = client.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await?;
+            if bookings.is_empty() {
+                println!("\nNo bookings to cancel.");
+                return Ok(());
+            }
+            cancel_bookings(&client, &bookings.iter().collect::<Vec<_>>(), yes, force).await?;
+        }
+        Commands::Snipe { class_id, spot, waitlist_immediately, accounts } => {
+            info!("Sniping class {}...", class_id);
+            if waitlist_immediately {
+                config.snipe.on_full = OnFull::WaitlistImmediately;
+            }
+            if accounts.is_empty() {
+                client.login().await?;
+                snipe::snipe_class(&config, &client, class_id, spot).await?;
+            } else {
+                let outcomes = accounts::snipe_for_accounts(&config, class_id, spot, &accounts).await?;
+                accounts::report(&format!("class {}", class_id), outcomes)?;
+            }
+        }
+        Commands::Watch { class_id } => {
+            info!("Watching class {}...", class_id);
+            client.login().await?;
+            watch::watch_class(&config, &client, class_id).await?;
+        }
+        Commands::WatchWaitlist { class_id } => {
+            info!("Watching the waitlist for class {}...", class_id);
+            client.login().await?;
+            match watch::watch_waitlist(&config, &client, class_id).await? {
+                WaitlistCheck::Promoted => println!("Promoted off the waitlist into class {}", class_id),
+                WaitlistCheck::Booked => println!("A spot opened and class {} was booked", class_id),
+                WaitlistCheck::Dropped(status) => {
+                    println!("Dropped off the waitlist for class {} without a place (now {})", class_id, status)
+                }
+                WaitlistCheck::Waiting(_) => println!("Class {} started while still on the waitlist", class_id),
+            }
+        }
+        Commands::Hold { class_id, prefer } => {
+            info!("Holding class {} while watching class {}...", class_id, prefer);
+            client.login().await?;
+            let holds_file = hold::holds_path(config.profile.as_deref());
+            match hold::hold_and_prefer(&config, &client, class_id, prefer, &holds_file, &SystemClock).await? {
+                HoldOutcome::Switched => println!("Switched to class {} and released class {}", prefer, class_id),
+                HoldOutcome::KeptHold => println!("Class {} never opened up - keeping class {}", prefer, class_id),
+                HoldOutcome::RolledBack { reason } => {
+                    println!("Could not book class {} ({}) - class {} was re-booked", prefer, reason, class_id)
+                }
+                HoldOutcome::Lost { reason } => {
+                    return Err(GymSniperError::Api(format!("Lost both classes: {}", reason)));
+                }
+            }
+        }
+        Commands::Queue { action } => run_queue(&config, &client, action).await?,
+        Commands::SnipeAdd { class_id } => {
+            run_queue(&config, &client, QueueCommand::Add { class_id, priority: 0, trainer: None }).await?
+        }
+        Commands::SnipeRemove { class_id } => run_queue(&config, &client, QueueCommand::Remove { class_id }).await?,
+        Commands::Snipes => run_queue(&config, &client, QueueCommand::List).await?,
+        Commands::QueueCompact => run_queue(&config, &client, QueueCommand::Compact).await?,
+        Commands::EstimateWindow => {
+            let timings_path = timing::timings_path(config.profile.as_deref());
+            let timings = timing::load(&timings_path)?;
+            let estimates = timing::estimate_windows(&timings);
 
-            if !non_pending.is_empty() {
-                println!("\nRecent completed/failed:");
-                println!("{:<8} {:<25} {:<18} {:<10}", "ID", "Class", "Class Time", "Status");
-                println!("{}", "-".repeat(63));
+            if estimates.is_empty() {
+                println!(
+                    "No usable snipe timings in {} yet. Timings are recorded by `snipe` and `snipe-daemon`.",
+                    timings_path.display()
+                );
+                return Ok(());
+            }
 
-                for snipe in non_pending {
-                    let status = match snipe.status {
-                        SnipeStatus::Completed => "Completed",
-                        SnipeStatus::Failed => "Failed",
-                        SnipeStatus::Pending => "Pending",
-                    };
-                    println!(
-                        "{:<8} {:<25} {:<18} {:<10}",
-                        snipe.class_id,
-                        truncate(&snipe.class_name, 23),
-                        snipe.class_time.format("%a %d %b %H:%M"),
-                        status
-                    );
-                }
+            println!(
+                "{:<8} {:<30} {:>7} {:>12} {:>20} {:>8} {:>10}",
+                "Club", "Class", "Samples", "Median (ms)", "Range (ms)", "Offset s", "Slack ms"
+            );
+            println!("{}", "-".repeat(101));
+            for e in &estimates {
+                println!(
+                    "{:<8} {:<30} {:>7} {:>12} {:>20} {:>8} {:>10}",
+                    e.club_id,
+                    truncate(&e.class_name, 30),
+                    e.samples,
+                    e.median_offset_ms,
+                    format!("{} to {}", e.min_offset_ms, e.max_offset_ms),
+                    e.suggested_offset_secs,
+                    e.suggested_slack_ms
+                );
+            }
+
+            if let Some(e) = estimates.iter().find(|e| e.club_id == config.gym.club_id) {
+                println!();
+                println!("Suggested [gym] settings for club {} ({}):", e.club_id, e.class_name);
+                println!("window_offset_secs = {}", e.suggested_offset_secs);
+                println!("window_slack_ms = {}", e.suggested_slack_ms);
+            }
+        }
+        Commands::Stats => {
+            let history_path = history::history_path(config.profile.as_deref());
+            let outcomes = history::load(&history_path)?;
+            let Some(first) = outcomes.first() else {
+                println!(
+                    "No snipe outcomes in {} yet. They are recorded by `snipe-daemon`.",
+                    history_path.display()
+                );
+                return Ok(());
+            };
+
+            let stats = history::stats(&outcomes);
+            println!("Snipes since {}:", first.recorded_at.format("%a %d %b %Y"));
+            println!("  Booked:           {}", stats.succeeded);
+            println!("  Failed:           {}", stats.failed);
+            if let Some(rate) = stats.success_rate() {
+                println!("  Success rate:     {:.0}%", rate);
+            }
+            if let Some((name, count)) = &stats.most_booked {
+                println!("  Most booked:      {} ({}x)", name, count);
+            }
+            if let Some(average) = stats.average_attempts {
+                println!("  Average attempts: {:.1}", average);
             }
         }
         Commands::SnipeDaemon => {
             info!("Starting snipe daemon...");
-            snipe::run_snipe_daemon(&config).await?;
+            snipe::run_snipe_daemon(&config, &Shutdown::on_signal()).await?;
+        }
+        Commands::Schedule { once: true, .. } => {
+            info!("Running a single scheduler pass...");
+            scheduler::run_once(&config, &client).await?;
         }
-        Commands::Schedule => {
+        Commands::Schedule { .. } => {
             info!("Starting scheduler...");
-            scheduler::run_scheduler(config, client).await?;
+            scheduler::run_scheduler(config, client, Shutdown::on_signal()).await?;
         }
     }
 
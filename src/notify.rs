@@ -0,0 +1,530 @@
+use chrono::Local;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+use crate::config::{Config, EmailConfig, WebhookConfig, WebhookFormat};
+use crate::email::{self, FailureExplanation};
+use crate::error::BookingErrorKind;
+
+/// Somewhere booking outcomes are reported
+pub trait Notifier {
+    fn notify_success(&self, class_name: &str, time: &str, trainer: Option<&str>) -> impl Future<Output = ()> + Send;
+
+    fn notify_failure(
+        &self,
+        class_name: &str,
+        time: &str,
+        trainer: Option<&str>,
+        failure: &FailureExplanation,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+/// Shared across every notification sent by this process
+static THROTTLE: LazyLock<Mutex<NotificationThrottle>> = LazyLock::new(Default::default);
+
+/// Suppresses identical notifications sent within a window of each other, counting the
+/// repeats so the next one that goes out can say how many there were
+#[derive(Debug, Default)]
+pub struct NotificationThrottle {
+    seen: HashMap<String, Seen>,
+}
+
+#[derive(Debug, Default)]
+struct Seen {
+    /// When one last went out, if one has
+    last_sent: Option<Instant>,
+    suppressed: u32,
+    /// One is being sent right now
+    in_flight: bool,
+}
+
+impl NotificationThrottle {
+    /// Whether a notification should go out at `now`. Returns how many identical
+    /// notifications it stands for (itself plus any suppressed since the last one sent),
+    /// or `None` to suppress it. One allowed out is reserved, so identical ones are held
+    /// back while it is being sent; call `sent` once it has gone out, or `failed` if not.
+    pub fn check(&mut self, key: &str, window: Duration, now: Instant) -> Option<u32> {
+        if window.is_zero() {
+            return Some(1);
+        }
+        self.seen.retain(|_, s| {
+            s.in_flight || s.suppressed > 0 || s.last_sent.is_some_and(|t| now.duration_since(t) < window)
+        });
+
+        let seen = self.seen.entry(key.to_string()).or_default();
+        if seen.in_flight || seen.last_sent.is_some_and(|t| now.duration_since(t) < window) {
+            seen.suppressed += 1;
+            return None;
+        }
+        seen.in_flight = true;
+        Some(seen.suppressed + 1)
+    }
+
+    /// Record that a notification went out at `now`, starting a new window
+    pub fn sent(&mut self, key: &str, now: Instant) {
+        self.seen.insert(key.to_string(), Seen { last_sent: Some(now), suppressed: 0, in_flight: false });
+    }
+
+    /// Release the reservation of a notification that failed to send, so the next
+    /// identical one isn't held back
+    pub fn failed(&mut self, key: &str) {
+        if let Some(seen) = self.seen.get_mut(key) {
+            seen.in_flight = false;
+        }
+    }
+}
+
+/// Send a notification unless an identical one (same `key`) went out within `window`, or
+/// is being sent. `send` is given how many notifications this one stands for and returns
+/// whether it got through; only then does a new window start. Returns whether it was sent.
+pub async fn throttled<F, Fut>(key: &str, window: Duration, send: F) -> bool
+where
+    F: FnOnce(u32) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let Some(repeats) = THROTTLE.lock().unwrap().check(key, window, Instant::now()) else {
+        info!("Suppressed repeated notification: {}", key.lines().next().unwrap_or_default());
+        return false;
+    };
+    let sent = send(repeats).await;
+    let mut throttle = THROTTLE.lock().unwrap();
+    if sent {
+        throttle.sent(key, Instant::now());
+    } else {
+        throttle.failed(key);
+    }
+    sent
+}
+
+/// Booking outcomes by email (`[email]`)
+pub struct EmailNotifier<'a>(pub &'a EmailConfig);
+
+/// Booking outcomes POSTed to a webhook URL (`[webhook]`)
+pub struct WebhookNotifier<'a>(pub &'a WebhookConfig);
+
+impl WebhookNotifier<'_> {
+    /// POST a notification standing for `repeats` identical ones. Returns whether it was sent.
+    async fn post(&self, payload: serde_json::Value, repeats: u32) -> bool {
+        match self.send(with_repeats(payload, repeats)).await {
+            Ok(()) => {
+                info!("Webhook notification sent");
+                true
+            }
+            Err(e) => {
+                error!("Failed to send webhook notification: {}", e);
+                false
+            }
+        }
+    }
+
+    async fn send(&self, payload: serde_json::Value) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        client
+            .post(&self.0.url)
+            .timeout(Duration::from_secs(10))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.without_url().to_string())
+    }
+
+    /// POST a sample booking and failure, marked as tests, and return the first error
+    async fn send_test(&self, sample: &TestSample) -> Result<(), String> {
+        for failure in [None, Some(&sample.failure)] {
+            let payload = webhook_payload(self.0.format, &sample.class_name, &sample.time, Some(&sample.trainer), failure);
+            self.send(mark_as_test(payload)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Flag a webhook payload as a test: a `test` field in JSON, a prefix on a Discord message
+fn mark_as_test(mut payload: serde_json::Value) -> serde_json::Value {
+    if let Some(content) = payload.get_mut("content").and_then(|c| c.as_str()).map(|c| format!("[Test] {}", c)) {
+        payload["content"] = content.into();
+    } else {
+        payload["test"] = true.into();
+    }
+    payload
+}
+
+/// Count held-back repeats in a webhook payload: a `repeats` field in JSON, "(xN)" after
+/// the headline of a Discord message. Left as-is for a single notification.
+fn with_repeats(mut payload: serde_json::Value, repeats: u32) -> serde_json::Value {
+    if repeats <= 1 {
+        return payload;
+    }
+    if let Some(content) = payload.get("content").and_then(|c| c.as_str()) {
+        let content = match content.split_once('\n') {
+            Some((headline, rest)) => format!("{} (x{})\n{}", headline, repeats, rest),
+            None => format!("{} (x{})", content, repeats),
+        };
+        payload["content"] = content.into();
+    } else {
+        payload["repeats"] = repeats.into();
+    }
+    payload
+}
+
+/// What a notification reports
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    Booked,
+    Failed(&'a FailureExplanation),
+    /// A booking that vanished was booked again
+    Rebooked,
+    /// A watched class has a spot; nothing was booked
+    Available,
+}
+
+impl Event<'_> {
+    /// The `outcome` field of a JSON webhook payload
+    fn outcome(&self) -> &'static str {
+        match self {
+            Event::Booked => "booked",
+            Event::Failed(_) => "failed",
+            Event::Rebooked => "rebooked",
+            Event::Available => "available",
+        }
+    }
+
+    /// Headline of a Discord message
+    fn headline(&self) -> &'static str {
+        match self {
+            Event::Booked => "Booked",
+            Event::Failed(_) => "Booking failed",
+            Event::Rebooked => "Booking restored",
+            Event::Available => "Spot open",
+        }
+    }
+}
+
+/// The JSON body for a webhook: `{class, time, trainer, outcome, reason}`, or a Discord
+/// message. `failure` is `None` for a successful booking.
+pub fn webhook_payload(
+    format: WebhookFormat,
+    class_name: &str,
+    time: &str,
+    trainer: Option<&str>,
+    failure: Option<&FailureExplanation>,
+) -> serde_json::Value {
+    event_payload(format, failure.map_or(Event::Booked, Event::Failed), class_name, time, trainer)
+}
+
+/// The JSON body for a webhook reporting `event`
+pub fn event_payload(
+    format: WebhookFormat,
+    event: Event,
+    class_name: &str,
+    time: &str,
+    trainer: Option<&str>,
+) -> serde_json::Value {
+    let reason = match event {
+        Event::Failed(failure) => Some(format!("{} {}", failure.summary, failure.action)),
+        _ => None,
+    };
+    match format {
+        WebhookFormat::Json => serde_json::json!({
+            "class": class_name,
+            "time": time,
+            "trainer": trainer,
+            "outcome": event.outcome(),
+            "reason": reason,
+        }),
+        WebhookFormat::Discord => {
+            let mut content = format!("**{}:** {} at {}", event.headline(), class_name, time);
+            if let Some(trainer) = trainer {
+                content.push_str(&format!(" with {}", trainer));
+            }
+            if let Some(reason) = reason {
+                content.push_str(&format!("\n{}", reason));
+            }
+            serde_json::json!({ "content": content })
+        }
+    }
+}
+
+/// Dummy class data for `test-notify`
+pub struct TestSample {
+    pub class_name: String,
+    pub time: String,
+    pub trainer: String,
+    pub failure: FailureExplanation,
+}
+
+impl Default for TestSample {
+    fn default() -> Self {
+        let time = Local::now().date_naive().succ_opt().unwrap_or_default().and_hms_opt(7, 0, 0).unwrap_or_default();
+        Self {
+            class_name: "Sample Class".to_string(),
+            time: time.format("%a %d %b %H:%M").to_string(),
+            trainer: "Sample Trainer".to_string(),
+            failure: FailureExplanation::from_kind(&BookingErrorKind::Full),
+        }
+    }
+}
+
+/// Every notifier configured: email, webhook, both or neither. Identical notifications
+/// within the dedupe window are held back once here, for every channel alike.
+pub struct Notifiers<'a> {
+    email: Option<EmailNotifier<'a>>,
+    webhook: Option<WebhookNotifier<'a>>,
+}
+
+impl<'a> Notifiers<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self {
+            email: config.email_notifications().map(EmailNotifier),
+            webhook: config.webhook.as_ref().map(WebhookNotifier),
+        }
+    }
+
+    /// The shortest `dedupe_window_secs` of the configured channels, so none is held back
+    /// for longer than it asks
+    fn dedupe_window(&self) -> Duration {
+        let email = self.email.as_ref().map(|email| email.0.dedupe_window_secs);
+        let webhook = self.webhook.as_ref().map(|webhook| webhook.0.dedupe_window_secs);
+        Duration::from_secs(email.into_iter().chain(webhook).min().unwrap_or_default())
+    }
+
+    /// Send a sample success and failure through each configured channel. Returns each
+    /// channel's name and whether both got through; empty if none is configured.
+    pub async fn send_test(&self, sample: &TestSample) -> Vec<(&'static str, Result<(), String>)> {
+        let mut results = Vec::new();
+        if let Some(email) = &self.email {
+            let result = email::send_test_notifications(
+                email.0,
+                &sample.class_name,
+                &sample.time,
+                Some(&sample.trainer),
+                &sample.failure,
+            )
+            .await;
+            results.push(("email", result));
+        }
+        if let Some(webhook) = &self.webhook {
+            results.push(("webhook", webhook.send_test(sample).await));
+        }
+        results
+    }
+}
+
+impl Notifiers<'_> {
+    /// Report that a booking which vanished has been booked again
+    pub async fn notify_defended(&self, class_name: &str, time: &str, trainer: Option<&str>) {
+        let key = format!("rebooked\n{}\n{}\n{}", class_name, time, trainer.unwrap_or_default());
+        throttled(&key, self.dedupe_window(), |repeats| async move {
+            let mut sent = false;
+            if let Some(email) = &self.email {
+                sent |= email::send_booking_defended(email.0, class_name, time, trainer, repeats).await;
+            }
+            if let Some(webhook) = &self.webhook {
+                let payload = event_payload(webhook.0.format, Event::Rebooked, class_name, time, trainer);
+                sent |= webhook.post(payload, repeats).await;
+            }
+            sent
+        })
+        .await;
+    }
+
+    /// Report that a watched class has a spot open
+    pub async fn notify_available(&self, class_name: &str, time: &str, trainer: Option<&str>) {
+        let key = format!("available\n{}\n{}\n{}", class_name, time, trainer.unwrap_or_default());
+        throttled(&key, self.dedupe_window(), |repeats| async move {
+            let mut sent = false;
+            if let Some(email) = &self.email {
+                sent |= email::send_availability_alert(email.0, class_name, time, trainer, repeats).await;
+            }
+            if let Some(webhook) = &self.webhook {
+                let payload = event_payload(webhook.0.format, Event::Available, class_name, time, trainer);
+                sent |= webhook.post(payload, repeats).await;
+            }
+            sent
+        })
+        .await;
+    }
+}
+
+impl Notifier for Notifiers<'_> {
+    async fn notify_success(&self, class_name: &str, time: &str, trainer: Option<&str>) {
+        let key = format!("booked\n{}\n{}\n{}", class_name, time, trainer.unwrap_or_default());
+        throttled(&key, self.dedupe_window(), |repeats| async move {
+            let mut sent = false;
+            if let Some(email) = &self.email {
+                sent |= email::send_booking_success(email.0, class_name, time, trainer, repeats).await;
+            }
+            if let Some(webhook) = &self.webhook {
+                let payload = webhook_payload(webhook.0.format, class_name, time, trainer, None);
+                sent |= webhook.post(payload, repeats).await;
+            }
+            sent
+        })
+        .await;
+    }
+
+    async fn notify_failure(&self, class_name: &str, time: &str, trainer: Option<&str>, failure: &FailureExplanation) {
+        let key = format!("failed\n{}\n{}\n{}\n{}", class_name, time, trainer.unwrap_or_default(), failure.body());
+        throttled(&key, self.dedupe_window(), |repeats| async move {
+            let mut sent = false;
+            if let Some(email) = &self.email {
+                sent |= email::send_booking_failure(email.0, class_name, time, trainer, failure, repeats).await;
+            }
+            if let Some(webhook) = &self.webhook {
+                let payload = webhook_payload(webhook.0.format, class_name, time, trainer, Some(failure));
+                sent |= webhook.post(payload, repeats).await;
+            }
+            sent
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check a notification and, if it may go out, record it as sent
+    fn send(throttle: &mut NotificationThrottle, key: &str, window: Duration, now: Instant) -> Option<u32> {
+        let count = throttle.check(key, window, now);
+        if count.is_some() {
+            throttle.sent(key, now);
+        }
+        count
+    }
+
+    #[test]
+    fn throttle_coalesces_identical_notifications() {
+        let mut throttle = NotificationThrottle::default();
+        let window = Duration::from_secs(600);
+        let start = Instant::now();
+
+        // Five identical failures a few seconds apart: only the first goes out
+        let sent: Vec<_> = (0..5)
+            .map(|i| send(&mut throttle, "auth failed", window, start + Duration::from_secs(i * 5)))
+            .collect();
+        assert_eq!(sent, vec![Some(1), None, None, None, None]);
+
+        // A different notification isn't held back
+        assert_eq!(send(&mut throttle, "booked", window, start + Duration::from_secs(30)), Some(1));
+
+        // After the window the next one goes out, counting the suppressed repeats
+        assert_eq!(send(&mut throttle, "auth failed", window, start + Duration::from_secs(700)), Some(5));
+        assert_eq!(send(&mut throttle, "auth failed", window, start + Duration::from_secs(1400)), Some(1));
+    }
+
+    #[test]
+    fn throttle_does_not_hold_back_after_a_failed_send() {
+        let mut throttle = NotificationThrottle::default();
+        let window = Duration::from_secs(600);
+        let start = Instant::now();
+
+        // The first attempt fails to send, so the next identical one still goes out
+        assert_eq!(throttle.check("booked", window, start), Some(1));
+        throttle.failed("booked");
+        assert_eq!(send(&mut throttle, "booked", window, start + Duration::from_secs(5)), Some(1));
+        assert_eq!(send(&mut throttle, "booked", window, start + Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn throttle_holds_back_identical_notifications_while_one_is_sending() {
+        let mut throttle = NotificationThrottle::default();
+        let window = Duration::from_secs(600);
+        let start = Instant::now();
+
+        // Two identical notifications at once: only the first is let through
+        assert_eq!(throttle.check("booked", window, start), Some(1));
+        assert_eq!(throttle.check("booked", window, start), None);
+        throttle.sent("booked", start + Duration::from_secs(1));
+        assert_eq!(throttle.check("booked", window, start + Duration::from_secs(2)), None);
+    }
+
+
+    #[test]
+    fn throttle_disabled_with_zero_window() {
+        let mut throttle = NotificationThrottle::default();
+        let now = Instant::now();
+        assert_eq!(send(&mut throttle, "a", Duration::ZERO, now), Some(1));
+        assert_eq!(send(&mut throttle, "a", Duration::ZERO, now), Some(1));
+    }
+
+    #[test]
+    fn json_payload_has_outcome_and_reason() {
+        let booked = webhook_payload(WebhookFormat::Json, "Yoga", "Mon 20 Jan 09:00", Some("Anna"), None);
+        assert_eq!(
+            booked,
+            serde_json::json!({
+                "class": "Yoga",
+                "time": "Mon 20 Jan 09:00",
+                "trainer": "Anna",
+                "outcome": "booked",
+                "reason": null,
+            })
+        );
+
+        let failure = FailureExplanation::from_kind(&BookingErrorKind::DailyLimit);
+        let failed = webhook_payload(WebhookFormat::Json, "Yoga", "Mon 20 Jan 09:00", None, Some(&failure));
+        assert_eq!(failed["outcome"], "failed");
+        assert_eq!(failed["trainer"], serde_json::Value::Null);
+        assert!(failed["reason"].as_str().unwrap().starts_with("Your account already has a class that day."));
+    }
+
+    #[test]
+    fn discord_payload_is_a_message() {
+        let failure = FailureExplanation::from_kind(&BookingErrorKind::Full);
+        let payload = webhook_payload(WebhookFormat::Discord, "Spin", "Tue 21 Jan 18:00", Some("Ben"), Some(&failure));
+        let content = payload["content"].as_str().unwrap();
+        assert!(content.starts_with("**Booking failed:** Spin at Tue 21 Jan 18:00 with Ben\nThe class was already full."));
+        assert_eq!(payload.as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_payloads_are_marked() {
+        let json = mark_as_test(webhook_payload(WebhookFormat::Json, "Spin", "Tue 21 Jan 18:00", None, None));
+        assert_eq!(json["test"], true);
+        assert_eq!(json["outcome"], "booked");
+
+        let discord = mark_as_test(webhook_payload(WebhookFormat::Discord, "Spin", "Tue 21 Jan 18:00", None, None));
+        assert_eq!(discord["content"], "[Test] **Booked:** Spin at Tue 21 Jan 18:00");
+        assert_eq!(discord.as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rebooked_payloads_name_the_event() {
+        let json = event_payload(WebhookFormat::Json, Event::Rebooked, "Spin", "Tue 21 Jan 18:00", None);
+        assert_eq!(json["outcome"], "rebooked");
+        assert_eq!(json["reason"], serde_json::Value::Null);
+
+        let discord = event_payload(WebhookFormat::Discord, Event::Rebooked, "Spin", "Tue 21 Jan 18:00", Some("Ben"));
+        assert_eq!(discord["content"], "**Booking restored:** Spin at Tue 21 Jan 18:00 with Ben");
+    }
+
+    #[test]
+    fn availability_payloads_name_the_event() {
+        let json = event_payload(WebhookFormat::Json, Event::Available, "Spin", "Tue 21 Jan 18:00", None);
+        assert_eq!(json["outcome"], "available");
+
+        let discord = event_payload(WebhookFormat::Discord, Event::Available, "Spin", "Tue 21 Jan 18:00", None);
+        assert_eq!(discord["content"], "**Spot open:** Spin at Tue 21 Jan 18:00");
+    }
+
+    #[test]
+    fn repeats_are_counted_in_webhook_payloads() {
+        let json = with_repeats(webhook_payload(WebhookFormat::Json, "Spin", "Tue 21 Jan 18:00", None, None), 5);
+        assert_eq!(json["repeats"], 5);
+        let single = with_repeats(webhook_payload(WebhookFormat::Json, "Spin", "Tue 21 Jan 18:00", None, None), 1);
+        assert!(single.get("repeats").is_none());
+
+        let failure = FailureExplanation::from_kind(&BookingErrorKind::Full);
+        let discord = with_repeats(
+            webhook_payload(WebhookFormat::Discord, "Spin", "Tue 21 Jan 18:00", None, Some(&failure)),
+            3,
+        );
+        let content = discord["content"].as_str().unwrap();
+        assert!(content.starts_with("**Booking failed:** Spin at Tue 21 Jan 18:00 (x3)\nThe class was already full."));
+    }
+}
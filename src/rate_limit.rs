@@ -0,0 +1,121 @@
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::clock::Clock;
+
+/// Limiters handed out by `RateLimiter::shared`, by portal URL and limit
+static SHARED: LazyLock<Mutex<HashMap<(String, u32), RateLimiter>>> = LazyLock::new(Default::default);
+
+/// A token bucket capping how many requests the API client sends. It holds up to
+/// `per_minute` permits and refills continuously at that rate, so a burst up to the limit
+/// goes out at once and anything beyond it is spread out. Clones share the bucket.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    per_minute: u32,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Permits available; negative when requests are already waiting for refills
+    tokens: f64,
+    /// When `tokens` was last brought up to date
+    updated: Option<DateTime<Local>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        let per_minute = per_minute.max(1);
+        Self {
+            per_minute,
+            bucket: Arc::new(Mutex::new(Bucket { tokens: per_minute as f64, updated: None })),
+        }
+    }
+
+    /// The limiter for `portal` in this process, so every client talking to the same portal
+    /// draws on one bucket however many are created
+    pub fn shared(portal: &str, per_minute: u32) -> Self {
+        SHARED
+            .lock()
+            .unwrap()
+            .entry((portal.to_string(), per_minute))
+            .or_insert_with(|| Self::new(per_minute))
+            .clone()
+    }
+
+    /// Wait, by `clock`, until a request may be sent
+    pub async fn acquire(&self, clock: &impl Clock) {
+        let wait = self.reserve(clock.now());
+        if !wait.is_zero() {
+            debug!("Request rate limit ({}/min) reached, waiting {}ms", self.per_minute, wait.as_millis());
+            clock.sleep(wait).await;
+        }
+    }
+
+    /// Take a permit at `now`, returning how long to wait until it is actually free.
+    /// Waiters queue in order of arrival, since each takes the next refill.
+    fn reserve(&self, now: DateTime<Local>) -> Duration {
+        let per_second = self.per_minute as f64 / 60.0;
+        let mut bucket = self.bucket.lock().unwrap();
+        if let Some(updated) = bucket.updated {
+            // A clock that went backwards refills nothing
+            let elapsed = (now - updated).to_std().unwrap_or_default().as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_second).min(self.per_minute as f64);
+        }
+        bucket.updated = Some(now);
+        bucket.tokens -= 1.0;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_up_to_the_limit_then_spreads_requests_out() {
+        let limiter = RateLimiter::new(60);
+        let start = Local::now();
+
+        for _ in 0..60 {
+            assert_eq!(limiter.reserve(start), Duration::ZERO);
+        }
+        // One permit a second from here, queued behind each other
+        assert_eq!(limiter.reserve(start), Duration::from_secs(1));
+        assert_eq!(limiter.reserve(start), Duration::from_secs(2));
+
+        // After a quiet minute the bucket is full again, but no fuller
+        let later = start + chrono::Duration::minutes(5);
+        for _ in 0..60 {
+            assert_eq!(limiter.reserve(later), Duration::ZERO);
+        }
+        assert!(limiter.reserve(later) > Duration::ZERO);
+    }
+
+    #[test]
+    fn clones_share_one_bucket() {
+        let limiter = RateLimiter::new(1);
+        let now = Local::now();
+        assert_eq!(limiter.clone().reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn shared_limiters_are_one_per_portal() {
+        let now = Local::now();
+        assert_eq!(RateLimiter::shared("https://a.example.com/clientportal2", 1).reserve(now), Duration::ZERO);
+        assert_eq!(
+            RateLimiter::shared("https://a.example.com/clientportal2", 1).reserve(now),
+            Duration::from_secs(60)
+        );
+        assert_eq!(RateLimiter::shared("https://b.example.com/clientportal2", 1).reserve(now), Duration::ZERO);
+    }
+}
@@ -0,0 +1,107 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::api::{PerfectGymClient, RawResponse, RequestPreview};
+use crate::config::Config;
+use crate::error::{GymSniperError, Result};
+use crate::redact;
+use crate::util::profile_file;
+
+pub const FAILURE_FILE: &str = "last_failure.json";
+
+/// Last-failure file for a profile (`last_failure.<profile>.json`)
+pub fn failure_path(profile: Option<&str>) -> PathBuf {
+    profile_file(FAILURE_FILE, profile)
+}
+
+/// Everything known about a booking that failed, for replaying it later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookingFailure {
+    pub recorded_at: DateTime<Local>,
+    pub class_id: u64,
+    pub class_name: String,
+    pub class_time: DateTime<Local>,
+    /// When the booking window was expected to open
+    pub window_opens: Option<DateTime<Local>>,
+    pub spot_id: Option<u64>,
+    pub error: String,
+    /// The BookClass request that was sent. Credentials are masked when saved.
+    pub request: RequestPreview,
+    /// The server's response, if the failure came from one rather than e.g. a timeout.
+    /// Credentials are masked when saved.
+    pub response: Option<RawResponse>,
+}
+
+/// The class a failed booking was for
+pub struct FailedClass<'a> {
+    pub class_id: u64,
+    pub class_name: &'a str,
+    pub class_time: DateTime<Local>,
+    pub window_opens: Option<DateTime<Local>>,
+    pub spot_id: Option<u64>,
+}
+
+/// Save the context of a booking that just failed on `client`, replacing any earlier
+/// record. Only logs if it can't be saved, so it never masks the booking error.
+pub async fn record_failure(config: &Config, client: &PerfectGymClient, class: FailedClass<'_>, error: &GymSniperError) {
+    if !config.snipe.record_failures {
+        return;
+    }
+    let result = match capture(client, class, error).await {
+        Ok(failure) => save(&failure_path(config.profile.as_deref()), &failure),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("Could not record failed booking: {}", e);
+    }
+}
+
+/// Collect the request and the server's response for a booking that just failed on `client`
+pub async fn capture(client: &PerfectGymClient, class: FailedClass<'_>, error: &GymSniperError) -> Result<BookingFailure> {
+    let request = client.preview_book_request_at(class.class_id, class.spot_id).await?;
+    Ok(BookingFailure {
+        recorded_at: Local::now(),
+        class_id: class.class_id,
+        class_name: class.class_name.to_string(),
+        class_time: class.class_time,
+        window_opens: class.window_opens,
+        spot_id: class.spot_id,
+        error: error.to_string(),
+        request,
+        response: client.last_booking_failure().await,
+    })
+}
+
+/// Write the failure with the token, cookies and any other credentials in its headers masked
+pub fn save(path: &Path, failure: &BookingFailure) -> Result<()> {
+    let mut failure = failure.clone();
+    failure.request.headers = redact::headers(&failure.request.headers);
+    if let Some(response) = &mut failure.response {
+        response.headers = redact::headers(&response.headers);
+    }
+    let json = serde_json::to_string_pretty(&failure)
+        .map_err(|e| GymSniperError::Config(format!("Failed to serialize booking failure: {}", e)))?;
+    fs::write(path, json)?;
+    Ok(())
+}
+
+/// Load the recorded failure, if there is one
+pub fn load(path: &Path) -> Result<Option<BookingFailure>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let failure = serde_json::from_str(&content).map_err(|e| {
+        GymSniperError::Config(format!("Failed to parse {}: {}", path.display(), e))
+    })?;
+    Ok(Some(failure))
+}
+
+/// Send the recorded BookClass request again, with a fresh token, and return the response
+/// untouched. Note that this really books the class if it succeeds.
+pub async fn replay(client: &PerfectGymClient, failure: &BookingFailure) -> Result<RawResponse> {
+    client.send_book_request(failure.class_id, failure.spot_id).await
+}
@@ -0,0 +1,50 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::api::{MyBooking, PerfectGymClient, DEFAULT_BOOKINGS_DAYS};
+use crate::config::Config;
+use crate::error::Result;
+use crate::snipe_queue::{SnipeEntry, SnipeQueue};
+use crate::timing::{self, SnipeTiming};
+
+/// Most recent snipe timings included in a report
+const HISTORY_LIMIT: usize = 50;
+
+/// A one-stop dump of the current setup and state, for support or moving machines
+#[derive(Debug, Serialize)]
+pub struct ReportDocument {
+    pub generated_at: DateTime<Local>,
+    pub profile: Option<String>,
+    /// Effective configuration with secrets masked
+    pub config: Config,
+    pub bookings: Vec<MyBooking>,
+    pub snipe_queue: Vec<SnipeEntry>,
+    /// Recent snipe timings, or `None` when `snipe.record_timings` is off
+    pub history: Option<Vec<SnipeTiming>>,
+}
+
+impl ReportDocument {
+    /// Gather the report. The client must be logged in.
+    pub async fn build(config: &Config, client: &PerfectGymClient, queue: &SnipeQueue) -> Result<Self> {
+        let bookings = client.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await?;
+        let snipe_queue = queue.pending_snipes().into_iter().cloned().collect();
+
+        let history = if config.snipe.record_timings {
+            let mut timings = timing::load(&timing::timings_path(config.profile.as_deref()))?;
+            let skip = timings.len().saturating_sub(HISTORY_LIMIT);
+            timings.drain(..skip);
+            Some(timings)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            generated_at: Local::now(),
+            profile: config.profile.clone(),
+            config: config.redacted(),
+            bookings,
+            snipe_queue,
+            history,
+        })
+    }
+}
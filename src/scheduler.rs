@@ -1,98 +1,701 @@
-use chrono::{Datelike, Local};
+use chrono::{DateTime, Datelike, IsoWeek, Local, NaiveDate};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
 use tokio::time::sleep;
-use tracing::{error, info};
-
-use crate::api::PerfectGymClient;
-use crate::config::Config;
-use crate::email;
-use crate::error::Result;
-use crate::util::{booking_window, weekday_matches};
-
-/// Run the scheduler to auto-book configured classes
-pub async fn run_scheduler(config: Config, client: PerfectGymClient) -> Result<()> {
-    client.login().await?;
-
-    loop {
-        let now = Local::now();
-        info!("Checking for classes to book at {}", now.format("%Y-%m-%d %H:%M:%S"));
-
-        // Get classes for the next 8 days (booking window is 7 days + 2 hours)
-        let classes = client.get_weekly_classes(8).await?;
-
-        for target in &config.targets {
-            // Find matching classes
-            for class in &classes {
-                let class_time = class.start_time;
-                let booking_opens = class_time - booking_window();
-
-                // Check if this class matches our target
-                let day_matches = target.days.as_ref().map_or(true, |days| {
-                    days.iter().any(|d| weekday_matches(d, class_time.weekday()))
-                });
+use tracing::{debug, error, info, warn};
+
+use crate::accounts;
+use crate::api::{ClassInfo, MyBooking, PerfectGymClient, DEFAULT_BOOKINGS_DAYS};
+use crate::config::{ClassTarget, Config, GymConfig, MatchingConfig, TieBreak};
+use crate::email::FailureExplanation;
+use crate::notify::{Notifier, Notifiers};
+use crate::replay::{self, FailedClass};
+use crate::shutdown::Shutdown;
+use crate::error::{BookingErrorKind, GymSniperError, Result};
+use crate::util::{booking_window, class_matches, class_name_matches, trainer_matches};
+
+/// Run the scheduler to auto-book configured classes until `shutdown` is requested
+pub async fn run_scheduler(config: Config, client: PerfectGymClient, shutdown: Shutdown) -> Result<()> {
+    client.ensure_session().await?;
+
+    // Classes skipped for a trainer change stay skipped, rather than being checked and
+    // reported again on every pass
+    let skipped = TrainerSkips::default();
+
+    // A pass waits for its bookings, so stopping between passes never cuts one short
+    while !shutdown.is_requested() {
+        scheduled_pass(&config, &client, false, &skipped).await?;
+
+        // Check every minute
+        tokio::select! {
+            _ = sleep(std::time::Duration::from_secs(60)) => {}
+            _ = shutdown.wait() => break,
+        }
+        client.refresh_token_if_due().await?;
+    }
+    info!("Scheduler stopped");
+    Ok(())
+}
 
-                let name_matches = class.name.to_lowercase().contains(&target.class_name.to_lowercase());
-                let time_matches = target.time.as_ref().map_or(true, |t| {
-                    class_time.format("%H:%M").to_string() == *t
+/// Run one pass for cron-style use: book every target whose window is already open,
+/// without waiting for windows about to open. Fails with the first booking error, if any.
+pub async fn run_once(config: &Config, client: &PerfectGymClient) -> Result<()> {
+    client.ensure_session().await?;
+
+    let failures = scheduled_pass(config, client, true, &TrainerSkips::default()).await?;
+    if !failures.is_empty() {
+        error!("{} booking(s) failed this pass", failures.len());
+    }
+    failures.into_iter().next().map_or(Ok(()), Err)
+}
+
+/// Run a scheduler for every `[[profiles]]` entry at once, each logged in as its own
+/// member. Returns when any of them stops with an error; with `once`, each profile makes a
+/// single pass and the first failure is returned after they all finish.
+pub async fn run_profiles(config: &Config, once: bool, shutdown: &Shutdown) -> Result<()> {
+    if config.profiles.is_empty() {
+        return Err(GymSniperError::Config("No [[profiles]] to schedule".to_string()));
+    }
+
+    let mut schedulers = JoinSet::new();
+    for profile in &config.profiles {
+        let config = config.for_profile(Some(&profile.name))?;
+        let name = profile.name.clone();
+        let shutdown = shutdown.clone();
+        let client = PerfectGymClient::new(&config)?;
+        schedulers.spawn(async move {
+            let result = if once {
+                run_once(&config, &client).await
+            } else {
+                run_scheduler(config, client, shutdown).await
+            };
+            (name, result)
+        });
+    }
+
+    let mut failure = None;
+    while let Some(joined) = schedulers.join_next().await {
+        match joined {
+            Ok((name, Err(e))) => {
+                error!("Scheduler for profile '{}' stopped: {}", name, e);
+                if !once {
+                    return Err(e);
+                }
+                failure.get_or_insert(e);
+            }
+            Ok((_, Ok(()))) => {}
+            Err(e) => return Err(GymSniperError::Api(format!("Scheduler task failed: {}", e))),
+        }
+    }
+    failure.map_or(Ok(()), Err)
+}
+
+/// Run a single pass over the configured targets, booking any class whose window is open
+/// or about to open. Bookings run concurrently, capped by `scheduler.max_concurrent_bookings`.
+pub async fn run_pass(config: &Config, client: &PerfectGymClient) -> Result<()> {
+    scheduled_pass(config, client, false, &TrainerSkips::default()).await.map(|_| ())
+}
+
+/// IDs of classes not booked because their trainer changed, shared by a scheduler's passes
+type TrainerSkips = Arc<Mutex<HashSet<u64>>>;
+
+/// One pass over the targets, returning the errors of any bookings that failed. With
+/// `open_only`, classes whose window has not opened yet are left for a later pass.
+/// Classes in `skipped` are left alone, and ones found to have a new trainer are added.
+async fn scheduled_pass(
+    config: &Config,
+    client: &PerfectGymClient,
+    open_only: bool,
+    skipped: &TrainerSkips,
+) -> Result<Vec<GymSniperError>> {
+    let now = Local::now();
+    info!("Checking for classes to book at {}", now.format("%Y-%m-%d %H:%M:%S"));
+
+    // Fetch far enough ahead to cover every class whose booking window has opened
+    let days = booking_window(&config.gym).num_days() as u32 + 1;
+    let classes = client.get_weekly_classes(days, None).await?;
+
+    // Weekly caps count what is already booked
+    let bookings = if config.targets.iter().any(|t| t.max_per_week.is_some()) {
+        client.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await?
+    } else {
+        Vec::new()
+    };
+
+    let slots = BookingSlots::new(config.scheduler.max_concurrent_bookings);
+    let mut tasks = JoinSet::new();
+
+    for target in &config.targets {
+        let mut weekly_cap = WeeklyCap::new(target, &bookings, &config.matching);
+
+        if target.flexible {
+            for mut day in flexible_candidates(target, &classes, &config.matching, &config.gym) {
+                day.retain(|c| !skipped.lock().unwrap().contains(&c.id));
+                if open_only {
+                    day.retain(|c| window_is_open(c, &config.gym));
+                }
+                if day.is_empty() {
+                    continue;
+                }
+                if !weekly_cap.take(&day[0]) {
+                    continue;
+                }
+                let config = config.clone();
+                let client = client.clone();
+                let slots = slots.clone();
+                let max_fill_ratio = target.max_fill_ratio;
+                let trainer = target.trainer.clone();
+                let skipped = Arc::clone(skipped);
+                tasks.spawn(async move {
+                    let _permit = slots.acquire(&day[0].name).await;
+                    book_first_available(&config, &client, &day, max_fill_ratio, trainer.as_deref(), &skipped).await
                 });
+            }
+            continue;
+        }
 
-                if name_matches && day_matches && time_matches && class.status == "Bookable" {
-                    // Check if booking window is open or about to open
-                    let time_until_booking = booking_opens.signed_duration_since(now);
-
-                    if time_until_booking.num_seconds() <= 0 {
-                        info!("Booking window open for {} at {}", class.name, class_time);
-                        match client.book_class(class.id).await {
-                            Ok(result) => {
-                                info!("Successfully booked: {}", result.name);
-                                if let Some(email_config) = &config.email {
-                                    let time_str = result.start_time.format("%a %d %b %H:%M").to_string();
-                                    email::send_booking_success(email_config, &result.name, &time_str, class.trainer.as_deref()).await;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to book: {}", e);
-                                if let Some(email_config) = &config.email {
-                                    let time_str = class_time.format("%a %d %b %H:%M").to_string();
-                                    email::send_booking_failure(email_config, &class.name, &time_str, class.trainer.as_deref(), &format!("{}", e)).await;
-                                }
-                            }
-                        }
-                    } else if time_until_booking.num_minutes() <= 5 {
-                        info!(
-                            "Booking opens in {} seconds for {} at {}",
-                            time_until_booking.num_seconds(),
-                            class.name,
-                            class_time
-                        );
-                        // Wait until booking opens
-                        sleep(std::time::Duration::from_secs(
-                            time_until_booking.num_seconds().max(0) as u64,
-                        ))
-                        .await;
-
-                        // Try to book immediately
-                        match client.book_class(class.id).await {
-                            Ok(result) => {
-                                info!("Successfully booked: {}", result.name);
-                                if let Some(email_config) = &config.email {
-                                    let time_str = result.start_time.format("%a %d %b %H:%M").to_string();
-                                    email::send_booking_success(email_config, &result.name, &time_str, class.trainer.as_deref()).await;
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to book: {}", e);
-                                if let Some(email_config) = &config.email {
-                                    let time_str = class_time.format("%a %d %b %H:%M").to_string();
-                                    email::send_booking_failure(email_config, &class.name, &time_str, class.trainer.as_deref(), &format!("{}", e)).await;
-                                }
-                            }
-                        }
-                    }
+        let matching: Vec<&ClassInfo> = classes
+            .iter()
+            .filter(|c| class_matches(c, target, &config.matching) && c.status == "Bookable")
+            .filter(|c| !skipped.lock().unwrap().contains(&c.id))
+            .collect();
+
+        for group in group_by_start_time(matching) {
+            let class = choose_class(client, target, &group).await.clone();
+            if open_only && !window_is_open(&class, &config.gym) {
+                debug!("Booking window for {} at {} not open yet, leaving it for a later run", class.name, class.start_time);
+                continue;
+            }
+            let Some(wait) = time_until_window(&class, &config.gym) else {
+                continue;
+            };
+            if !weekly_cap.take(&class) {
+                continue;
+            }
+
+            let config = config.clone();
+            let client = client.clone();
+            let slots = slots.clone();
+            let max_fill_ratio = target.max_fill_ratio;
+            let accounts = target.accounts.clone();
+            let trainer = target.trainer.clone();
+            let skipped = Arc::clone(skipped);
+            tasks.spawn(async move {
+                // Wait until booking opens, then queue for a booking slot
+                sleep(wait).await;
+                let _permit = slots.acquire(&class.name).await;
+                if over_fill_limit(&client, &class, max_fill_ratio).await {
+                    return Ok(());
+                }
+                if let Some(kind) = trainer_changed(&client, &class, trainer.as_deref()).await {
+                    skipped.lock().unwrap().insert(class.id);
+                    let time_str = class.start_time.format("%a %d %b %H:%M").to_string();
+                    let failure = FailureExplanation::from_kind(&kind);
+                    Notifiers::new(&config).notify_failure(&class.name, &time_str, class.trainer.as_deref(), &failure).await;
+                    return Err(GymSniperError::Booking(kind));
                 }
+                if accounts.is_empty() {
+                    book_and_notify(&config, &client, &class).await
+                } else {
+                    book_accounts_and_notify(&config, &class, &accounts).await
+                }
+            });
+        }
+    }
+
+    let mut failures = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(Err(e)) => failures.push(e),
+            Ok(Ok(())) => {}
+            Err(e) => failures.push(GymSniperError::Api(format!("Booking task failed: {}", e))),
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Caps how many bookings run at once, logging at debug level whenever a booking has to
+/// queue for a slot so self-throttling shows up when diagnosing a late booking
+#[derive(Debug, Clone)]
+struct BookingSlots {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl BookingSlots {
+    fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self { semaphore: Arc::new(Semaphore::new(limit)), limit }
+    }
+
+    /// Bookings currently holding a slot
+    fn in_flight(&self) -> usize {
+        self.limit - self.semaphore.available_permits()
+    }
+
+    /// Wait for a free slot. Only waits that actually block are logged.
+    async fn acquire(&self, class_name: &str) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() {
+            return Some(permit);
+        }
+
+        debug!(
+            "{} waiting for a booking slot ({} of {} in flight)",
+            class_name,
+            self.in_flight(),
+            self.limit
+        );
+        let started = Instant::now();
+        let permit = Arc::clone(&self.semaphore).acquire_owned().await.ok()?;
+        debug!(
+            "{} waited {}ms for a booking slot ({} of {} in flight)",
+            class_name,
+            started.elapsed().as_millis(),
+            self.in_flight(),
+            self.limit
+        );
+        Some(permit)
+    }
+}
+
+/// A target's `max_per_week`, counting existing bookings and bookings started this pass
+/// by ISO week. Only bookings from today on are known, so earlier classes in the current
+/// week don't count.
+struct WeeklyCap<'a> {
+    target: &'a ClassTarget,
+    counts: HashMap<IsoWeek, u32>,
+}
+
+impl<'a> WeeklyCap<'a> {
+    fn new(target: &'a ClassTarget, bookings: &[MyBooking], matching: &MatchingConfig) -> Self {
+        let mut counts = HashMap::new();
+        if target.max_per_week.is_some() {
+            for booking in bookings.iter().filter(|b| booking_matches_target(target, b, matching)) {
+                *counts.entry(booking.start_time.iso_week()).or_default() += 1;
             }
         }
+        Self { target, counts }
+    }
 
-        // Check every minute
-        sleep(std::time::Duration::from_secs(60)).await;
+    /// Count a booking of `class` against its week, or log and return false if the week is full
+    fn take(&mut self, class: &ClassInfo) -> bool {
+        let Some(max) = self.target.max_per_week else {
+            return true;
+        };
+        let week = class.start_time.iso_week();
+        let count = self.counts.entry(week).or_default();
+        if *count >= max {
+            info!(
+                "Skipping {} at {}: already {} '{}' booking(s) in week {} of {} (max_per_week = {})",
+                class.name,
+                class.start_time.format("%a %d %b %H:%M"),
+                count,
+                self.target.label(),
+                week.week(),
+                week.year(),
+                max
+            );
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+/// Whether an existing booking counts towards a target: same class ID, or a matching name
+fn booking_matches_target(target: &ClassTarget, booking: &MyBooking, matching: &MatchingConfig) -> bool {
+    match (target.class_id, &target.class_name) {
+        (Some(id), _) => booking.id == id,
+        (None, Some(name)) => class_name_matches(&booking.name, name, matching),
+        (None, None) => false,
+    }
+}
+
+/// Whether a class's booking window has already opened
+fn window_is_open(class: &ClassInfo, gym: &GymConfig) -> bool {
+    class.window_opens(gym) <= Local::now()
+}
+
+/// How long until a class's booking window opens, if it is open or opens within 5 minutes
+fn time_until_window(class: &ClassInfo, gym: &GymConfig) -> Option<std::time::Duration> {
+    let booking_opens = class.window_opens(gym);
+    let time_until_booking = booking_opens.signed_duration_since(Local::now());
+
+    if time_until_booking.num_seconds() <= 0 {
+        info!("Booking window open for {} at {}", class.name, class.start_time);
+        Some(std::time::Duration::ZERO)
+    } else if time_until_booking.num_minutes() <= 5 {
+        info!(
+            "Booking opens in {} seconds for {} at {}",
+            time_until_booking.num_seconds(),
+            class.name,
+            class.start_time
+        );
+        Some(std::time::Duration::from_secs(time_until_booking.num_seconds().max(0) as u64))
+    } else {
+        None
+    }
+}
+
+/// For a flexible target, the bookable matches on each day (earliest first) whose windows
+/// are open or about to open. Days that already have a matching booking are skipped.
+fn flexible_candidates(
+    target: &ClassTarget,
+    classes: &[ClassInfo],
+    matching: &MatchingConfig,
+    gym: &GymConfig,
+) -> Vec<Vec<ClassInfo>> {
+    let mut days: Vec<(NaiveDate, Vec<&ClassInfo>)> = Vec::new();
+    for class in classes.iter().filter(|c| class_matches(c, target, matching)) {
+        let date = class.start_time.date_naive();
+        match days.iter_mut().find(|(d, _)| *d == date) {
+            Some((_, day)) => day.push(class),
+            None => days.push((date, vec![class])),
+        }
+    }
+
+    days.into_iter()
+        .filter(|(date, day)| {
+            let booked = day.iter().any(|c| c.status == "Booked" || c.status == "Awaiting");
+            if booked {
+                debug!("Already booked a '{}' class on {}, skipping day", target.label(), date);
+            }
+            !booked
+        })
+        .map(|(_, day)| {
+            let mut bookable: Vec<ClassInfo> = day
+                .into_iter()
+                .filter(|c| c.status == "Bookable" && time_until_window(c, gym).is_some())
+                .cloned()
+                .collect();
+            bookable.sort_by_key(|c| (c.start_time, c.id));
+            bookable
+        })
+        .filter(|day| !day.is_empty())
+        .collect()
+}
+
+/// Try each class in order until one books. Stops early if the daily limit is hit.
+/// Returns the last booking error if none of them booked. Classes passed over for a new
+/// trainer are added to `skipped`.
+async fn book_first_available(
+    config: &Config,
+    client: &PerfectGymClient,
+    classes: &[ClassInfo],
+    max_fill_ratio: Option<f64>,
+    trainer: Option<&str>,
+    skipped: &TrainerSkips,
+) -> Result<()> {
+    let mut last_error = None;
+
+    for class in classes {
+        if let Some(wait) = time_until_window(class, &config.gym) {
+            sleep(wait).await;
+        }
+        if over_fill_limit(client, class, max_fill_ratio).await {
+            continue;
+        }
+        if let Some(kind) = trainer_changed(client, class, trainer).await {
+            skipped.lock().unwrap().insert(class.id);
+            last_error = Some((class, GymSniperError::Booking(kind)));
+            continue;
+        }
+
+        match client.book_class(class.id).await {
+            Ok(result) => {
+                info!("Successfully booked: {}", result.name);
+                let time_str = result.start_time.format("%a %d %b %H:%M").to_string();
+                Notifiers::new(config).notify_success(&result.name, &time_str, class.trainer.as_deref()).await;
+                return Ok(());
+            }
+            Err(e @ GymSniperError::Booking(BookingErrorKind::DailyLimit)) => {
+                error!("Daily booking limit reached - not trying other classes that day");
+                replay::record_failure(config, client, failed_class(class, &config.gym), &e).await;
+                last_error = Some((class, e));
+                break;
+            }
+            Err(e) => {
+                info!("Could not book {} at {} ({}), trying next class...", class.name, class.start_time.format("%H:%M"), e);
+                replay::record_failure(config, client, failed_class(class, &config.gym), &e).await;
+                last_error = Some((class, e));
+            }
+        }
+    }
+
+    if let Some((class, e)) = last_error {
+        error!("Failed to book any '{}' class that day: {}", class.name, e);
+        let time_str = class.start_time.format("%a %d %b %H:%M").to_string();
+        let failure = FailureExplanation::from_error(&e);
+        Notifiers::new(config).notify_failure(&class.name, &time_str, class.trainer.as_deref(), &failure).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Why not to book the class, if its trainer no longer contains `trainer` (e.g. a substitute
+/// was put in). Classes whose trainer can't be checked are booked as normal.
+async fn trainer_changed(client: &PerfectGymClient, class: &ClassInfo, trainer: Option<&str>) -> Option<BookingErrorKind> {
+    let wanted = trainer?;
+    let details = match client.get_class_details(class.id).await {
+        Ok(details) => details,
+        Err(e) => {
+            warn!("Could not check the trainer of {}, booking anyway: {}", class.name, e);
+            return None;
+        }
+    };
+    if trainer_matches(details.trainer.as_deref(), wanted) {
+        return None;
+    }
+    let kind = BookingErrorKind::TrainerChanged { wanted: wanted.to_string(), trainer: details.trainer };
+    info!("Skipping {} at {}: {}", class.name, class.start_time.format("%a %d %b %H:%M"), kind);
+    Some(kind)
+}
+
+/// True if the class is already at least `max_fill_ratio` full and should not be booked.
+/// Classes whose occupancy can't be determined are booked as normal.
+async fn over_fill_limit(client: &PerfectGymClient, class: &ClassInfo, max_fill_ratio: Option<f64>) -> bool {
+    let Some(max) = max_fill_ratio else {
+        return false;
+    };
+    let details = match client.get_class_details(class.id).await {
+        Ok(details) => details,
+        Err(e) => {
+            warn!("Could not check occupancy of {}, booking anyway: {}", class.name, e);
+            return false;
+        }
+    };
+    match details.fill_ratio() {
+        Some(ratio) if ratio >= max => {
+            info!(
+                "Skipping {} at {}: {:.0}% full ({}/{}), max_fill_ratio is {:.0}%",
+                class.name,
+                class.start_time.format("%a %d %b %H:%M"),
+                ratio * 100.0,
+                details.booked_count.unwrap_or_default(),
+                details.capacity.unwrap_or_default(),
+                max * 100.0
+            );
+            true
+        }
+        Some(_) => false,
+        None => {
+            debug!("Capacity unknown for {}, booking anyway", class.name);
+            false
+        }
+    }
+}
+
+/// Group classes that start at the same moment, preserving start-time order
+fn group_by_start_time(classes: Vec<&ClassInfo>) -> Vec<Vec<&ClassInfo>> {
+    let mut groups: Vec<(DateTime<Local>, Vec<&ClassInfo>)> = Vec::new();
+    for class in classes {
+        match groups.iter_mut().find(|(t, _)| *t == class.start_time) {
+            Some((_, group)) => group.push(class),
+            None => groups.push((class.start_time, vec![class])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Pick a single class from simultaneous matches so only one booking is attempted
+async fn choose_class<'a>(
+    client: &PerfectGymClient,
+    target: &ClassTarget,
+    group: &[&'a ClassInfo],
+) -> &'a ClassInfo {
+    let mut by_id = group.to_vec();
+    by_id.sort_by_key(|c| c.id);
+    if by_id.len() == 1 {
+        return by_id[0];
+    }
+
+    let (chosen, reason) = match target.tie_break {
+        TieBreak::FirstById => (by_id[0], "lowest class ID".to_string()),
+        TieBreak::PreferredZone => {
+            let preferred = target.preferred_zone.as_deref().unwrap_or_default().to_lowercase();
+            match by_id.iter().find(|c| c.zone.to_lowercase() == preferred) {
+                Some(class) => (*class, format!("in preferred zone {}", class.zone)),
+                None => (by_id[0], "lowest class ID (no class in preferred zone)".to_string()),
+            }
+        }
+        TieBreak::FewestBooked => {
+            let mut best = (by_id[0], u32::MAX);
+            for class in &by_id {
+                let booked = match client.get_class_details(class.id).await {
+                    Ok(details) => details.booked_count.unwrap_or(u32::MAX),
+                    Err(_) => u32::MAX,
+                };
+                if booked < best.1 {
+                    best = (class, booked);
+                }
+            }
+            if best.1 == u32::MAX {
+                (best.0, "lowest class ID (booking counts unavailable)".to_string())
+            } else {
+                (best.0, format!("fewest booked ({})", best.1))
+            }
+        }
+    };
+
+    let skipped: Vec<String> = by_id
+        .iter()
+        .filter(|c| c.id != chosen.id)
+        .map(|c| format!("{} ({})", c.id, c.zone))
+        .collect();
+    info!(
+        "{} classes match '{}' at {}: choosing {} in {} by {}; skipping {}",
+        by_id.len(),
+        target.label(),
+        chosen.start_time.format("%a %d %b %H:%M"),
+        chosen.id,
+        chosen.zone,
+        reason,
+        skipped.join(", ")
+    );
+
+    chosen
+}
+
+/// Book a class and send the matching notification
+async fn book_and_notify(config: &Config, client: &PerfectGymClient, class: &ClassInfo) -> Result<()> {
+    match client.book_class(class.id).await {
+        Ok(result) => {
+            info!("Successfully booked: {}", result.name);
+            let time_str = result.start_time.format("%a %d %b %H:%M").to_string();
+            Notifiers::new(config).notify_success(&result.name, &time_str, class.trainer.as_deref()).await;
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to book: {}", e);
+            replay::record_failure(config, client, failed_class(class, &config.gym), &e).await;
+            let time_str = class.start_time.format("%a %d %b %H:%M").to_string();
+            Notifiers::new(config).notify_failure(&class.name, &time_str, class.trainer.as_deref(), &FailureExplanation::from_error(&e)).await;
+            Err(e)
+        }
+    }
+}
+
+fn failed_class<'a>(class: &'a ClassInfo, gym: &GymConfig) -> FailedClass<'a> {
+    FailedClass {
+        class_id: class.id,
+        class_name: &class.name,
+        class_time: class.start_time,
+        window_opens: Some(class.window_opens(gym)),
+        spot_id: None,
+    }
+}
+
+/// Book a class for the main account and each of `accounts`, notifying per account.
+/// Returns the first account's booking error, if any.
+async fn book_accounts_and_notify(config: &Config, class: &ClassInfo, accounts: &[String]) -> Result<()> {
+    let outcomes = match accounts::book_for_accounts(config, class.id, accounts).await {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            error!("Failed to book {} for {}: {}", class.name, accounts.join(", "), e);
+            return Err(e);
+        }
+    };
+
+    let mut failure = None;
+
+    let time_str = class.start_time.format("%a %d %b %H:%M").to_string();
+    for outcome in outcomes {
+        let label = format!("{} ({})", class.name, outcome.account);
+        match outcome.result {
+            Ok(()) => {
+                info!("Successfully booked: {}", label);
+                Notifiers::new(config).notify_success(&label, &time_str, class.trainer.as_deref()).await;
+            }
+            Err(e) => {
+                error!("Failed to book {}: {}", label, e);
+                Notifiers::new(config).notify_failure(&label, &time_str, class.trainer.as_deref(), &FailureExplanation::from_error(&e)).await;
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    failure.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::LogBuffer;
+
+    fn hiit_class(id: u64, start: DateTime<Local>) -> ClassInfo {
+        ClassInfo {
+            id,
+            name: "HIIT Express".to_string(),
+            start_time: start,
+            status: "Bookable".to_string(),
+            trainer: None,
+            zone: "Studio A".to_string(),
+            bookable_from: None,
+            duration_minutes: 30,
+            capacity: None,
+            booked_count: None,
+        }
+    }
+
+    #[test]
+    fn weekly_cap_buckets_by_iso_week() {
+        use chrono::TimeZone;
+
+        let target: ClassTarget = toml::from_str("class_name = \"hiit\"\nmax_per_week = 2").unwrap();
+        let at = |d: u32| Local.with_ymd_and_hms(2025, 3, d, 18, 0, 0).unwrap();
+        // Sunday 9 March closes one ISO week, Monday 10 March starts the next
+        let booked = MyBooking {
+            id: 1,
+            name: "HIIT".to_string(),
+            start_time: at(9),
+            status: "Booked".to_string(),
+            waitlist_position: None,
+            trainer: None,
+            booked_count: None,
+            capacity: None,
+            bookable_from: None,
+            description: None,
+            zone: None,
+            duration_minutes: 0,
+        };
+        let mut cap = WeeklyCap::new(&target, &[booked], &MatchingConfig::default());
+
+        assert!(cap.take(&hiit_class(2, at(8))));
+        assert!(!cap.take(&hiit_class(3, at(7))), "week of 3-9 March already has two");
+        assert!(cap.take(&hiit_class(4, at(10))));
+        assert!(cap.take(&hiit_class(5, at(16))));
+        assert!(!cap.take(&hiit_class(6, at(12))));
+    }
+
+    #[tokio::test]
+    async fn saturated_slots_log_wait_and_in_flight_count() {
+        let logs = LogBuffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(logs.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let slots = BookingSlots::new(1);
+        let held = slots.acquire("Spin").await.unwrap();
+        assert_eq!(slots.in_flight(), 1);
+
+        let release = async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            drop(held);
+        };
+        let (permit, ()) = tokio::join!(slots.acquire("Yoga"), release);
+        assert!(permit.is_some());
+
+        let output = logs.contents();
+        assert!(!output.contains("Spin waiting"), "uncontended acquire logged: {}", output);
+        assert!(output.contains("Yoga waiting for a booking slot (1 of 1 in flight)"), "{}", output);
+        let waited = output.lines().find(|l| l.contains("Yoga waited")).expect("wait not logged");
+        let ms: u64 = waited.split("waited ").nth(1).unwrap().split("ms").next().unwrap().parse().unwrap();
+        assert!(ms >= 40, "waited only {}ms", ms);
     }
 }
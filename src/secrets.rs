@@ -0,0 +1,29 @@
+//! Login passwords kept in the system keyring (macOS Keychain, Windows Credential
+//! Manager, Secret Service on Linux) rather than in `config.toml`.
+
+use keyring::Entry;
+
+use crate::error::{GymSniperError, Result};
+
+/// The password stored under `service` for `email`
+pub fn stored_password(service: &str, email: &str) -> Result<String> {
+    entry(service, email)?.get_password().map_err(|e| match e {
+        keyring::Error::NoEntry => GymSniperError::Auth(format!(
+            "No password for {} in keyring service '{}' (store one with `gym_sniper set-password`)",
+            email, service
+        )),
+        e => GymSniperError::Auth(format!("Failed to read password from keyring service '{}': {}", service, e)),
+    })
+}
+
+/// Store `password` under `service` for `email`, replacing any stored before
+pub fn store_password(service: &str, email: &str, password: &str) -> Result<()> {
+    entry(service, email)?.set_password(password).map_err(|e| {
+        GymSniperError::Config(format!("Failed to store password in keyring service '{}': {}", service, e))
+    })
+}
+
+fn entry(service: &str, email: &str) -> Result<Entry> {
+    Entry::new(service, email)
+        .map_err(|e| GymSniperError::Config(format!("Invalid keyring entry '{}' for {}: {}", service, email, e)))
+}
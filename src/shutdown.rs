@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// A request to stop a long-running loop (the snipe daemon or scheduler) at its next
+/// safe point, rather than mid-booking or mid-save
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A shutdown requested by Ctrl+C (or SIGTERM on Unix, as sent by systemd and launchd).
+    /// A second signal exits straight away, for when the current snipe can't be waited for.
+    pub fn on_signal() -> Self {
+        let shutdown = Self::new();
+        let handle = shutdown.clone();
+        tokio::spawn(async move {
+            if !wait_for_signal().await {
+                return;
+            }
+            info!("Shutdown requested, finishing the current work first (press Ctrl+C again to quit now)");
+            handle.request();
+            if wait_for_signal().await {
+                warn!("Second shutdown signal, quitting immediately");
+                std::process::exit(130);
+            }
+        });
+        shutdown
+    }
+
+    pub fn request(&self) {
+        self.inner.requested.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once shutdown is requested (immediately if it already has been)
+    pub async fn wait(&self) {
+        let notified = self.inner.notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        if self.is_requested() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Wait for Ctrl+C or SIGTERM. Returns false if no handler could be installed.
+async fn wait_for_signal() -> bool {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                warn!("Could not listen for SIGTERM: {}", e);
+                return tokio::signal::ctrl_c().await.is_ok();
+            }
+        };
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.is_ok(),
+            _ = terminate.recv() => true,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn wait_returns_once_requested_from_any_clone() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_requested());
+
+        let waiter = shutdown.clone();
+        let waiting = tokio::spawn(async move { waiter.wait().await });
+        shutdown.clone().request();
+        tokio::time::timeout(Duration::from_secs(1), waiting).await.unwrap().unwrap();
+
+        // Already requested, so later waits return straight away
+        assert!(shutdown.is_requested());
+        tokio::time::timeout(Duration::from_secs(1), shutdown.wait()).await.unwrap();
+    }
+}
@@ -1,21 +1,68 @@
-use chrono::{Duration, Local};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::{self, JoinSet};
 use crate::util::booking_window;
-use tokio::time::sleep;
-use tracing::{error, info, warn};
-
-use crate::api::PerfectGymClient;
-use crate::config::Config;
-use crate::email;
-use crate::error::Result;
-use crate::snipe_queue::SnipeQueue;
-use crate::util::format_duration;
-
-/// Snipe a class - wait for booking window and book immediately
-pub async fn snipe_class(config: &Config, client: &PerfectGymClient, class_id: u64) -> Result<()> {
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::api::{MyBooking, PerfectGymClient, DEFAULT_BOOKINGS_DAYS};
+use crate::clock::{self, Clock, SystemClock};
+use crate::config::{ClockSync, Config, OnFull};
+use crate::confirm::BookingConfirmations;
+use crate::defend::BookingDefender;
+use crate::email::FailureExplanation;
+use crate::error::{BookingErrorKind, GymSniperError, Result};
+use crate::history;
+use crate::hold;
+use crate::notify::{Notifier, Notifiers};
+use crate::replay::{self, FailedClass};
+use crate::shutdown::Shutdown;
+use crate::snipe_queue::{SnipeEntry, SnipeQueue};
+use crate::timing::{self, SnipeTiming};
+use crate::util::{format_duration, trainer_matches, AttemptDelays, Backoff};
+use crate::watch::WaitlistMonitor;
+
+/// Snipe a class - wait for booking window and book immediately.
+/// `spot_id` picks a specific spot (e.g. a numbered bike) for classes that offer them.
+pub async fn snipe_class(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    spot_id: Option<u64>,
+) -> Result<()> {
+    snipe_class_with_clock(config, client, class_id, spot_id, &SystemClock).await
+}
+
+/// Snipe a class, timing the wait for the booking window with the given clock
+#[instrument(name = "snipe", skip_all, fields(class_id = class_id))]
+pub async fn snipe_class_with_clock(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    spot_id: Option<u64>,
+    clock: &impl Clock,
+) -> Result<()> {
+    snipe_class_recorded(config, client, class_id, spot_id, None, clock, &mut AttemptTimes::default()).await
+}
+
+/// Snipe a class, noting each booking attempt in `times`. With `required_trainer`, the
+/// booking is skipped if the class's trainer no longer matches when the window opens.
+async fn snipe_class_recorded(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    spot_id: Option<u64>,
+    required_trainer: Option<&str>,
+    clock: &impl Clock,
+    times: &mut AttemptTimes,
+) -> Result<()> {
     // Get initial class details
     let booking = client.get_class_details(class_id).await?;
     let class_time = booking.start_time;
-    let booking_window_opens = class_time - booking_window();
+    // The window opens by the gym's clock; convert to ours if they disagree
+    let booking_window_opens = booking.window_opens(&config.gym) - clock_offset(config, client).await;
+    let attempts_start = booking_window_opens - Duration::milliseconds(config.gym.window_slack_ms as i64);
 
     info!(
         "Target: {} at {}",
@@ -31,16 +78,20 @@ pub async fn snipe_class(config: &Config, client: &PerfectGymClient, class_id: u
     // If already bookable, try immediately
     if booking.status == "Bookable" {
         info!("Class is already bookable! Attempting to book...");
-        return attempt_booking(config, class_id).await;
+        check_fill_ratio(config, client, class_id).await?;
+        check_trainer(config, client, class_id, required_trainer).await?;
+        return attempt_booking_with_clock(config, class_id, spot_id, clock, times).await;
     }
 
     // If already booked or on waitlist, nothing to do
     if booking.status == "Booked" || booking.status == "Awaiting" {
         info!("Already booked or on waitlist for this class!");
+        times.on_waitlist = booking.status == "Awaiting";
+        times.waitlist_position = booking.waitlist_position;
         return Ok(());
     }
 
-    let now = Local::now();
+    let now = clock.now();
     let time_until_window = booking_window_opens.signed_duration_since(now);
 
     // If more than 1 minute until window, sleep until 1 minute before
@@ -57,13 +108,37 @@ pub async fn snipe_class(config: &Config, client: &PerfectGymClient, class_id: u
         // Sleep in chunks to show progress
         let total_sleep_secs = sleep_duration.num_seconds().max(0) as u64;
         let mut slept_secs = 0u64;
+        let keep_alive_every = Duration::minutes(config.snipe.keep_alive_minutes as i64);
+        let mut keep_alive_due = (config.snipe.keep_alive_minutes > 0).then(|| clock.now() + keep_alive_every);
 
         while slept_secs < total_sleep_secs {
             let remaining = total_sleep_secs - slept_secs;
-            let chunk = remaining.min(3600); // Sleep max 1 hour at a time
-            sleep(std::time::Duration::from_secs(chunk)).await;
+            let mut chunk = remaining.min(3600); // Sleep max 1 hour at a time
+            // Wake up in time to refresh the token on its configured interval
+            if let Some(due) = client.token_refresh_due_at().await {
+                let until_due = due.signed_duration_since(clock.now()).num_seconds().max(1) as u64;
+                chunk = chunk.min(until_due);
+            }
+            if let Some(due) = keep_alive_due {
+                let until_due = due.signed_duration_since(clock.now()).num_seconds().max(1) as u64;
+                chunk = chunk.min(until_due);
+            }
+            clock.sleep(std::time::Duration::from_secs(chunk)).await;
             slept_secs += chunk;
 
+            match client.refresh_token_if_due().await {
+                Ok(true) => info!("Login token refreshed."),
+                Ok(false) => {}
+                Err(e) => warn!("Token refresh failed, will log in again before the window: {}", e),
+            }
+
+            if let Some(due) = keep_alive_due
+                && clock.now() >= due
+            {
+                keep_alive(client, class_id).await;
+                keep_alive_due = Some(clock.now() + keep_alive_every);
+            }
+
             if remaining > 3600 {
                 let hours_left = (remaining - chunk) / 3600;
                 let mins_left = ((remaining - chunk) % 3600) / 60;
@@ -74,199 +149,823 @@ pub async fn snipe_class(config: &Config, client: &PerfectGymClient, class_id: u
 
     // Refresh token 1 minute before window
     info!("Refreshing login token...");
-    let fresh_client = PerfectGymClient::new(config);
-    fresh_client.login().await?;
+    let fresh_client = PerfectGymClient::new(config)?;
+    fresh_client.login_fresh().await?;
     info!("Token refreshed.");
 
-    // Sleep until exactly when window opens
-    let now = Local::now();
-    let time_until_window = booking_window_opens.signed_duration_since(now);
+    // Warm up DNS/TLS shortly before the window so the first booking request is fast
+    if config.snipe.prime_lead_secs > 0 {
+        let prime_at = attempts_start - Duration::seconds(config.snipe.prime_lead_secs as i64);
+        let until_prime = prime_at.signed_duration_since(clock.now());
+        if until_prime.num_milliseconds() > 0 {
+            clock.sleep(std::time::Duration::from_millis(until_prime.num_milliseconds() as u64)).await;
+        }
+        if attempts_start > clock.now() {
+            debug!("Priming connection {}s before the window...", config.snipe.prime_lead_secs);
+            if let Err(e) = fresh_client.get_class_details(class_id).await {
+                warn!("Connection warm-up request failed: {}", e);
+            }
+        }
+    }
+
+    // Sleep until the window opens, less any configured slack
+    let now = clock.now();
+    let time_until_window = attempts_start.signed_duration_since(now);
     if time_until_window.num_milliseconds() > 0 {
         info!("Waiting {}ms until booking window opens...", time_until_window.num_milliseconds());
-        sleep(std::time::Duration::from_millis(time_until_window.num_milliseconds() as u64)).await;
+        clock.sleep(std::time::Duration::from_millis(time_until_window.num_milliseconds() as u64)).await;
     }
 
+    check_fill_ratio(config, &fresh_client, class_id).await?;
+    check_trainer(config, &fresh_client, class_id, required_trainer).await?;
     info!("Booking window open - starting booking attempts NOW!");
-    attempt_booking(config, class_id).await
+    let result = run_attempts(config, &fresh_client, class_id, spot_id, Some(&booking), clock, times).await;
+
+    if let Some(first_attempt_at) = times.first_attempt_at
+        && config.snipe.record_timings
+    {
+        let record = SnipeTiming {
+            club_id: fresh_client.club_id(),
+            class_id,
+            class_name: booking.name.clone(),
+            class_time,
+            // Without `window_offset_secs`, so the offset `estimate-window` suggests stays absolute
+            expected_open: booking.bookable_from.unwrap_or(class_time - booking_window(&config.gym)),
+            first_attempt_at,
+            opened_at: times.opened_at,
+            attempts: times.attempts,
+        };
+        if let Err(e) = timing::record(&timing::timings_path(config.profile.as_deref()), &record) {
+            warn!("Could not record snipe timing: {}", e);
+        }
+    }
+
+    if let Err(e) = &result {
+        let class = FailedClass {
+            class_id,
+            class_name: &booking.name,
+            class_time,
+            window_opens: Some(booking_window_opens),
+            spot_id,
+        };
+        replay::record_failure(config, &fresh_client, class, e).await;
+    }
+
+    result
+}
+
+/// Keep an idle session warm with a cheap authenticated request, logging in again if the
+/// portal has dropped it (`snipe.keep_alive_minutes`)
+async fn keep_alive(client: &PerfectGymClient, class_id: u64) {
+    match client.get_class_details(class_id).await {
+        Ok(_) => debug!("Keep-alive request OK"),
+        Err(e) => {
+            warn!("Keep-alive request failed ({}), logging in again", e);
+            if let Err(e) = client.login().await {
+                warn!("Login after failed keep-alive failed, will log in again before the window: {}", e);
+            }
+        }
+    }
+}
+
+/// How far the gym's clock is ahead of ours, from the source `snipe.clock_sync` names
+async fn clock_offset(config: &Config, client: &PerfectGymClient) -> Duration {
+    let (offset, source) = match config.snipe.clock_sync {
+        ClockSync::Gym => (client.server_offset().await, "gym server at login"),
+        ClockSync::Ntp => match clock::ntp_offset(&config.snipe.ntp_server).await {
+            Ok(offset) => (offset, "NTP"),
+            Err(e) => {
+                warn!("NTP server {} didn't answer ({}), checking against the gym instead", config.snipe.ntp_server, e);
+                (clock::server_offset(client.base_url()).await, "gym server")
+            }
+        },
+        ClockSync::Off => (Duration::zero(), "off"),
+    };
+    info!("Clock offset: {:+.3}s ({})", offset.num_milliseconds() as f64 / 1000.0, source);
+    offset
+}
+
+/// Refuse to book when the class is already at least `snipe.max_fill_ratio` full.
+/// Costs one extra request at window time, so only runs when the option is set.
+async fn check_fill_ratio(config: &Config, client: &PerfectGymClient, class_id: u64) -> Result<()> {
+    let Some(max) = config.snipe.max_fill_ratio else {
+        return Ok(());
+    };
+    match client.get_class_details(class_id).await {
+        Ok(details) => match details.fill_ratio() {
+            Some(ratio) if ratio >= max => {
+                info!(
+                    "Skipping {}: {:.0}% full ({}/{}), max_fill_ratio is {:.0}%",
+                    details.name,
+                    ratio * 100.0,
+                    details.booked_count.unwrap_or_default(),
+                    details.capacity.unwrap_or_default(),
+                    max * 100.0
+                );
+                Err(GymSniperError::Booking(BookingErrorKind::TooFull(ratio)))
+            }
+            _ => Ok(()),
+        },
+        Err(e) => {
+            warn!("Could not check occupancy, booking anyway: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Refuse to book when the class's trainer no longer contains `wanted`, e.g. after a
+/// substitute was put in, and send a failure notification saying so. Costs one extra
+/// request at window time, so only runs when a trainer is required.
+async fn check_trainer(config: &Config, client: &PerfectGymClient, class_id: u64, wanted: Option<&str>) -> Result<()> {
+    let Some(wanted) = wanted else {
+        return Ok(());
+    };
+    let details = match client.get_class_details(class_id).await {
+        Ok(details) => details,
+        Err(e) => {
+            warn!("Could not check the trainer, booking anyway: {}", e);
+            return Ok(());
+        }
+    };
+    if trainer_matches(details.trainer.as_deref(), wanted) {
+        return Ok(());
+    }
+
+    let kind = BookingErrorKind::TrainerChanged { wanted: wanted.to_string(), trainer: details.trainer.clone() };
+    info!("Skipping {}: {}", details.name, kind);
+    let time_str = details.start_time.format("%a %d %b %H:%M").to_string();
+    Notifiers::new(config)
+        .notify_failure(&details.name, &time_str, details.trainer.as_deref(), &FailureExplanation::from_kind(&kind))
+        .await;
+    Err(GymSniperError::Booking(kind))
 }
 
-/// Attempt to book a class with retries
+/// When booking attempts were made, for timing telemetry and the snipe queue's attempt log
+#[derive(Debug, Default)]
+struct AttemptTimes {
+    first_attempt_at: Option<DateTime<Local>>,
+    opened_at: Option<DateTime<Local>>,
+    attempts: u32,
+    /// Each attempt's time and outcome
+    log: Vec<(DateTime<Local>, String)>,
+    /// Ended on the waitlist rather than with a place
+    on_waitlist: bool,
+    waitlist_position: Option<u32>,
+}
+
+/// Attempt to book a class with retries, using a freshly logged-in client
 pub async fn attempt_booking(config: &Config, class_id: u64) -> Result<()> {
-    // Login token should already be fresh from snipe_class
-    // but refresh if this is called directly (e.g., from book command)
-    let client = PerfectGymClient::new(config);
+    attempt_booking_with_clock(config, class_id, None, &SystemClock, &mut AttemptTimes::default()).await
+}
+
+async fn attempt_booking_with_clock(
+    config: &Config,
+    class_id: u64,
+    spot_id: Option<u64>,
+    clock: &impl Clock,
+    times: &mut AttemptTimes,
+) -> Result<()> {
+    let client = PerfectGymClient::new(config)?;
     client.login().await?;
+    run_attempts(config, &client, class_id, spot_id, None, clock, times).await
+}
 
-    // Get class details for email notifications
-    let class_details = client.get_class_details(class_id).await.ok();
-    let class_name = class_details.as_ref().map(|d| d.name.as_str()).unwrap_or("Unknown");
-    let class_time = class_details.as_ref().map(|d| d.start_time.format("%a %d %b %H:%M").to_string()).unwrap_or_default();
-    let class_trainer = class_details.as_ref().and_then(|d| d.trainer.as_deref());
+/// Run booking attempts, noting when each phase happened
+#[instrument(name = "booking", skip_all, fields(class_id = class_id, spot_id = ?spot_id))]
+async fn run_attempts(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    spot_id: Option<u64>,
+    details: Option<&MyBooking>,
+    clock: &impl Clock,
+    times: &mut AttemptTimes,
+) -> Result<()> {
+    attempt_booking_timed(config, client, class_id, spot_id, details, times, clock).await
+}
+
+async fn attempt_booking_timed(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    spot_id: Option<u64>,
+    details: Option<&MyBooking>,
+    times: &mut AttemptTimes,
+    clock: &impl Clock,
+) -> Result<()> {
+    // Get class details for notifications, unless the caller already has them
+    let fetched = match details {
+        Some(_) => None,
+        None => client.get_class_details(class_id).await.ok(),
+    };
+    let class_details = details.or(fetched.as_ref());
+    let class_name = class_details.map(|d| d.name.as_str()).unwrap_or("Unknown");
+    let class_time = class_details.map(|d| d.start_time.format("%a %d %b %H:%M").to_string()).unwrap_or_default();
+    let class_trainer = class_details.and_then(|d| d.trainer.as_deref());
 
     let mut attempts = 0;
-    const MAX_ATTEMPTS: u32 = 10;
+    let max_attempts = config.snipe.max_attempts.max(1);
+    let mut delays = AttemptDelays::new(&config.stealth.attempt_delay);
+    let mut backoff = Backoff::new(
+        std::time::Duration::from_millis(config.snipe.initial_backoff_ms),
+        std::time::Duration::from_millis(config.snipe.max_backoff_ms),
+        config.snipe.backoff_multiplier,
+    );
 
     loop {
         attempts += 1;
+        times.attempts = attempts;
+        let attempt_at = clock.now();
+        times.first_attempt_at.get_or_insert(attempt_at);
+
+        let outcome = client.book_class_at(class_id, spot_id).await;
+        let noted = match &outcome {
+            Ok(result) => format!("Booked {}", result.name),
+            Err(e) => e.to_string(),
+        };
+        times.log.push((attempt_at, noted));
+        let too_soon = matches!(&outcome, Err(GymSniperError::Booking(BookingErrorKind::TooSoon)));
+        if !too_soon {
+            times.opened_at.get_or_insert(attempt_at);
+        }
 
-        match client.book_class(class_id).await {
+        match outcome {
             Ok(result) => {
                 info!(
+                    attempt = attempts,
+                    status = "booked",
                     "SUCCESS! Booked {} at {} (attempt #{})",
                     result.name,
                     result.start_time.format("%a %d %b %H:%M"),
                     attempts
                 );
 
-                // Send success email
-                if let Some(email_config) = &config.email {
-                    let time_str = result.start_time.format("%a %d %b %H:%M").to_string();
-                    email::send_booking_success(email_config, &result.name, &time_str, class_trainer).await;
-                }
+                // Send success notifications
+                let time_str = result.start_time.format("%a %d %b %H:%M").to_string();
+                Notifiers::new(config).notify_success(&result.name, &time_str, class_trainer).await;
 
                 return Ok(());
             }
+            Err(GymSniperError::Booking(BookingErrorKind::DailyLimit)) => {
+                // Permanent failure - stop immediately
+                error!(attempt = attempts, status = "daily_limit", "Daily booking limit reached - cannot book another class today");
+                Notifiers::new(config).notify_failure(
+                    class_name,
+                    &class_time,
+                    class_trainer,
+                    &FailureExplanation::from_kind(&BookingErrorKind::DailyLimit),
+                ).await;
+                return Err(GymSniperError::Booking(BookingErrorKind::DailyLimit));
+            }
+            Err(GymSniperError::Booking(
+                kind @ (BookingErrorKind::PaymentRequired | BookingErrorKind::InsufficientFunds),
+            )) => {
+                // Retrying won't help until the payment is sorted out
+                error!(attempt = attempts, status = "payment_required", "Booking needs payment: {}", kind);
+                Notifiers::new(config).notify_failure(class_name, &class_time, class_trainer, &FailureExplanation::from_kind(&kind)).await;
+                return Err(GymSniperError::Booking(kind));
+            }
+            Err(GymSniperError::Booking(BookingErrorKind::WaiverRequired)) => {
+                // Only reached without auto_accept_waivers, or if accepting didn't help
+                error!(attempt = attempts, status = "waiver_required", "Booking needs a waiver accepted");
+                let kind = BookingErrorKind::WaiverRequired;
+                Notifiers::new(config).notify_failure(class_name, &class_time, class_trainer, &FailureExplanation::from_kind(&kind)).await;
+                return Err(GymSniperError::Booking(kind));
+            }
+            Err(GymSniperError::Booking(BookingErrorKind::AlreadyBooked)) => {
+                info!(attempt = attempts, status = "already_booked", "Already booked or on waitlist!");
+                return Ok(());
+            }
             Err(e) => {
-                let err_str = format!("{}", e);
-
-                // Permanent failures - stop immediately
-                if err_str.contains("DailyBookingLimitReached") {
-                    error!("Daily booking limit reached - cannot book another class today");
-                    if let Some(email_config) = &config.email {
-                        email::send_booking_failure(
-                            email_config,
-                            class_name,
-                            &class_time,
-                            class_trainer,
-                            "Daily booking limit reached - you already have a class booked on this day",
-                        ).await;
+                match &e {
+                    GymSniperError::Booking(BookingErrorKind::TooSoon) => {
+                        info!(attempt = attempts, status = "too_soon", "Attempt #{}: Window not open yet, retrying...", attempts);
                     }
-                    return Err(crate::error::GymSniperError::Api("Daily booking limit reached".to_string()));
+                    GymSniperError::Booking(BookingErrorKind::Full)
+                        if config.snipe.on_full == OnFull::WaitlistImmediately =>
+                    {
+                        info!(
+                            attempt = attempts,
+                            status = "full",
+                            "Attempt #{}: Class is full, joining the waitlist without retrying",
+                            attempts
+                        );
+                        return join_waitlist_once(config, client, class_id, class_name, &class_time, class_trainer, times).await;
+                    }
+                    GymSniperError::Booking(BookingErrorKind::Full) => {
+                        // A spot may still free up in the first seconds, so keep trying before the waitlist
+                        info!(attempt = attempts, status = "full", "Attempt #{}: Class is full, retrying...", attempts);
+                    }
+                    _ => error!(attempt = attempts, status = "error", "Attempt #{}: {}", attempts, e),
                 }
 
-                if err_str.contains("TooSoonToBook") {
-                    info!("Attempt #{}: Window not open yet, retrying...", attempts);
-                } else if err_str.contains("already") || err_str.contains("Already") {
-                    info!("Already booked or on waitlist!");
-                    return Ok(());
-                } else if err_str.contains("Full") || err_str.contains("full") || err_str.contains("Awaitable") {
-                    // Class is full - try to join waitlist
-                    info!("Attempt #{}: Class is full, attempting to join waitlist...", attempts);
-                } else {
-                    error!("Attempt #{}: {}", attempts, e);
+                // Stop after max attempts
+                if attempts >= max_attempts {
+                    if matches!(e, GymSniperError::Booking(BookingErrorKind::Full)) {
+                        info!(attempt = attempts, status = "full", "Still full after {} attempts, joining the waitlist", attempts);
+                        return join_waitlist_once(config, client, class_id, class_name, &class_time, class_trainer, times).await;
+                    }
+                    error!(attempt = attempts, status = "gave_up", "Gave up after {} attempts", attempts);
+
+                    // Send failure notifications
+                    Notifiers::new(config).notify_failure(
+                        class_name,
+                        &class_time,
+                        class_trainer,
+                        &FailureExplanation::from_error(&e),
+                    ).await;
+
+                    return Err(e);
                 }
             }
         }
 
-        // Stop after max attempts
-        if attempts >= MAX_ATTEMPTS {
-            error!("Gave up after {} attempts", attempts);
+        clock.sleep(delays.next_delay() + backoff.next_delay()).await;
+    }
+}
 
-            // Send failure email
-            if let Some(email_config) = &config.email {
-                email::send_booking_failure(
-                    email_config,
-                    class_name,
-                    &class_time,
-                    class_trainer,
-                    "Max booking attempts reached",
-                ).await;
+/// Join the standby queue of a class that came back full, and report the queue position
+async fn join_waitlist_once(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    class_name: &str,
+    class_time: &str,
+    class_trainer: Option<&str>,
+    times: &mut AttemptTimes,
+) -> Result<()> {
+    let joined = match client.join_waitlist(class_id).await {
+        Ok(result) => Ok(result.waitlist_position),
+        Err(GymSniperError::Booking(BookingErrorKind::AlreadyBooked)) => Ok(None),
+        Err(e) => Err(e),
+    };
+    match joined {
+        Ok(position) => {
+            let position = match position {
+                Some(position) => Some(position),
+                None => match client.get_class_details(class_id).await {
+                    Ok(details) => details.waitlist_position,
+                    Err(e) => {
+                        warn!("Joined the waitlist but could not fetch the position: {}", e);
+                        None
+                    }
+                },
+            };
+            let label = match position {
+                Some(pos) => format!("{} (waitlist #{})", class_name, pos),
+                None => format!("{} (waitlist)", class_name),
+            };
+            info!("Joined the waitlist: {}", label);
+            times.on_waitlist = true;
+            times.waitlist_position = position;
+            Notifiers::new(config).notify_success(&label, class_time, class_trainer).await;
+            Ok(())
+        }
+        Err(e) => {
+            error!("Could not join the waitlist: {}", e);
+            let failure = FailureExplanation::from_error(&e)
+                .with_context("The class was full and joining the waitlist failed");
+            Notifiers::new(config).notify_failure(class_name, class_time, class_trainer, &failure).await;
+            Err(e)
+        }
+    }
+}
+
+/// Find an existing booking (other than this class) on the same day as `class_time`
+pub async fn same_day_booking(
+    client: &PerfectGymClient,
+    class_id: u64,
+    class_time: DateTime<Local>,
+) -> Result<Option<MyBooking>> {
+    let date = class_time.date_naive();
+    let bookings = client.get_my_bookings(DEFAULT_BOOKINGS_DAYS).await?;
+    Ok(bookings
+        .into_iter()
+        .find(|b| b.id != class_id && b.status == "Booked" && b.start_time.date_naive() == date))
+}
+
+/// Run one queued snipe with a logged-in client and settle its queue entry.
+/// Returns whether the class was booked.
+async fn execute_snipe(
+    config: &Config,
+    queue: &Mutex<SnipeQueue>,
+    client: &PerfectGymClient,
+    entry: &SnipeEntry,
+    confirmations: &Mutex<BookingConfirmations>,
+    clock: &impl Clock,
+) -> Result<bool> {
+    let class_id = entry.class_id;
+    let class_name = &entry.class_name;
+
+    // A running `hold` switches to this class itself once it opens, releasing its backup
+    // first if the daily limit needs it; sniping it here would race it
+    let holds = hold::load_active_holds(&hold::holds_path(config.profile.as_deref()), clock.now());
+    if let Some(active) = holds.iter().find(|h| h.prefer_id == class_id) {
+        let note = format!("Left to the running hold on class {}", active.hold_id);
+        warn!("Skipping snipe for {}: {}", class_name, note);
+        let mut queue = queue.lock().await;
+        queue.reload()?;
+        queue.mark_failed(class_id, &note)?;
+        skip_backups(&mut queue, entry, "was left to a running hold")?;
+        history::record_outcome(config, entry, 0, Some(&note), clock.now());
+        return Ok(false);
+    }
+
+    // With a daily limit, a booking elsewhere that day would make the snipe fail anyway
+    if config.snipe.check_same_day_bookings {
+        match same_day_booking(client, class_id, entry.class_time).await {
+            Ok(Some(existing)) => {
+                let note = format!(
+                    "Already booked {} at {} that day",
+                    existing.name,
+                    existing.start_time.format("%H:%M")
+                );
+                warn!("Skipping snipe for {}: {}", class_name, note);
+                let mut queue = queue.lock().await;
+                queue.reload()?;
+                queue.mark_failed(class_id, &note)?;
+                skip_backups(&mut queue, entry, "was skipped")?;
+                history::record_outcome(config, entry, 0, Some(&note), clock.now());
+                return Ok(false);
             }
+            Ok(None) => {}
+            Err(e) => warn!("Could not check existing bookings, sniping anyway: {}", e),
+        }
+    }
 
-            return Err(crate::error::GymSniperError::Api("Max attempts reached".to_string()));
+    // Execute the snipe
+    let mut times = AttemptTimes::default();
+    let required_trainer = entry.required_trainer.as_deref();
+    let outcome = snipe_class_recorded(config, client, class_id, None, required_trainer, clock, &mut times).await;
+    let mut queue = queue.lock().await;
+    queue.reload()?;
+    match outcome {
+        Ok(()) if times.on_waitlist => {
+            // Keep the entry so the daemon watches for a promotion until the class starts
+            info!(class_id, status = "waitlisted", "Snipe for {} ended on the waitlist, watching for a place", class_name);
+            queue.mark_waitlisted_with_log(class_id, times.waitlist_position, times.log)?;
+            skip_backups(&mut queue, entry, "ended on the waitlist")?;
+            Ok(false)
         }
+        Ok(()) => {
+            info!(class_id, status = "booked", "Snipe successful for {}", class_name);
+            if config.snipe.confirm_after_minutes > 0 {
+                let delay = Duration::minutes(config.snipe.confirm_after_minutes as i64);
+                confirmations.lock().await.schedule(
+                    class_id,
+                    class_name,
+                    entry.class_time,
+                    entry.trainer.as_deref(),
+                    clock.now(),
+                    delay,
+                );
+            }
+            queue.remove(class_id)?;
+            skip_backups(&mut queue, entry, "was booked")?;
+            history::record_outcome(config, entry, times.attempts, None, clock.now());
+            Ok(true)
+        }
+        Err(e) => {
+            // Keep the entry, marked failed, so the attempts can be looked over afterwards
+            let message = e.to_string();
+            history::record_outcome(config, entry, times.attempts, Some(&message), clock.now());
+            queue.mark_failed_with_log(class_id, &message, times.log)?;
+            // Only the daily limit leaves the day's backups to be tried
+            if matches!(e, GymSniperError::Booking(BookingErrorKind::DailyLimit)) {
+                warn!(class_id, status = "daily_limit", "Daily booking limit reached for {}", class_name);
+            } else {
+                error!(class_id, status = "failed", "Snipe failed for {}: {}", class_name, e);
+                skip_backups(&mut queue, entry, "failed for another reason than the daily limit")?;
+            }
+            Ok(false)
+        }
+    }
+}
 
-        // Fixed 200ms delay between attempts
-        sleep(std::time::Duration::from_millis(200)).await;
+/// Mark the same-day backups for `entry` as not needed, now that it `outcome` rather than
+/// hitting the daily limit
+fn skip_backups(queue: &mut SnipeQueue, entry: &SnipeEntry, outcome: &str) -> Result<()> {
+    let note = format!("Not needed: a backup in case {} hit the daily limit, but it {}", entry.class_name, outcome);
+    let skipped = queue.skip_backups(entry, &note)?;
+    if skipped > 0 {
+        info!("Dropping {} backup snipe(s) for {}, which {}", skipped, entry.class_name, outcome);
     }
+    Ok(())
 }
 
-/// Run the snipe daemon - continuously monitors and executes queued snipes
-pub async fn run_snipe_daemon(config: &Config) -> Result<()> {
+/// Run the snipes for one day, starting with `entry`, with a fresh login. Only one of these
+/// runs per day at a time, so the same-day check and the gym's daily limit always see the
+/// outcome of the previous snipe that day.
+async fn snipe_day(
+    config: Config,
+    queue: Arc<Mutex<SnipeQueue>>,
+    confirmations: Arc<Mutex<BookingConfirmations>>,
+    mut entry: SnipeEntry,
+    clock: impl Clock,
+    shutdown: Shutdown,
+) -> Result<()> {
+    info!("Executing snipe for {} (class ID {})...", entry.class_name, entry.class_id);
+
+    // Create fresh client for the snipe
+    let client = PerfectGymClient::new(&config)?;
+    if let Err(e) = client.login().await {
+        error!("Failed to login for snipe: {}", e);
+        let mut queue = queue.lock().await;
+        queue.reload()?;
+        queue.remove(entry.class_id)?;
+        return Ok(());
+    }
+
+    // With several classes a day allowed, go straight on to the next snipe that day once
+    // one is booked, rather than pausing and re-sorting first. Only classes wanted as much
+    // are chained into: lower-priority backups are for the daily limit, not a second booking.
+    let date = entry.class_time.date_naive();
+    while execute_snipe(&config, &queue, &client, &entry, &confirmations, &clock).await?
+        && config.snipe.allow_multiple_per_day
+        && !shutdown.is_requested()
+    {
+        let mut queue = queue.lock().await;
+        queue.reload()?;
+        let Some((next, due)) = queue.next_snipe_by(|day| day == date) else { break };
+        if next.priority < entry.priority {
+            break;
+        }
+        if due.signed_duration_since(clock.now()).num_minutes() > config.snipe.takeover_minutes as i64 {
+            break;
+        }
+        info!("Chaining into {} at {}, also that day", next.class_name, next.class_time.format("%a %d %b %H:%M"));
+        entry = next.clone();
+    }
+    Ok(())
+}
+
+/// Run the snipe daemon - continuously monitors and executes queued snipes until `shutdown`
+pub async fn run_snipe_daemon(config: &Config, shutdown: &Shutdown) -> Result<()> {
     info!("Snipe daemon started. Monitoring snipe queue...");
 
-    loop {
-        // Clean up old entries
-        let mut queue = SnipeQueue::load()?;
-        queue.cleanup_old_entries()?;
+    let mut queue = SnipeQueue::load_profile(config.profile.as_deref())?;
+    if let Err(e) = queue.check_writable() {
+        error!(
+            "Cannot write snipe queue file ({}). Running in memory-only mode: results will not be \
+             saved and queue changes made from the CLI or GUI won't be seen until restart. Run the \
+             daemon from a writable directory to fix this.",
+            e
+        );
+        queue.use_memory_only();
+    }
 
-        // Get pending snipes
-        let pending = queue.pending_snipes();
+    run_daemon_loop(config, &mut queue, &SystemClock, None, shutdown).await?;
+    // Every change was saved as it was made, so there is nothing left to write
+    info!("Snipe daemon stopped, snipe queue saved");
+    Ok(())
+}
 
-        if pending.is_empty() {
-            info!("No pending snipes. Checking again in 60 seconds...");
-            sleep(std::time::Duration::from_secs(60)).await;
-            continue;
+/// The snipe daemon's main loop over an already loaded queue. Runs until `shutdown` is
+/// requested, or the clock passes `until` if given. Snipes under way are finished first.
+///
+/// Each snipe is handed to its own task `takeover_minutes` before its window, so windows
+/// for different days opening close together are sniped side by side. The tasks share the
+/// queue; snipes on the same day still run one after another.
+pub async fn run_daemon_loop(
+    config: &Config,
+    queue: &mut SnipeQueue,
+    clock: &(impl Clock + Clone + 'static),
+    until: Option<DateTime<Local>>,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let shared = Arc::new(Mutex::new(std::mem::take(queue)));
+    let mut snipes = JoinSet::new();
+    let result = daemon_loop(config, &shared, &mut snipes, clock, until, shutdown).await;
+
+    // Only reached early on an error, when the remaining snipes are abandoned
+    snipes.shutdown().await;
+    *queue = Arc::try_unwrap(shared).expect("every snipe task has finished").into_inner();
+    result
+}
+
+async fn daemon_loop<C: Clock + Clone + 'static>(
+    config: &Config,
+    queue: &Arc<Mutex<SnipeQueue>>,
+    snipes: &mut JoinSet<Result<()>>,
+    clock: &C,
+    until: Option<DateTime<Local>>,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    let mut defender = BookingDefender::new();
+    let mut last_defend_check: Option<DateTime<Local>> = None;
+    let confirmations = Arc::new(Mutex::new(BookingConfirmations::new()));
+    let mut waitlists = WaitlistMonitor::new();
+    let mut waitlist_client: Option<PerfectGymClient> = None;
+    // Days with a snipe task running, and when that day's snipe was due
+    let mut busy_days: HashMap<NaiveDate, DateTime<Local>> = HashMap::new();
+    let mut task_days: HashMap<task::Id, NaiveDate> = HashMap::new();
+    let takeover = config.snipe.takeover_minutes as i64;
+
+    while until.is_none_or(|until| clock.now() < until) {
+        if shutdown.is_requested() {
+            info!("Shutting down the snipe daemon");
+            break;
         }
 
-        // Find the next snipe (earliest booking window)
-        let next_snipe = pending[0];
-        let now = Local::now();
-        let time_until_window = next_snipe.booking_window.signed_duration_since(now);
+        // Pick up queue changes from the CLI/GUI, then clean up old entries
+        {
+            let mut queue = queue.lock().await;
+            queue.reload()?;
+            queue.cleanup_old_entries_at(clock.now())?;
+        }
 
-        info!(
-            "Next snipe: {} at {} (window opens in {})",
-            next_snipe.class_name,
-            next_snipe.class_time.format("%a %d %b %H:%M"),
-            format_duration(time_until_window)
-        );
+        if config.snipe.defend_bookings {
+            let due = last_defend_check.is_none_or(|t| {
+                clock.now() - t >= Duration::minutes(config.snipe.defend_interval_minutes as i64)
+            });
+            if due {
+                last_defend_check = Some(clock.now());
+                let result = async {
+                    let client = PerfectGymClient::new(config)?;
+                    client.login().await?;
+                    defender.check(config, &client).await
+                }
+                .await;
+                if let Err(e) = result {
+                    warn!("Booking defence check failed: {}", e);
+                }
+            }
+        }
 
-        // If window is more than 5 minutes away, sleep and check again
-        if time_until_window.num_minutes() > 5 {
-            let sleep_duration = if time_until_window.num_minutes() > 60 {
-                // More than 1 hour away - check every 30 minutes
-                std::time::Duration::from_secs(30 * 60)
-            } else if time_until_window.num_minutes() > 30 {
-                // 30-60 min away - check every 10 minutes
-                std::time::Duration::from_secs(10 * 60)
-            } else {
-                // 5-30 min away - check every minute
-                std::time::Duration::from_secs(60)
-            };
+        if confirmations.lock().await.has_due(clock.now()) {
+            let result = async {
+                let client = PerfectGymClient::new(config)?;
+                client.login().await?;
+                confirmations.lock().await.check_due(config, &client, clock.now()).await
+            }
+            .await;
+            if let Err(e) = result {
+                warn!("Booking confirmation check failed, will retry: {}", e);
+            }
+        }
 
-            info!("Sleeping for {} seconds...", sleep_duration.as_secs());
-            sleep(sleep_duration).await;
+        // Snipe tasks need the queue to settle, so it isn't held while logging in or checking
+        let waitlists_due = waitlists.has_due(&*queue.lock().await, clock.now());
+        if waitlists_due {
+            let result = async {
+                // Waitlists are checked every few minutes, so one session is kept for them
+                if waitlist_client.is_none() {
+                    let client = PerfectGymClient::new(config)?;
+                    client.login().await?;
+                    waitlist_client = Some(client);
+                }
+                let client = waitlist_client.as_ref().expect("logged in above");
+                client.refresh_token_if_due().await?;
+                waitlists.check_due(config, client, queue, clock.now()).await
+            }
+            .await;
+            if let Err(e) = result {
+                warn!("Waitlist check failed, will retry: {}", e);
+            }
+        }
+
+        let queue_guard = queue.lock().await;
+
+        // Hand every snipe about to open on a day not already being sniped to its own task
+        while let Some((entry, due)) = queue_guard.next_snipe_by(|day| !busy_days.contains_key(&day))
+            && due.signed_duration_since(clock.now()).num_minutes() <= takeover
+        {
+            let date = entry.class_time.date_naive();
+            busy_days.insert(date, due);
+            let day = snipe_day(
+                config.clone(),
+                Arc::clone(queue),
+                Arc::clone(&confirmations),
+                entry.clone(),
+                clock.clone(),
+                shutdown.clone(),
+            );
+            task_days.insert(snipes.spawn(day).id(), date);
+        }
+
+        // Find the next snipe: the earliest window, after any higher-priority snipes that day
+        let Some((next_snipe, due)) = queue_guard.next_snipe_by(|day| !busy_days.contains_key(&day)) else {
+            drop(queue_guard);
+            if snipes.is_empty() {
+                info!("No pending snipes. Checking again in 60 seconds...");
+                idle(clock, std::time::Duration::from_secs(60), shutdown).await;
+            } else if let Some(finished) = snipes.join_next_with_id().await {
+                settle_snipe_task(finished, &mut task_days, &mut busy_days);
+                // Brief pause before checking for next snipe
+                idle(clock, std::time::Duration::from_secs(5), shutdown).await;
+            }
             continue;
+        };
+        let now = clock.now();
+        let time_until_window = due.signed_duration_since(now);
+
+        if due > next_snipe.booking_window {
+            info!(
+                "Next snipe: {} at {} (after higher-priority snipes that day, in {})",
+                next_snipe.class_name,
+                next_snipe.class_time.format("%a %d %b %H:%M"),
+                format_duration(time_until_window)
+            );
+        } else {
+            info!(
+                "Next snipe: {} at {} (window opens in {})",
+                next_snipe.class_name,
+                next_snipe.class_time.format("%a %d %b %H:%M"),
+                format_duration(time_until_window)
+            );
         }
 
-        // Time to snipe! Execute it
-        let class_id = next_snipe.class_id;
-        let class_name = next_snipe.class_name.clone();
+        // Check again before the window, more often as it nears
+        let mut sleep_duration = config.snipe.daemon_poll_interval(time_until_window);
+        // Wake in time for the next waitlist check
+        if let Some(check_at) = waitlists.next_due(&queue_guard, clock.now()) {
+            let until_check = check_at.signed_duration_since(clock.now()).num_seconds().max(1) as u64;
+            sleep_duration = sleep_duration.min(std::time::Duration::from_secs(until_check));
+        }
+        drop(queue_guard);
 
-        info!("Executing snipe for {} (class ID {})...", class_name, class_id);
+        if snipes.is_empty() {
+            info!("Sleeping for {} seconds...", sleep_duration.as_secs());
+            idle(clock, sleep_duration, shutdown).await;
+            continue;
+        }
 
-        // Create fresh client for the snipe
-        let client = PerfectGymClient::new(config);
-        match client.login().await {
-            Ok(()) => {},
-            Err(e) => {
-                error!("Failed to login for snipe: {}", e);
-                let mut queue = SnipeQueue::load()?;
-                queue.remove(class_id)?;
-                continue;
+        // Snipes are under way. They finish moments after their windows open, so only a
+        // window opening close behind theirs needs checking for while they run.
+        let close_behind = busy_days.values().any(|busy| (due - *busy).num_minutes() <= takeover);
+        let finished = if close_behind {
+            tokio::select! {
+                finished = snipes.join_next_with_id() => finished,
+                _ = idle(clock, sleep_duration, shutdown) => None,
             }
+        } else {
+            snipes.join_next_with_id().await
         };
+        if let Some(finished) = finished {
+            settle_snipe_task(finished, &mut task_days, &mut busy_days);
+            // Brief pause before checking for next snipe
+            idle(clock, std::time::Duration::from_secs(5), shutdown).await;
+        }
+    }
 
-        // Execute the snipe
-        match snipe_class(config, &client, class_id).await {
-            Ok(()) => {
-                info!("Snipe successful for {}", class_name);
-                let mut queue = SnipeQueue::load()?;
-                queue.remove(class_id)?;
-            }
-            Err(e) => {
-                let err_str = format!("{}", e);
-                if err_str.contains("DailyBookingLimitReached") {
-                    warn!("Daily booking limit reached for {}", class_name);
-                } else {
-                    error!("Snipe failed for {}: {}", class_name, e);
-                }
-                let mut queue = SnipeQueue::load()?;
-                queue.remove(class_id)?;
-            }
+    // Let snipes under way finish
+    while let Some(finished) = snipes.join_next_with_id().await {
+        settle_snipe_task(finished, &mut task_days, &mut busy_days);
+    }
+    Ok(())
+}
+
+/// Free the day of a finished snipe task. A task that failed is only logged, so one day's
+/// trouble (say, a queue file that couldn't be written) never stops the other snipes.
+fn settle_snipe_task(
+    finished: std::result::Result<(task::Id, Result<()>), task::JoinError>,
+    task_days: &mut HashMap<task::Id, NaiveDate>,
+    busy_days: &mut HashMap<NaiveDate, DateTime<Local>>,
+) {
+    let (id, result) = match finished {
+        Ok((id, result)) => (id, result.map_err(|e| e.to_string())),
+        Err(e) => (e.id(), Err(e.to_string())),
+    };
+    let Some(date) = task_days.remove(&id) else { return };
+    busy_days.remove(&date);
+    if let Err(e) = result {
+        error!("Snipe for {} stopped with an error: {}", date.format("%a %d %b"), e);
+    }
+}
+
+/// Sleep between daemon checks, waking early if shutdown is requested
+async fn idle(clock: &impl Clock, duration: std::time::Duration, shutdown: &Shutdown) {
+    tokio::select! {
+        _ = clock.sleep(duration) => {}
+        _ = shutdown.wait() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failed_snipe_tasks_free_their_day() {
+        let now = Local::now();
+        let mut snipes = JoinSet::new();
+        let mut task_days = HashMap::new();
+        let mut busy_days = HashMap::new();
+        for (offset, task) in [
+            (1, Box::pin(async { Err(GymSniperError::Config("queue file unwritable".to_string())) })
+                as std::pin::Pin<Box<dyn Future<Output = Result<()>> + Send>>),
+            (2, Box::pin(async { panic!("snipe task blew up") })),
+            (3, Box::pin(async { Ok(()) })),
+        ] {
+            let date = (now + Duration::days(offset)).date_naive();
+            busy_days.insert(date, now);
+            task_days.insert(snipes.spawn(task).id(), date);
         }
 
-        // Brief pause before checking for next snipe
-        sleep(std::time::Duration::from_secs(5)).await;
+        while let Some(finished) = snipes.join_next_with_id().await {
+            settle_snipe_task(finished, &mut task_days, &mut busy_days);
+        }
+        assert!(busy_days.is_empty());
+        assert!(task_days.is_empty());
     }
 }
@@ -3,7 +3,9 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::config::GymConfig;
 use crate::error::{GymSniperError, Result};
+use crate::util::{profile_file, replace_file, window_opens};
 
 const SNIPES_FILE: &str = "snipes.json";
 
@@ -18,6 +20,62 @@ pub struct SnipeEntry {
     pub status: SnipeStatus,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error_message: Option<String>,
+    /// Among pending snipes on the same day, higher priorities are tried first. Lower ones
+    /// are backups, only tried if a higher one hits the daily limit.
+    #[serde(default)]
+    pub priority: u8,
+    /// Booking attempts made when the snipe ran
+    #[serde(default)]
+    pub attempts: u32,
+    /// When each booking attempt was made and what came of it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempt_log: Vec<(DateTime<Local>, String)>,
+    /// Last known place in the standby queue, while the snipe is on the waitlist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waitlist_position: Option<u32>,
+    /// Only book while the class's trainer contains this (case-insensitive), checked
+    /// again when the window opens. `trainer` is who was listed when the snipe was queued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_trainer: Option<String>,
+}
+
+impl SnipeEntry {
+    /// A new pending snipe. The window is the gym's own opening time if it gave one,
+    /// otherwise the configured booking window before the class.
+    pub fn new(
+        class_id: u64,
+        class_name: String,
+        class_time: DateTime<Local>,
+        bookable_from: Option<DateTime<Local>>,
+        trainer: Option<String>,
+        gym: &GymConfig,
+    ) -> Self {
+        Self {
+            class_id,
+            class_name,
+            class_time,
+            booking_window: window_opens(class_time, bookable_from, gym),
+            trainer,
+            added_at: Local::now(),
+            status: SnipeStatus::Pending,
+            error_message: None,
+            priority: 0,
+            attempts: 0,
+            attempt_log: Vec::new(),
+            waitlist_position: None,
+            required_trainer: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_required_trainer(mut self, trainer: Option<String>) -> Self {
+        self.required_trainer = trainer;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -26,6 +84,8 @@ pub enum SnipeStatus {
     Pending,
     Completed,
     Failed,
+    /// Landed on the waitlist; the daemon keeps watching for a promotion until the class starts
+    Waitlisted,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -33,20 +93,29 @@ pub struct SnipeQueue {
     pub snipes: Vec<SnipeEntry>,
     #[serde(skip)]
     file_path: Option<PathBuf>,
+    /// When set, changes are kept in memory only and never written to disk
+    #[serde(skip)]
+    memory_only: bool,
 }
 
 impl SnipeQueue {
     /// Load the snipe queue from file, or create empty if doesn't exist
     pub fn load() -> Result<Self> {
-        Self::load_from(Path::new(SNIPES_FILE))
+        Self::load_profile(None)
+    }
+
+    /// Load the snipe queue for a profile (`snipes.<profile>.json`)
+    pub fn load_profile(profile: Option<&str>) -> Result<Self> {
+        Self::load_from(&profile_file(SNIPES_FILE, profile))
     }
 
     /// Load the snipe queue from a specific path
     pub fn load_from(path: &Path) -> Result<Self> {
         if !path.exists() {
-            let mut queue = Self::default();
-            queue.file_path = Some(path.to_path_buf());
-            return Ok(queue);
+            return Ok(Self {
+                file_path: Some(path.to_path_buf()),
+                ..Default::default()
+            });
         }
 
         let content = fs::read_to_string(path).map_err(|e| {
@@ -61,16 +130,47 @@ impl SnipeQueue {
         Ok(queue)
     }
 
+    /// Reload from file to pick up changes made by other processes.
+    /// Does nothing in memory-only mode, where the in-memory queue is authoritative.
+    pub fn reload(&mut self) -> Result<()> {
+        if self.memory_only {
+            return Ok(());
+        }
+        let path = self.file_path.clone().unwrap_or_else(|| PathBuf::from(SNIPES_FILE));
+        *self = Self::load_from(&path)?;
+        Ok(())
+    }
+
+    /// Check the queue file can be written by rewriting its current contents
+    pub fn check_writable(&self) -> Result<()> {
+        self.write_file()
+    }
+
+    /// Stop persisting changes, e.g. when the queue location is read-only
+    pub fn use_memory_only(&mut self) {
+        self.memory_only = true;
+    }
+
+    pub fn is_memory_only(&self) -> bool {
+        self.memory_only
+    }
+
     /// Save the snipe queue to file
     pub fn save(&self) -> Result<()> {
+        if self.memory_only {
+            return Ok(());
+        }
+        self.write_file()
+    }
+
+    fn write_file(&self) -> Result<()> {
         let path = self.file_path.as_deref().unwrap_or(Path::new(SNIPES_FILE));
         let content = serde_json::to_string_pretty(self).map_err(|e| {
             GymSniperError::Config(format!("Failed to serialize snipes: {}", e))
         })?;
 
-        fs::write(path, content).map_err(|e| {
-            GymSniperError::Config(format!("Failed to write snipes file: {}", e))
-        })?;
+        replace_file(path, content.as_bytes(), false)
+            .map_err(|e| GymSniperError::Config(format!("Failed to write snipes file: {}", e)))?;
 
         Ok(())
     }
@@ -82,14 +182,24 @@ impl SnipeQueue {
         })
     }
 
-    /// Add a new snipe entry
+    /// Add a new snipe entry, refusing a second pending snipe on the same day
     pub fn add(&mut self, entry: SnipeEntry) -> Result<()> {
+        self.add_with(entry, false)
+    }
+
+    /// Add a new snipe entry, allowing other pending snipes on the same day if
+    /// `allow_multiple_per_day` is set (`snipe.allow_multiple_per_day`). A failed entry for
+    /// the same class is replaced, so a failed snipe can be queued again.
+    pub fn add_with(&mut self, entry: SnipeEntry, allow_multiple_per_day: bool) -> Result<()> {
         let class_date = entry.class_time.date_naive();
 
         // Check if there's already a pending snipe for this date
-        if let Some(existing) = self.has_snipe_for_date(class_date) {
+        if !allow_multiple_per_day
+            && let Some(existing) = self.has_snipe_for_date(class_date)
+        {
             return Err(GymSniperError::Config(format!(
-                "Already have a snipe queued for {}: {} at {} (class ID {}). Only one class per day allowed.",
+                "Already have a snipe queued for {}: {} at {} (class ID {}). Only one class per day allowed \
+                 (set snipe.allow_multiple_per_day to queue more).",
                 class_date.format("%a %d %b"),
                 existing.class_name,
                 existing.class_time.format("%H:%M"),
@@ -98,13 +208,14 @@ impl SnipeQueue {
         }
 
         // Check if this class is already in the queue
-        if self.snipes.iter().any(|s| s.class_id == entry.class_id) {
+        if self.snipes.iter().any(|s| s.class_id == entry.class_id && s.status != SnipeStatus::Failed) {
             return Err(GymSniperError::Config(format!(
                 "Class {} is already in the snipe queue",
                 entry.class_id
             )));
         }
 
+        self.snipes.retain(|s| s.class_id != entry.class_id);
         self.snipes.push(entry);
         self.save()?;
         Ok(())
@@ -123,18 +234,155 @@ impl SnipeQueue {
         }
     }
 
+    /// Mark a snipe as failed with a note explaining why
+    pub fn mark_failed(&mut self, class_id: u64, message: &str) -> Result<bool> {
+        let Some(entry) = self.snipes.iter_mut().find(|s| s.class_id == class_id) else {
+            return Ok(false);
+        };
+        entry.status = SnipeStatus::Failed;
+        entry.error_message = Some(message.to_string());
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Mark a snipe as failed after booking attempts, keeping the log of each attempt
+    pub fn mark_failed_with_log(
+        &mut self,
+        class_id: u64,
+        message: &str,
+        attempt_log: Vec<(DateTime<Local>, String)>,
+    ) -> Result<bool> {
+        let Some(entry) = self.snipes.iter_mut().find(|s| s.class_id == class_id) else {
+            return Ok(false);
+        };
+        entry.status = SnipeStatus::Failed;
+        entry.error_message = Some(message.to_string());
+        entry.attempts = attempt_log.len() as u32;
+        entry.attempt_log = attempt_log;
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Mark a snipe as sitting on the waitlist, or update its place there
+    pub fn mark_waitlisted(&mut self, class_id: u64, position: Option<u32>) -> Result<bool> {
+        let Some(entry) = self.snipes.iter_mut().find(|s| s.class_id == class_id) else {
+            return Ok(false);
+        };
+        entry.status = SnipeStatus::Waitlisted;
+        entry.waitlist_position = position;
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Mark a snipe as sitting on the waitlist after booking attempts, keeping the log of
+    /// each attempt so its outcome is recorded with them once the waitlist settles
+    pub fn mark_waitlisted_with_log(
+        &mut self,
+        class_id: u64,
+        position: Option<u32>,
+        attempt_log: Vec<(DateTime<Local>, String)>,
+    ) -> Result<bool> {
+        let Some(entry) = self.snipes.iter_mut().find(|s| s.class_id == class_id) else {
+            return Ok(false);
+        };
+        entry.status = SnipeStatus::Waitlisted;
+        entry.waitlist_position = position;
+        entry.attempts = attempt_log.len() as u32;
+        entry.attempt_log = attempt_log;
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Snipes that ended on a waitlist and are still being watched, soonest class first
+    pub fn waitlisted_snipes(&self) -> Vec<&SnipeEntry> {
+        let mut waitlisted: Vec<_> = self.snipes.iter()
+            .filter(|s| s.status == SnipeStatus::Waitlisted)
+            .collect();
+        waitlisted.sort_by_key(|s| (s.class_time, s.class_id));
+        waitlisted
+    }
+
     /// Get all pending snipes sorted by booking window time
     pub fn pending_snipes(&self) -> Vec<&SnipeEntry> {
         let mut pending: Vec<_> = self.snipes.iter()
             .filter(|s| s.status == SnipeStatus::Pending)
             .collect();
-        pending.sort_by_key(|s| s.booking_window);
+        pending.sort_by_key(|s| (s.booking_window, s.class_id));
         pending
     }
 
+    /// Pending snipes whose booking window passed before `now` without the daemon running
+    /// them, e.g. because it was stopped
+    pub fn missed_snipes(&self, now: DateTime<Local>) -> Vec<&SnipeEntry> {
+        self.pending_snipes().into_iter().filter(|s| s.booking_window < now).collect()
+    }
+
+    /// Mark the pending snipes that are backups for `entry` (lower priority, same day) as
+    /// failed with `note`. Backups are only wanted if `entry` hits the daily limit, so this is
+    /// called once it settles any other way. Returns how many were marked.
+    pub fn skip_backups(&mut self, entry: &SnipeEntry, note: &str) -> Result<usize> {
+        let date = entry.class_time.date_naive();
+        let mut skipped = 0;
+        for backup in self.snipes.iter_mut().filter(|s| {
+            s.status == SnipeStatus::Pending && s.class_time.date_naive() == date && s.priority < entry.priority
+        }) {
+            backup.status = SnipeStatus::Failed;
+            backup.error_message = Some(note.to_string());
+            skipped += 1;
+        }
+        if skipped > 0 {
+            self.save()?;
+        }
+        Ok(skipped)
+    }
+
+    /// When the daemon should run a pending snipe: once its window opens and every
+    /// higher-priority pending snipe on the same day has had its turn
+    pub fn due_at(&self, entry: &SnipeEntry) -> DateTime<Local> {
+        let date = entry.class_time.date_naive();
+        self.snipes
+            .iter()
+            .filter(|s| {
+                s.status == SnipeStatus::Pending
+                    && s.class_time.date_naive() == date
+                    && s.priority > entry.priority
+            })
+            .map(|s| s.booking_window)
+            .fold(entry.booking_window, DateTime::max)
+    }
+
+    /// The pending snipe the daemon should run next and when it is due. Ties go to the
+    /// higher priority, then the lower class ID.
+    pub fn next_snipe(&self) -> Option<(&SnipeEntry, DateTime<Local>)> {
+        self.next_snipe_by(|_| true)
+    }
+
+    /// Like `next_snipe`, among only the snipes for classes on days `day` accepts
+    pub fn next_snipe_by(&self, day: impl Fn(NaiveDate) -> bool) -> Option<(&SnipeEntry, DateTime<Local>)> {
+        self.snipes
+            .iter()
+            .filter(|s| s.status == SnipeStatus::Pending && day(s.class_time.date_naive()))
+            .map(|s| (s, self.due_at(s)))
+            .min_by_key(|(s, due)| (*due, std::cmp::Reverse(s.priority), s.class_id))
+    }
+
+    /// Remove every completed/failed entry regardless of age and rewrite the file.
+    /// Entries still being watched on a waitlist are kept. Returns how many were removed.
+    pub fn compact(&mut self) -> Result<usize> {
+        let initial_len = self.snipes.len();
+        self.snipes.retain(|s| matches!(s.status, SnipeStatus::Pending | SnipeStatus::Waitlisted));
+        self.save()?;
+        Ok(initial_len - self.snipes.len())
+    }
+
     /// Clean up old completed/failed entries (older than 7 days)
     pub fn cleanup_old_entries(&mut self) -> Result<()> {
-        let cutoff = Local::now() - chrono::Duration::days(7);
+        self.cleanup_old_entries_at(Local::now())
+    }
+
+    /// Clean up completed/failed entries older than 7 days as of `now`
+    pub fn cleanup_old_entries_at(&mut self, now: DateTime<Local>) -> Result<()> {
+        let cutoff = now - chrono::Duration::days(7);
         let initial_len = self.snipes.len();
 
         self.snipes.retain(|s| {
@@ -151,7 +399,7 @@ impl SnipeQueue {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Duration;
+    use chrono::{Duration, Timelike};
     use tempfile::TempDir;
 
     fn make_entry(class_id: u64, name: &str, days_from_now: i64, status: SnipeStatus) -> SnipeEntry {
@@ -166,6 +414,11 @@ mod tests {
             added_at: Local::now(),
             status,
             error_message: None,
+            priority: 0,
+            attempts: 0,
+            attempt_log: Vec::new(),
+            waitlist_position: None,
+            required_trainer: None,
         }
     }
 
@@ -174,6 +427,31 @@ mod tests {
         SnipeQueue::load_from(&path).unwrap()
     }
 
+    #[test]
+    fn new_entry_uses_configured_booking_window() {
+        let gym: GymConfig = toml::from_str(
+            "base_url = \"https://example.com\"\nclub_id = 1\nbooking_window_days = 14\nbooking_window_hours = 0\n",
+        )
+        .unwrap();
+        let class_time = Local::now() + Duration::days(15);
+
+        let entry = SnipeEntry::new(100, "Yoga".to_string(), class_time, None, None, &gym);
+        assert_eq!(entry.booking_window, class_time - Duration::days(14));
+        assert_eq!(entry.status, SnipeStatus::Pending);
+
+        // The gym's own opening time still wins
+        let opens = class_time - Duration::days(3);
+        let entry = SnipeEntry::new(100, "Yoga".to_string(), class_time, Some(opens), None, &gym);
+        assert_eq!(entry.booking_window, opens);
+
+        // window_offset_secs shifts the computed window only
+        let gym = GymConfig { window_offset_secs: 2, ..gym };
+        let entry = SnipeEntry::new(100, "Yoga".to_string(), class_time, None, None, &gym);
+        assert_eq!(entry.booking_window, class_time - Duration::days(14) + Duration::seconds(2));
+        let entry = SnipeEntry::new(100, "Yoga".to_string(), class_time, Some(opens), None, &gym);
+        assert_eq!(entry.booking_window, opens);
+    }
+
     #[test]
     fn add_succeeds() {
         let dir = TempDir::new().unwrap();
@@ -193,6 +471,20 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn add_replaces_a_failed_entry_for_the_same_class() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        queue.add(make_entry(1, "Yoga", 10, SnipeStatus::Pending)).unwrap();
+        queue.mark_failed(1, "Booking failed: class is full").unwrap();
+
+        queue.add(make_entry(1, "Yoga", 10, SnipeStatus::Pending)).unwrap();
+        assert_eq!(queue.snipes.len(), 1);
+        assert_eq!(queue.snipes[0].status, SnipeStatus::Pending);
+        assert_eq!(queue.snipes[0].error_message, None);
+        assert_eq!(SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap().snipes.len(), 1);
+    }
+
     #[test]
     fn add_rejects_same_date_conflict() {
         let dir = TempDir::new().unwrap();
@@ -203,6 +495,96 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn add_with_allows_same_date_when_enabled() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        queue.add_with(make_entry(100, "Yoga", 8, SnipeStatus::Pending), true).unwrap();
+        queue.add_with(make_entry(200, "Spin", 8, SnipeStatus::Pending), true).unwrap();
+        assert_eq!(queue.pending_snipes().len(), 2);
+
+        // Still one snipe per class
+        assert!(queue.add_with(make_entry(200, "Spin", 8, SnipeStatus::Pending), true).is_err());
+        // And the default stays strict
+        assert!(queue.add(make_entry(300, "Row", 8, SnipeStatus::Pending)).is_err());
+    }
+
+    #[test]
+    fn next_snipe_holds_back_lower_priority_entries_on_the_same_day() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+
+        // The backup is earlier in the day so its window opens first, but the preferred
+        // class is tried first
+        let at = |hour| make_entry(0, "", 8, SnipeStatus::Pending).class_time.with_hour(hour).unwrap();
+        let backup = SnipeEntry {
+            class_time: at(7),
+            booking_window: at(7) - Duration::days(7),
+            ..make_entry(1, "Backup", 8, SnipeStatus::Pending)
+        };
+        let preferred = SnipeEntry {
+            class_time: at(18),
+            booking_window: at(18) - Duration::days(7),
+            ..make_entry(2, "Preferred", 8, SnipeStatus::Pending)
+        }
+        .with_priority(1);
+        let other_day = make_entry(3, "Other day", 9, SnipeStatus::Pending);
+        let (backup_window, preferred_window) = (backup.booking_window, preferred.booking_window);
+        for entry in [backup, preferred, other_day] {
+            queue.add_with(entry, true).unwrap();
+        }
+
+        let (next, due) = queue.next_snipe().unwrap();
+        assert_eq!((next.class_id, due), (2, preferred_window));
+        assert_eq!(queue.due_at(&queue.snipes[0]), preferred_window);
+
+        // With the preferred class done, the backup is due from its own window
+        queue.remove(2).unwrap();
+        let (next, due) = queue.next_snipe().unwrap();
+        assert_eq!((next.class_id, due), (1, backup_window));
+
+        queue.remove(1).unwrap();
+        assert_eq!(queue.next_snipe().unwrap().0.class_id, 3);
+    }
+
+    #[test]
+    fn skip_backups_only_marks_lower_priorities_on_the_same_day() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        let preferred = make_entry(1, "Preferred", 8, SnipeStatus::Pending).with_priority(2);
+        let entries = [
+            preferred.clone(),
+            make_entry(2, "Also wanted", 8, SnipeStatus::Pending).with_priority(2),
+            make_entry(3, "Backup", 8, SnipeStatus::Pending).with_priority(1),
+            make_entry(4, "Other day", 9, SnipeStatus::Pending),
+        ];
+        for entry in entries {
+            queue.add_with(entry, true).unwrap();
+        }
+
+        assert_eq!(queue.skip_backups(&preferred, "Not needed").unwrap(), 1);
+        let status = |id: u64| queue.snipes.iter().find(|s| s.class_id == id).unwrap().status.clone();
+        assert_eq!(status(3), SnipeStatus::Failed);
+        assert!([1, 2, 4].iter().all(|id| status(*id) == SnipeStatus::Pending));
+        assert_eq!(queue.skip_backups(&preferred, "Not needed").unwrap(), 0);
+    }
+
+    #[test]
+    fn next_snipe_breaks_ties_by_priority() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        let low = make_entry(1, "Low", 8, SnipeStatus::Pending);
+        let mut high = make_entry(2, "High", 8, SnipeStatus::Pending).with_priority(5);
+        high.booking_window = low.booking_window;
+        high.class_time = low.class_time;
+        queue.add_with(low, true).unwrap();
+        queue.add_with(high, true).unwrap();
+
+        assert_eq!(queue.next_snipe().unwrap().0.class_id, 2);
+        assert!(queue.mark_failed(2, "Daily booking limit reached").unwrap());
+        assert_eq!(queue.next_snipe().unwrap().0.class_id, 1);
+    }
+
     #[test]
     fn remove_returns_true_when_found() {
         let dir = TempDir::new().unwrap();
@@ -272,5 +654,164 @@ mod tests {
         assert_eq!(loaded.snipes.len(), 1);
         assert_eq!(loaded.snipes[0].class_id, 42);
         assert_eq!(loaded.snipes[0].class_name, "Yoga Flow");
+        // Saved through a temporary file that is renamed into place
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(files, ["snipes.json"]);
+    }
+
+    #[test]
+    fn unwritable_location_falls_back_to_memory_only() {
+        let dir = TempDir::new().unwrap();
+        // A regular file as the parent makes the queue path unwritable even when
+        // the tests run as root (which ignores directory permissions)
+        let blocker = dir.path().join("not_a_dir");
+        fs::write(&blocker, "").unwrap();
+        let path = blocker.join("snipes.json");
+
+        let mut queue = SnipeQueue::load_from(&path).unwrap();
+        assert!(queue.check_writable().is_err());
+        assert!(queue.add(make_entry(1, "Yoga", 8, SnipeStatus::Pending)).is_err());
+
+        queue.snipes.clear();
+        queue.use_memory_only();
+        queue.add(make_entry(1, "Yoga", 8, SnipeStatus::Pending)).unwrap();
+        queue.add(make_entry(2, "Spin", 9, SnipeStatus::Pending)).unwrap();
+        assert!(queue.remove(1).unwrap());
+        queue.cleanup_old_entries().unwrap();
+        queue.reload().unwrap();
+
+        assert_eq!(queue.snipes.len(), 1);
+        assert_eq!(queue.snipes[0].class_id, 2);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn compact_keeps_only_pending_entries_intact() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+
+        queue.snipes.push(make_entry(1, "Done", 5, SnipeStatus::Completed));
+        queue.snipes.push(make_entry(2, "Later", 10, SnipeStatus::Pending));
+        queue.snipes.push(make_entry(3, "Broken", 3, SnipeStatus::Failed));
+        queue.snipes.push(make_entry(4, "Sooner", 8, SnipeStatus::Pending));
+        queue.save().unwrap();
+        let before: Vec<String> = queue.pending_snipes().iter()
+            .map(|s| serde_json::to_string(s).unwrap())
+            .collect();
+
+        assert_eq!(queue.compact().unwrap(), 2);
+
+        let loaded = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+        assert_eq!(loaded.snipes.len(), 2);
+        assert!(loaded.snipes.iter().all(|s| s.status == SnipeStatus::Pending));
+        let after: Vec<String> = loaded.pending_snipes().iter()
+            .map(|s| serde_json::to_string(s).unwrap())
+            .collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn waitlisted_entries_are_watched_not_sniped_and_survive_compact() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        queue.add(make_entry(1, "Yoga", 8, SnipeStatus::Pending)).unwrap();
+        queue.add(make_entry(2, "Spin", 9, SnipeStatus::Pending)).unwrap();
+
+        let log = vec![(Local::now(), "ClassIsFull".to_string()), (Local::now(), "Waitlisted".to_string())];
+        assert!(queue.mark_waitlisted_with_log(1, Some(4), log).unwrap());
+        assert!(queue.mark_waitlisted(1, Some(2)).unwrap());
+        assert!(!queue.mark_waitlisted(99, None).unwrap());
+        assert_eq!(queue.next_snipe().unwrap().0.class_id, 2);
+
+        queue.snipes.push(make_entry(3, "Done", 5, SnipeStatus::Completed));
+        assert_eq!(queue.compact().unwrap(), 1);
+
+        let loaded = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+        let waitlisted = loaded.waitlisted_snipes();
+        assert_eq!(waitlisted.len(), 1);
+        assert_eq!((waitlisted[0].class_id, waitlisted[0].waitlist_position), (1, Some(2)));
+        assert_eq!(waitlisted[0].attempts, 2);
+        assert!(loaded.has_snipe_for_date(waitlisted[0].class_time.date_naive()).is_none());
+    }
+
+    #[test]
+    fn missed_snipes_are_pending_ones_past_their_window() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        // make_entry puts the window 7 days and 2 hours before the class
+        queue.add(make_entry(1, "Missed", 3, SnipeStatus::Pending)).unwrap();
+        queue.add(make_entry(2, "Upcoming", 9, SnipeStatus::Pending)).unwrap();
+        queue.snipes.push(make_entry(3, "Failed", 4, SnipeStatus::Failed));
+
+        let missed: Vec<u64> = queue.missed_snipes(Local::now()).iter().map(|s| s.class_id).collect();
+        assert_eq!(missed, [1]);
+        assert!(queue.missed_snipes(Local::now() - Duration::days(10)).is_empty());
+    }
+
+    #[test]
+    fn mark_failed_records_note_and_leaves_pending_queue() {
+        let dir = TempDir::new().unwrap();
+        let mut queue = test_queue(&dir);
+        queue.add(make_entry(1, "Yoga", 8, SnipeStatus::Pending)).unwrap();
+
+        assert!(queue.mark_failed(1, "Already booked Spin at 07:00 that day").unwrap());
+        assert!(!queue.mark_failed(99, "nope").unwrap());
+
+        let loaded = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+        assert!(loaded.pending_snipes().is_empty());
+        assert_eq!(loaded.snipes[0].status, SnipeStatus::Failed);
+        assert_eq!(
+            loaded.snipes[0].error_message.as_deref(),
+            Some("Already booked Spin at 07:00 that day")
+        );
+    }
+
+    #[test]
+    fn attempt_log_is_saved_and_old_files_still_load() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("snipes.json");
+        let mut queue = test_queue(&dir);
+        queue.add(make_entry(1, "Yoga", 8, SnipeStatus::Pending)).unwrap();
+
+        // Files written before attempts were logged have neither field
+        let mut old: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        old["snipes"][0].as_object_mut().unwrap().remove("attempts");
+        assert!(!old.to_string().contains("attempt"));
+        fs::write(&path, old.to_string()).unwrap();
+        let loaded = SnipeQueue::load_from(&path).unwrap();
+        assert_eq!(loaded.snipes[0].attempts, 0);
+        assert!(loaded.snipes[0].attempt_log.is_empty());
+
+        let at = Local::now();
+        let log = vec![
+            (at, "Booking failed: booking window not open yet".to_string()),
+            (at + Duration::milliseconds(300), "Booking failed: class is full".to_string()),
+        ];
+        assert!(queue.mark_failed_with_log(1, "Booking failed: class is full", log.clone()).unwrap());
+
+        let loaded = SnipeQueue::load_from(&path).unwrap();
+        assert_eq!(loaded.snipes[0].status, SnipeStatus::Failed);
+        assert_eq!(loaded.snipes[0].attempts, 2);
+        assert_eq!(loaded.snipes[0].attempt_log, log);
+    }
+
+    #[test]
+    fn profiles_use_separate_queue_files() {
+        let dir = TempDir::new().unwrap();
+        let default_path = dir.path().join(profile_file(SNIPES_FILE, None));
+        let work_path = dir.path().join(profile_file(SNIPES_FILE, Some("work")));
+        assert_ne!(default_path, work_path);
+
+        let mut default_queue = SnipeQueue::load_from(&default_path).unwrap();
+        default_queue.add(make_entry(1, "Yoga", 8, SnipeStatus::Pending)).unwrap();
+        let mut work_queue = SnipeQueue::load_from(&work_path).unwrap();
+        work_queue.add(make_entry(2, "Spin", 8, SnipeStatus::Pending)).unwrap();
+
+        let default_queue = SnipeQueue::load_from(&default_path).unwrap();
+        let work_queue = SnipeQueue::load_from(&work_path).unwrap();
+        assert_eq!(default_queue.snipes.len(), 1);
+        assert_eq!(default_queue.snipes[0].class_id, 1);
+        assert_eq!(work_queue.snipes.len(), 1);
+        assert_eq!(work_queue.snipes[0].class_id, 2);
     }
 }
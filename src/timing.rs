@@ -0,0 +1,183 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{GymSniperError, Result};
+use crate::util::profile_file;
+
+pub const TIMINGS_FILE: &str = "snipe_timings.jsonl";
+
+/// Timings file for a profile (`snipe_timings.<profile>.jsonl`)
+pub fn timings_path(profile: Option<&str>) -> PathBuf {
+    profile_file(TIMINGS_FILE, profile)
+}
+
+/// Timing of a single snipe, recorded once booking attempts finish
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnipeTiming {
+    pub club_id: u32,
+    pub class_id: u64,
+    pub class_name: String,
+    pub class_time: DateTime<Local>,
+    /// When the configured booking window said the class would open
+    pub expected_open: DateTime<Local>,
+    pub first_attempt_at: DateTime<Local>,
+    /// First attempt that wasn't rejected as too soon to book
+    pub opened_at: Option<DateTime<Local>>,
+    pub attempts: u32,
+}
+
+impl SnipeTiming {
+    /// Observed offset between the expected and actual window opening, in milliseconds.
+    /// Only known if at least one attempt was rejected as too soon first.
+    pub fn offset_ms(&self) -> Option<i64> {
+        let opened_at = self.opened_at?;
+        if opened_at <= self.first_attempt_at && opened_at > self.expected_open {
+            // Bookable on the first try after the expected time: the real open time is unknown
+            return None;
+        }
+        Some(opened_at.signed_duration_since(self.expected_open).num_milliseconds())
+    }
+}
+
+/// Append a timing record to the timings file
+pub fn record(path: &Path, timing: &SnipeTiming) -> Result<()> {
+    let line = serde_json::to_string(timing).map_err(|e| {
+        GymSniperError::Config(format!("Failed to serialize snipe timing: {}", e))
+    })?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// Load all timing records, skipping lines that fail to parse
+pub fn load(path: &Path) -> Result<Vec<SnipeTiming>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Suggested window settings for one class at one gym
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowEstimate {
+    pub club_id: u32,
+    pub class_name: String,
+    pub samples: usize,
+    pub median_offset_ms: i64,
+    pub min_offset_ms: i64,
+    pub max_offset_ms: i64,
+    pub suggested_offset_secs: i64,
+    pub suggested_slack_ms: u64,
+}
+
+/// Estimate the real window opening per gym and class from recorded timings
+pub fn estimate_windows(timings: &[SnipeTiming]) -> Vec<WindowEstimate> {
+    let mut groups: Vec<((u32, String), Vec<i64>)> = Vec::new();
+    for timing in timings {
+        let Some(offset) = timing.offset_ms() else {
+            continue;
+        };
+        let key = (timing.club_id, timing.class_name.clone());
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, offsets)) => offsets.push(offset),
+            None => groups.push((key, vec![offset])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|((club_id, class_name), mut offsets)| {
+            offsets.sort_unstable();
+            let median = offsets[offsets.len() / 2];
+            let min = offsets[0];
+            let max = offsets[offsets.len() - 1];
+            let suggested_offset_secs = median.div_euclid(1000);
+            // Start early enough to cover the earliest observed opening, rounded up to 100ms
+            let early_by = (suggested_offset_secs * 1000 - min).max(0) as u64;
+            let suggested_slack_ms = early_by.div_ceil(100).max(1) * 100;
+            WindowEstimate {
+                club_id,
+                class_name,
+                samples: offsets.len(),
+                median_offset_ms: median,
+                min_offset_ms: min,
+                max_offset_ms: max,
+                suggested_offset_secs,
+                suggested_slack_ms,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use tempfile::TempDir;
+
+    fn make_timing(class_name: &str, first_attempt_ms: i64, opened_ms: Option<i64>) -> SnipeTiming {
+        let expected_open = Local.with_ymd_and_hms(2025, 2, 1, 8, 0, 0).unwrap();
+        SnipeTiming {
+            club_id: 1,
+            class_id: 42,
+            class_name: class_name.to_string(),
+            class_time: expected_open + Duration::days(7),
+            expected_open,
+            first_attempt_at: expected_open + Duration::milliseconds(first_attempt_ms),
+            opened_at: opened_ms.map(|ms| expected_open + Duration::milliseconds(ms)),
+            attempts: 5,
+        }
+    }
+
+    #[test]
+    fn estimate_suggests_offset_from_median() {
+        let timings = vec![
+            make_timing("Yoga", -500, Some(2300)),
+            make_timing("Yoga", -500, Some(2500)),
+            make_timing("Yoga", -500, Some(1800)),
+            make_timing("Spin", -500, Some(-300)),
+            // Never opened, and opened on the first late attempt: both ignored
+            make_timing("Yoga", -500, None),
+            make_timing("Yoga", 100, Some(100)),
+        ];
+
+        let estimates = estimate_windows(&timings);
+        assert_eq!(estimates.len(), 2);
+
+        let yoga = &estimates[0];
+        assert_eq!(yoga.class_name, "Yoga");
+        assert_eq!(yoga.samples, 3);
+        assert_eq!(yoga.median_offset_ms, 2300);
+        assert_eq!(yoga.min_offset_ms, 1800);
+        assert_eq!(yoga.max_offset_ms, 2500);
+        assert_eq!(yoga.suggested_offset_secs, 2);
+        assert_eq!(yoga.suggested_slack_ms, 200);
+
+        let spin = &estimates[1];
+        assert_eq!(spin.suggested_offset_secs, -1);
+        assert_eq!(spin.suggested_slack_ms, 100);
+    }
+
+    #[test]
+    fn record_and_load_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(TIMINGS_FILE);
+        assert!(load(&path).unwrap().is_empty());
+
+        record(&path, &make_timing("Yoga", -500, Some(2300))).unwrap();
+        record(&path, &make_timing("Spin", -500, None)).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].offset_ms(), Some(2300));
+        assert_eq!(loaded[1].opened_at, None);
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{GymSniperError, Result};
+use crate::util::profile_file;
+
+pub const TOKEN_FILE: &str = "token.json";
+
+/// Saved-token file for a profile (`token.<profile>.json`)
+pub fn token_path(profile: Option<&str>) -> PathBuf {
+    profile_file(TOKEN_FILE, profile)
+}
+
+/// A login token saved by `login --save-token`, so later runs can skip logging in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedToken {
+    /// Login the token belongs to
+    pub email: String,
+    /// Portal the token was issued by
+    pub base_url: String,
+    pub token: String,
+    /// When the token expires by our clock, from its `exp` claim
+    pub expires_at: DateTime<Local>,
+    pub logged_in_at: DateTime<Local>,
+    /// Club requests were for, which may have come from the login response
+    pub club_id: u32,
+    /// How far the gym server's clock was ahead of ours at login, in milliseconds
+    pub server_offset_ms: i64,
+}
+
+impl CachedToken {
+    /// Whether the token can stand in for a fresh login by `email` at `base_url`: it belongs
+    /// to them and is still valid for at least `margin` after `now`
+    pub fn usable_for(&self, email: &str, base_url: &str, now: DateTime<Local>, margin: chrono::Duration) -> bool {
+        self.email == email && self.base_url == base_url && self.expires_at - margin > now
+    }
+}
+
+/// Write the token, readable by the current user only on Unix
+pub fn save(path: &Path, cached: &CachedToken) -> Result<()> {
+    let json = serde_json::to_string_pretty(cached)
+        .map_err(|e| GymSniperError::Config(format!("Failed to serialize token: {}", e)))?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // `mode` only applies to new files, so tighten one left by an older version too
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// The saved token, or `None` if there is none or it can't be read
+pub fn load(path: &Path) -> Option<CachedToken> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Delete the saved token. Returns whether there was one.
+pub fn remove(path: &Path) -> Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn cached(expires_at: DateTime<Local>) -> CachedToken {
+        CachedToken {
+            email: "me@example.com".to_string(),
+            base_url: "https://gym.example.com/clientportal2".to_string(),
+            token: "header.payload.sig".to_string(),
+            expires_at,
+            logged_in_at: expires_at - chrono::Duration::hours(1),
+            club_id: 2,
+            server_offset_ms: 0,
+        }
+    }
+
+    #[test]
+    fn save_load_and_remove_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(TOKEN_FILE);
+        let token = cached(Local.with_ymd_and_hms(2026, 3, 10, 8, 0, 0).unwrap());
+
+        save(&path, &token).unwrap();
+        assert_eq!(load(&path), Some(token));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        assert!(remove(&path).unwrap());
+        assert!(!remove(&path).unwrap());
+        assert_eq!(load(&path), None);
+    }
+
+    #[test]
+    fn usable_only_for_the_same_login_before_expiry() {
+        let expires_at = Local.with_ymd_and_hms(2026, 3, 10, 8, 0, 0).unwrap();
+        let token = cached(expires_at);
+        let margin = chrono::Duration::seconds(60);
+        let url = "https://gym.example.com/clientportal2";
+
+        assert!(token.usable_for("me@example.com", url, expires_at - chrono::Duration::minutes(5), margin));
+        assert!(!token.usable_for("me@example.com", url, expires_at - chrono::Duration::seconds(30), margin));
+        assert!(!token.usable_for("other@example.com", url, expires_at - chrono::Duration::minutes(5), margin));
+        assert!(!token.usable_for("me@example.com", "https://other.example.com", expires_at - chrono::Duration::minutes(5), margin));
+    }
+}
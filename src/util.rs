@@ -1,8 +1,57 @@
-use chrono::{Duration, Weekday};
+use chrono::{DateTime, Datelike, Duration, Local, Weekday};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::path::{Path, PathBuf};
 
-/// The booking window: how far before class time the booking opens (7 days + 2 hours)
-pub fn booking_window() -> Duration {
-    Duration::days(7) + Duration::hours(2)
+use crate::api::{ClassInfo, MyBooking};
+use crate::config::{AttemptDelayConfig, ClassTarget, DelayDistribution, GymConfig, MatchingConfig};
+use crate::error::{GymSniperError, Result};
+
+/// The booking window: how far before class time the booking opens (7 days + 2 hours
+/// unless the gym config says otherwise)
+pub fn booking_window(gym: &GymConfig) -> Duration {
+    Duration::days(gym.booking_window_days as i64) + Duration::hours(gym.booking_window_hours as i64)
+}
+
+/// When booking opens for a class: the gym's own time if given, otherwise computed from
+/// the class time and shifted by `gym.window_offset_secs`
+pub fn window_opens(start_time: DateTime<Local>, bookable_from: Option<DateTime<Local>>, gym: &GymConfig) -> DateTime<Local> {
+    bookable_from.unwrap_or_else(|| start_time - booking_window(gym) + Duration::seconds(gym.window_offset_secs))
+}
+
+/// Refuse a cancellation of a class starting within `gym.min_cancel_notice_hours` of `now`
+pub fn check_cancel_notice(class_name: &str, start_time: DateTime<Local>, now: DateTime<Local>, gym: &GymConfig) -> Result<()> {
+    let Some(hours) = gym.min_cancel_notice_hours else {
+        return Ok(());
+    };
+    let left = start_time - now;
+    if left < Duration::hours(hours as i64) {
+        return Err(GymSniperError::CancelRefused(format!(
+            "{} starts in {}, inside the {}h minimum cancel notice",
+            class_name,
+            format_duration(left.max(Duration::zero())),
+            hours
+        )));
+    }
+    Ok(())
+}
+
+/// Class length for listings, e.g. "45 min", or "-" when the gym doesn't say
+pub fn format_class_length(duration_minutes: u32) -> String {
+    if duration_minutes == 0 {
+        "-".to_string()
+    } else {
+        format!("{} min", duration_minutes)
+    }
+}
+
+/// A status for listings with places taken out of the total when both are known,
+/// e.g. "Bookable (3/20)"
+pub fn status_with_places(status: &str, booked_count: Option<u32>, capacity: Option<u32>) -> String {
+    match (booked_count, capacity) {
+        (Some(booked), Some(capacity)) => format!("{} ({}/{})", status, booked, capacity),
+        _ => status.to_string(),
+    }
 }
 
 /// Format a duration as human-readable string (e.g., "2h 30m 15s")
@@ -30,6 +79,53 @@ pub fn truncate(s: &str, max_len: usize) -> String {
     }
 }
 
+/// Namespace a state or config file by profile: `snipes.json` becomes `snipes.<profile>.json`.
+/// With no profile the name is unchanged.
+pub fn profile_file(base: &str, profile: Option<&str>) -> PathBuf {
+    let Some(profile) = profile else {
+        return PathBuf::from(base);
+    };
+    let path = Path::new(base);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(base);
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}.{}.{}", stem, profile, ext),
+        None => format!("{}.{}", stem, profile),
+    };
+    path.with_file_name(name)
+}
+
+/// Replace the file at `path` with `content` by writing a temporary file alongside and
+/// renaming it over, so the file is never left half-written. The temporary name is unique
+/// to this process and call, so concurrent savers can't clobber each other's writes, and
+/// it is synced to disk before the rename. With `owner_only` it is readable by the
+/// current user only on Unix.
+pub fn replace_file(path: &Path, content: &[u8], owner_only: bool) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.{}.tmp", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if owner_only {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = owner_only;
+    let written = options.open(&temp_path).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    written.and_then(|()| std::fs::rename(&temp_path, path)).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp_path);
+    })
+}
+
 /// Check if a day string matches a weekday
 pub fn weekday_matches(day_str: &str, weekday: Weekday) -> bool {
     matches!(
@@ -44,10 +140,313 @@ pub fn weekday_matches(day_str: &str, weekday: Weekday) -> bool {
     )
 }
 
+/// Canonical form of a class name for matching: trademark symbols removed, whitespace
+/// collapsed and lowercased, plus the optional rules in `MatchingConfig`
+pub fn normalize_class_name(name: &str, rules: &MatchingConfig) -> String {
+    let mut cleaned = String::with_capacity(name.len());
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '™' | '®' | '©' => {}
+            '(' | '[' if rules.strip_parenthesized => depth += 1,
+            ')' | ']' if rules.strip_parenthesized && depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            _ => cleaned.push(c),
+        }
+    }
+
+    let mut words: Vec<String> = cleaned.split_whitespace().map(|w| w.to_lowercase()).collect();
+
+    for prefix in &rules.strip_prefixes {
+        let prefix: Vec<String> = prefix.split_whitespace().map(|w| w.to_lowercase()).collect();
+        if !prefix.is_empty() && words.len() > prefix.len() && words.starts_with(&prefix) {
+            words.drain(..prefix.len());
+        }
+    }
+
+    if rules.strip_trailing_numbers {
+        while words.len() > 1 && words.last().is_some_and(|w| w.chars().all(|c| c.is_ascii_digit())) {
+            words.pop();
+        }
+    }
+
+    words.join(" ")
+}
+
+/// Whether a class name contains a search term, comparing normalized forms
+pub fn class_name_matches(class_name: &str, search: &str, rules: &MatchingConfig) -> bool {
+    normalize_class_name(class_name, rules).contains(&normalize_class_name(search, rules))
+}
+
+/// Whether a class's trainer contains `wanted`, ignoring case. A class with no trainer
+/// listed never matches.
+pub fn trainer_matches(trainer: Option<&str>, wanted: &str) -> bool {
+    trainer.is_some_and(|t| t.to_lowercase().contains(&wanted.to_lowercase()))
+}
+
+/// Check if a class matches a target: its exact ID, or its name, day and time filters
+pub fn class_matches(class: &ClassInfo, target: &ClassTarget, matching: &MatchingConfig) -> bool {
+    if let Some(id) = target.class_id {
+        return class.id == id;
+    }
+    let Some(class_name) = &target.class_name else {
+        return false;
+    };
+    let class_time = class.start_time;
+
+    let day_matches = target.days.as_ref().is_none_or(|days| {
+        days.iter().any(|d| weekday_matches(d, class_time.weekday()))
+    });
+
+    let name_matches = class_name_matches(&class.name, class_name, matching);
+    let time_matches = target.time.as_ref().is_none_or(|t| {
+        class_time.format("%H:%M").to_string() == *t
+    });
+
+    name_matches && day_matches && time_matches
+}
+
+/// The one class matching a target, for booking by name without an ID. Errors when none
+/// match, or when several do and the target needs narrowing.
+pub fn single_match<'a>(classes: &'a [ClassInfo], target: &ClassTarget, matching: &MatchingConfig) -> Result<&'a ClassInfo> {
+    let matches: Vec<&ClassInfo> = classes.iter().filter(|c| class_matches(c, target, matching)).collect();
+    match matches.as_slice() {
+        [class] => Ok(class),
+        [] => Err(GymSniperError::NotFound(format!("No class matches {}", describe_target(target)))),
+        several => Err(GymSniperError::Config(format!(
+            "{} classes match {}: {} - book one by ID instead",
+            several.len(),
+            describe_target(target),
+            several
+                .iter()
+                .map(|c| format!("{} ({}, {})", c.id, c.name, c.zone))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// A target's filters for messages, e.g. "'Yoga' on mon at 09:00"
+fn describe_target(target: &ClassTarget) -> String {
+    let mut description = format!("'{}'", target.label());
+    if let Some(days) = &target.days {
+        description.push_str(&format!(" on {}", days.join("/")));
+    }
+    if let Some(time) = &target.time {
+        description.push_str(&format!(" at {}", time));
+    }
+    description
+}
+
+/// Bookings whose name matches `pattern` and that start within the next `days` days
+pub fn bookings_matching<'a>(
+    bookings: &'a [MyBooking],
+    pattern: &str,
+    days: u32,
+    now: DateTime<Local>,
+    rules: &MatchingConfig,
+) -> Vec<&'a MyBooking> {
+    let until = now + Duration::days(days as i64);
+    bookings
+        .iter()
+        .filter(|b| b.start_time > now && b.start_time <= until)
+        .filter(|b| class_name_matches(&b.name, pattern, rules))
+        .collect()
+}
+
+/// Produces the delays between booking attempts from an `AttemptDelayConfig`
+pub struct AttemptDelays {
+    config: AttemptDelayConfig,
+    rng: StdRng,
+    jitter_spent_ms: u64,
+    /// Delays handed out so far
+    given: u32,
+}
+
+impl AttemptDelays {
+    pub fn new(config: &AttemptDelayConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            config: config.clone(),
+            rng,
+            jitter_spent_ms: 0,
+            given: 0,
+        }
+    }
+
+    /// Next delay, always within `min_ms..=max_ms` except with the `burst` pattern, which
+    /// has its own delays
+    pub fn next_delay(&mut self) -> std::time::Duration {
+        self.given += 1;
+        let min = self.config.min_ms;
+        let max = self.config.max_ms.max(min);
+
+        let sample = match self.config.distribution {
+            DelayDistribution::Uniform => self.rng.gen_range(min..=max),
+            DelayDistribution::Lognormal => {
+                // Box-Muller transform for a standard normal sample
+                let u1: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+                let u2: f64 = self.rng.gen_range(0.0..1.0);
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                let value = self.config.median_ms as f64 * (self.config.sigma * z).exp();
+                (value.round() as u64).clamp(min, max)
+            }
+            DelayDistribution::Burst => {
+                // The first `burst_count` attempts are `burst_delay_ms` apart
+                let ms = if self.given < self.config.burst_count {
+                    self.config.burst_delay_ms
+                } else {
+                    self.config.steady_delay_ms
+                };
+                return std::time::Duration::from_millis(ms);
+            }
+        };
+
+        let mut jitter = sample - min;
+        if let Some(budget) = self.config.jitter_budget_ms {
+            jitter = jitter.min(budget.saturating_sub(self.jitter_spent_ms));
+        }
+        self.jitter_spent_ms += jitter;
+
+        std::time::Duration::from_millis(min + jitter)
+    }
+}
+
+/// Exponential backoff with jitter: each delay falls between half and all of the current
+/// step, which starts at `base` and grows by `multiplier` up to `max`
+pub struct Backoff {
+    max: std::time::Duration,
+    multiplier: f64,
+    current: std::time::Duration,
+    rng: StdRng,
+}
+
+impl Backoff {
+    pub fn new(base: std::time::Duration, max: std::time::Duration, multiplier: f64) -> Self {
+        Self::with_rng(base, max, multiplier, StdRng::from_entropy())
+    }
+
+    /// Same as `new` but with reproducible jitter
+    pub fn seeded(base: std::time::Duration, max: std::time::Duration, multiplier: f64, seed: u64) -> Self {
+        Self::with_rng(base, max, multiplier, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(base: std::time::Duration, max: std::time::Duration, multiplier: f64, rng: StdRng) -> Self {
+        Self {
+            max,
+            multiplier: multiplier.max(1.0),
+            current: base.min(max),
+            rng,
+        }
+    }
+
+    /// Next delay; zero if `base` was zero
+    pub fn next_delay(&mut self) -> std::time::Duration {
+        let step = self.current.as_millis() as u64;
+        let delay = self.rng.gen_range(step / 2..=step);
+        // An absurd multiplier overflows; the step is capped at `max` anyway
+        self.current = std::time::Duration::try_from_secs_f64(self.current.as_secs_f64() * self.multiplier)
+            .map_or(self.max, |next| next.min(self.max));
+        std::time::Duration::from_millis(delay)
+    }
+}
+
+/// Aggregate counts over a list of classes, for summary footers
+#[derive(Debug, PartialEq)]
+pub struct ClassSummary {
+    pub total: usize,
+    pub bookable: usize,
+    pub full: usize,
+    /// Classes per zone, in order of first appearance
+    pub zones: Vec<(String, usize)>,
+}
+
+/// Count classes by status and zone
+pub fn summarize_classes(classes: &[ClassInfo]) -> ClassSummary {
+    let mut zones: Vec<(String, usize)> = Vec::new();
+    for class in classes {
+        match zones.iter_mut().find(|(z, _)| *z == class.zone) {
+            Some((_, count)) => *count += 1,
+            None => zones.push((class.zone.clone(), 1)),
+        }
+    }
+
+    ClassSummary {
+        total: classes.len(),
+        bookable: classes.iter().filter(|c| c.status == "Bookable").count(),
+        full: classes
+            .iter()
+            .filter(|c| c.status == "Full" || c.status == "Awaitable")
+            .count(),
+        zones,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn make_class(id: u64, status: &str, zone: &str) -> ClassInfo {
+        ClassInfo {
+            id,
+            name: format!("Class {}", id),
+            start_time: Local::now(),
+            status: status.to_string(),
+            trainer: None,
+            zone: zone.to_string(),
+            bookable_from: None,
+            duration_minutes: 0,
+            capacity: None,
+            booked_count: None,
+        }
+    }
+
+    #[test]
+    fn single_match_needs_name_day_and_time_to_pick_one_class() {
+        use chrono::TimeZone;
+
+        // Monday 3 March 2025
+        let at = |day: u32, hour: u32, minute: u32| Local.with_ymd_and_hms(2025, 3, day, hour, minute, 0).unwrap();
+        let class = |id: u64, name: &str, start: DateTime<Local>, zone: &str| ClassInfo {
+            name: name.to_string(),
+            start_time: start,
+            ..make_class(id, "Bookable", zone)
+        };
+        let classes = vec![
+            class(1, "Yoga Flow", at(3, 9, 0), "Studio 1"),
+            class(2, "Yoga Flow", at(4, 9, 0), "Studio 1"),
+            class(3, "Hot Yoga", at(3, 18, 30), "Studio 2"),
+            class(4, "Yoga™ Flow", at(3, 18, 30), "Studio 1"),
+        ];
+        let target = |name: &str, day: &str, time: &str| ClassTarget {
+            class_name: Some(name.to_string()),
+            days: Some(vec![day.to_string()]),
+            time: Some(time.to_string()),
+            ..Default::default()
+        };
+        let rules = MatchingConfig::default();
+
+        assert_eq!(single_match(&classes, &target("yoga flow", "mon", "09:00"), &rules).unwrap().id, 1);
+        assert_eq!(single_match(&classes, &target("Yoga Flow", "Tuesday", "09:00"), &rules).unwrap().id, 2);
+        assert_eq!(single_match(&classes, &target("Flow", "mon", "18:30"), &rules).unwrap().id, 4);
+
+        let none = single_match(&classes, &target("Yoga", "wed", "09:00"), &rules).unwrap_err();
+        assert!(matches!(none, GymSniperError::NotFound(_)), "{}", none);
+        let several = single_match(&classes, &target("Yoga", "mon", "18:30"), &rules).unwrap_err().to_string();
+        assert!(several.contains("2 classes match 'Yoga' on mon at 18:30"), "{}", several);
+        assert!(several.contains("3 (Hot Yoga, Studio 2)") && several.contains("4 (Yoga™ Flow, Studio 1)"), "{}", several);
+    }
+
+    #[test]
+    fn status_with_places_only_when_both_counts_known() {
+        assert_eq!(status_with_places("Bookable", Some(3), Some(20)), "Bookable (3/20)");
+        assert_eq!(status_with_places("Bookable", None, Some(20)), "Bookable");
+        assert_eq!(status_with_places("Waitlist #2", Some(20), None), "Waitlist #2");
+    }
+
     #[test]
     fn format_duration_hours_mins_secs() {
         let d = chrono::Duration::hours(2) + chrono::Duration::minutes(30) + chrono::Duration::seconds(15);
@@ -87,6 +486,39 @@ mod tests {
         assert_eq!(truncate("hello world", 8), "hello...");
     }
 
+    #[test]
+    fn profile_file_namespaces_by_profile() {
+        assert_eq!(profile_file("snipes.json", None), PathBuf::from("snipes.json"));
+        assert_eq!(profile_file("snipes.json", Some("work")), PathBuf::from("snipes.work.json"));
+        assert_eq!(profile_file("conf/config.toml", Some("gym2")), PathBuf::from("conf/config.gym2.toml"));
+        assert_eq!(profile_file("state", Some("a")), PathBuf::from("state.a"));
+    }
+
+    #[test]
+    fn concurrent_replacements_each_land_whole() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("snipes.json");
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || replace_file(&path, format!("writer {}", i).repeat(1000).as_bytes(), true))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!((0..8).any(|i| content == format!("writer {}", i).repeat(1000)));
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
     #[test]
     fn weekday_matches_full_names() {
         assert!(weekday_matches("monday", Weekday::Mon));
@@ -122,4 +554,213 @@ mod tests {
         assert!(!weekday_matches("xyz", Weekday::Mon));
         assert!(!weekday_matches("", Weekday::Mon));
     }
+
+    #[test]
+    fn trainer_matches_substring_ignoring_case() {
+        assert!(trainer_matches(Some("Alice Smith"), "alice"));
+        assert!(trainer_matches(Some("alice smith"), "SMITH"));
+        assert!(!trainer_matches(Some("Coach Mike"), "alice"));
+        assert!(!trainer_matches(None, "alice"));
+    }
+
+    #[test]
+    fn normalize_messy_names_to_same_form() {
+        let rules = MatchingConfig {
+            strip_trailing_numbers: true,
+            strip_prefixes: vec!["Les Mills".to_string()],
+            ..MatchingConfig::default()
+        };
+        for name in [
+            "LES MILLS™ BODYPUMP 45",
+            "BODYPUMP (Express)",
+            "  BodyPump®  ",
+            "Les  Mills BODYPUMP [Virtual] 30",
+            "bodypump",
+        ] {
+            assert_eq!(normalize_class_name(name, &rules), "bodypump", "{}", name);
+        }
+    }
+
+    #[test]
+    fn normalize_respects_disabled_rules() {
+        let rules = MatchingConfig {
+            strip_parenthesized: false,
+            strip_trailing_numbers: false,
+            strip_prefixes: Vec::new(),
+        };
+        assert_eq!(normalize_class_name("BODYPUMP™ (Express) 45", &rules), "bodypump (express) 45");
+        // A name that is only a number is never stripped to nothing
+        let rules = MatchingConfig { strip_trailing_numbers: true, ..MatchingConfig::default() };
+        assert_eq!(normalize_class_name("45", &rules), "45");
+    }
+
+    #[test]
+    fn class_name_matches_normalized() {
+        let rules = MatchingConfig::default();
+        assert!(class_name_matches("LES MILLS™ BODYPUMP 45", "bodypump", &rules));
+        assert!(class_name_matches("Body  Pump", "body pump", &rules));
+        assert!(!class_name_matches("BODYCOMBAT", "bodypump", &rules));
+    }
+
+    #[test]
+    fn bookings_matching_filters_by_name_and_range() {
+        let now = Local::now();
+        let booking = |id: u64, name: &str, in_days: i64| MyBooking {
+            id,
+            name: name.to_string(),
+            start_time: now + Duration::days(in_days),
+            status: "Booked".to_string(),
+            waitlist_position: None,
+            trainer: None,
+            booked_count: None,
+            capacity: None,
+            bookable_from: None,
+            description: None,
+            zone: None,
+            duration_minutes: 0,
+        };
+        let bookings = vec![
+            booking(1, "Spin 45", 1),
+            booking(2, "Yoga", 2),
+            booking(3, "SPIN (Express)", 6),
+            booking(4, "Spin", 10),
+            booking(5, "Spin", -1),
+        ];
+
+        let ids: Vec<u64> = bookings_matching(&bookings, "spin", 7, now, &MatchingConfig::default())
+            .iter()
+            .map(|b| b.id)
+            .collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn summarize_classes_counts_statuses_and_zones() {
+        let classes = vec![
+            make_class(1, "Bookable", "Studio A"),
+            make_class(2, "Full", "Studio A"),
+            make_class(3, "Awaitable", "Pool"),
+            make_class(4, "Bookable", "Studio B"),
+            make_class(5, "Booked", "Pool"),
+            make_class(6, "Unavailable", "Studio A"),
+        ];
+
+        let summary = summarize_classes(&classes);
+        assert_eq!(summary.total, 6);
+        assert_eq!(summary.bookable, 2);
+        assert_eq!(summary.full, 2);
+        assert_eq!(
+            summary.zones,
+            vec![
+                ("Studio A".to_string(), 3),
+                ("Pool".to_string(), 2),
+                ("Studio B".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn summarize_classes_empty() {
+        let summary = summarize_classes(&[]);
+        assert_eq!(summary.total, 0);
+        assert!(summary.zones.is_empty());
+    }
+
+    fn delay_config(distribution: DelayDistribution) -> AttemptDelayConfig {
+        AttemptDelayConfig {
+            distribution,
+            min_ms: 150,
+            max_ms: 600,
+            median_ms: 300,
+            sigma: 0.5,
+            jitter_budget_ms: None,
+            seed: Some(42),
+            ..AttemptDelayConfig::default()
+        }
+    }
+
+    #[test]
+    fn attempt_delays_respect_bounds() {
+        for distribution in [DelayDistribution::Uniform, DelayDistribution::Lognormal] {
+            let mut delays = AttemptDelays::new(&delay_config(distribution));
+            for _ in 0..10_000 {
+                let ms = delays.next_delay().as_millis() as u64;
+                assert!((150..=600).contains(&ms), "{:?} delay {}ms out of bounds", distribution, ms);
+            }
+        }
+    }
+
+    #[test]
+    fn attempt_delays_seed_is_deterministic() {
+        let config = delay_config(DelayDistribution::Lognormal);
+        let mut a = AttemptDelays::new(&config);
+        let mut b = AttemptDelays::new(&config);
+        let seq_a: Vec<_> = (0..20).map(|_| a.next_delay()).collect();
+        let seq_b: Vec<_> = (0..20).map(|_| b.next_delay()).collect();
+        assert_eq!(seq_a, seq_b);
+    }
+
+    #[test]
+    fn attempt_delays_jitter_budget_caps_total() {
+        let mut config = delay_config(DelayDistribution::Uniform);
+        config.jitter_budget_ms = Some(1000);
+        let mut delays = AttemptDelays::new(&config);
+
+        let total: u64 = (0..50).map(|_| delays.next_delay().as_millis() as u64).sum();
+        assert!(total <= 50 * 150 + 1000, "total {}ms exceeds budget", total);
+        assert_eq!(delays.next_delay().as_millis(), 150);
+    }
+
+    #[test]
+    fn backoff_grows_within_bounds() {
+        let base = std::time::Duration::from_millis(100);
+        let max = std::time::Duration::from_millis(1000);
+        let mut backoff = Backoff::seeded(base, max, 2.0, 7);
+
+        // Steps are 100, 200, 400, 800 and then capped at 1000
+        for step in [100, 200, 400, 800, 1000, 1000, 1000] {
+            let ms = backoff.next_delay().as_millis() as u64;
+            assert!((step / 2..=step).contains(&ms), "{}ms outside the {}ms step", ms, step);
+        }
+    }
+
+    #[test]
+    fn backoff_caps_a_multiplier_that_overflows() {
+        let base = std::time::Duration::from_millis(100);
+        let max = std::time::Duration::from_millis(1000);
+        for multiplier in [1e300, f64::INFINITY, f64::NAN] {
+            let mut backoff = Backoff::seeded(base, max, multiplier, 7);
+            backoff.next_delay();
+            assert!(backoff.next_delay() <= max);
+        }
+    }
+
+    #[test]
+    fn backoff_with_zero_base_never_waits() {
+        let mut backoff = Backoff::new(std::time::Duration::ZERO, std::time::Duration::from_secs(5), 2.0);
+        for _ in 0..5 {
+            assert_eq!(backoff.next_delay(), std::time::Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn attempt_delays_default_is_flat_200ms() {
+        let mut delays = AttemptDelays::new(&AttemptDelayConfig::default());
+        assert_eq!(delays.next_delay(), std::time::Duration::from_millis(200));
+        assert_eq!(delays.next_delay(), std::time::Duration::from_millis(200));
+    }
+
+    #[test]
+    fn burst_delays_then_steady() {
+        let config = AttemptDelayConfig {
+            distribution: DelayDistribution::Burst,
+            burst_count: 3,
+            burst_delay_ms: 40,
+            steady_delay_ms: 600,
+            ..AttemptDelayConfig::default()
+        };
+        let mut delays = AttemptDelays::new(&config);
+        let ms: Vec<u64> = (0..5).map(|_| delays.next_delay().as_millis() as u64).collect();
+        assert_eq!(ms, vec![40, 40, 600, 600, 600]);
+    }
 }
@@ -0,0 +1,182 @@
+//! Checks for a loaded config that would otherwise only fail at runtime, such as a target
+//! time that can never match or an email address the mailer can't parse.
+
+use chrono::{NaiveTime, Weekday};
+use reqwest::Url;
+use std::time::Duration;
+
+use crate::config::{ClassTarget, Config};
+use crate::email;
+use crate::util::weekday_matches;
+
+/// How long to wait for a portal to answer the reachability check
+const REACHABLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// Every problem found in `config` without touching the network, one line each
+pub fn config_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for url in config.gym.portal_urls() {
+        if let Err(e) = web_url(&url) {
+            problems.push(format!("gym: base URL '{}' {}", url, e));
+        }
+    }
+
+    target_problems(&config.targets, "targets", &mut problems);
+    for (i, profile) in config.profiles.iter().enumerate() {
+        target_problems(&profile.targets, &format!("profiles[{}].targets", i), &mut problems);
+    }
+
+    if let Some(email) = config.email_notifications() {
+        if email.smtp_server.trim().is_empty() {
+            problems.push("email.smtp_server is empty".to_string());
+        }
+        problems.extend(email::address_problems(email));
+    }
+
+    if let Some(webhook) = &config.webhook
+        && let Err(e) = web_url(&webhook.url)
+    {
+        // The URL carries the webhook's secret, so it isn't echoed
+        problems.push(format!("webhook.url {}", e));
+    }
+
+    problems
+}
+
+/// Problems with the `time` and `days` filters of one list of targets
+fn target_problems(targets: &[ClassTarget], label: &str, problems: &mut Vec<String>) {
+    for (i, target) in targets.iter().enumerate() {
+        if let Some(time) = &target.time
+            && !is_hh_mm(time)
+        {
+            problems.push(format!(
+                "{}[{}] ({}): time '{}' is not HH:MM (e.g. \"09:00\"), so it never matches",
+                label,
+                i,
+                target.label(),
+                time
+            ));
+        }
+        for day in target.days.iter().flatten() {
+            if !WEEKDAYS.iter().any(|w| weekday_matches(day, *w)) {
+                problems.push(format!(
+                    "{}[{}] ({}): '{}' is not a weekday (use e.g. \"Monday\" or \"Mon\")",
+                    label,
+                    i,
+                    target.label(),
+                    day
+                ));
+            }
+        }
+    }
+}
+
+/// Whether `time` is a 24-hour time written as the scheduler compares it, e.g. "09:00"
+fn is_hh_mm(time: &str) -> bool {
+    time.len() == 5 && NaiveTime::parse_from_str(time, "%H:%M").is_ok()
+}
+
+/// Parse an http(s) URL, describing what is wrong with it otherwise
+fn web_url(url: &str) -> std::result::Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("is not a valid URL ({})", e))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(format!("must be http or https, not {}", scheme)),
+    }
+}
+
+/// Portals that don't answer a HEAD request, with the reason. Any HTTP response counts as
+/// reachable; only connection failures and timeouts are reported.
+pub async fn unreachable_portals(config: &Config) -> Vec<String> {
+    let client = reqwest::Client::new();
+    let mut problems = Vec::new();
+    for url in config.gym.portal_urls() {
+        if web_url(&url).is_err() {
+            continue;
+        }
+        if let Err(e) = client.head(&url).timeout(REACHABLE_TIMEOUT).send().await {
+            problems.push(format!("gym: base URL '{}' is not reachable ({})", url, e.without_url()));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(extra: &str) -> Config {
+        toml::from_str(&format!(
+            "[gym]\nbase_url = \"https://example.com/clientportal2\"\n\n[credentials]\nemail = \"me@example.com\"\npassword = \"pw\"\n\n{}",
+            extra
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        let config = config_with(
+            r#"
+[[targets]]
+class_name = "Yoga"
+days = ["Monday", "fri"]
+time = "09:00"
+
+[email]
+smtp_server = "smtp.example.com"
+smtp_port = 587
+username = "me"
+password = "pw"
+from = "Gym Sniper <me@example.com>"
+to = "me@example.com"
+
+[webhook]
+url = "https://example.com/hook"
+"#,
+        );
+        assert_eq!(config_problems(&config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let config = config_with(
+            r#"
+[[targets]]
+class_name = "Yoga"
+days = ["Monday", "Mondy"]
+time = "9:00"
+
+[email]
+smtp_server = ""
+smtp_port = 587
+username = "me"
+password = "pw"
+from = "me@example.com"
+to = ["partner@example.com", "not an address"]
+cc = ["coach@"]
+
+[webhook]
+url = "ftp://example.com/s3cr3t"
+"#,
+        );
+        let problems = config_problems(&config);
+        assert_eq!(problems.len(), 6, "{:#?}", problems);
+        assert!(problems[0].starts_with("targets[0] (Yoga): time '9:00' is not HH:MM"));
+        assert!(problems[1].contains("'Mondy' is not a weekday"));
+        assert_eq!(problems[2], "email.smtp_server is empty");
+        assert!(problems[3].starts_with("email.to: 'not an address'"));
+        assert!(problems[4].starts_with("email.cc: 'coach@'"));
+        assert_eq!(problems[5], "webhook.url must be http or https, not ftp");
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::{DateTime, Duration, Local};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::api::PerfectGymClient;
+use crate::config::Config;
+use crate::email::FailureExplanation;
+use crate::error::{GymSniperError, Result};
+use crate::history;
+use crate::notify::{Notifier, Notifiers};
+use crate::snipe_queue::SnipeQueue;
+use crate::util::format_duration;
+
+/// Watch a class and notify when it becomes bookable - never books
+pub async fn watch_class(config: &Config, client: &PerfectGymClient, class_id: u64) -> Result<()> {
+    let details = client.get_class_details(class_id).await?;
+    let mut last_status = details.status.clone();
+
+    info!(
+        "Watching {} at {} (current status: {})",
+        details.name,
+        details.start_time.format("%a %d %b %H:%M"),
+        last_status
+    );
+
+    loop {
+        let time_until_class = details.start_time.signed_duration_since(Local::now());
+        if time_until_class <= Duration::zero() {
+            info!("{} has started - stopping watch", details.name);
+            return Ok(());
+        }
+
+        let interval = poll_interval(time_until_class);
+        info!(
+            "Class starts in {}. Checking again in {}...",
+            format_duration(time_until_class),
+            format_duration(interval)
+        );
+        sleep(interval.to_std().unwrap_or_default()).await;
+        client.refresh_token_if_due().await?;
+
+        let current = match client.get_class_details(class_id).await {
+            Ok(current) => current,
+            Err(e) => {
+                // Session may have expired during a long watch
+                info!("Status check failed ({}), logging in again...", e);
+                client.login().await?;
+                client.get_class_details(class_id).await?
+            }
+        };
+
+        if current.status != last_status {
+            info!("Status changed: {} -> {}", last_status, current.status);
+        }
+
+        if availability_opened(&last_status, &current.status) {
+            info!("A spot opened up in {}!", current.name);
+            let time_str = current.start_time.format("%a %d %b %H:%M").to_string();
+            Notifiers::new(config).notify_available(&current.name, &time_str, current.trainer.as_deref()).await;
+            return Ok(());
+        }
+
+        last_status = current.status;
+    }
+}
+
+/// What a check on a class you're waitlisted for found
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaitlistCheck {
+    /// Still on the waitlist, at this position when the gym gives it
+    Waiting(Option<u32>),
+    /// The gym moved you off the waitlist into a place
+    Promoted,
+    /// A spot came free and was booked straight away
+    Booked,
+    /// Off the waitlist without a place; holds the class's status now
+    Dropped(String),
+}
+
+/// Check a waitlisted class once. Logs a change of position, notifies on promotion, and
+/// books at once if the class has come back as bookable rather than waiting on the gym.
+pub async fn check_waitlist(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    last_position: Option<u32>,
+) -> Result<WaitlistCheck> {
+    let details = client.get_class_details(class_id).await?;
+    let time_str = details.start_time.format("%a %d %b %H:%M").to_string();
+    let trainer = details.trainer.as_deref();
+
+    match details.status.as_str() {
+        "Awaiting" => {
+            if details.waitlist_position != last_position {
+                info!(
+                    class_id,
+                    "Waitlist position for {}: {} -> {}",
+                    details.name,
+                    position_label(last_position),
+                    position_label(details.waitlist_position)
+                );
+            }
+            Ok(WaitlistCheck::Waiting(details.waitlist_position))
+        }
+        "Booked" => {
+            info!(class_id, status = "promoted", "Promoted off the waitlist into {}!", details.name);
+            let label = format!("{} (promoted from waitlist)", details.name);
+            Notifiers::new(config).notify_success(&label, &time_str, trainer).await;
+            Ok(WaitlistCheck::Promoted)
+        }
+        "Bookable" => {
+            info!(class_id, "A spot opened in {}, booking it now...", details.name);
+            let result = client.book_class(class_id).await?;
+            info!(class_id, status = "booked", "Booked {} from the waitlist", result.name);
+            Notifiers::new(config).notify_success(&result.name, &time_str, trainer).await;
+            Ok(WaitlistCheck::Booked)
+        }
+        status => {
+            warn!(class_id, "No longer on the waitlist for {} (status: {})", details.name, status);
+            let failure = FailureExplanation::other(format!(
+                "You dropped off the waitlist without getting a place (the class is now {})",
+                status
+            ));
+            Notifiers::new(config).notify_failure(&details.name, &time_str, trainer, &failure).await;
+            Ok(WaitlistCheck::Dropped(status.to_string()))
+        }
+    }
+}
+
+/// `check_waitlist`, logging in again and checking once more if the server rejected the
+/// session, e.g. because the token expired during a long watch
+async fn check_waitlist_in_session(
+    config: &Config,
+    client: &PerfectGymClient,
+    class_id: u64,
+    last_position: Option<u32>,
+) -> Result<WaitlistCheck> {
+    match check_waitlist(config, client, class_id, last_position).await {
+        Err(GymSniperError::Auth(e)) => {
+            info!("Session rejected ({}), logging in again...", e);
+            client.login().await?;
+            check_waitlist(config, client, class_id, last_position).await
+        }
+        check => check,
+    }
+}
+
+/// Watch a class you're waitlisted for until you get a place, drop off the waitlist or the
+/// class starts. Returns the last check's result.
+pub async fn watch_waitlist(config: &Config, client: &PerfectGymClient, class_id: u64) -> Result<WaitlistCheck> {
+    let details = client.get_class_details(class_id).await?;
+    if details.status != "Awaiting" {
+        return Err(GymSniperError::NotFound(format!(
+            "You're not on the waitlist for {} (status: {})",
+            details.name, details.status
+        )));
+    }
+    let mut position = details.waitlist_position;
+
+    info!(
+        "Watching the waitlist for {} at {} (position {})",
+        details.name,
+        details.start_time.format("%a %d %b %H:%M"),
+        position_label(position)
+    );
+
+    loop {
+        let time_until_class = details.start_time.signed_duration_since(Local::now());
+        if time_until_class <= Duration::zero() {
+            info!("{} has started while still on the waitlist - stopping watch", details.name);
+            return Ok(WaitlistCheck::Waiting(position));
+        }
+
+        let interval = poll_interval(time_until_class);
+        info!(
+            "Class starts in {}. Checking again in {}...",
+            format_duration(time_until_class),
+            format_duration(interval)
+        );
+        sleep(interval.to_std().unwrap_or_default()).await;
+        client.refresh_token_if_due().await?;
+
+        match check_waitlist_in_session(config, client, class_id, position).await {
+            Ok(WaitlistCheck::Waiting(current)) => position = current,
+            Ok(outcome) => return Ok(outcome),
+            Err(e) => warn!("Waitlist check failed, will retry: {}", e),
+        }
+    }
+}
+
+/// Watches the snipe daemon's waitlisted entries, checking each on the `poll_interval` for
+/// how far off its class is. Promoted or booked entries leave the queue; ones that drop
+/// off the waitlist, or are still on it when the class starts, are marked failed.
+#[derive(Debug, Default)]
+pub struct WaitlistMonitor {
+    next_check: HashMap<u64, DateTime<Local>>,
+}
+
+impl WaitlistMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When the next check is due, if anything is waitlisted. Entries not checked yet are due at `now`.
+    pub fn next_due(&self, queue: &SnipeQueue, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        queue
+            .waitlisted_snipes()
+            .iter()
+            .map(|s| self.next_check.get(&s.class_id).copied().unwrap_or(now))
+            .min()
+    }
+
+    /// True if any waitlisted entry is due a check at `now`
+    pub fn has_due(&self, queue: &SnipeQueue, now: DateTime<Local>) -> bool {
+        self.next_due(queue, now).is_some_and(|due| due <= now)
+    }
+
+    /// Check every due waitlisted entry and settle it in the queue, logging `client` in again
+    /// if its session is rejected. A failed check is logged and tried again at the next
+    /// interval. The queue is only locked to read and settle entries, never while waiting on
+    /// the gym.
+    pub async fn check_due(
+        &mut self,
+        config: &Config,
+        client: &PerfectGymClient,
+        queue: &Mutex<SnipeQueue>,
+        now: DateTime<Local>,
+    ) -> Result<()> {
+        let due: Vec<_> = queue
+            .lock()
+            .await
+            .waitlisted_snipes()
+            .into_iter()
+            .filter(|s| self.next_check.get(&s.class_id).is_none_or(|t| *t <= now))
+            .cloned()
+            .collect();
+
+        for entry in due {
+            let class_id = entry.class_id;
+            if entry.class_time <= now {
+                info!(class_id, "{} started while still on the waitlist", entry.class_name);
+                let note = format!("Still on the waitlist ({}) when the class started", position_label(entry.waitlist_position));
+                let mut queue = queue.lock().await;
+                queue.reload()?;
+                queue.mark_failed(class_id, &note)?;
+                history::record_outcome(config, &entry, entry.attempts, Some(&note), now);
+                self.next_check.remove(&class_id);
+                continue;
+            }
+
+            let check = check_waitlist_in_session(config, client, class_id, entry.waitlist_position).await;
+            let mut queue = queue.lock().await;
+            queue.reload()?;
+            match check {
+                Ok(WaitlistCheck::Waiting(position)) => {
+                    if position != entry.waitlist_position {
+                        queue.mark_waitlisted(class_id, position)?;
+                    }
+                }
+                Ok(WaitlistCheck::Promoted | WaitlistCheck::Booked) => {
+                    queue.remove(class_id)?;
+                    history::record_outcome(config, &entry, entry.attempts, None, now);
+                    self.next_check.remove(&class_id);
+                    continue;
+                }
+                Ok(WaitlistCheck::Dropped(status)) => {
+                    let note = format!("Dropped off the waitlist (class is {})", status);
+                    queue.mark_failed(class_id, &note)?;
+                    history::record_outcome(config, &entry, entry.attempts, Some(&note), now);
+                    self.next_check.remove(&class_id);
+                    continue;
+                }
+                Err(e) => warn!(class_id, "Waitlist check for {} failed, will retry: {}", entry.class_name, e),
+            }
+            self.next_check.insert(class_id, now + poll_interval(entry.class_time - now));
+        }
+        Ok(())
+    }
+}
+
+/// A waitlist position for logs, e.g. "#3", or "unknown" when the gym doesn't give it
+fn position_label(position: Option<u32>) -> String {
+    position.map(|p| format!("#{}", p)).unwrap_or_else(|| "unknown".to_string())
+}
+
+/// A watched class has opened up when it moves into "Bookable" from any other status
+pub fn availability_opened(previous: &str, current: &str) -> bool {
+    previous != "Bookable" && current == "Bookable"
+}
+
+/// Poll less often while the class is far off
+pub fn poll_interval(time_until_class: Duration) -> Duration {
+    if time_until_class > Duration::days(1) {
+        Duration::minutes(15)
+    } else if time_until_class > Duration::hours(3) {
+        Duration::minutes(5)
+    } else {
+        Duration::minutes(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_to_bookable_triggers() {
+        assert!(availability_opened("Full", "Bookable"));
+        assert!(availability_opened("Awaitable", "Bookable"));
+    }
+
+    #[test]
+    fn unchanged_or_other_transitions_do_not_trigger() {
+        assert!(!availability_opened("Bookable", "Bookable"));
+        assert!(!availability_opened("Full", "Full"));
+        assert!(!availability_opened("Bookable", "Full"));
+        assert!(!availability_opened("Full", "Booked"));
+    }
+
+    #[test]
+    fn poll_interval_backs_off_when_far() {
+        assert_eq!(poll_interval(Duration::days(3)), Duration::minutes(15));
+        assert_eq!(poll_interval(Duration::hours(6)), Duration::minutes(5));
+        assert_eq!(poll_interval(Duration::minutes(30)), Duration::minutes(1));
+    }
+}
@@ -1,23 +1,103 @@
-use wiremock::matchers::{method, path, query_param};
-use wiremock::{Mock, MockServer, ResponseTemplate};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use gym_sniper::api::PerfectGymClient;
-use gym_sniper::config::{Config, Credentials, GymConfig};
+use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use gym_sniper::api::{Category, PerfectGymClient, Spot};
+use gym_sniper::clock::SharedClock;
+use gym_sniper::clock::{Clock, SimulatedClock};
+use gym_sniper::config::Config;
+use gym_sniper::confirm::BookingConfirmations;
+use gym_sniper::defend::{BookingDefender, Defense};
+use gym_sniper::error::{BookingErrorKind, GymSniperError};
+use gym_sniper::logging::LogBuffer;
+use gym_sniper::notify::{Notifiers, TestSample};
+use gym_sniper::replay;
+use gym_sniper::report::ReportDocument;
+use gym_sniper::scheduler;
+use gym_sniper::shutdown::Shutdown;
+use gym_sniper::snipe;
+use gym_sniper::snipe_queue::{SnipeEntry, SnipeQueue, SnipeStatus};
+use gym_sniper::validate;
 
 /// Create a test config pointed at the mock server
 fn test_config(base_url: &str) -> Config {
-    Config {
-        gym: GymConfig {
-            base_url: base_url.to_string(),
-            club_id: 1,
-        },
-        credentials: Credentials {
-            email: "test@example.com".to_string(),
-            password: "password123".to_string(),
-        },
-        targets: vec![],
-        email: None,
-    }
+    test_config_with(base_url, "")
+}
+
+/// Create a test config pointed at the mock server, with extra TOML appended
+fn test_config_with(base_url: &str, extra: &str) -> Config {
+    let mut config: Config = toml::from_str(&format!(
+        r#"
+[gym]
+base_url = "{}"
+club_id = 1
+
+[credentials]
+email = "test@example.com"
+password = "password123"
+
+{}
+"#,
+        base_url, extra
+    ))
+    .unwrap();
+    // Keep snipe outcomes, failures and timings out of the working directory
+    config.snipe.record_history = false;
+    config.snipe.record_failures = false;
+    config.snipe.record_timings = false;
+    config
+}
+
+/// Format a local time the way the API sends it
+fn api_time(t: chrono::DateTime<chrono::Local>) -> String {
+    t.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+/// Mount a weekly classes response with the given classes in a single zone
+async fn mount_weekly_classes(server: &MockServer, classes: serde_json::Value) {
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "CalendarData": [
+                {
+                    "ZoneName": "Studio A",
+                    "ClassesPerHour": [ { "ClassesPerDay": [ classes ] } ]
+                }
+            ]
+        })))
+        .mount(server)
+        .await;
+}
+
+/// Mount a successful BookClass response expected `times` times for the given class ID
+async fn mount_book_class(server: &MockServer, class_id: u64, name: &str, start: &str, times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(body_partial_json(serde_json::json!({ "classId": class_id })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Tickets": [ { "Name": name, "StartTime": start, "Trainer": null } ],
+            "ClassId": class_id
+        })))
+        .expect(times)
+        .mount(server)
+        .await;
+}
+
+/// Mount a successful standby queue join, reporting `position` if given
+async fn mount_join_waitlist(server: &MockServer, class_id: u64, name: &str, start: &str, position: Option<u32>, times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookToStandbyQueue"))
+        .and(body_partial_json(serde_json::json!({ "classId": class_id })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Tickets": [ { "Name": name, "StartTime": start, "Trainer": null } ],
+            "StandByQueueNumber": position,
+            "ClassId": class_id
+        })))
+        .expect(times)
+        .mount(server)
+        .await;
 }
 
 /// Mount a successful login mock that returns a JWT token
@@ -43,17 +123,122 @@ async fn mount_login(server: &MockServer) {
 
 // ── Login tests ──────────────────────────────────────────────────
 
+#[test]
+fn client_without_a_portal_url_is_a_config_error() {
+    let mut config = test_config("http://127.0.0.1:1");
+    config.gym.base_url.clear();
+    config.gym.base_urls.clear();
+    assert!(matches!(PerfectGymClient::new(&config), Err(GymSniperError::Config(_))));
+}
+
 #[tokio::test]
 async fn login_success() {
     let server = MockServer::start().await;
     mount_login(&server).await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     let result = client.login().await;
     assert!(result.is_ok());
 }
 
+#[tokio::test]
+async fn saved_token_is_reused_until_it_expires() {
+    use base64::Engine;
+
+    let server = MockServer::start().await;
+    let exp = (chrono::Local::now() + chrono::Duration::minutes(45)).timestamp();
+    let encode = |json: String| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+    let jwt = format!("{}.{}.sig", encode(r#"{"alg":"HS256"}"#.to_string()), encode(format!(r#"{{"exp":{}}}"#, exp)));
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", jwt.as_str())
+                .set_body_json(serde_json::json!({ "User": null })),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let token_file = dir.path().join("token.json");
+    let config = test_config(&server.uri());
+    let client = |config: &Config| {
+        PerfectGymClient::builder(config).token_file(Some(token_file.clone())).build().unwrap()
+    };
+
+    // Without `login --save-token`, nothing is written
+    client(&config).login().await.unwrap();
+    assert!(!token_file.exists());
+
+    let saving = client(&config);
+    saving.set_save_token(true);
+    saving.login_fresh().await.unwrap();
+    let saved = gym_sniper::token_cache::load(&token_file).unwrap();
+    assert_eq!(saved.token, jwt);
+    assert_eq!(saved.expires_at.timestamp(), exp);
+
+    // A later client starts out holding the saved token and doesn't log in again
+    let reusing = client(&config);
+    assert_eq!(reusing.token_expires_at().await.map(|t| t.timestamp()), Some(exp));
+    reusing.login().await.unwrap();
+
+    // The same portal given only through `base_urls` shares the token
+    let mut mirrors_only = config.clone();
+    mirrors_only.gym.base_url = String::new();
+    mirrors_only.gym.base_urls = vec![format!("{}/", server.uri())];
+    assert_eq!(client(&mirrors_only).token_expires_at().await.map(|t| t.timestamp()), Some(exp));
+
+    // Another account's token is never used
+    let mut other = config.clone();
+    other.credentials.email = "someone-else@example.com".to_string();
+    assert_eq!(client(&other).token_expires_at().await, None);
+}
+
+#[tokio::test]
+async fn revoked_saved_token_is_replaced_by_a_fresh_login() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", "fresh-token")
+                .set_body_json(serde_json::json!({ "User": null })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(header("Authorization", "Bearer revoked-token"))
+        .respond_with(ResponseTemplate::new(401))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    mount_weekly_classes(&server, serde_json::json!([])).await;
+
+    // Saved by an earlier `login --save-token`, still unexpired, but revoked by the server
+    let dir = tempfile::TempDir::new().unwrap();
+    let token_file = dir.path().join("token.json");
+    let config = test_config(&server.uri());
+    let now = chrono::Local::now();
+    let saved = gym_sniper::token_cache::CachedToken {
+        email: config.credentials.email.clone(),
+        base_url: server.uri(),
+        token: "revoked-token".to_string(),
+        expires_at: now + chrono::Duration::hours(1),
+        logged_in_at: now,
+        club_id: 1,
+        server_offset_ms: 0,
+    };
+    gym_sniper::token_cache::save(&token_file, &saved).unwrap();
+
+    let client = PerfectGymClient::builder(&config).token_file(Some(token_file)).build().unwrap();
+    client.login().await.unwrap();
+    client.get_weekly_classes(1, None).await.unwrap();
+}
+
 #[tokio::test]
 async fn login_failure_401() {
     let server = MockServer::start().await;
@@ -65,7 +250,7 @@ async fn login_failure_401() {
         .await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     let result = client.login().await;
     assert!(result.is_err());
     let err = match result {
@@ -75,6 +260,185 @@ async fn login_failure_401() {
     assert!(err.contains("Authentication"), "Expected auth error, got: {}", err);
 }
 
+#[tokio::test]
+async fn stale_session_is_replaced_by_a_fresh_login_on_startup() {
+    let server = MockServer::start().await;
+
+    // The first token handed out has since been revoked by the server
+    let logins = Arc::new(Mutex::new(0));
+    let login_count = logins.clone();
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .respond_with(move |_: &Request| {
+            let mut count = login_count.lock().unwrap();
+            *count += 1;
+            let token = if *count == 1 { "stale-token" } else { "fresh-token" };
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", token)
+                .set_body_json(serde_json::json!({ "User": null }))
+        })
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(header("Authorization", "Bearer stale-token"))
+        .respond_with(ResponseTemplate::new(401))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    mount_weekly_classes(&server, serde_json::json!([])).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    assert!(client.ensure_session().await.unwrap());
+    assert_eq!(*logins.lock().unwrap(), 2);
+    // The session is now good, so checking again doesn't log in
+    assert!(!client.ensure_session().await.unwrap());
+    client.get_weekly_classes(1, None).await.unwrap();
+    assert_eq!(*logins.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn omitted_club_id_is_taken_from_login_response() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", "test-jwt-token-123")
+                .set_body_json(serde_json::json!({
+                    "User": { "Member": { "Id": 42, "FirstName": "Test", "HomeClub": { "Id": 55, "Name": "Central" } } }
+                })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(body_partial_json(serde_json::json!({ "clubId": 55 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "CalendarData": [] })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config: Config = toml::from_str(&format!(
+        "[gym]\nbase_url = \"{}\"\n\n[credentials]\nemail = \"test@example.com\"\npassword = \"password123\"\n",
+        server.uri()
+    ))
+    .unwrap();
+    assert_eq!(config.gym.club_id, 0);
+
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    assert_eq!(client.club_id(), 55);
+    client.get_weekly_classes(7, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn debug_logs_mask_token_and_password() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    let logs = LogBuffer::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(logs.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let _ = client.get_class_details(5).await;
+
+    let output = logs.contents();
+    assert!(output.contains("Bearer test…-123"), "no masked bearer token in {}", output);
+    assert!(output.contains("test@example.com"));
+    assert!(!output.contains("test-jwt-token-123"));
+    assert!(!output.contains("password123"));
+}
+
+// ── mirror tests ─────────────────────────────────────────────────
+
+#[tokio::test]
+async fn unreachable_base_url_fails_over_to_next_mirror() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_book_class(&server, 4242, "Yoga", "2025-02-01T08:00:00", 1).await;
+
+    // A port nothing listens on, so connecting is refused
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let dead_url = format!("http://{}", closed.local_addr().unwrap());
+    drop(closed);
+
+    let mut config = test_config(&dead_url);
+    config.gym.base_urls = vec![server.uri()];
+    let client = PerfectGymClient::new(&config).unwrap();
+
+    client.login().await.unwrap();
+    assert_eq!(client.base_url(), server.uri());
+
+    // Later requests go straight to the working mirror, with its own Origin
+    client.book_class(4242).await.unwrap();
+    let requests = server.received_requests().await.unwrap();
+    let booking = requests.iter().find(|r| r.url.path().ends_with("/BookClass")).unwrap();
+    assert_eq!(booking.headers.get("origin").unwrap().to_str().unwrap(), server.uri());
+}
+
+#[tokio::test]
+async fn validate_reports_only_unreachable_portals() {
+    let server = MockServer::start().await;
+
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let dead_url = format!("http://{}", closed.local_addr().unwrap());
+    drop(closed);
+
+    // The mock answers HEAD with a 404, which still counts as reachable
+    let mut config = test_config(&server.uri());
+    config.gym.base_urls = vec![dead_url.clone()];
+    let problems = validate::unreachable_portals(&config).await;
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(problems[0].starts_with(&format!("gym: base URL '{}' is not reachable", dead_url)));
+}
+
+// ── client builder tests ─────────────────────────────────────────
+
+#[tokio::test]
+async fn builder_sends_custom_header_to_overridden_base_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .and(header("X-Tenant", "north"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", "test-jwt-token-123")
+                .set_body_json(serde_json::json!({ "User": null })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The config points elsewhere; the builder redirects it to the mock server
+    let config = test_config("http://127.0.0.1:9");
+    let client = PerfectGymClient::builder(&config)
+        .base_url(server.uri())
+        .header("x-tenant".parse().unwrap(), "north".parse().unwrap())
+        .timeout(Duration::from_secs(5))
+        .clock(SharedClock::System)
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+
+    let preview = client.preview_book_request(1).await.unwrap();
+    assert!(preview.headers.contains(&("x-tenant".to_string(), "north".to_string())));
+}
+
 // ── get_weekly_classes tests ─────────────────────────────────────
 
 #[tokio::test]
@@ -121,9 +485,9 @@ async fn get_weekly_classes_parses_and_sorts() {
         .await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let classes = client.get_weekly_classes(7).await.unwrap();
+    let classes = client.get_weekly_classes(7, None).await.unwrap();
 
     assert_eq!(classes.len(), 2);
     // Should be sorted by start_time, so Yoga (09:00) first
@@ -137,6 +501,32 @@ async fn get_weekly_classes_parses_and_sorts() {
     assert_eq!(classes[1].trainer, Some("Bob".to_string()));
 }
 
+#[tokio::test]
+async fn get_weekly_classes_orders_same_time_classes_by_id() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    let class = |id: u64, start: &str| {
+        serde_json::json!({ "Id": id, "Name": format!("Class {}", id), "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null })
+    };
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            class(9, "2025-01-15T18:00:00"),
+            class(1, "2025-01-15T19:00:00"),
+            class(3, "2025-01-15T18:00:00"),
+            class(5, "2025-01-15T18:00:00"),
+        ]),
+    )
+    .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let ids: Vec<u64> = client.get_weekly_classes(7, None).await.unwrap().iter().map(|c| c.id).collect();
+    assert_eq!(ids, [3, 5, 9, 1]);
+}
+
 #[tokio::test]
 async fn get_weekly_classes_empty_response() {
     let server = MockServer::start().await;
@@ -153,144 +543,3477 @@ async fn get_weekly_classes_empty_response() {
         .await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let classes = client.get_weekly_classes(7).await.unwrap();
+    let classes = client.get_weekly_classes(7, None).await.unwrap();
     assert!(classes.is_empty());
 }
 
-// ── book_class tests ─────────────────────────────────────────────
-
 #[tokio::test]
-async fn book_class_success() {
+async fn get_weekly_classes_sends_category_when_given() {
     let server = MockServer::start().await;
     mount_login(&server).await;
 
     Mock::given(method("POST"))
-        .and(path("/Classes/ClassCalendar/BookClass"))
-        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
-            "Tickets": [
-                {
-                    "Name": "Morning Yoga",
-                    "StartTime": "2025-01-20T09:00:00",
-                    "Trainer": "Alice"
-                }
-            ],
-            "ClassId": 555
-        })))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(body_partial_json(serde_json::json!({ "categoryId": 31, "daysInWeek": 7 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "CalendarData": [] })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(body_partial_json(serde_json::json!({ "categoryId": null })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "CalendarData": [] })))
+        .expect(1)
         .mount(&server)
         .await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let result = client.book_class(555).await.unwrap();
-
-    assert_eq!(result.name, "Morning Yoga");
-    assert_eq!(result.trainer, Some("Alice".to_string()));
-    assert_eq!(
-        result.start_time.format("%Y-%m-%d %H:%M").to_string(),
-        "2025-01-20 09:00"
-    );
+    client.get_weekly_classes(7, Some(31)).await.unwrap();
+    client.get_weekly_classes(7, None).await.unwrap();
 }
 
 #[tokio::test]
-async fn book_class_failure_400() {
+async fn weekly_classes_carry_the_zone_they_are_listed_under() {
     let server = MockServer::start().await;
     mount_login(&server).await;
-
+    let class = |id: u64, name: &str| {
+        serde_json::json!({ "Id": id, "Name": name, "StartTime": "2025-01-15T18:00:00", "Duration": "45", "Status": "Bookable", "Trainer": null })
+    };
     Mock::given(method("POST"))
-        .and(path("/Classes/ClassCalendar/BookClass"))
-        .respond_with(ResponseTemplate::new(400).set_body_string("TooSoonToBook"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "CalendarData": [
+                { "ZoneName": "Cycle Studio", "ClassesPerHour": [ { "ClassesPerDay": [ [ class(1, "Spin") ] ] } ] },
+                { "ZoneName": "Main Hall", "ClassesPerHour": [ { "ClassesPerDay": [ [ class(2, "Spin"), class(3, "Yoga") ] ] } ] }
+            ]
+        })))
         .mount(&server)
         .await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let result = client.book_class(555).await;
-    assert!(result.is_err());
-    let err = format!("{}", result.unwrap_err());
-    assert!(err.contains("400") || err.contains("Booking failed"), "Got: {}", err);
-}
+    let classes = client.get_weekly_classes(7, None).await.unwrap();
 
-// ── get_class_details tests ──────────────────────────────────────
+    let zones: Vec<_> = classes.iter().map(|c| (c.id, c.zone.as_str())).collect();
+    assert_eq!(zones, [(1, "Cycle Studio"), (2, "Main Hall"), (3, "Main Hall")]);
+}
 
 #[tokio::test]
-async fn get_class_details_with_trainer_and_waitlist() {
+async fn weekly_classes_serialize_to_json_with_rfc3339_times() {
     let server = MockServer::start().await;
     mount_login(&server).await;
-
-    Mock::given(method("GET"))
-        .and(path("/Classes/ClassCalendar/Details"))
-        .and(query_param("classId", "123"))
-        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
-            "Id": 123,
-            "Name": "HIIT",
-            "Status": "Awaiting",
-            "StartTime": "2025-02-01T10:30:00",
-            "TrainerDetails": { "Title": "Coach Mike" },
-            "Users": [
-                {
-                    "Status": "Awaiting",
-                    "StandByQueueNumber": 3,
-                    "User": { "IsCurrentUser": true }
-                },
-                {
-                    "Status": "Booked",
-                    "StandByQueueNumber": null,
-                    "User": { "IsCurrentUser": false }
-                }
-            ]
-        })))
-        .mount(&server)
-        .await;
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 7, "Name": "Spin", "StartTime": "2025-01-15T18:00:00", "Duration": "45", "Status": "Bookable", "Trainer": "Bob" }
+        ]),
+    )
+    .await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let booking = client.get_class_details(123).await.unwrap();
+    let classes = client.get_weekly_classes(7, None).await.unwrap();
 
-    assert_eq!(booking.id, 123);
-    assert_eq!(booking.name, "HIIT");
-    assert_eq!(booking.trainer, Some("Coach Mike".to_string()));
-    assert_eq!(booking.waitlist_position, Some(3));
+    let json = serde_json::to_value(&classes).unwrap();
+    assert_eq!(json[0]["id"], 7);
+    assert_eq!(json[0]["name"], "Spin");
+    assert_eq!(json[0]["trainer"], "Bob");
+    assert_eq!(json[0]["zone"], "Studio A");
+    let start = json[0]["start_time"].as_str().unwrap();
+    assert_eq!(chrono::DateTime::parse_from_rfc3339(start).unwrap(), classes[0].start_time);
 }
 
-// ── cancel_booking tests ─────────────────────────────────────────
-
 #[tokio::test]
-async fn cancel_booking_success() {
+async fn get_my_bookings_scans_requested_horizon() {
     let server = MockServer::start().await;
     mount_login(&server).await;
 
-    Mock::given(method("POST"))
-        .and(path("/Classes/ClassCalendar/CancelBooking"))
+    // No bookings endpoint here, so the calendar is scanned
+    Mock::given(method("GET"))
+        .and(path("/Members/Bookings/Future"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(body_partial_json(serde_json::json!({ "daysInWeek": 21 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "CalendarData": [] })))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    assert!(client.get_my_bookings(21).await.unwrap().is_empty());
+    // The missing endpoint is remembered rather than asked for again
+    assert!(client.get_my_bookings(21).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn get_my_bookings_reads_bookings_endpoint_directly() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let soon = chrono::Local::now().with_nanosecond(0).unwrap() + chrono::Duration::days(2);
+    let later = soon + chrono::Duration::days(20);
+    let finished = chrono::Local::now() - chrono::Duration::hours(3);
+    Mock::given(method("GET"))
+        .and(path("/Members/Bookings/Future"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ClassId": 71, "Name": "Yoga", "StartTime": api_time(later), "Duration": "60", "Status": "StandBy",
+              "StandByQueueNumber": 3, "Trainer": null, "Zone": "Studio 2" },
+            { "ClassId": 70, "Name": "Spin", "StartTime": api_time(soon), "Duration": "45", "Status": "Booked",
+              "StandByQueueNumber": null, "Trainer": "Anna", "Zone": "Studio 1" },
+            { "ClassId": 69, "Name": "Pump", "StartTime": api_time(finished), "Duration": "60", "Status": "Booked" },
+            { "ClassId": 72, "Name": "Broken", "StartTime": "sometime soon", "Duration": "60", "Status": "Booked" },
+            { "ClassId": 68, "Name": "Row", "StartTime": api_time(soon), "Duration": "30", "Status": "Booked" }
+        ])))
+        .expect(2)
+        .mount(&server)
+        .await;
+    // Neither the calendar nor class details are needed
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let bookings = client.get_my_bookings(30).await.unwrap();
+
+    // Sorted by time then ID, with the finished class and the unreadable one dropped
+    assert_eq!(bookings.iter().map(|b| b.id).collect::<Vec<_>>(), [68, 70, 71]);
+    assert_eq!((bookings[1].id, bookings[1].name.as_str(), bookings[1].status.as_str()), (70, "Spin", "Booked"));
+    assert_eq!(bookings[1].start_time, soon);
+    assert_eq!(bookings[1].trainer.as_deref(), Some("Anna"));
+    assert_eq!(bookings[1].zone.as_deref(), Some("Studio 1"));
+    assert_eq!(bookings[1].duration_minutes, 45);
+    assert!(bookings[2].is_waitlisted());
+    assert_eq!(bookings[2].waitlist_position, Some(3));
+
+    // The days ahead only limit a calendar scan: every future booking is listed
+    let bookings = client.get_my_bookings(14).await.unwrap();
+    assert_eq!(bookings.iter().map(|b| b.id).collect::<Vec<_>>(), [68, 70, 71]);
+}
+
+// ── categories tests ─────────────────────────────────────────────
+
+#[tokio::test]
+async fn get_categories_parses_nested_categories_into_a_tree() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Categories"))
+        .and(query_param("clubId", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            {
+                "Id": 10,
+                "Name": "Cardio",
+                "Children": [
+                    { "Id": 31, "Name": "Group Cycling", "Children": [
+                        { "Id": 311, "Name": "Rhythm Ride" }
+                    ] },
+                    { "Id": 32, "Name": "HIIT", "Children": null }
+                ]
+            },
+            { "Id": 20, "Name": "Mind & Body", "Children": [] }
+        ])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let categories = client.get_categories().await.unwrap();
+
+    let leaf = |id, name: &str| Category { id, name: name.to_string(), children: vec![] };
+    assert_eq!(
+        categories,
+        vec![
+            Category {
+                id: 10,
+                name: "Cardio".to_string(),
+                children: vec![
+                    Category {
+                        id: 31,
+                        name: "Group Cycling".to_string(),
+                        children: vec![leaf(311, "Rhythm Ride")],
+                    },
+                    leaf(32, "HIIT"),
+                ],
+            },
+            leaf(20, "Mind & Body"),
+        ]
+    );
+
+    let walked: Vec<(usize, u32)> = categories[0].walk().into_iter().map(|(depth, c)| (depth, c.id)).collect();
+    assert_eq!(walked, vec![(0, 10), (1, 31), (2, 311), (1, 32)]);
+}
+
+// ── book_class tests ─────────────────────────────────────────────
+
+#[tokio::test]
+async fn book_class_success() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Tickets": [
+                {
+                    "Name": "Morning Yoga",
+                    "StartTime": "2025-01-20T09:00:00",
+                    "Trainer": "Alice"
+                }
+            ],
+            "ClassId": 555
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let result = client.book_class(555).await.unwrap();
+
+    assert_eq!(result.name, "Morning Yoga");
+    assert_eq!(result.trainer, Some("Alice".to_string()));
+    assert_eq!(
+        result.start_time.format("%Y-%m-%d %H:%M").to_string(),
+        "2025-01-20 09:00"
+    );
+}
+
+#[tokio::test]
+async fn book_class_failure_400() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("TooSoonToBook"))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let result = client.book_class(555).await;
+    assert!(result.is_err());
+    let err = format!("{}", result.unwrap_err());
+    assert!(err.contains("400") || err.contains("Booking failed"), "Got: {}", err);
+}
+
+#[tokio::test]
+async fn book_class_classifies_errors_by_code_not_wording() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // A localized message with a code, then prose that only mentions being full
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "ErrorCode": "AlreadyBooked",
+            "Message": "Sie sind bereits für diesen Kurs angemeldet"
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Your cart is full of classes"))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    assert!(matches!(
+        client.book_class(555).await,
+        Err(GymSniperError::Booking(BookingErrorKind::AlreadyBooked))
+    ));
+    match client.book_class(555).await {
+        Err(GymSniperError::Booking(BookingErrorKind::Unknown(raw))) => {
+            assert_eq!(raw, "(400 Bad Request) Your cart is full of classes")
+        }
+        other => panic!("expected an unknown booking error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn book_class_payment_required() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(402).set_body_string("{}"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Tickets": [],
+            "ClassId": 555,
+            "PaymentRequired": true
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    for _ in 0..2 {
+        match client.book_class(555).await {
+            Err(GymSniperError::Booking(BookingErrorKind::PaymentRequired)) => {}
+            other => panic!("expected PaymentRequired, got {:?}", other.map(|r| r.name)),
+        }
+    }
+}
+
+/// BookClass refuses with WaiverRequired until AcceptWaiver has been called
+async fn mount_waiver_class(server: &MockServer, class_id: u64, accept_times: u64) {
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "Errors": [ { "Code": "WaiverRequired", "Message": "Please accept the class terms" } ]
+        })))
+        .up_to_n_times(1)
+        .mount(server)
+        .await;
+    mount_book_class(server, class_id, "Aerial Yoga", "2025-02-01T08:00:00", accept_times).await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/AcceptWaiver"))
+        .and(body_partial_json(serde_json::json!({ "classId": class_id })))
         .respond_with(ResponseTemplate::new(200))
+        .expect(accept_times)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn book_class_waiver_required_without_auto_accept() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_waiver_class(&server, 321, 0).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    match client.book_class(321).await {
+        Err(GymSniperError::Booking(BookingErrorKind::WaiverRequired)) => {}
+        other => panic!("expected WaiverRequired, got {:?}", other.map(|r| r.name)),
+    }
+}
+
+#[tokio::test]
+async fn book_class_accepts_waiver_and_retries_when_auto_accept_is_set() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_waiver_class(&server, 321, 1).await;
+
+    let mut config = test_config(&server.uri());
+    config.gym.auto_accept_waivers = true;
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let result = client.book_class(321).await.unwrap();
+    assert_eq!(result.name, "Aerial Yoga");
+
+    // The waiver was accepted between the refused and the successful booking
+    let requests = server.received_requests().await.unwrap();
+    let calls: Vec<_> = requests
+        .iter()
+        .map(|r| r.url.path().rsplit('/').next().unwrap().to_string())
+        .filter(|p| p != "Login")
+        .collect();
+    assert_eq!(calls, ["BookClass", "AcceptWaiver", "BookClass"]);
+}
+
+#[tokio::test]
+async fn book_class_insufficient_funds() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "Errors": [ { "Code": "InsufficientFunds", "Message": "Not enough credit" } ]
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let err = client.book_class(555).await.unwrap_err();
+    assert!(matches!(err, GymSniperError::Booking(BookingErrorKind::InsufficientFunds)), "Got: {}", err);
+    assert!(err.to_string().contains("top up"));
+}
+
+#[tokio::test]
+async fn book_class_at_sends_chosen_spot() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(body_partial_json(serde_json::json!({ "classId": 77, "spotId": 12 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Tickets": [ { "Name": "Spin", "StartTime": "2025-01-20T09:00:00", "Trainer": null } ],
+            "ClassId": 77
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    client.book_class_at(77, Some(12)).await.unwrap();
+
+    // Without a spot the field is left out entirely
+    let preview = client.preview_book_request(77).await.unwrap();
+    assert!(!preview.body.contains("spotId"), "unexpected spot in {}", preview.body);
+}
+
+// ── multi-account tests ──────────────────────────────────────────
+
+/// Log in `email` with its own token, ahead of the default login mock
+async fn mount_account_login(server: &MockServer, email: &str, token: &str) {
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .and(body_partial_json(serde_json::json!({ "Login": email })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", token)
+                .set_body_json(serde_json::json!({ "User": null })),
+        )
+        .with_priority(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn class_is_booked_for_every_account_with_per_account_outcomes() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_account_login(&server, "alice@example.com", "alice-token").await;
+    mount_account_login(&server, "bob@example.com", "bob-token").await;
+
+    // Bob already has a class that day
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(header("Authorization", "Bearer bob-token"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("DailyBookingLimitReached"))
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    // Main account and Alice
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(2));
+    mount_book_class(&server, 40, "Kids Swim", &start, 2).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        r#"
+[[accounts]]
+name = "alice"
+email = "alice@example.com"
+password = "alice-pw"
+
+[[accounts]]
+name = "bob"
+email = "bob@example.com"
+password = "bob-pw"
+"#,
+    );
+    let names = vec!["alice".to_string(), "bob".to_string()];
+    let outcomes = gym_sniper::accounts::book_for_accounts(&config, 40, &names).await.unwrap();
+
+    let accounts: Vec<&str> = outcomes.iter().map(|o| o.account.as_str()).collect();
+    assert_eq!(accounts, ["main", "alice", "bob"]);
+    assert!(outcomes[0].result.is_ok());
+    assert!(outcomes[1].result.is_ok());
+    assert!(matches!(
+        outcomes[2].result,
+        Err(GymSniperError::Booking(BookingErrorKind::DailyLimit))
+    ));
+
+    let err = gym_sniper::accounts::report("Kids Swim", outcomes).unwrap_err();
+    assert!(matches!(err, GymSniperError::Booking(BookingErrorKind::DailyLimit)));
+}
+
+// ── get_class_details tests ──────────────────────────────────────
+
+#[tokio::test]
+async fn get_class_details_with_trainer_and_waitlist() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "123"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 123,
+            "Name": "HIIT",
+            "Status": "Awaiting",
+            "StartTime": "2025-02-01T10:30:00",
+            "TrainerDetails": { "Title": "Coach Mike" },
+            "Users": [
+                {
+                    "Status": "Awaiting",
+                    "StandByQueueNumber": 3,
+                    "User": { "IsCurrentUser": true }
+                },
+                {
+                    "Status": "Booked",
+                    "StandByQueueNumber": null,
+                    "User": { "IsCurrentUser": false }
+                }
+            ]
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let booking = client.get_class_details(123).await.unwrap();
+
+    assert_eq!(booking.id, 123);
+    assert_eq!(booking.name, "HIIT");
+    assert_eq!(booking.trainer, Some("Coach Mike".to_string()));
+    assert_eq!(booking.waitlist_position, Some(3));
+    assert_eq!(booking.description, None);
+}
+
+#[tokio::test]
+async fn get_class_details_with_description() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "321"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 321,
+            "Name": "Reformer Pilates",
+            "Status": "Bookable",
+            "StartTime": "2025-02-01T10:30:00",
+            "TrainerDetails": null,
+            "Description": "Bring grip socks. Beginners welcome.",
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let booking = client.get_class_details(321).await.unwrap();
+
+    assert_eq!(
+        booking.description.as_deref(),
+        Some("Bring grip socks. Beginners welcome.")
+    );
+}
+
+#[tokio::test]
+async fn get_class_details_without_users() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "654"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 654,
+            "Name": "Spin",
+            "Status": "Bookable",
+            "StartTime": "2025-02-01T07:00:00",
+            "TrainerDetails": null,
+            "MaxParticipants": 20
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "655"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 655,
+            "Name": "Spin",
+            "Status": "Awaiting",
+            "StartTime": "2025-02-01T08:00:00",
+            "TrainerDetails": null,
+            "Users": null
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let booking = client.get_class_details(654).await.unwrap();
+    assert_eq!(booking.name, "Spin");
+    assert_eq!(booking.waitlist_position, None);
+    // Neither BookedCount nor Users: how full the class is isn't known, rather than empty
+    assert_eq!(booking.booked_count, None);
+    assert_eq!(booking.fill_ratio(), None);
+
+    let booking = client.get_class_details(655).await.unwrap();
+    assert_eq!(booking.status, "Awaiting");
+    assert_eq!(booking.waitlist_position, None);
+    assert_eq!(booking.booked_count, None);
+}
+
+#[tokio::test]
+async fn get_class_details_reads_capacity_and_booked_count() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // The gym's own count wins over the (partial) Users list
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "656"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 656,
+            "Name": "Spin",
+            "Status": "Bookable",
+            "StartTime": "2025-02-01T09:00:00",
+            "TrainerDetails": null,
+            "MaxParticipants": 20,
+            "BookedCount": 17,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let booking = client.get_class_details(656).await.unwrap();
+    assert_eq!((booking.booked_count, booking.capacity), (Some(17), Some(20)));
+    assert_eq!(booking.spots_remaining(), Some(3));
+}
+
+#[tokio::test]
+async fn get_class_spots_lists_spots() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "77"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 77,
+            "Name": "Spin",
+            "Status": "Bookable",
+            "StartTime": "2025-02-01T10:30:00",
+            "TrainerDetails": null,
+            "Users": [],
+            "Spots": [
+                { "Id": 11, "Name": "Bike 1", "IsAvailable": false },
+                { "Id": 12, "Name": "Bike 2", "IsAvailable": true },
+                { "Id": 13 }
+            ]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "78"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 78,
+            "Name": "Yoga",
+            "Status": "Bookable",
+            "StartTime": "2025-02-01T10:30:00",
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let spots = client.get_class_spots(77).await.unwrap();
+    assert_eq!(
+        spots,
+        vec![
+            Spot { id: 11, name: "Bike 1".to_string(), available: false },
+            Spot { id: 12, name: "Bike 2".to_string(), available: true },
+            Spot { id: 13, name: "Spot 13".to_string(), available: true },
+        ]
+    );
+    assert!(client.get_class_spots(78).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn get_class_details_retries_after_two_timeouts() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let details = serde_json::json!({
+        "Id": 79,
+        "Name": "Yoga",
+        "Status": "Bookable",
+        "StartTime": "2025-02-01T10:30:00",
+        "TrainerDetails": null,
+        "Users": []
+    });
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&details).set_delay(Duration::from_secs(2)))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&details))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::builder(&config)
+        .timeout(Duration::from_millis(300))
+        .clock(SharedClock::System)
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+
+    let details = client.get_class_details(79).await.unwrap();
+    assert_eq!(details.name, "Yoga");
+}
+
+#[tokio::test]
+async fn book_class_is_not_retried_after_timeout() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // The booking may have gone through even though the response never arrived
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::builder(&config)
+        .timeout(Duration::from_millis(300))
+        .clock(SharedClock::System)
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+
+    assert!(client.book_class(80).await.is_err());
+}
+
+// ── cancel_booking tests ─────────────────────────────────────────
+
+#[tokio::test]
+async fn cancel_booking_success() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let result = client.cancel_booking(999).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn cancel_booking_failure() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Cannot cancel"))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let result = client.cancel_booking(999).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn cancel_booking_retries_after_503() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let result = client.cancel_booking(999).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn cancel_applied_before_a_503_is_not_reported_as_failed() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // The first attempt cancels but the reply is lost, so the retry finds nothing to cancel
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"NotBooked\"]}"))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(vec![(999, "Spin", "Bookable", start.clone()), (998, "Yoga", "Booked", start)]))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    client.cancel_booking(999).await.unwrap();
+
+    // Still booked after a refused retry, so the cancel really failed
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    assert!(client.cancel_booking(998).await.is_err());
+}
+
+#[tokio::test]
+async fn cancel_booking_does_not_retry_4xx() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Cannot cancel"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    assert!(client.cancel_booking(999).await.is_err());
+}
+
+#[tokio::test]
+async fn cancel_within_min_notice_is_refused_unless_forced() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_cancel(&server, 1, 0).await;
+    mount_cancel(&server, 2, 1).await;
+    mount_cancel(&server, 3, 1).await;
+
+    let mut config = test_config(&server.uri());
+    config.gym.min_cancel_notice_hours = Some(12);
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let now = chrono::Local::now();
+
+    let result = client
+        .cancel_booking_with_notice(1, "Yoga", now + chrono::Duration::hours(3), false)
+        .await;
+    match result {
+        Err(GymSniperError::CancelRefused(msg)) => assert!(msg.contains("12h minimum cancel notice"), "{}", msg),
+        other => panic!("expected CancelRefused, got {:?}", other),
+    }
+
+    client
+        .cancel_booking_with_notice(2, "Spin", now + chrono::Duration::hours(30), false)
+        .await
+        .unwrap();
+    client
+        .cancel_booking_with_notice(3, "HIIT", now + chrono::Duration::hours(3), true)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn preview_book_request_matches_sent_request() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_book_class(&server, 4242, "Yoga", "2025-02-01T08:00:00", 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let preview = client.preview_book_request(4242).await.unwrap();
+    client.book_class(4242).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let sent = requests.iter().find(|r| r.url.path().ends_with("/BookClass")).unwrap();
+
+    assert_eq!(preview.method, "POST");
+    assert!(preview.url.ends_with(sent.url.path()));
+    let preview_body: serde_json::Value = serde_json::from_str(&preview.body).unwrap();
+    let sent_body: serde_json::Value = serde_json::from_slice(&sent.body).unwrap();
+    assert_eq!(preview_body, sent_body);
+    assert_eq!(preview_body, serde_json::json!({ "classId": 4242, "clubId": "1" }));
+
+    for (name, value) in &preview.headers {
+        let sent_value = sent.headers.get(name.as_str()).unwrap_or_else(|| panic!("{} not sent", name));
+        assert_eq!(sent_value.to_str().unwrap(), value, "{}", name);
+    }
+}
+
+#[tokio::test]
+async fn preview_book_request_without_login_uses_placeholder_token() {
+    let config = test_config("http://gym.invalid/clientportal2");
+    let client = PerfectGymClient::new(&config).unwrap();
+
+    let preview = client.preview_book_request(7).await.unwrap();
+    assert!(preview.headers.contains(&("authorization".to_string(), "Bearer <token>".to_string())));
+    assert_eq!(preview.url, "http://gym.invalid/clientportal2/Classes/ClassCalendar/BookClass");
+}
+
+#[tokio::test]
+async fn replay_resends_recorded_request_and_returns_fresh_response() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Full when first booked, then a spot opens up
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(
+            ResponseTemplate::new(400)
+                .insert_header("x-request-id", "first")
+                .insert_header("set-cookie", "session=0123456789abcdef")
+                .set_body_string("ClassIsFull"),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    let start = chrono::Local::now() + chrono::Duration::days(2);
+    mount_book_class(&server, 50, "Spin", &api_time(start), 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let err = client.book_class_at(50, Some(3)).await.unwrap_err();
+
+    let class = replay::FailedClass {
+        class_id: 50,
+        class_name: "Spin",
+        class_time: start,
+        window_opens: None,
+        spot_id: Some(3),
+    };
+    let failure = replay::capture(&client, class, &err).await.unwrap();
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join(replay::FAILURE_FILE);
+    replay::save(&path, &failure).unwrap();
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("test-jwt-token-123"), "{}", saved);
+    assert!(!saved.contains("0123456789abcdef"), "{}", saved);
+
+    let loaded = replay::load(&path).unwrap().unwrap();
+    let original = loaded.response.clone().unwrap();
+    assert_eq!(original.status, 400);
+    assert_eq!(original.body, "ClassIsFull");
+    assert!(original.headers.contains(&("x-request-id".to_string(), "first".to_string())));
+    assert!(loaded.request.headers.contains(&("authorization".to_string(), "Bearer test…-123".to_string())));
+    assert!(loaded.request.body.contains("\"spotId\":3"));
+
+    let fresh = replay::replay(&client, &loaded).await.unwrap();
+    assert_eq!(fresh.status, 200);
+    assert!(fresh.body.contains("\"ClassId\":50"), "{}", fresh.body);
+
+    let sent = server.received_requests().await.unwrap();
+    let books: Vec<_> = sent.iter().filter(|r| r.url.path().ends_with("/BookClass")).collect();
+    assert_eq!(books.len(), 2);
+    assert_eq!(books[0].body, books[1].body);
+}
+
+// ── waitlist tests ───────────────────────────────────────────────
+
+#[tokio::test]
+async fn waitlisted_class_is_listed_with_position_and_can_be_left() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(2));
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 60, "Name": "Yoga", "StartTime": start, "Duration": "60", "Status": "Awaiting", "Trainer": null },
+            { "Id": 61, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Booked", "Trainer": null }
+        ]),
+    )
+    .await;
+    for (id, name, user) in [
+        (60, "Yoga", serde_json::json!({ "Status": "Awaiting", "StandByQueueNumber": 3, "User": { "IsCurrentUser": true } })),
+        (61, "Spin", serde_json::json!({ "Status": "Booked", "StandByQueueNumber": null, "User": { "IsCurrentUser": true } })),
+    ] {
+        Mock::given(method("GET"))
+            .and(path("/Classes/ClassCalendar/Details"))
+            .and(query_param("classId", id.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Id": id,
+                "Name": name,
+                "Status": "Full",
+                "StartTime": start,
+                "TrainerDetails": null,
+                "Users": [ user ]
+            })))
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .and(body_partial_json(serde_json::json!({ "classId": 60 })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/CancelBooking"))
+        .and(body_partial_json(serde_json::json!({ "classId": 61 })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let waitlists = client.get_my_waitlists(14).await.unwrap();
+    assert_eq!(waitlists.len(), 1);
+    assert_eq!(waitlists[0].id, 60);
+    assert_eq!(waitlists[0].waitlist_position, Some(3));
+
+    client.leave_waitlist(60).await.unwrap();
+    // A confirmed booking isn't a waitlist entry
+    assert!(matches!(client.leave_waitlist(61).await, Err(GymSniperError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn join_waitlist_reports_standby_position() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    mount_join_waitlist(&server, 37, "Spin", &start, Some(3), 1).await;
+
+    let client = PerfectGymClient::new(&test_config(&server.uri())).unwrap();
+    client.login().await.unwrap();
+    let result = client.join_waitlist(37).await.unwrap();
+    assert_eq!(result.name, "Spin");
+    assert_eq!(result.waitlist_position, Some(3));
+}
+
+// ── rate limit tests ─────────────────────────────────────────────
+
+#[tokio::test]
+async fn book_class_waits_out_429_with_retry_after_seconds() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 555, "Yoga", "2025-02-01T08:00:00", 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let started = Instant::now();
+    let result = client.book_class(555).await.unwrap();
+    assert_eq!(result.name, "Yoga");
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn get_class_details_waits_out_429_with_retry_after_date() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let retry_at = chrono::Utc::now() + chrono::Duration::seconds(2);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(
+            ResponseTemplate::new(429)
+                .insert_header("Retry-After", retry_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 777,
+            "Name": "Spin",
+            "Status": "Bookable",
+            "StartTime": "2025-02-01T07:00:00",
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let started = Instant::now();
+    let booking = client.get_class_details(777).await.unwrap();
+    assert_eq!(booking.name, "Spin");
+    // HTTP dates have whole-second precision, so allow for truncation
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn client_spreads_requests_beyond_max_requests_per_minute() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_book_class(&server, 555, "Yoga", "2025-02-01T08:00:00", 3).await;
+
+    let mut config = test_config(&server.uri());
+    config.gym.max_requests_per_minute = 2;
+    let clock = Arc::new(SimulatedClock::new(chrono::Local::now()));
+    let client = PerfectGymClient::builder(&config)
+        .clock(SharedClock::Simulated(clock.clone()))
+        .build()
+        .unwrap();
+
+    // Login and the first booking use up the bucket; later ones wait for refills
+    client.login().await.unwrap();
+    for _ in 0..3 {
+        client.book_class(555).await.unwrap();
+    }
+    assert_eq!(clock.sleeps(), vec![Duration::from_secs(30), Duration::from_secs(30)]);
+}
+
+// ── scheduler tests ──────────────────────────────────────────────
+
+#[tokio::test]
+async fn scheduler_runs_every_profile_with_its_own_login_and_club() {
+    let server = MockServer::start().await;
+    for email in ["me@example.com", "partner@example.com"] {
+        Mock::given(method("POST"))
+            .and(path("/Auth/Login"))
+            .and(body_partial_json(serde_json::json!({ "Login": email })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("jwt-token", "test-jwt-token-123")
+                    .set_body_json(serde_json::json!({ "User": null })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    for club_id in [1, 7] {
+        Mock::given(method("POST"))
+            .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+            .and(body_partial_json(serde_json::json!({ "clubId": club_id })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "CalendarData": [] })))
+            .expect(1..)
+            .mount(&server)
+            .await;
+    }
+
+    let config: Config = toml::from_str(&format!(
+        r#"
+[gym]
+base_url = "{}"
+club_id = 1
+
+[[targets]]
+class_name = "Yoga"
+
+[[profiles]]
+name = "me"
+credentials = {{ email = "me@example.com", password = "a" }}
+
+[[profiles]]
+name = "partner"
+credentials = {{ email = "partner@example.com", password = "b" }}
+club_id = 7
+"#,
+        server.uri()
+    ))
+    .unwrap();
+
+    // Each scheduler makes its first pass straight away, then waits a minute
+    let shutdown = Shutdown::new();
+    let run = scheduler::run_profiles(&config, false, &shutdown);
+    tokio::pin!(run);
+    let early = tokio::time::timeout(Duration::from_secs(2), &mut run).await;
+    assert!(early.is_err(), "schedulers keep running: {:?}", early);
+
+    // A shutdown request cuts the wait short and every scheduler stops cleanly
+    shutdown.request();
+    tokio::time::timeout(Duration::from_secs(5), run).await.expect("schedulers stop on shutdown").unwrap();
+}
+
+
+#[tokio::test]
+async fn scheduler_books_only_one_of_simultaneous_matches() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Window already open: class is within 7d+2h
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 20, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null },
+            { "Id": 10, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+    mount_book_class(&server, 10, "Spin", &start, 1).await;
+    mount_book_class(&server, 20, "Spin", &start, 0).await;
+
+    let config = test_config_with(&server.uri(), "[[targets]]\nclass_name = \"Spin\"\n");
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+#[tokio::test]
+async fn scheduler_fetches_classes_across_a_longer_booking_window() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // With a 14-day window, a class 13 days out is already bookable and has to be fetched
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(13));
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .and(body_partial_json(serde_json::json!({ "daysInWeek": 15 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "CalendarData": [
+                {
+                    "ZoneName": "Studio A",
+                    "ClassesPerHour": [ { "ClassesPerDay": [ [
+                        { "Id": 40, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null }
+                    ] ] } ]
+                }
+            ]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 40, "Spin", &start, 1).await;
+
+    let mut config = test_config_with(&server.uri(), "[[targets]]\nclass_name = \"Spin\"\n");
+    config.gym.booking_window_days = 14;
+    config.gym.booking_window_hours = 0;
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+#[tokio::test]
+async fn scheduler_books_exact_class_id_target() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Same name and time, but only class 31 is the target
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 30, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null },
+            { "Id": 31, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null },
+            { "Id": 32, "Name": "Yoga", "StartTime": start, "Duration": "60", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+    mount_book_class(&server, 31, "Spin", &start, 1).await;
+    mount_book_class(&server, 30, "Spin", &start, 0).await;
+    mount_book_class(&server, 32, "Yoga", &start, 0).await;
+
+    let config = test_config_with(&server.uri(), "[[targets]]\nclass_id = 31\n");
+    config.validate().unwrap();
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+#[tokio::test]
+async fn scheduler_once_books_open_windows_and_reports_failures() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Spin's window is open but it is full; Yoga's window opens in a few minutes
+    let spin = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    let yoga = api_time(chrono::Local::now() + chrono::Duration::days(7) + chrono::Duration::minutes(123));
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 40, "Name": "Spin", "StartTime": spin, "Duration": "45", "Status": "Bookable", "Trainer": null },
+            { "Id": 41, "Name": "Yoga", "StartTime": yoga, "Duration": "60", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(body_partial_json(serde_json::json!({ "classId": 40 })))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({ "ErrorCode": "ClassIsFull" })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 41, "Yoga", &yoga, 0).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[[targets]]\nclass_name = \"Spin\"\n\n[[targets]]\nclass_name = \"Yoga\"\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+
+    // Returns without waiting for Yoga's window, failing so cron can alert
+    let run = tokio::time::timeout(Duration::from_secs(5), scheduler::run_once(&config, &client))
+        .await
+        .expect("a single pass does not wait for windows to open");
+    match run {
+        Err(e @ GymSniperError::Booking(BookingErrorKind::Full)) => assert_eq!(e.exit_code(), 7),
+        other => panic!("expected a full-class failure, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn scheduler_once_fails_when_the_trainer_was_substituted() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // The calendar still lists Anna, but the class details name a substitute
+    let start = chrono::Local::now() + chrono::Duration::days(1);
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 60, "Name": "Spin", "StartTime": api_time(start), "Duration": "45", "Status": "Bookable", "Trainer": "Anna" }
+        ]),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "60"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 60,
+            "Name": "Spin",
+            "Status": "Bookable",
+            "StartTime": api_time(start),
+            "TrainerDetails": { "Title": "Ben" },
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 60, "Spin", &api_time(start), 0).await;
+
+    let config = test_config_with(&server.uri(), "[[targets]]\nclass_name = \"Spin\"\ntrainer = \"Anna\"\n");
+    let client = PerfectGymClient::new(&config).unwrap();
+
+    match scheduler::run_once(&config, &client).await {
+        Err(GymSniperError::Booking(BookingErrorKind::TrainerChanged { wanted, trainer })) => {
+            assert_eq!(wanted, "Anna");
+            assert_eq!(trainer.as_deref(), Some("Ben"));
+        }
+        other => panic!("expected a trainer-change failure, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn scheduler_weekly_cap_blocks_further_booking() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Tomorrow morning's HIIT is already booked, so the evening one would be a second that week
+    let day = (chrono::Local::now() + chrono::Duration::days(1)).date_naive();
+    let at = |h: u32| api_time(day.and_hms_opt(h, 0, 0).unwrap().and_local_timezone(chrono::Local).unwrap());
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 70, "Name": "HIIT", "StartTime": at(6), "Duration": "30", "Status": "Booked", "Trainer": null },
+            { "Id": 71, "Name": "HIIT", "StartTime": at(20), "Duration": "30", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(vec![(70, "HIIT", "Booked", at(6)), (71, "HIIT", "Bookable", at(20))]))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 71, "HIIT", &at(20), 0).await;
+    // Fetched once for the pass, however many targets have a cap
+    Mock::given(method("GET"))
+        .and(path("/Members/Bookings/Future"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ClassId": 70, "Name": "HIIT", "StartTime": at(6), "Duration": "30", "Status": "Booked" }
+        ])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[[targets]]\nclass_name = \"HIIT\"\nmax_per_week = 1\n\n[[targets]]\nclass_name = \"Yoga\"\nmax_per_week = 2\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+#[tokio::test]
+async fn scheduler_tie_break_prefers_fewest_booked() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 10, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null },
+            { "Id": 20, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+
+    for (id, booked) in [(10, 2), (20, 1)] {
+        let users: Vec<_> = (0..booked)
+            .map(|_| serde_json::json!({ "Status": "Booked", "StandByQueueNumber": null, "User": { "IsCurrentUser": false } }))
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/Classes/ClassCalendar/Details"))
+            .and(query_param("classId", id.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Id": id,
+                "Name": "Spin",
+                "Status": "Bookable",
+                "StartTime": start,
+                "TrainerDetails": null,
+                "Users": users
+            })))
+            .mount(&server)
+            .await;
+    }
+    mount_book_class(&server, 20, "Spin", &start, 1).await;
+    mount_book_class(&server, 10, "Spin", &start, 0).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[[targets]]\nclass_name = \"Spin\"\ntie_break = \"fewest_booked\"\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+/// Records when each request arrived so tests can reconstruct how many were in flight
+struct ArrivalRecorder {
+    arrivals: Arc<Mutex<Vec<Instant>>>,
+    delay: Duration,
+}
+
+impl Respond for ArrivalRecorder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        self.arrivals.lock().unwrap().push(Instant::now());
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        ResponseTemplate::new(200)
+            .set_delay(self.delay)
+            .set_body_json(serde_json::json!({
+                "Tickets": [ { "Name": "Class", "StartTime": "2025-01-20T09:00:00", "Trainer": null } ],
+                "ClassId": body["classId"]
+            }))
+    }
+}
+
+#[tokio::test]
+async fn scheduler_caps_concurrent_bookings() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let classes: Vec<_> = (1..=5)
+        .map(|i| {
+            let start = api_time(chrono::Local::now() + chrono::Duration::hours(i));
+            serde_json::json!({ "Id": i, "Name": format!("Class{}", i), "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null })
+        })
+        .collect();
+    mount_weekly_classes(&server, serde_json::json!(classes)).await;
+
+    let delay = Duration::from_millis(200);
+    let arrivals = Arc::new(Mutex::new(Vec::new()));
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ArrivalRecorder { arrivals: Arc::clone(&arrivals), delay })
+        .expect(5)
+        .mount(&server)
+        .await;
+
+    let targets: String = (1..=5)
+        .map(|i| format!("[[targets]]\nclass_name = \"Class{}\"\n", i))
+        .collect();
+    let config = test_config_with(
+        &server.uri(),
+        &format!("[scheduler]\nmax_concurrent_bookings = 2\n\n{}", targets),
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+
+    // A request can only start once an earlier one has had its full response delay,
+    // so any arrivals closer together than the delay were in flight at the same time
+    let arrivals = arrivals.lock().unwrap();
+    assert_eq!(arrivals.len(), 5);
+    for (i, t) in arrivals.iter().enumerate() {
+        let in_flight = arrivals[..=i].iter().filter(|a| t.duration_since(**a) < delay).count();
+        assert!(in_flight <= 2, "{} bookings in flight at once", in_flight);
+    }
+}
+
+#[tokio::test]
+async fn scheduler_flexible_target_moves_to_next_class_when_full() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let day = (chrono::Local::now() + chrono::Duration::days(1)).date_naive();
+    let at = |h: u32| api_time(day.and_hms_opt(h, 0, 0).unwrap().and_local_timezone(chrono::Local).unwrap());
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 3, "Name": "Yoga", "StartTime": at(18), "Duration": "60", "Status": "Bookable", "Trainer": null },
+            { "Id": 1, "Name": "Yoga", "StartTime": at(8), "Duration": "60", "Status": "Bookable", "Trainer": null },
+            { "Id": 2, "Name": "Yoga", "StartTime": at(12), "Duration": "60", "Status": "Bookable", "Trainer": null },
+            { "Id": 4, "Name": "Spin", "StartTime": at(9), "Duration": "45", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+
+    for id in [1, 2] {
+        Mock::given(method("POST"))
+            .and(path("/Classes/ClassCalendar/BookClass"))
+            .and(body_partial_json(serde_json::json!({ "classId": id })))
+            .respond_with(ResponseTemplate::new(400).set_body_string("ClassIsFull"))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    mount_book_class(&server, 3, "Yoga", &at(18), 1).await;
+    mount_book_class(&server, 4, "Spin", &at(9), 0).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[[targets]]\nclass_name = \"Yoga\"\nflexible = true\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+#[tokio::test]
+async fn scheduler_flexible_target_stops_on_daily_limit() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let day = (chrono::Local::now() + chrono::Duration::days(1)).date_naive();
+    let at = |h: u32| api_time(day.and_hms_opt(h, 0, 0).unwrap().and_local_timezone(chrono::Local).unwrap());
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 1, "Name": "Yoga", "StartTime": at(8), "Duration": "60", "Status": "Bookable", "Trainer": null },
+            { "Id": 2, "Name": "Yoga", "StartTime": at(12), "Duration": "60", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(body_partial_json(serde_json::json!({ "classId": 1 })))
+        .respond_with(ResponseTemplate::new(400).set_body_string("DailyBookingLimitReached"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 2, "Yoga", &at(12), 0).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[[targets]]\nclass_name = \"Yoga\"\nflexible = true\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+/// Mount class details with `booked` confirmed users out of `capacity` places
+async fn mount_details_with_occupancy(server: &MockServer, class_id: u64, name: &str, start: &str, booked: usize, capacity: u32) {
+    let users: Vec<_> = (0..booked)
+        .map(|_| serde_json::json!({ "Status": "Booked", "StandByQueueNumber": null, "User": { "IsCurrentUser": false } }))
+        .collect();
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", class_id.to_string()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": class_id,
+            "Name": name,
+            "Status": "Bookable",
+            "StartTime": start,
+            "TrainerDetails": null,
+            "Users": users,
+            "MaxParticipants": capacity
+        })))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn scheduler_skips_class_over_max_fill_ratio() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = api_time(chrono::Local::now() + chrono::Duration::days(1));
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 40, "Name": "Spin", "StartTime": start, "Duration": "45", "Status": "Bookable", "Trainer": null },
+            { "Id": 41, "Name": "Yoga", "StartTime": start, "Duration": "60", "Status": "Bookable", "Trainer": null }
+        ]),
+    )
+    .await;
+    mount_details_with_occupancy(&server, 40, "Spin", &start, 19, 20).await;
+    mount_details_with_occupancy(&server, 41, "Yoga", &start, 5, 20).await;
+    mount_book_class(&server, 40, "Spin", &start, 0).await;
+    mount_book_class(&server, 41, "Yoga", &start, 1).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[[targets]]\nclass_name = \"Spin\"\nmax_fill_ratio = 0.9\n\n[[targets]]\nclass_name = \"Yoga\"\nmax_fill_ratio = 0.9\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    scheduler::run_pass(&config, &client).await.unwrap();
+}
+
+// ── snipe tests ──────────────────────────────────────────────────
+
+#[tokio::test]
+async fn same_day_booking_blocks_snipe() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let day = (chrono::Local::now() + chrono::Duration::days(7)).date_naive();
+    let at = |h: u32| day.and_hms_opt(h, 0, 0).unwrap().and_local_timezone(chrono::Local).unwrap();
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 10, "Name": "Spin", "StartTime": api_time(at(7)), "Duration": "45", "Status": "Booked", "Trainer": null },
+            { "Id": 11, "Name": "Yoga", "StartTime": api_time(at(18)), "Duration": "60", "Status": "Awaitable", "Trainer": null }
+        ]),
+    )
+    .await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "10"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 10,
+            "Name": "Spin",
+            "Status": "Booked",
+            "StartTime": api_time(at(7)),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let existing = snipe::same_day_booking(&client, 11, at(18)).await.unwrap();
+    assert_eq!(existing.map(|b| b.name), Some("Spin".to_string()));
+
+    // A different day is free
+    let next_day = at(18) + chrono::Duration::days(1);
+    assert!(snipe::same_day_booking(&client, 12, next_day).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn successful_booking_is_posted_to_webhook() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(2);
+    mount_book_class(&server, 40, "Pilates", &api_time(start), 1).await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 40,
+            "Name": "Pilates",
+            "Status": "Bookable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(body_partial_json(serde_json::json!({ "class": "Pilates", "outcome": "booked", "reason": null })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config_with(&server.uri(), &format!("[webhook]\nurl = \"{}/hook\"\n", server.uri()));
+    snipe::attempt_booking(&config, 40).await.unwrap();
+}
+
+#[tokio::test]
+async fn availability_alert_is_posted_to_webhook() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(body_partial_json(serde_json::json!({ "class": "Boxfit", "outcome": "available", "reason": null })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config_with(&server.uri(), &format!("[webhook]\nurl = \"{}/hook\"\n", server.uri()));
+    Notifiers::new(&config).notify_available("Boxfit", "Wed 22 Jan 07:00", None).await;
+}
+
+#[tokio::test]
+async fn repeated_webhook_notifications_are_coalesced() {
+    use gym_sniper::email::FailureExplanation;
+    use gym_sniper::notify::Notifier;
+
+    let server = MockServer::start().await;
+    // The first post fails, so it doesn't start a dedupe window
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+
+    let config = test_config_with(
+        &server.uri(),
+        &format!("[webhook]\nurl = \"{}/hook\"\ndedupe_window_secs = 1\n", server.uri()),
+    );
+    let failure = FailureExplanation::from_kind(&BookingErrorKind::Full);
+    let notifiers = Notifiers::new(&config);
+    for _ in 0..4 {
+        notifiers.notify_failure("Coalesced Spin", "Tue 21 Jan 18:00", None, &failure).await;
+    }
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    notifiers.notify_failure("Coalesced Spin", "Tue 21 Jan 18:00", None, &failure).await;
+
+    // Failed, sent, two held back, then one standing for itself and the two held back
+    let posts: Vec<serde_json::Value> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect();
+    assert_eq!(posts.len(), 3);
+    assert!(posts[1].get("repeats").is_none());
+    assert_eq!(posts[2]["repeats"], 3);
+}
+
+#[tokio::test]
+async fn test_notify_reports_each_channel() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(body_partial_json(serde_json::json!({ "class": "Sample Class", "test": true })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/broken"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let sample = TestSample::default();
+    let config = test_config_with(&server.uri(), &format!("[webhook]\nurl = \"{}/hook\"\n", server.uri()));
+    let results = Notifiers::new(&config).send_test(&sample).await;
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, "webhook");
+    assert!(results[0].1.is_ok(), "{:?}", results[0].1);
+
+    let config = test_config_with(&server.uri(), &format!("[webhook]\nurl = \"{}/broken\"\n", server.uri()));
+    let results = Notifiers::new(&config).send_test(&sample).await;
+    assert!(results[0].1.as_ref().unwrap_err().contains("500"), "{:?}", results[0].1);
+
+    assert!(Notifiers::new(&test_config(&server.uri())).send_test(&sample).await.is_empty());
+}
+
+#[tokio::test]
+async fn booking_attempts_burst_then_slow_down() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 41,
+            "Name": "Yoga",
+            "Status": "Bookable",
+            "StartTime": api_time(start + chrono::Duration::days(2)),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+
+    // The gym keeps saying "too soon" for the first second
+    let attempts: SimulatedAttempts = Arc::new(Mutex::new(Vec::new()));
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(SimulatedWindow {
+            clock: Arc::clone(&clock),
+            opens: start + chrono::Duration::seconds(1),
+            attempts: Arc::clone(&attempts),
+        })
+        .mount(&server)
+        .await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[stealth.attempt_delay]\ndistribution = \"burst\"\nburst_count = 3\nburst_delay_ms = 50\nsteady_delay_ms = 400\n",
+    );
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    snipe::snipe_class_with_clock(&config, &client, 41, None, clock.as_ref()).await.unwrap();
+
+    // Three attempts 50ms apart, then 400ms apart until the window opens
+    let attempts = attempts.lock().unwrap();
+    let gaps: Vec<i64> = attempts.windows(2).map(|w| (w[1].1 - w[0].1).num_milliseconds()).collect();
+    assert_eq!(gaps, vec![50, 50, 400, 400, 400]);
+}
+
+/// Records when each class details request arrived
+struct DetailsRecorder {
+    arrivals: Arc<Mutex<Vec<Instant>>>,
+    start: String,
+}
+
+impl Respond for DetailsRecorder {
+    fn respond(&self, _request: &Request) -> ResponseTemplate {
+        self.arrivals.lock().unwrap().push(Instant::now());
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 30,
+            "Name": "Reformer",
+            "Status": "Awaitable",
+            "StartTime": self.start,
+            "TrainerDetails": null,
+            "Users": []
+        }))
+    }
+}
+
+#[tokio::test]
+async fn snipe_primes_connection_before_window() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Window opens ~4 seconds from now
+    let start = chrono::Local::now() + chrono::Duration::days(7) + chrono::Duration::hours(2) + chrono::Duration::seconds(4);
+    let details = Arc::new(Mutex::new(Vec::new()));
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsRecorder { arrivals: Arc::clone(&details), start: api_time(start) })
+        .mount(&server)
+        .await;
+
+    let bookings = Arc::new(Mutex::new(Vec::new()));
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ArrivalRecorder { arrivals: Arc::clone(&bookings), delay: Duration::ZERO })
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let config = test_config_with(&server.uri(), "[snipe]\nprime_lead_secs = 2\n");
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    snipe::snipe_class(&config, &client, 30, None).await.unwrap();
+
+    // Initial lookup, then the prime request; no further lookup before booking
+    let details = details.lock().unwrap();
+    let bookings = bookings.lock().unwrap();
+    assert_eq!(details.len(), 2);
+    let prime_lead = bookings[0].duration_since(details[1]);
+    assert!(prime_lead >= Duration::from_millis(1500), "prime only {:?} before booking", prime_lead);
+}
+
+#[tokio::test]
+async fn snipe_uses_bookable_from_over_computed_window() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Computed window is days away, but the gym says booking opens in 2 seconds
+    let start = chrono::Local::now() + chrono::Duration::days(10);
+    let opens = chrono::Local::now() + chrono::Duration::seconds(2);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 31,
+            "Name": "Reformer",
+            "Status": "Awaitable",
+            "StartTime": api_time(start),
+            "BookableFrom": api_time(opens),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 31, "Reformer", &api_time(start), 1).await;
+
+    let config = test_config_with(&server.uri(), "[snipe]\nprime_lead_secs = 0\n");
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let details = client.get_class_details(31).await.unwrap();
+    assert_eq!(details.window_opens(&config.gym).timestamp(), opens.timestamp());
+
+    let started = Instant::now();
+    snipe::snipe_class(&config, &client, 31, None).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn snipe_joins_waitlist_immediately_after_first_full() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(3);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 32,
+            "Name": "Reformer",
+            "Status": "Bookable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": [ { "Status": "Awaiting", "StandByQueueNumber": 4, "User": { "IsCurrentUser": true } } ]
+        })))
+        .mount(&server)
+        .await;
+
+    // One booking attempt comes back full, then a single waitlist join
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("ClassIsFull"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    // No position in the join response, so it comes from the class details
+    mount_join_waitlist(&server, 32, "Reformer", &api_time(start), None, 1).await;
+
+    let config = test_config_with(&server.uri(), "[snipe]\non_full = \"waitlist_immediately\"\n");
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let clock = SimulatedClock::new(chrono::Local::now());
+    snipe::snipe_class_with_clock(&config, &client, 32, None, &clock).await.unwrap();
+    assert!(clock.sleeps().is_empty(), "retried: {:?}", clock.sleeps());
+}
+
+#[tokio::test]
+async fn snipe_joins_waitlist_after_retrying_a_full_class() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(3);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 36,
+            "Name": "Reformer",
+            "Status": "Bookable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("ClassIsFull"))
+        .expect(10)
+        .mount(&server)
+        .await;
+    mount_join_waitlist(&server, 36, "Reformer", &api_time(start), Some(7), 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let clock = SimulatedClock::new(chrono::Local::now());
+    snipe::snipe_class_with_clock(&config, &client, 36, None, &clock).await.unwrap();
+    assert_eq!(clock.sleeps().len(), 9);
+}
+
+#[tokio::test]
+async fn snipe_refreshes_token_on_configured_interval_while_waiting() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Window opens 3 hours from now, so the wait before the final login is 2h59m
+    let now = chrono::Local::now();
+    let start = now + chrono::Duration::days(7) + chrono::Duration::hours(5);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 34,
+            "Name": "Reformer",
+            "Status": "Awaitable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 34, "Reformer", &api_time(start), 1).await;
+
+    let mut config = test_config(&server.uri());
+    config.gym.token_refresh_minutes = Some(60);
+    let clock = Arc::new(SimulatedClock::new(now));
+    let client = PerfectGymClient::builder(&config)
+        .clock(SharedClock::Simulated(clock.clone()))
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+
+    snipe::snipe_class_with_clock(&config, &client, 34, None, clock.as_ref()).await.unwrap();
+
+    // The wait wakes on the hour to refresh, rather than sleeping in fixed chunks
+    let sleeps = clock.sleeps();
+    assert_eq!(sleeps[0], Duration::from_secs(3600));
+    assert_eq!(sleeps[1], Duration::from_secs(3600));
+
+    // Initial login, refreshes after 1h and 2h, then the fresh login just before the window
+    let logins = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/Auth/Login")
+        .count();
+    assert_eq!(logins, 4);
+}
+
+#[tokio::test]
+async fn snipe_keeps_session_alive_while_waiting() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Window opens 3 hours from now, so the wait before the final login is 2h59m. The
+    // portal drops the session once, answering the second keep-alive with a 401.
+    let now = chrono::Local::now();
+    let start = now + chrono::Duration::days(7) + chrono::Duration::hours(5);
+    let details_requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&details_requests);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(move |_: &Request| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 2 {
+                return ResponseTemplate::new(401);
+            }
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Id": 37,
+                "Name": "Reformer",
+                "Status": "Awaitable",
+                "StartTime": api_time(start),
+                "TrainerDetails": null,
+                "Users": []
+            }))
+        })
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 37, "Reformer", &api_time(start), 1).await;
+
+    let config = test_config_with(
+        &server.uri(),
+        "[snipe]\nkeep_alive_minutes = 20\n",
+    );
+    let clock = Arc::new(SimulatedClock::new(now));
+    let client = PerfectGymClient::builder(&config)
+        .clock(SharedClock::Simulated(clock.clone()))
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+
+    snipe::snipe_class_with_clock(&config, &client, 37, None, clock.as_ref()).await.unwrap();
+
+    // Woken every 20 minutes for a keep-alive: 8 of them in the 179-minute wait
+    let sleeps = clock.sleeps();
+    assert_eq!(&sleeps[..8], &[Duration::from_secs(20 * 60); 8]);
+    assert!(sleeps[8] < Duration::from_secs(20 * 60));
+
+    // The first details lookup, 8 keep-alives and the warm-up request before the window
+    assert_eq!(details_requests.load(Ordering::SeqCst), 10);
+
+    // Initial login, one after the dropped session, then the fresh login before the window
+    let logins = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/Auth/Login")
+        .count();
+    assert_eq!(logins, 3);
+}
+
+#[tokio::test]
+async fn snipe_refreshes_token_before_jwt_expiry_while_waiting() {
+    use base64::Engine;
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    // `exp` has whole seconds only
+    let now = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(now));
+
+    // Each login issues a token that expires 45 minutes later by the simulated clock, which
+    // the server's Date header follows too so no clock skew is detected
+    let issuing_clock = clock.clone();
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .respond_with(move |_: &Request| {
+            let issued = issuing_clock.now();
+            let exp = (issued + chrono::Duration::minutes(45)).timestamp();
+            let encode = |json: String| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+            let jwt = format!("{}.{}.sig", encode(r#"{"alg":"HS256"}"#.to_string()), encode(format!(r#"{{"exp":{}}}"#, exp)));
+            let date = issued.with_timezone(&chrono::Utc).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            ResponseTemplate::new(200)
+                .append_header("date", date.as_str())
+                .append_header("jwt-token", jwt.as_str())
+                .set_body_json(serde_json::json!({ "User": null }))
+        })
+        .mount(&server)
+        .await;
+
+    // Window opens 3 hours from now
+    let start = now + chrono::Duration::days(7) + chrono::Duration::hours(5);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 34,
+            "Name": "Reformer",
+            "Status": "Awaitable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 34, "Reformer", &api_time(start), 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::builder(&config)
+        .clock(SharedClock::Simulated(clock.clone()))
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+    let expires_at = client.token_expires_at().await.unwrap();
+    assert_eq!(expires_at.timestamp(), (now + chrono::Duration::minutes(45)).timestamp());
+
+    snipe::snipe_class_with_clock(&config, &client, 34, None, clock.as_ref()).await.unwrap();
+
+    // Woken a minute before expiry to refresh, without token_refresh_minutes set
+    assert_eq!(clock.sleeps()[0], Duration::from_secs(44 * 60));
+
+    // Initial login, refreshes at 44m, 88m, 132m and 176m, then the login before the window
+    let logins = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/Auth/Login")
+        .count();
+    assert_eq!(logins, 6);
+}
+
+#[tokio::test]
+async fn snipe_shifts_window_by_server_clock_offset() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    // The gym's clock is 30 seconds ahead of ours
+    let now = chrono::Local::now().with_nanosecond(0).unwrap();
+    let server_date = (now + chrono::Duration::seconds(30)).with_timezone(&chrono::Utc);
+    Mock::given(method("POST"))
+        .and(path("/Auth/Login"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .append_header("jwt-token", "test-jwt-token-123")
+                .append_header("Date", server_date.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+                .set_body_json(serde_json::json!({ "User": null })),
+        )
+        .mount(&server)
+        .await;
+
+    // Window opens 10 minutes from now by the gym's clock
+    let start = now + chrono::Duration::days(7) + chrono::Duration::hours(2) + chrono::Duration::minutes(10);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 35,
+            "Name": "Reformer",
+            "Status": "Awaitable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 35, "Reformer", &api_time(start), 1).await;
+
+    let config = test_config(&server.uri());
+    let clock = Arc::new(SimulatedClock::new(now));
+    let client = PerfectGymClient::builder(&config)
+        .clock(SharedClock::Simulated(clock.clone()))
+        .build()
+        .unwrap();
+    client.login().await.unwrap();
+    // Half a second is added because the header is truncated to the second
+    assert_eq!(client.server_offset().await, chrono::Duration::milliseconds(30_500));
+
+    snipe::snipe_class_with_clock(&config, &client, 35, None, clock.as_ref()).await.unwrap();
+
+    // Attempts started when the window opened by the gym's clock, not ours
+    let window_local = start - chrono::Duration::days(7) - chrono::Duration::hours(2) - chrono::Duration::milliseconds(30_500);
+    assert_eq!(clock.now(), window_local);
+}
+
+/// Answer one SNTP request on a local UDP port as a server whose clock is `ahead` of ours.
+/// Returns the server's address.
+async fn spawn_ntp_server(ahead: chrono::Duration) -> String {
+    fn ntp_bytes(time: chrono::DateTime<chrono::Utc>) -> [u8; 8] {
+        let seconds = (time.timestamp() + 2_208_988_800) as u32;
+        let fraction = ((time.timestamp_subsec_nanos() as u64) << 32) / 1_000_000_000;
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+        bytes[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+        bytes
+    }
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let mut packet = [0u8; 48];
+        let (_, peer) = socket.recv_from(&mut packet).await.unwrap();
+        let server_now = chrono::Utc::now() + ahead;
+        packet[0] = 0x24;
+        packet[32..40].copy_from_slice(&ntp_bytes(server_now));
+        packet[40..48].copy_from_slice(&ntp_bytes(server_now));
+        socket.send_to(&packet, peer).await.unwrap();
+    });
+    address
+}
+
+#[tokio::test]
+async fn snipe_shifts_window_by_ntp_offset() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Our clock is 30 seconds behind true time; the window opens 10 minutes from now
+    let now = chrono::Local::now().with_nanosecond(0).unwrap();
+    let start = now + chrono::Duration::days(7) + chrono::Duration::hours(2) + chrono::Duration::minutes(10);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 36,
+            "Name": "Reformer",
+            "Status": "Awaitable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 36, "Reformer", &api_time(start), 1).await;
+
+    let ntp = spawn_ntp_server(chrono::Duration::seconds(30)).await;
+    let config = test_config_with(
+        &server.uri(),
+        &format!("[snipe]\nclock_sync = \"ntp\"\nntp_server = \"{}\"\n", ntp),
+    );
+    let clock = SimulatedClock::new(now);
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    snipe::snipe_class_with_clock(&config, &client, 36, None, &clock).await.unwrap();
+
+    // Attempts started when the window opened by true time
+    let window_local = start - chrono::Duration::days(7) - chrono::Duration::hours(2) - chrono::Duration::seconds(30);
+    let error = (clock.now() - window_local).abs();
+    assert!(error < chrono::Duration::milliseconds(200), "started {} off", error);
+}
+
+#[tokio::test]
+async fn snipe_logs_booking_as_json_with_span_fields() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(3);
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 33,
+            "Name": "Reformer",
+            "Status": "Bookable",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 33, "Reformer", &api_time(start), 1).await;
+
+    let logs = LogBuffer::default();
+    let _guard = tracing::subscriber::set_default(gym_sniper::logging::json_subscriber(logs.clone()));
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+    let clock = SimulatedClock::new(chrono::Local::now());
+    snipe::snipe_class_with_clock(&config, &client, 33, None, &clock).await.unwrap();
+
+    let output = logs.contents();
+    let events: Vec<serde_json::Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let booked = events
+        .iter()
+        .find(|e| e["fields"]["status"] == "booked")
+        .unwrap_or_else(|| panic!("no booking event in {}", output));
+    assert_eq!(booked["level"], "INFO");
+    assert_eq!(booked["fields"]["attempt"], 1);
+    assert!(booked["fields"]["message"].as_str().unwrap().contains("Reformer"));
+    assert_eq!(booked["span"]["name"], "booking");
+    assert_eq!(booked["span"]["class_id"], 33);
+    let spans: Vec<&str> = booked["spans"].as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+    assert_eq!(spans, vec!["snipe", "booking"]);
+}
+
+// ── defend tests ─────────────────────────────────────────────────
+
+#[tokio::test]
+async fn defender_rebooks_vanished_booking() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(2);
+    let class = |status: &str| {
+        serde_json::json!([
+            { "Id": 20, "Name": "Pilates", "StartTime": api_time(start), "Duration": "60", "Status": status, "Trainer": null }
+        ])
+    };
+
+    // First check sees the booking, the second finds it gone
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "CalendarData": [
+                {
+                    "ZoneName": "Studio A",
+                    "ClassesPerHour": [ { "ClassesPerDay": [ class("Booked") ] } ]
+                }
+            ]
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    mount_weekly_classes(&server, class("Bookable")).await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "20"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 20,
+            "Name": "Pilates",
+            "Status": "Booked",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 20, "Pilates", &api_time(start), 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let mut defender = BookingDefender::new();
+    assert!(defender.check(&config, &client).await.unwrap().is_empty());
+    assert_eq!(
+        defender.check(&config, &client).await.unwrap(),
+        vec![Defense::Rebooked { class_id: 20, name: "Pilates".to_string() }]
+    );
+}
+
+#[tokio::test]
+async fn defender_leaves_cancelled_booking_alone() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(2);
+    let class = |status: &str| {
+        serde_json::json!([
+            { "Id": 20, "Name": "Pilates", "StartTime": api_time(start), "Duration": "60", "Status": status, "Trainer": null }
+        ])
+    };
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "CalendarData": [
+                {
+                    "ZoneName": "Studio A",
+                    "ClassesPerHour": [ { "ClassesPerDay": [ class("Booked") ] } ]
+                }
+            ]
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    mount_weekly_classes(&server, class("Bookable")).await;
+    mount_book_class(&server, 20, "Pilates", &api_time(start), 0).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let cancelled = dir.path().join(gym_sniper::defend::CANCELLED_FILE);
+    let mut defender = BookingDefender::new().with_cancelled_file(cancelled.clone());
+    assert!(defender.check(&config, &client).await.unwrap().is_empty());
+
+    // The user cancelled it between checks, so it isn't booked again
+    gym_sniper::defend::record_cancelled(&cancelled, 20, start, chrono::Local::now()).unwrap();
+    assert!(defender.check(&config, &client).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn defender_leaves_a_running_hold_alone() {
+    use gym_sniper::hold::{self, ActiveHold};
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(2);
+    let class = |status: &str| {
+        serde_json::json!([
+            { "Id": 20, "Name": "Pilates", "StartTime": api_time(start), "Duration": "60", "Status": status, "Trainer": null }
+        ])
+    };
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/WeeklyClasses"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "CalendarData": [
+                {
+                    "ZoneName": "Studio A",
+                    "ClassesPerHour": [ { "ClassesPerDay": [ class("Booked") ] } ]
+                }
+            ]
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    mount_weekly_classes(&server, class("Bookable")).await;
+    mount_book_class(&server, 20, "Pilates", &api_time(start), 0).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    // A hold is swapping Pilates for another class, so its release isn't undone
+    let dir = tempfile::TempDir::new().unwrap();
+    let holds = dir.path().join(hold::HOLDS_FILE);
+    let running = ActiveHold { hold_id: 20, prefer_id: 21, until: start };
+    std::fs::write(&holds, serde_json::to_string(&[running]).unwrap()).unwrap();
+    let mut defender = BookingDefender::new()
+        .with_cancelled_file(dir.path().join(gym_sniper::defend::CANCELLED_FILE))
+        .with_holds_file(holds);
+    assert!(defender.check(&config, &client).await.unwrap().is_empty());
+    assert!(defender.check(&config, &client).await.unwrap().is_empty());
+}
+
+// ── confirm tests ────────────────────────────────────────────────
+
+#[tokio::test]
+async fn confirmation_reports_booking_that_vanished() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    // Pilates is still booked; the Spin booking has been rolled back
+    let start = chrono::Local::now() + chrono::Duration::days(7);
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 20, "Name": "Pilates", "StartTime": api_time(start), "Duration": "60", "Status": "Booked", "Trainer": null }
+        ]),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "20"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 20,
+            "Name": "Pilates",
+            "Status": "Booked",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let now = chrono::Local::now();
+    let mut confirmations = BookingConfirmations::new();
+    confirmations.schedule(20, "Pilates", start, None, now, chrono::Duration::hours(3));
+    confirmations.schedule(21, "Spin", start, None, now, chrono::Duration::hours(3));
+    assert!(!confirmations.has_due(now));
+
+    let later = now + chrono::Duration::hours(3);
+    assert!(confirmations.has_due(later));
+    assert_eq!(confirmations.check_due(&config, &client, later).await.unwrap(), vec![21]);
+    assert!(!confirmations.has_due(later + chrono::Duration::days(1)));
+}
+
+// ── report tests ─────────────────────────────────────────────────
+
+#[tokio::test]
+async fn report_contains_every_section() {
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now() + chrono::Duration::days(2);
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 20, "Name": "Pilates", "StartTime": api_time(start), "Duration": "60", "Status": "Booked", "Trainer": null }
+        ]),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "20"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 20,
+            "Name": "Pilates",
+            "Status": "Booked",
+            "StartTime": api_time(start),
+            "TrainerDetails": null,
+            "Users": []
+        })))
         .mount(&server)
         .await;
 
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    queue.add(queued(101, "Yoga", start + chrono::Duration::days(7), SnipeStatus::Pending)).unwrap();
+
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let result = client.cancel_booking(999).await;
-    assert!(result.is_ok());
+
+    let report = ReportDocument::build(&config, &client, &queue).await.unwrap();
+    let doc = serde_json::to_value(&report).unwrap();
+
+    assert!(doc["generated_at"].is_string());
+    assert_eq!(doc["config"]["credentials"]["email"], "test@example.com");
+    assert_ne!(doc["config"]["credentials"]["password"], "password123");
+    assert_eq!(doc["bookings"][0]["name"], "Pilates");
+    assert_eq!(doc["snipe_queue"][0]["class_id"], 101);
+    // Timings are off, so there's no history to report
+    assert!(doc["history"].is_null());
+}
+
+// ── daemon tests ─────────────────────────────────────────────────
+
+/// Class details keyed by class ID: (name, status, start time)
+struct DetailsTable(Vec<(u64, &'static str, &'static str, String)>);
+
+impl Respond for DetailsTable {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let class_id: u64 = request
+            .url
+            .query_pairs()
+            .find(|(k, _)| k == "classId")
+            .and_then(|(_, v)| v.parse().ok())
+            .unwrap_or_default();
+        match self.0.iter().find(|(id, ..)| *id == class_id) {
+            Some((id, name, status, start)) => ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Id": id,
+                "Name": name,
+                "Status": status,
+                "StartTime": start,
+                "TrainerDetails": null,
+                "Users": []
+            })),
+            None => ResponseTemplate::new(404),
+        }
+    }
+}
+
+/// Class ID and simulated time of each booking attempt
+type SimulatedAttempts = Arc<Mutex<Vec<(u64, chrono::DateTime<chrono::Local>)>>>;
+
+/// Rejects bookings as too soon until the simulated clock reaches `opens`, recording
+/// the simulated time of every attempt
+struct SimulatedWindow {
+    clock: Arc<SimulatedClock>,
+    opens: chrono::DateTime<chrono::Local>,
+    attempts: SimulatedAttempts,
+}
+
+impl Respond for SimulatedWindow {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let now = self.clock.now();
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        self.attempts.lock().unwrap().push((body["classId"].as_u64().unwrap(), now));
+        if now < self.opens {
+            return ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"TooSoonToBook\"]}");
+        }
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Tickets": [ { "Name": "Yoga", "StartTime": "2025-01-20T09:00:00", "Trainer": null } ],
+            "ClassId": body["classId"]
+        }))
+    }
+}
+
+fn queued(class_id: u64, name: &str, class_time: chrono::DateTime<chrono::Local>, status: SnipeStatus) -> SnipeEntry {
+    SnipeEntry {
+        class_id,
+        class_name: name.to_string(),
+        class_time,
+        booking_window: class_time - chrono::Duration::days(7) - chrono::Duration::hours(2),
+        trainer: None,
+        added_at: class_time - chrono::Duration::days(8),
+        status,
+        error_message: None,
+        priority: 0,
+        attempts: 0,
+        attempt_log: Vec::new(),
+        waitlist_position: None,
+        required_trainer: None,
+    }
 }
 
 #[tokio::test]
-async fn cancel_booking_failure() {
+async fn daemon_runs_scripted_scenario_on_simulated_clock() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+
+    // Yoga opens in 90 minutes; Pilates opens a day later, on a day that already has a booking
+    let yoga = start + chrono::Duration::days(7) + chrono::Duration::hours(2) + chrono::Duration::minutes(90);
+    let pilates = yoga + chrono::Duration::days(1);
+    // An hour either side of Pilates, whichever keeps it on the same day
+    let spin = [pilates - chrono::Duration::hours(1), pilates + chrono::Duration::hours(1)]
+        .into_iter()
+        .find(|t| t.date_naive() == pilates.date_naive())
+        .unwrap();
+    mount_weekly_classes(
+        &server,
+        serde_json::json!([
+            { "Id": 900, "Name": "Spin", "StartTime": api_time(spin), "Duration": "45", "Status": "Booked", "Trainer": null }
+        ]),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(vec![
+            (101, "Yoga", "Awaitable", api_time(yoga)),
+            (102, "Pilates", "Awaitable", api_time(pilates)),
+            (900, "Spin", "Booked", api_time(spin)),
+        ]))
+        .mount(&server)
+        .await;
+
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let yoga_opens = start + chrono::Duration::minutes(90);
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(SimulatedWindow { clock: Arc::clone(&clock), opens: yoga_opens, attempts: Arc::clone(&attempts) })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    queue.add(queued(101, "Yoga", yoga, SnipeStatus::Pending)).unwrap();
+    queue.add(queued(102, "Pilates", pilates, SnipeStatus::Pending)).unwrap();
+    queue.add(queued(50, "Old Class", start - chrono::Duration::days(10), SnipeStatus::Completed)).unwrap();
+
+    let mut config = test_config(&server.uri());
+    config.gym.window_slack_ms = 500;
+    let until = start + chrono::Duration::days(1) + chrono::Duration::hours(2);
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(until), &Shutdown::new()).await.unwrap();
+
+    // Polls back off while the window is far away, then tighten to every minute
+    let sleeps = clock.sleeps();
+    let mins = |m: u64| Duration::from_secs(m * 60);
+    assert_eq!(&sleeps[..5], &[mins(30), mins(10), mins(10), mins(10), mins(1)]);
+
+    // Attempts start just before the window and stop at the first success
+    let attempts = attempts.lock().unwrap();
+    assert!(attempts.iter().all(|(id, _)| *id == 101), "only Yoga is booked: {:?}", attempts);
+    assert!(attempts[0].1 < yoga_opens);
+    assert!(attempts[attempts.len() - 2].1 < yoga_opens);
+    assert!(attempts[attempts.len() - 1].1 >= yoga_opens);
+
+    // Yoga left the queue, Pilates was skipped for the Spin booking, the old entry was cleaned up
+    assert!(queue.snipes.iter().all(|s| s.class_id != 101 && s.class_id != 50));
+    let skipped = queue.snipes.iter().find(|s| s.class_id == 102).unwrap();
+    assert_eq!(skipped.status, SnipeStatus::Failed);
+    assert!(skipped.error_message.as_deref().unwrap().contains("Spin"));
+    assert!(clock.now() >= until);
+}
+
+#[tokio::test]
+async fn daemon_snipes_windows_for_different_days_side_by_side() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    mount_weekly_classes(&server, serde_json::json!([])).await;
+
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+
+    // Classes on consecutive days whose windows (as the gym gives them) open at the same
+    // moment, 3 minutes from now
+    let opens = start + chrono::Duration::minutes(3);
+    let monday = start + chrono::Duration::days(8);
+    let tuesday = monday + chrono::Duration::days(1);
+    for (id, name, class_time) in [(101, "Yoga", monday), (102, "Pilates", tuesday)] {
+        Mock::given(method("GET"))
+            .and(path("/Classes/ClassCalendar/Details"))
+            .and(query_param("classId", id.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Id": id,
+                "Name": name,
+                "Status": "Awaitable",
+                "StartTime": api_time(class_time),
+                "BookableFrom": api_time(opens),
+                "TrainerDetails": null,
+                "Users": []
+            })))
+            .mount(&server)
+            .await;
+    }
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(SimulatedWindow { clock: Arc::clone(&clock), opens, attempts: Arc::clone(&attempts) })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    for (id, name, class_time) in [(101, "Yoga", monday), (102, "Pilates", tuesday)] {
+        queue.add(SnipeEntry { booking_window: opens, ..queued(id, name, class_time, SnipeStatus::Pending) }).unwrap();
+    }
+
+    let mut config = test_config(&server.uri());
+    config.gym.window_slack_ms = 500;
+    let until = start + chrono::Duration::minutes(30);
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(until), &Shutdown::new()).await.unwrap();
+
+    // Both were sniped from before the window opened, rather than Pilates waiting for Yoga
+    let attempts = attempts.lock().unwrap();
+    for id in [101, 102] {
+        let first = attempts.iter().find(|(class_id, _)| *class_id == id).unwrap();
+        assert!(first.1 < opens, "{} started late: {:?}", id, attempts);
+    }
+    assert!(queue.snipes.is_empty(), "both booked: {:?}", queue.snipes);
+}
+
+#[tokio::test]
+async fn daemon_tries_higher_priority_snipe_first_and_falls_back_on_daily_limit() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+
+    mount_login(&server).await;
+    mount_weekly_classes(&server, serde_json::json!([])).await;
+
+    // A morning backup and a preferred evening class on the same day; the backup's window opens first
+    let day = (start + chrono::Duration::days(8)).date_naive();
+    let at = |hour| day.and_hms_opt(hour, 0, 0).unwrap().and_local_timezone(chrono::Local).unwrap();
+    let (backup, preferred) = (at(7), at(18));
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(vec![
+            (201, "Early Spin", "Awaitable", api_time(backup)),
+            (202, "Evening Yoga", "Awaitable", api_time(preferred)),
+        ]))
+        .mount(&server)
+        .await;
+
+    // Once open, the preferred class hits the daily limit (booked elsewhere by hand) and the backup books
+    let opens = |class_time| class_time - chrono::Duration::days(7) - chrono::Duration::hours(2);
+    let attempts: SimulatedAttempts = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&attempts);
+    let booking_clock = Arc::clone(&clock);
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(move |request: &Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let class_id = body["classId"].as_u64().unwrap();
+            let now = booking_clock.now();
+            recorded.lock().unwrap().push((class_id, now));
+            if now < opens(if class_id == 202 { preferred } else { backup }) {
+                return ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"TooSoonToBook\"]}");
+            }
+            if class_id == 202 {
+                return ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"DailyBookingLimitReached\"]}");
+            }
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Tickets": [ { "Name": "Early Spin", "StartTime": api_time(backup), "Trainer": null } ],
+                "ClassId": class_id
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    queue.add_with(queued(201, "Early Spin", backup, SnipeStatus::Pending), true).unwrap();
+    queue.add_with(queued(202, "Evening Yoga", preferred, SnipeStatus::Pending).with_priority(1), true).unwrap();
+
+    let config = test_config_with(
+        &server.uri(),
+        "[snipe]\ncheck_same_day_bookings = false\nallow_multiple_per_day = true\n",
+    );
+    let until = start + chrono::Duration::days(2);
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(until), &Shutdown::new()).await.unwrap();
+
+    // The backup waited for the preferred class to be turned down, even though its window
+    // opened hours earlier, and was booked on its first attempt
+    let attempts = attempts.lock().unwrap();
+    let ids: Vec<u64> = attempts.iter().map(|(id, _)| *id).collect();
+    let preferred_attempts = ids.iter().filter(|id| **id == 202).count();
+    assert!(ids[..preferred_attempts].iter().all(|id| *id == 202), "preferred class first: {:?}", ids);
+    assert_eq!(&ids[preferred_attempts..], &[201]);
+    let (_, turned_down_at) = attempts[preferred_attempts - 1];
+    assert!(turned_down_at >= opens(preferred), "the daily limit isn't retried");
+    assert!(attempts[preferred_attempts].1 >= turned_down_at);
+    assert!(queue.pending_snipes().is_empty());
+
+    // The turned-down class stays in the queue, marked failed, with a log of every attempt
+    let failed = queue.snipes.iter().find(|s| s.class_id == 202).unwrap();
+    assert_eq!(failed.status, SnipeStatus::Failed);
+    assert_eq!(failed.attempts as usize, preferred_attempts);
+    assert_eq!(failed.attempt_log.len(), preferred_attempts);
+    let (last_at, last_outcome) = failed.attempt_log.last().unwrap();
+    assert_eq!(*last_at, turned_down_at);
+    assert!(last_outcome.contains("daily booking limit reached"));
+    assert!(SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap().snipes.iter().any(|s| s.attempts > 0));
+}
+
+#[tokio::test]
+async fn daemon_only_falls_back_to_a_backup_on_the_daily_limit() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+
+    mount_login(&server).await;
+    mount_weekly_classes(&server, serde_json::json!([])).await;
+
+    // On the first day the preferred class is full, on the second it books; each has a backup
+    let at = |days, hour| {
+        (start + chrono::Duration::days(days)).date_naive().and_hms_opt(hour, 0, 0).unwrap()
+            .and_local_timezone(chrono::Local).unwrap()
+    };
+    let classes = [(401, at(8, 7)), (402, at(8, 18)), (411, at(9, 7)), (412, at(9, 18))];
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(
+            classes.iter().map(|(id, time)| (*id, "Spin", "Awaitable", api_time(*time))).collect(),
+        ))
+        .mount(&server)
+        .await;
+
+    let opens = |class_time| class_time - chrono::Duration::days(7) - chrono::Duration::hours(2);
+    let attempts: SimulatedAttempts = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&attempts);
+    let booking_clock = Arc::clone(&clock);
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(move |request: &Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let class_id = body["classId"].as_u64().unwrap();
+            let now = booking_clock.now();
+            recorded.lock().unwrap().push((class_id, now));
+            let (_, class_time) = classes.iter().find(|(id, _)| *id == class_id).unwrap();
+            if now < opens(*class_time) {
+                return ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"TooSoonToBook\"]}");
+            }
+            if class_id == 402 {
+                return ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"ClassIsFull\"]}");
+            }
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Tickets": [ { "Name": "Spin", "StartTime": api_time(*class_time), "Trainer": null } ],
+                "ClassId": class_id
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    for (id, time) in classes {
+        let priority = if id % 2 == 0 { 1 } else { 0 };
+        queue.add_with(queued(id, "Spin", time, SnipeStatus::Pending).with_priority(priority), true).unwrap();
+    }
+
+    let config = test_config_with(
+        &server.uri(),
+        "[snipe]\ncheck_same_day_bookings = false\nallow_multiple_per_day = true\nmax_attempts = 3\n",
+    );
+    let until = start + chrono::Duration::days(3);
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(until), &Shutdown::new()).await.unwrap();
+
+    // Neither backup was tried: they are kept, marked failed with why they weren't needed
+    let attempts = attempts.lock().unwrap();
+    assert!(attempts.iter().all(|(id, _)| *id == 402 || *id == 412), "attempts: {:?}", attempts);
+    for id in [401, 411] {
+        let backup = queue.snipes.iter().find(|s| s.class_id == id).unwrap();
+        assert_eq!(backup.status, SnipeStatus::Failed);
+        assert!(backup.error_message.as_deref().unwrap().starts_with("Not needed"), "{:?}", backup.error_message);
+    }
+    assert!(queue.snipes.iter().all(|s| s.class_id != 412));
+    assert!(queue.pending_snipes().is_empty());
+}
+
+#[tokio::test]
+async fn daemon_chains_into_second_same_day_snipe_after_success() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+
+    mount_login(&server).await;
+    mount_weekly_classes(&server, serde_json::json!([])).await;
+
+    // Two classes wanted at the same time on a day allowing two bookings, and a backup
+    let day = (start + chrono::Duration::days(8)).date_naive();
+    let at = |hour| day.and_hms_opt(hour, 0, 0).unwrap().and_local_timezone(chrono::Local).unwrap();
+    let (morning, evening) = (at(7), at(18));
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(vec![
+            (301, "Evening Spin", "Awaitable", api_time(evening)),
+            (302, "Evening Yoga", "Awaitable", api_time(evening)),
+            (303, "Early Row", "Awaitable", api_time(morning)),
+        ]))
+        .mount(&server)
+        .await;
+
+    let opens = |class_time| class_time - chrono::Duration::days(7) - chrono::Duration::hours(2);
+    let attempts: SimulatedAttempts = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&attempts);
+    let booking_clock = Arc::clone(&clock);
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(move |request: &Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let class_id = body["classId"].as_u64().unwrap();
+            let now = booking_clock.now();
+            recorded.lock().unwrap().push((class_id, now));
+            let name = if class_id == 302 { "Evening Yoga" } else { "Evening Spin" };
+            if now < opens(evening) {
+                return ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"TooSoonToBook\"]}");
+            }
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Tickets": [ { "Name": name, "StartTime": api_time(evening), "Trainer": null } ],
+                "ClassId": class_id
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    queue.add_with(queued(301, "Evening Spin", evening, SnipeStatus::Pending).with_priority(1), true).unwrap();
+    queue.add_with(queued(302, "Evening Yoga", evening, SnipeStatus::Pending).with_priority(1), true).unwrap();
+    queue.add_with(queued(303, "Early Row", morning, SnipeStatus::Pending), true).unwrap();
+
+    let config = test_config_with(
+        &server.uri(),
+        "[snipe]\ncheck_same_day_bookings = false\nallow_multiple_per_day = true\n",
+    );
+    let until = start + chrono::Duration::days(2);
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(until), &Shutdown::new()).await.unwrap();
+
+    // The first class books as the window opens and the second follows with no pause
+    let attempts = attempts.lock().unwrap();
+    let (last_id, last_at) = attempts[attempts.len() - 1];
+    let (prev_id, prev_at) = attempts[attempts.len() - 2];
+    assert_eq!((prev_id, last_id), (301, 302), "attempts: {:?}", attempts);
+    assert!(prev_at >= opens(evening));
+    assert!(last_at - prev_at < chrono::Duration::seconds(5), "chained after {}", last_at - prev_at);
+    assert_eq!(attempts.iter().filter(|(id, _)| *id == 302).count(), 1);
+    assert!(attempts.iter().all(|(id, _)| *id != 303), "attempts: {:?}", attempts);
+    assert!(queue.pending_snipes().is_empty());
+}
+
+/// From when a class has a status, and your waitlist position while it is "Awaiting"
+type TimelineStep = (chrono::DateTime<chrono::Local>, &'static str, Option<u32>);
+
+/// Class details that change on the simulated clock: for each class ID, its start time and
+/// the steps it goes through
+struct WaitlistTimeline {
+    clock: Arc<SimulatedClock>,
+    classes: Vec<(u64, String, Vec<TimelineStep>)>,
+}
+
+impl Respond for WaitlistTimeline {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let class_id: u64 = request
+            .url
+            .query_pairs()
+            .find(|(k, _)| k == "classId")
+            .and_then(|(_, v)| v.parse().ok())
+            .unwrap_or_default();
+        let Some((id, start, steps)) = self.classes.iter().find(|(id, ..)| *id == class_id) else {
+            return ResponseTemplate::new(404);
+        };
+        let now = self.clock.now();
+        let (_, status, position) = steps.iter().rev().find(|(from, ..)| *from <= now).unwrap_or(&steps[0]);
+        let users = match status {
+            &"Awaiting" => serde_json::json!([
+                { "Status": "Awaiting", "StandByQueueNumber": position, "User": { "IsCurrentUser": true } }
+            ]),
+            _ => serde_json::json!([]),
+        };
+        ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": id,
+            "Name": format!("Class {}", id),
+            "Status": status,
+            "StartTime": start,
+            "TrainerDetails": null,
+            "Users": users
+        }))
+    }
+}
+
+#[tokio::test]
+async fn daemon_keeps_watching_a_snipe_that_lands_on_the_waitlist() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+    let class_time = start + chrono::Duration::hours(5);
+    let minutes = |m: i64| start + chrono::Duration::minutes(m);
+
+    // Full when sniped, then on the waitlist moving up, then a spot comes free
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(WaitlistTimeline {
+            clock: Arc::clone(&clock),
+            classes: vec![(
+                130,
+                api_time(class_time),
+                vec![
+                    (start, "Full", None),
+                    (start + chrono::Duration::seconds(1), "Awaiting", Some(7)),
+                    (minutes(12), "Awaiting", Some(3)),
+                    (minutes(31), "Bookable", None),
+                ],
+            )],
+        })
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("ClassIsFull"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_join_waitlist(&server, 130, "Class 130", &api_time(class_time), Some(7), 1).await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    queue.add(queued(130, "Class 130", class_time, SnipeStatus::Pending)).unwrap();
+    let config = test_config_with(
+        &server.uri(),
+        "[snipe]\non_full = \"waitlist_immediately\"\n",
+    );
+
+    // The snipe joins the waitlist and stays queued, following the position
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(minutes(20)), &Shutdown::new()).await.unwrap();
+    let entry = queue.snipes.iter().find(|s| s.class_id == 130).unwrap();
+    assert_eq!(entry.status, SnipeStatus::Waitlisted);
+    assert_eq!(entry.waitlist_position, Some(3));
+    assert!(queue.next_snipe().is_none());
+
+    // Once the class is bookable again it is booked straight away and leaves the queue
+    mount_book_class(&server, 130, "Class 130", &api_time(class_time), 1).await;
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(minutes(60)), &Shutdown::new()).await.unwrap();
+    assert!(queue.snipes.is_empty(), "{:?}", queue.snipes);
+}
+
+#[tokio::test]
+async fn daemon_skips_a_snipe_whose_trainer_was_substituted() {
+    use chrono::Timelike;
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+    let class_time = start + chrono::Duration::hours(5);
+
+    // Queued for Alice's class, but Mike is covering it by the time it's sniped
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 140,
+            "Name": "Pilates",
+            "Status": "Bookable",
+            "StartTime": api_time(class_time),
+            "TrainerDetails": { "Title": "Coach Mike" },
+            "Users": []
+        })))
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 140, "Pilates", &api_time(class_time), 0).await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    let entry = SnipeEntry { required_trainer: Some("ALICE".to_string()), ..queued(140, "Pilates", class_time, SnipeStatus::Pending) };
+    queue.add(entry).unwrap();
+    let config = test_config(&server.uri());
+
+    let until = start + chrono::Duration::minutes(10);
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(until), &Shutdown::new()).await.unwrap();
+    let entry = queue.snipes.iter().find(|s| s.class_id == 140).unwrap();
+    assert_eq!(entry.status, SnipeStatus::Failed);
+    assert_eq!(entry.error_message.as_deref(), Some("Booking failed: trainer is now Coach Mike, not 'ALICE'"));
+}
+
+#[tokio::test]
+async fn daemon_stops_without_starting_a_snipe_once_shutdown_is_requested() {
+    // Nothing is mounted: the snipe is due, but no request may be made
+    let server = MockServer::start().await;
+    let start = chrono::Local::now();
+    let clock = Arc::new(SimulatedClock::new(start));
+    let class_time = start + chrono::Duration::hours(2);
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    let entry = SnipeEntry { booking_window: start, ..queued(150, "Spin", class_time, SnipeStatus::Pending) };
+    queue.add(entry).unwrap();
+    let config = test_config(&server.uri());
+
+    // No end time, so only the shutdown request lets the loop return
+    let shutdown = Shutdown::new();
+    shutdown.request();
+    let run = snipe::run_daemon_loop(&config, &mut queue, &clock, None, &shutdown);
+    tokio::time::timeout(Duration::from_secs(5), run).await.expect("daemon stops").unwrap();
+
+    assert_eq!(queue.snipes[0].status, SnipeStatus::Pending);
+    assert!(clock.sleeps().is_empty());
+    assert_eq!(SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap().snipes.len(), 1);
+}
+
+#[tokio::test]
+async fn daemon_settles_waitlisted_snipes_on_promotion_drop_or_class_start() {
+    use chrono::Timelike;
+
     let server = MockServer::start().await;
     mount_login(&server).await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = Arc::new(SimulatedClock::new(start));
+    let minutes = |m: i64| start + chrono::Duration::minutes(m);
+    let later = minutes(120);
+    let soon = minutes(20);
+
+    // The session is dropped once, and a lookup fails once for another reason
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "141"))
+        .respond_with(ResponseTemplate::new(401))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "142"))
+        .respond_with(ResponseTemplate::new(404))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(WaitlistTimeline {
+            clock: Arc::clone(&clock),
+            classes: vec![
+                (141, api_time(later), vec![(start, "Awaiting", Some(1)), (minutes(10), "Booked", None)]),
+                (142, api_time(later), vec![(start, "Awaiting", Some(5)), (minutes(10), "Full", None)]),
+                (143, api_time(soon), vec![(start, "Awaiting", Some(2))]),
+            ],
+        })
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let mut queue = SnipeQueue::load_from(&dir.path().join("snipes.json")).unwrap();
+    for (id, class_time, position) in [(141, later, 1), (142, later, 5), (143, soon, 2)] {
+        queue.add_with(queued(id, &format!("Class {}", id), class_time, SnipeStatus::Pending), true).unwrap();
+        queue.mark_waitlisted(id, Some(position)).unwrap();
+    }
+
+    let config = test_config(&server.uri());
+    snipe::run_daemon_loop(&config, &mut queue, &clock, Some(minutes(40)), &Shutdown::new()).await.unwrap();
+
+    // Promoted: done with. Dropped, or still waiting at the start: kept as failed with a note
+    assert!(queue.snipes.iter().all(|s| s.class_id != 141));
+    let note = |id: u64| {
+        let entry = queue.snipes.iter().find(|s| s.class_id == id).unwrap();
+        assert_eq!(entry.status, SnipeStatus::Failed);
+        entry.error_message.clone().unwrap()
+    };
+    assert!(note(142).contains("Dropped off the waitlist"), "{}", note(142));
+    assert!(note(143).contains("#2"), "{}", note(143));
+
+    // One session for every check, logged in again only when the server rejected it
+    let logins = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/Auth/Login")
+        .count();
+    assert_eq!(logins, 2);
+}
+
+// ── soft hold tests ──────────────────────────────────────────────
+
+/// Hold class 201 and prefer class 202, both later today
+async fn mount_hold_details(server: &MockServer, start: chrono::DateTime<chrono::Local>) {
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .respond_with(DetailsTable(vec![
+            (201, "Pilates", "Bookable", api_time(start + chrono::Duration::hours(3))),
+            (202, "Yoga", "Bookable", api_time(start + chrono::Duration::hours(2))),
+        ]))
+        .mount(server)
+        .await;
+}
 
+async fn mount_cancel(server: &MockServer, class_id: u64, times: u64) {
     Mock::given(method("POST"))
         .and(path("/Classes/ClassCalendar/CancelBooking"))
-        .respond_with(ResponseTemplate::new(400).set_body_string("Cannot cancel"))
+        .and(body_partial_json(serde_json::json!({ "classId": class_id })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(times)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn hold_switches_when_preferred_opens() {
+    use chrono::Timelike;
+    use gym_sniper::hold::{self, HoldOutcome};
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = SimulatedClock::new(start);
+
+    // Yoga is full for the first look and the first poll, then a spot opens
+    Mock::given(method("GET"))
+        .and(path("/Classes/ClassCalendar/Details"))
+        .and(query_param("classId", "202"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "Id": 202,
+            "Name": "Yoga",
+            "Status": "Full",
+            "StartTime": api_time(start + chrono::Duration::hours(2)),
+            "TrainerDetails": null,
+            "Users": []
+        })))
+        .up_to_n_times(2)
         .mount(&server)
         .await;
+    mount_hold_details(&server, start).await;
+    mount_book_class(&server, 201, "Pilates", &api_time(start + chrono::Duration::hours(3)), 1).await;
+    mount_book_class(&server, 202, "Yoga", &api_time(start + chrono::Duration::hours(2)), 1).await;
+    mount_cancel(&server, 201, 1).await;
 
     let config = test_config(&server.uri());
-    let client = PerfectGymClient::new(&config);
+    let client = PerfectGymClient::new(&config).unwrap();
     client.login().await.unwrap();
-    let result = client.cancel_booking(999).await;
-    assert!(result.is_err());
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let holds = dir.path().join(hold::HOLDS_FILE);
+    let outcome = hold::hold_and_prefer(&config, &client, 201, 202, &holds, &clock).await.unwrap();
+    assert_eq!(outcome, HoldOutcome::Switched);
+    assert_eq!(clock.sleeps(), vec![Duration::from_secs(60), Duration::from_secs(60)]);
+    // The hold was recorded while it ran and cleared once it finished
+    assert!(holds.exists());
+    assert!(hold::load_active_holds(&holds, start).is_empty());
+}
+
+#[tokio::test]
+async fn hold_is_rebooked_when_preferred_booking_fails() {
+    use chrono::Timelike;
+    use gym_sniper::hold::{self, HoldOutcome};
+
+    let server = MockServer::start().await;
+    mount_login(&server).await;
+    let start = chrono::Local::now().with_nanosecond(0).unwrap();
+    let clock = SimulatedClock::new(start);
+    mount_hold_details(&server, start).await;
+
+    // Yoga first hits the daily limit held by Pilates, then fills up once Pilates is released
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(body_partial_json(serde_json::json!({ "classId": 202 })))
+        .respond_with(ResponseTemplate::new(400).set_body_string("{\"Errors\":[\"DailyBookingLimitReached\"]}"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/Classes/ClassCalendar/BookClass"))
+        .and(body_partial_json(serde_json::json!({ "classId": 202 })))
+        .respond_with(ResponseTemplate::new(400).set_body_string("ClassIsFull"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount_book_class(&server, 201, "Pilates", &api_time(start + chrono::Duration::hours(3)), 2).await;
+    mount_cancel(&server, 201, 1).await;
+
+    let config = test_config(&server.uri());
+    let client = PerfectGymClient::new(&config).unwrap();
+    client.login().await.unwrap();
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let holds = dir.path().join(hold::HOLDS_FILE);
+    let outcome = hold::hold_and_prefer(&config, &client, 201, 202, &holds, &clock).await.unwrap();
+    match outcome {
+        HoldOutcome::RolledBack { reason } => assert!(reason.contains("full"), "unexpected reason: {}", reason),
+        other => panic!("Expected a rollback, got {:?}", other),
+    }
+    assert!(clock.sleeps().is_empty());
 }